@@ -0,0 +1,261 @@
+//! Port shared by the examples: a simulated Sabertooth 2x32 on a
+//! `MockPort` by default, or the serial port named by the `SABERRS_PORT`
+//! environment variable. `SABERRS_PORT=dry-run` runs them on the dry run
+//! of the crate instead, which logs the frames at the info level, when they
+//! are built with the "emulator" feature.
+//!
+//! The simulated device speaks packetized serial with checksums, at any
+//! address. It remembers the commanded speeds and reports a current
+//! proportional to them, within the ratings of the Sabertooth 2x32 profile,
+//! a 24 V battery and a 30 °C temperature.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::time::Duration;
+
+#[cfg(feature = "emulator")]
+use saberrs::testing::DryRun;
+use saberrs::testing::MockPort;
+use saberrs::{DeviceProfile, Result, SabertoothSerial};
+
+#[cfg(feature = "serialport")]
+use saberrs::SabertoothPortShared;
+
+/// Environment variable naming the serial port of real hardware.
+pub const PORT_VAR: &str = "SABERRS_PORT";
+
+/// Value of `SABERRS_PORT` selecting the dry run.
+#[cfg(feature = "emulator")]
+pub const DRY_RUN: &str = "dry-run";
+
+/// Current drawn by a simulated motor at full speed, in amperes.
+const FULL_SPEED_CURRENT: f32 = 20.0;
+
+/// Serial port of the examples. Clones share the same port.
+#[derive(Clone)]
+pub enum Port {
+    Mock(MockPort),
+    #[cfg(feature = "serialport")]
+    Real(SabertoothPortShared),
+    #[cfg(feature = "emulator")]
+    DryRun(DryRun),
+}
+
+impl Port {
+    /// Return true for the simulated device.
+    pub fn is_mock(&self) -> bool {
+        matches!(self, Port::Mock(_))
+    }
+}
+
+/// Open the port named by `SABERRS_PORT`, or a simulated device if the
+/// variable is not set.
+pub fn open() -> Result<Port> {
+    match env::var(PORT_VAR) {
+        #[cfg(feature = "emulator")]
+        Ok(name) if name == DRY_RUN => {
+            eprintln!("using the dry run");
+            Ok(Port::DryRun(DryRun::new()))
+        }
+        Ok(name) => open_real(&name),
+        Err(_) => {
+            eprintln!("{} not set, using a simulated device", PORT_VAR);
+            Ok(Port::Mock(simulated()))
+        }
+    }
+}
+
+#[cfg(feature = "serialport")]
+fn open_real(name: &str) -> Result<Port> {
+    eprintln!("using the Sabertooth on {}", name);
+    Ok(Port::Real(SabertoothPortShared::new(name)?))
+}
+
+#[cfg(not(feature = "serialport"))]
+fn open_real(_name: &str) -> Result<Port> {
+    Err(saberrs::Error::Unsupported(
+        "real hardware needs the serialport feature".to_string(),
+    ))
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Motors {
+    speed: [f32; 2],
+    drive: f32,
+    turn: f32,
+}
+
+/// Return a `MockPort` answering as Sabertooth 2x32 devices in packetized
+/// serial mode with checksums.
+pub fn simulated() -> MockPort {
+    let profile = DeviceProfile::sabertooth_2x32();
+    let mut devices: HashMap<u8, Motors> = HashMap::new();
+    MockPort::with_responder(move |frame| {
+        if frame.len() < 7 {
+            return Vec::new();
+        }
+        let motors = devices.entry(frame[0]).or_default();
+        match frame[1] {
+            40 if frame.len() == 9 => {
+                set(motors, frame);
+                Vec::new()
+            }
+            41 => get(motors, &profile, frame),
+            _ => Vec::new(),
+        }
+    })
+}
+
+fn set(motors: &mut Motors, frame: &[u8]) {
+    let raw = i32::from(frame[4]) | i32::from(frame[5]) << 7;
+    let mut ratio = raw as f32 / 2047.0;
+    if frame[2] & 1 != 0 {
+        ratio = -ratio;
+    }
+    let channel = usize::from(frame[7].wrapping_sub(b'1'));
+    match (frame[2] & !1, frame[6]) {
+        (0, b'M') if channel < 2 => motors.speed[channel] = ratio,
+        (0, b'M') if frame[7] == b'D' => motors.drive = ratio,
+        (0, b'M') if frame[7] == b'T' => motors.turn = ratio,
+        (0x20, b'M') if raw == 1 && channel < 2 => motors.speed[channel] = 0.0,
+        _ => return,
+    }
+    if frame[7] == b'D' || frame[7] == b'T' {
+        motors.speed = [
+            (motors.drive + motors.turn).clamp(-1.0, 1.0),
+            (motors.drive - motors.turn).clamp(-1.0, 1.0),
+        ];
+    }
+}
+
+fn get(motors: &Motors, profile: &DeviceProfile, frame: &[u8]) -> Vec<u8> {
+    let speed = match frame[5] {
+        b'2' => motors.speed[1],
+        _ => motors.speed[0],
+    };
+    let value: i32 = match frame[2] {
+        0 => (speed * 2047.0).round() as i32,
+        0x10 => 240,
+        0x20 => {
+            let current = speed.abs() * FULL_SPEED_CURRENT;
+            current.min(profile.peak_current()).round() as i32
+        }
+        0x40 => 30,
+        _ => return Vec::new(),
+    };
+    let sign = if value < 0 { 1 } else { 0 };
+    let magnitude = value.unsigned_abs();
+    let mut reply = vec![frame[0], 73, frame[2] | sign, 0];
+    reply[3] = checksum(&reply[..3]);
+    reply.extend_from_slice(&[
+        (magnitude & 0x7f) as u8,
+        ((magnitude >> 7) & 0x7f) as u8,
+        frame[4],
+        frame[5],
+    ]);
+    reply.push(checksum(&reply[4..]));
+    reply
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) & 0x7f
+}
+
+impl io::Read for Port {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Port::Mock(port) => port.read(buf),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.read(buf),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.read(buf),
+        }
+    }
+}
+
+impl io::Write for Port {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Port::Mock(port) => port.write(buf),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.write(buf),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Port::Mock(port) => port.flush(),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.flush(),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.flush(),
+        }
+    }
+}
+
+impl SabertoothSerial for Port {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        match self {
+            Port::Mock(port) => port.set_timeout(timeout),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.set_timeout(timeout),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.set_timeout(timeout),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        match self {
+            Port::Mock(port) => port.timeout(),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.timeout(),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.timeout(),
+        }
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        match self {
+            Port::Mock(port) => port.set_baud_rate(baud_rate),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.set_baud_rate(baud_rate),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.set_baud_rate(baud_rate),
+        }
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        match self {
+            Port::Mock(port) => port.baud_rate(),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.baud_rate(),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.baud_rate(),
+        }
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        match self {
+            Port::Mock(port) => port.clear_all(),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.clear_all(),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.clear_all(),
+        }
+    }
+
+    fn clear_input(&self) -> Result<()> {
+        match self {
+            Port::Mock(port) => port.clear_input(),
+            #[cfg(feature = "serialport")]
+            Port::Real(port) => port.clear_input(),
+            #[cfg(feature = "emulator")]
+            Port::DryRun(port) => port.clear_input(),
+        }
+    }
+}
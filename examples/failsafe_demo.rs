@@ -0,0 +1,59 @@
+//! Failsafe demo: a software fuse trips on an overloaded motor, the control
+//! loop checks its deadlines, and everything ends with an emergency stop.
+//!
+//! Runs against a simulated device, where a motor at full speed draws 20 A,
+//! or set `SABERRS_PORT` to the serial port of a Sabertooth 2x32 in
+//! packetized serial mode at address 128, with the wheels off the ground.
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use saberrs::sabertooth2x32::{PacketSerial, PacketType, Sabertooth2x32};
+use saberrs::{Error, FailsafeAction, FuseEvent, Result, SoftFuse};
+
+fn main() -> Result<()> {
+    let port = common::open()?;
+    let mut saber = PacketSerial::from(port).with_packet_type(PacketType::Checksum);
+
+    // 5 A continuous, 20 A for about 0.25 s
+    let mut fuse = SoftFuse::new(5.0, 100.0).with_action(FailsafeAction::ZeroSpeed);
+    let deadline = Duration::from_millis(20);
+
+    for step in 0..20 {
+        let ratio = if step < 10 { 0.2 } else { 1.0 };
+        for channel in 1..=2 {
+            if fuse.is_tripped(channel) {
+                continue;
+            }
+            match saber.set_speed_deadline(channel, ratio, deadline) {
+                Ok(_) => {}
+                Err(Error::DeadlineMissed { elapsed, .. }) => {
+                    println!("channel {}: late by {:?}", channel, elapsed - deadline)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        thread::sleep(Duration::from_millis(50));
+
+        fuse.poll(&mut saber)?;
+        for event in fuse.drain_events() {
+            if let FuseEvent::Tripped { channel, current } = event {
+                println!("channel {} tripped at {:.1} A", channel, current);
+            }
+        }
+        if fuse.is_tripped(1) && fuse.is_tripped(2) {
+            break;
+        }
+    }
+
+    saber.emergency_stop(FailsafeAction::HardBrake)?;
+    let stats = saber.stats();
+    println!(
+        "emergency stop, {} deadline misses, max latency {:?}",
+        stats.deadline_misses(),
+        stats.max_latency()
+    );
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Multi-device bus: two Sabertooth 2x32 sharing a serial port, found by
+//! name in an address book.
+//!
+//! Runs against simulated devices, or set `SABERRS_PORT` to a serial port
+//! with two Sabertooth 2x32 in packetized serial mode at addresses 128 and
+//! 129, at 9600 baud.
+
+mod common;
+
+use saberrs::sabertooth2x32::{PacketSerial, PacketType, Sabertooth2x32, Strictness};
+use saberrs::{AddressBook, Result};
+
+fn main() -> Result<()> {
+    let port = common::open()?;
+
+    let mut book = AddressBook::new();
+    book.insert_with_baud_rate("front", "bus", 128, 9600);
+    book.insert_with_baud_rate("rear", "bus", 129, 9600);
+
+    // One handle per device, on clones of the same port. PacketSerial::
+    // open_named() does the same from a port name with a real port.
+    let mut devices = Vec::new();
+    for (name, bus, address) in book.iter() {
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_address(address)
+            .with_name(name)
+            // Replies from the other device are skipped
+            .with_strictness(Strictness::Skip);
+        if let Some(baud_rate) = book.baud_rate(name) {
+            saber = saber.with_baud_rate(baud_rate);
+        }
+        devices.push((book.label(bus, address), saber));
+    }
+
+    for (i, (_, saber)) in devices.iter_mut().enumerate() {
+        saber.set_speed(1, 0.2 * (i + 1) as f32)?;
+    }
+    for (label, saber) in devices.iter_mut() {
+        println!(
+            "{}: speed {:+.2}, battery {:.1} V",
+            label,
+            saber.get_speed(1)?,
+            saber.get_voltage(1)?
+        );
+    }
+    for (_, saber) in devices.iter_mut() {
+        saber.all_stop()?;
+    }
+    Ok(())
+}
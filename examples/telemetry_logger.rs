@@ -0,0 +1,38 @@
+//! Telemetry logger: record the measurements of both channels while ramping
+//! the speed, then export them as CSV on the standard output.
+//!
+//! Runs against a simulated device, or set `SABERRS_PORT` to the serial port
+//! of a Sabertooth 2x32 in packetized serial mode at address 128.
+
+mod common;
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use saberrs::sabertooth2x32::{PacketSerial, PacketType, Sabertooth2x32};
+use saberrs::telemetry::{MemoryStore, TelemetryLog};
+use saberrs::Result;
+
+fn main() -> Result<()> {
+    let port = common::open()?;
+    let period = if port.is_mock() {
+        Duration::from_millis(10)
+    } else {
+        Duration::from_millis(200)
+    };
+    let mut saber = PacketSerial::from(port).with_packet_type(PacketType::Checksum);
+    let mut log = TelemetryLog::new(MemoryStore::new(1000));
+
+    for step in 0..=4 {
+        let ratio = step as f32 * 0.1;
+        saber.set_speed(1, ratio)?;
+        saber.set_speed(2, -ratio)?;
+        thread::sleep(period);
+        log.poll(&mut saber, 1)?;
+        log.poll(&mut saber, 2)?;
+    }
+    saber.all_stop()?;
+
+    log.write_csv(io::stdout())
+}
@@ -0,0 +1,58 @@
+//! Teleoperation: drive a differential robot from a scripted joystick.
+//!
+//! Runs against a simulated device, or set `SABERRS_PORT` to the serial port
+//! of a Sabertooth 2x32 in packetized serial mode at address 128, with the
+//! wheels off the ground.
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use saberrs::sabertooth2x32::{PacketSerial, PacketType, Sabertooth2x32};
+use saberrs::{Percent, RatioSetters, Result};
+
+/// Joystick positions (drive, turn), as percentages.
+const STICK: &[(f32, f32)] = &[
+    (0.0, 0.0),
+    (25.0, 0.0),
+    (50.0, 0.0),
+    (50.0, 25.0),
+    (50.0, -25.0),
+    (-25.0, 0.0),
+    (0.0, 50.0),
+];
+
+fn main() -> Result<()> {
+    let port = common::open()?;
+    let period = if port.is_mock() {
+        Duration::from_millis(10)
+    } else {
+        Duration::from_millis(500)
+    };
+    let mut saber = PacketSerial::from(port).with_packet_type(PacketType::Checksum);
+
+    for &(drive, turn) in STICK {
+        saber.set_drive_to(Percent::new(drive)?)?;
+        saber.set_turn_to(Percent::new(turn)?)?;
+        thread::sleep(period);
+        println!(
+            "drive {:>7} turn {:>7} -> speeds {:+.2} {:+.2}",
+            Percent::new(drive)?,
+            Percent::new(turn)?,
+            saber.get_speed(1)?,
+            saber.get_speed(2)?
+        );
+    }
+
+    // The same with plain ratios
+    saber.drive_arcade(0.1, 0.0)?;
+    thread::sleep(period);
+    saber.all_stop()?;
+    println!(
+        "{} frames sent, {} received",
+        saber.stats().frames_sent(),
+        saber.stats().frames_received()
+    );
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+/// Mapping between human-readable device names and their location on the
+/// serial links, ie. a port name and a packet address, with an optional baud
+/// rate for the fleets mixing several rates.
+///
+/// A handle opened with `open_named()`, or named with `with_name()`, shows its
+/// name in the frame dumps, its warnings and its diagnostics bundle. The
+/// errors and the `Stats` carry no name: use [label()](#method.label) for
+/// naming them in the messages of the application.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::AddressBook;
+///
+/// let mut book = AddressBook::new();
+/// book.insert("left-drive", "/dev/ttyUSB0", 128);
+/// book.insert("rear-axle", "/dev/ttyUSB0", 129);
+///
+/// assert_eq!(book.lookup("rear-axle"), Some(("/dev/ttyUSB0", 129)));
+/// assert_eq!(book.name_of("/dev/ttyUSB0", 129), Some("rear-axle"));
+/// assert_eq!(book.label("/dev/ttyUSB0", 129), "rear-axle (/dev/ttyUSB0@129)");
+/// assert_eq!(book.label("/dev/ttyUSB1", 130), "/dev/ttyUSB1@130");
+///
+/// book.insert_with_baud_rate("winch", "/dev/ttyUSB0", 130, 9600);
+/// assert_eq!(book.baud_rate("winch"), Some(9600));
+/// assert_eq!(book.baud_rate("rear-axle"), None);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AddressBook {
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    port: String,
+    address: u8,
+    baud_rate: Option<u32>,
+}
+
+impl AddressBook {
+    /// Create an empty address book.
+    pub fn new() -> AddressBook {
+        AddressBook::default()
+    }
+
+    /// Assign *name* to the device at *address* on *port*. An existing entry
+    /// with the same name is replaced.
+    pub fn insert(&mut self, name: &str, port: &str, address: u8) {
+        self.insert_entry(name, port, address, None);
+    }
+
+    /// Assign *name* to the device at *address* on *port*, talking at
+    /// *baud_rate*. The handles opened with `open_named()` re-assert the baud
+    /// rate before each transaction, so that devices at different rates can
+    /// share a port through a multiplexer.
+    pub fn insert_with_baud_rate(&mut self, name: &str, port: &str, address: u8, baud_rate: u32) {
+        self.insert_entry(name, port, address, Some(baud_rate));
+    }
+
+    fn insert_entry(&mut self, name: &str, port: &str, address: u8, baud_rate: Option<u32>) {
+        let entry = Entry {
+            port: port.to_string(),
+            address,
+            baud_rate,
+        };
+        self.entries.insert(name.to_string(), entry);
+    }
+
+    /// Remove the entry named *name*.
+    pub fn remove(&mut self, name: &str) {
+        self.entries.remove(name);
+    }
+
+    /// Return the port and the address of the device named *name*.
+    pub fn lookup(&self, name: &str) -> Option<(&str, u8)> {
+        self.entries
+            .get(name)
+            .map(|entry| (entry.port.as_str(), entry.address))
+    }
+
+    /// Return the baud rate of the device named *name*, if it has one.
+    pub fn baud_rate(&self, name: &str) -> Option<u32> {
+        self.entries.get(name).and_then(|entry| entry.baud_rate)
+    }
+
+    /// Return the name of the device at *address* on *port*.
+    pub fn name_of(&self, port: &str, address: u8) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(_, entry)| entry.port == port && entry.address == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Return a label suitable for logs and error messages, containing the
+    /// device name when it is known.
+    pub fn label(&self, port: &str, address: u8) -> String {
+        match self.name_of(port, address) {
+            Some(name) => format!("{} ({}@{})", name, port, address),
+            None => format!("{}@{}", port, address),
+        }
+    }
+
+    /// Iterate over the entries as `(name, port, address)`, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, u8)> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry.port.as_str(), entry.address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert() {
+        let mut book = AddressBook::new();
+        book.insert("left-drive", "/dev/ttyUSB0", 128);
+        book.insert_with_baud_rate("left-drive", "/dev/ttyUSB1", 130, 9600);
+        assert_eq!(Some(("/dev/ttyUSB1", 130)), book.lookup("left-drive"));
+        assert_eq!(Some(9600), book.baud_rate("left-drive"));
+        assert_eq!(None, book.name_of("/dev/ttyUSB0", 128));
+
+        book.remove("left-drive");
+        assert_eq!(None, book.lookup("left-drive"));
+        assert_eq!(None, book.baud_rate("left-drive"));
+        book.remove("left-drive");
+    }
+
+    #[test]
+    fn test_name_of() {
+        let mut book = AddressBook::new();
+        book.insert("front", "/dev/ttyUSB0", 128);
+        book.insert("rear", "/dev/ttyUSB1", 128);
+        assert_eq!(Some("front"), book.name_of("/dev/ttyUSB0", 128));
+        assert_eq!(Some("rear"), book.name_of("/dev/ttyUSB1", 128));
+        assert_eq!(None, book.name_of("/dev/ttyUSB0", 129));
+        assert_eq!("rear (/dev/ttyUSB1@128)", book.label("/dev/ttyUSB1", 128));
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut book = AddressBook::new();
+        book.insert("winch", "/dev/ttyUSB0", 130);
+        book.insert("arm", "/dev/ttyUSB0", 129);
+        assert_eq!(
+            vec![("arm", "/dev/ttyUSB0", 129), ("winch", "/dev/ttyUSB0", 130)],
+            book.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(0, AddressBook::new().iter().count());
+    }
+
+    #[test]
+    #[cfg(feature = "serialport")]
+    fn test_open_named() {
+        use crate::sabertooth2x32::PacketSerial;
+        use crate::Error;
+
+        let book = AddressBook::new();
+        let res = PacketSerial::open_named(&book, "left-drive");
+        assert!(matches!(res, Err(Error::InvalidInput(_))));
+    }
+}
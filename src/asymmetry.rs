@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::sabertooth2x32::Sabertooth2x32;
+#[cfg(all(feature = "async", feature = "telemetry"))]
+use crate::telemetry::{Quantity, TelemetrySnapshot};
+use crate::Result;
+
+/// Default time the asymmetry must last before it is reported.
+pub const DEFAULT_SUSTAIN: Duration = Duration::from_secs(2);
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// Current under which both motors are considered idle, in amperes.
+const IDLE_CURRENT: f32 = 0.1;
+
+/// Event emitted by [AsymmetryMonitor](struct.AsymmetryMonitor.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AsymmetryEvent {
+    /// The currents of the two channels differed by more than the threshold
+    /// during straight driving for the sustain time. *asymmetry* is the
+    /// relative difference of the currents per unit of command, and
+    /// *currents* the last currents of channels 1 and 2, in amperes.
+    Detected {
+        asymmetry: f32,
+        currents: (f32, f32),
+    },
+
+    /// The currents are balanced again.
+    Cleared,
+}
+
+/// Monitor comparing the two channels during straight driving, for detecting
+/// a failing motor, a mechanical binding or a wiring fault.
+///
+/// When both channels are commanded about the same speed, the motors of a
+/// healthy drivetrain draw about the same current. The monitor divides the
+/// current of each channel by its command, and computes the relative
+/// difference of the results: 0.0 for balanced channels, 1.0 when one of
+/// them draws nothing. When it stays above the threshold for the sustain
+/// time, an [AsymmetryEvent](enum.AsymmetryEvent.html) is emitted.
+///
+/// The samples taken while turning or at low command are ignored, since the
+/// currents then differ for good reasons, and they restart the sustain time.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{AsymmetryEvent, AsymmetryMonitor};
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// let mut monitor = AsymmetryMonitor::new(0.4);
+///
+/// // In the control loop
+/// monitor.poll(&mut saber)?;
+/// for event in monitor.drain_events() {
+///     if let AsymmetryEvent::Detected { currents, .. } = event {
+///         eprintln!("check the drivetrain: {:?} A", currents);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AsymmetryMonitor {
+    threshold: f32,
+    sustain: Duration,
+    straight_tolerance: f32,
+    min_command: f32,
+    since: Option<Instant>,
+    detected: bool,
+    asymmetry: Option<f32>,
+    events: VecDeque<AsymmetryEvent>,
+}
+
+impl AsymmetryMonitor {
+    /// Create a new monitor reporting a relative difference above
+    /// *threshold* lasting for [DEFAULT_SUSTAIN]. Straight driving is
+    /// detected for commands of the same sign, at least 0.2, differing by at
+    /// most 0.05.
+    ///
+    /// [DEFAULT_SUSTAIN]: constant.DEFAULT_SUSTAIN.html
+    pub fn new(threshold: f32) -> AsymmetryMonitor {
+        AsymmetryMonitor {
+            threshold,
+            sustain: DEFAULT_SUSTAIN,
+            straight_tolerance: 0.05,
+            min_command: 0.2,
+            since: None,
+            detected: false,
+            asymmetry: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Set how long the asymmetry must last before it is reported.
+    pub fn with_sustain(mut self, sustain: Duration) -> Self {
+        self.sustain = sustain;
+        self
+    }
+
+    /// Set the largest difference between the commands of the two channels
+    /// considered as straight driving.
+    pub fn with_straight_tolerance(mut self, tolerance: f32) -> Self {
+        self.straight_tolerance = tolerance.abs();
+        self
+    }
+
+    /// Set the smallest command considered, below which the currents are
+    /// dominated by friction and noise.
+    pub fn with_min_command(mut self, min_command: f32) -> Self {
+        self.min_command = min_command.abs();
+        self
+    }
+
+    /// Relative difference of the last sample taken during straight
+    /// driving, if any.
+    pub fn asymmetry(&self) -> Option<f32> {
+        self.asymmetry
+    }
+
+    /// Return true if an asymmetry was detected and has not cleared since.
+    pub fn is_detected(&self) -> bool {
+        self.detected
+    }
+
+    /// Remove and return the pending events, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = AsymmetryEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Check the *commands* and the *currents* of channels 1 and 2, measured
+    /// by other means than [poll()](#method.poll). Return true if an
+    /// asymmetry is detected with this sample.
+    pub fn update(&mut self, commands: (f32, f32), currents: (f32, f32)) -> bool {
+        self.update_at(commands, currents, Instant::now())
+    }
+
+    /// Read the speed and the current of both channels of *saber*, and check
+    /// them.
+    pub fn poll<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S) -> Result<()> {
+        let commands = (saber.get_commanded_speed(1)?, saber.get_commanded_speed(2)?);
+        let currents = (saber.get_current(1)?, saber.get_current(2)?);
+        self.update(commands, currents);
+        Ok(())
+    }
+
+    /// Check the speeds and the currents of a snapshot of the
+    /// [telemetry_stream()]. Snapshots missing one of them are ignored.
+    ///
+    /// **Requires** the "async" and "telemetry" features.
+    ///
+    /// [telemetry_stream()]: telemetry/fn.telemetry_stream.html
+    #[cfg(all(feature = "async", feature = "telemetry"))]
+    pub fn update_snapshot(&mut self, snapshot: &TelemetrySnapshot) -> bool {
+        let get = |channel, quantity| snapshot.get(channel, quantity);
+        match (
+            get(1, Quantity::Speed),
+            get(2, Quantity::Speed),
+            get(1, Quantity::Current),
+            get(2, Quantity::Current),
+        ) {
+            (Some(cmd1), Some(cmd2), Some(cur1), Some(cur2)) => {
+                self.update((cmd1, cmd2), (cur1, cur2))
+            }
+            _ => false,
+        }
+    }
+
+    fn update_at(&mut self, commands: (f32, f32), currents: (f32, f32), now: Instant) -> bool {
+        let asymmetry = match self.straight_asymmetry(commands, currents) {
+            Some(asymmetry) => asymmetry,
+            None => {
+                self.since = None;
+                return false;
+            }
+        };
+        self.asymmetry = Some(asymmetry);
+
+        if asymmetry <= self.threshold {
+            self.since = None;
+            if self.detected {
+                self.detected = false;
+                log_info!("channel currents balanced again");
+                self.push_event(AsymmetryEvent::Cleared);
+            }
+            return false;
+        }
+
+        let since = *self.since.get_or_insert(now);
+        if self.detected || now.saturating_duration_since(since) < self.sustain {
+            return false;
+        }
+        self.detected = true;
+        log_warn!(
+            "channel currents asymmetric by {:.0}%: {:.1} A and {:.1} A",
+            asymmetry * 100.0,
+            currents.0,
+            currents.1
+        );
+        self.push_event(AsymmetryEvent::Detected {
+            asymmetry,
+            currents,
+        });
+        true
+    }
+
+    /// Relative difference of the currents per unit of command, or `None`
+    /// if the sample was not taken during straight driving.
+    fn straight_asymmetry(&self, commands: (f32, f32), currents: (f32, f32)) -> Option<f32> {
+        let (cmd1, cmd2) = commands;
+        let straight = cmd1.signum() == cmd2.signum()
+            && cmd1.abs().min(cmd2.abs()) >= self.min_command
+            && (cmd1 - cmd2).abs() <= self.straight_tolerance;
+        if !straight {
+            return None;
+        }
+        if currents.0.abs().max(currents.1.abs()) < IDLE_CURRENT {
+            return Some(0.0);
+        }
+        let load1 = currents.0.abs() / cmd1.abs();
+        let load2 = currents.1.abs() / cmd2.abs();
+        Some((load1 - load2).abs() / load1.max(load2))
+    }
+
+    fn push_event(&mut self, event: AsymmetryEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_update() {
+        let mut monitor = AsymmetryMonitor::new(0.3).with_sustain(Duration::from_secs(1));
+        let t0 = Instant::now();
+        let later = |ms| t0 + Duration::from_millis(ms);
+
+        // Balanced, then turning with unbalanced currents
+        assert!(!monitor.update_at((0.5, 0.5), (4.0, 4.4), t0));
+        assert!((monitor.asymmetry().unwrap() - 0.4 / 4.4).abs() < 1e-6);
+        assert!(!monitor.update_at((0.5, -0.5), (4.0, 1.0), later(100)));
+        assert!(!monitor.update_at((0.1, 0.1), (4.0, 1.0), later(200)));
+
+        // Sustained asymmetry
+        assert!(!monitor.update_at((0.5, 0.5), (4.0, 1.0), later(300)));
+        assert!(!monitor.update_at((0.5, 0.5), (4.0, 1.0), later(1200)));
+        assert!(monitor.update_at((0.5, 0.5), (4.0, 1.0), later(1300)));
+        assert!(monitor.is_detected());
+        assert!(!monitor.update_at((0.5, 0.5), (4.0, 1.0), later(2500)));
+
+        monitor.update_at((-0.5, -0.5), (-4.0, -4.0), later(2600));
+        assert!(!monitor.is_detected());
+        let events: Vec<_> = monitor.drain_events().collect();
+        assert_eq!(
+            events,
+            vec![
+                AsymmetryEvent::Detected {
+                    asymmetry: 0.75,
+                    currents: (4.0, 1.0)
+                },
+                AsymmetryEvent::Cleared,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_poll() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        let mut monitor = AsymmetryMonitor::new(0.3).with_sustain(Duration::from_secs(0));
+
+        port.queue_reply(b"M1: 1023\r\n");
+        port.queue_reply(b"M2: 1023\r\n");
+        port.queue_reply(b"M1:C50\r\n");
+        port.queue_reply(b"M2:C10\r\n");
+        monitor.poll(&mut saber).unwrap();
+        assert_eq!(
+            port.take_written(),
+            b"M1: get\r\nM2: get\r\nM1: getc\r\nM2: getc\r\n"
+        );
+        assert!(monitor.is_detected());
+    }
+}
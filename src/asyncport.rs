@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::{Result, SabertoothSerial};
+
+/// Asynchronous counterpart of [SabertoothSerial], the port of the
+/// asynchronous handles like `sabertooth2x32::AsyncPacketSerial`.
+///
+/// The handles do not depend on a runtime, so they rely on the port for the
+/// timeouts: a read must complete with an `io::ErrorKind::TimedOut` error
+/// when nothing is received within the timeout of the port. With the "tokio"
+/// feature, [TokioPort] implements it for the tokio streams, like the
+/// `SerialStream` of tokio-serial.
+///
+/// [SabertoothSerial]: trait.SabertoothSerial.html
+/// [TokioPort]: struct.TokioPort.html
+pub trait AsyncSabertoothSerial: Send {
+    /// Write all the bytes of *buf*.
+    fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> impl Future<Output = Result<()>> + Send + 'a;
+
+    /// Read at least one byte into *buf*, and return the number of bytes
+    /// read.
+    fn read<'a>(&'a mut self, buf: &'a mut [u8])
+        -> impl Future<Output = Result<usize>> + Send + 'a;
+}
+
+#[derive(Debug, Default)]
+struct BridgeInner {
+    tx: Vec<u8>,
+    rx: VecDeque<u8>,
+    timeout: Duration,
+    baud_rate: u32,
+}
+
+/// In-memory [SabertoothSerial] through which an asynchronous handle runs its
+/// synchronous handle: the frames written by the handle are collected, and
+/// the bytes read from the asynchronous port are handed over. It never
+/// blocks, reading with nothing received fails with
+/// `io::ErrorKind::WouldBlock`.
+///
+/// Clones share the same buffers.
+///
+/// [SabertoothSerial]: trait.SabertoothSerial.html
+#[derive(Clone, Debug, Default)]
+pub struct BridgePort {
+    inner: Arc<Mutex<BridgeInner>>,
+}
+
+impl BridgePort {
+    pub(crate) fn new() -> BridgePort {
+        BridgePort::default()
+    }
+
+    /// Remove and return the bytes written so far.
+    pub(crate) fn take_tx(&self) -> Vec<u8> {
+        std::mem::take(&mut self.lock().tx)
+    }
+
+    /// Hand the bytes *data* over to the reads.
+    pub(crate) fn push_rx(&self, data: &[u8]) {
+        self.lock().rx.extend(data);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BridgeInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl io::Read for BridgePort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut inner = self.lock();
+        if inner.rx.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "no data"));
+        }
+        let len = buf.len().min(inner.rx.len());
+        for (dst, src) in buf.iter_mut().zip(inner.rx.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for BridgePort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SabertoothSerial for BridgePort {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.lock().timeout = timeout;
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.lock().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        self.lock().baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(self.lock().baud_rate)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        self.lock().rx.clear();
+        Ok(())
+    }
+}
+
+/// Default timeout of the reads of a [TokioPort](struct.TokioPort.html).
+#[cfg(feature = "tokio")]
+pub const DEFAULT_ASYNC_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// [AsyncSabertoothSerial] on a tokio stream, like the `SerialStream` of
+/// tokio-serial, with the timeouts of the reads run by the tokio timer.
+///
+/// **Requires** the "tokio" feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use saberrs::TokioPort;
+/// use saberrs::sabertooth2x32::{AsyncPacketSerial, AsyncSabertooth2x32};
+/// use tokio_serial::SerialPortBuilderExt;
+///
+/// let stream = tokio_serial::new("/dev/ttyS0", 9600).open_native_async()?;
+/// let mut saber = AsyncPacketSerial::new(TokioPort::new(stream));
+/// saber.set_speed(1, 0.5).await?;
+/// let voltage = saber.get_voltage(1).await?;
+/// ```
+///
+/// [AsyncSabertoothSerial]: trait.AsyncSabertoothSerial.html
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct TokioPort<T> {
+    stream: T,
+    timeout: Duration,
+}
+
+#[cfg(feature = "tokio")]
+impl<T> TokioPort<T> {
+    /// Wrap *stream*, with a read timeout of 100 ms.
+    pub fn new(stream: T) -> TokioPort<T> {
+        TokioPort {
+            stream,
+            timeout: DEFAULT_ASYNC_TIMEOUT,
+        }
+    }
+
+    /// Set the timeout of the reads.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Return the wrapped stream.
+    pub fn into_inner(self) -> T {
+        self.stream
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> AsyncSabertoothSerial for TokioPort<T>
+where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send,
+{
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        tokio::io::AsyncWriteExt::write_all(&mut self.stream, buf).await?;
+        Ok(())
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = tokio::io::AsyncReadExt::read(&mut self.stream, buf);
+        match tokio::time::timeout(self.timeout, read).await {
+            Ok(Ok(0)) => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(res) => Ok(res?),
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "no reply").into()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokio_port() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (client, mut device) = tokio::io::duplex(64);
+            let mut port = TokioPort::new(client).with_timeout(Duration::from_millis(10));
+            port.write_all(b"M1: getb\r\n").await.unwrap();
+
+            let mut buf = [0u8; 16];
+            let count = tokio::io::AsyncReadExt::read(&mut device, &mut buf).await;
+            assert_eq!(b"M1: getb\r\n", &buf[..count.unwrap()]);
+            let err = port.read(&mut buf).await.unwrap_err();
+            assert!(matches!(err, crate::Error::Io(ref e) if e.kind() == io::ErrorKind::TimedOut));
+
+            tokio::io::AsyncWriteExt::write_all(&mut device, b"M1:B240\r\n")
+                .await
+                .unwrap();
+            assert_eq!(9, port.read(&mut buf).await.unwrap());
+        });
+    }
+}
@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::sabertooth2x32::{Operation, PacketType};
+use crate::stats::BITS_PER_BYTE;
+
+/// Longest "Plain Text" set command, like `MD: -2047\r\n`.
+const TEXT_SET_SIZE: usize = 11;
+
+/// Longest "Plain Text" get request, like `M1: getb\r\n`.
+const TEXT_GET_SIZE: usize = 10;
+
+/// Longest "Plain Text" reply, like `M1: -2047\r\n`.
+pub(crate) const TEXT_REPLY_SIZE: usize = 11;
+
+/// Time taken by *bytes* on the wire at *baud_rate*.
+pub(crate) fn transfer_time(baud_rate: u32, bytes: usize) -> Duration {
+    if baud_rate == 0 {
+        return Duration::ZERO;
+    }
+    let bits = bytes as u64 * BITS_PER_BYTE;
+    Duration::from_nanos(bits * 1_000_000_000 / u64::from(baud_rate))
+}
+
+/// Estimator of the load of a serial link, for checking a command and
+/// telemetry schedule before running it on a slow link.
+///
+/// The load is computed from the frame sizes of the protocol: checksum
+/// frames are the most compact (9 bytes per set command, 7 per get request
+/// and 9 per reply), then CRC frames and the "Plain Text" protocol. On a
+/// half-duplex link, like most radio modems, the requests and the replies
+/// share the same channel.
+///
+/// For the low-bandwidth links, use checksum frames, a
+/// [CommandQueue](struct.CommandQueue.html) with coalescing and a frame
+/// budget, and the [SparseTelemetryScheduler] for spacing the telemetry.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::LinkBudget;
+/// use saberrs::sabertooth2x32::{Operation, PacketType};
+///
+/// // 2400 baud radio: 10 setpoints per second, 5 telemetry requests
+/// let budget = LinkBudget::packet(2400, PacketType::Checksum)
+///     .with_half_duplex()
+///     .with_setpoint_rate(10.0)
+///     .with_telemetry_rate(5.0);
+/// assert!(budget.check().is_ok());
+/// println!("{:.0}% of the link", budget.utilization() * 100.0);
+/// println!("at most {:.1} requests/s", budget.max_telemetry_rate());
+///
+/// // 9 bytes for a setpoint, 7 + 9 bytes for a get request
+/// let set = budget.transaction_time(Operation::SetSpeed(1, 0.5));
+/// assert_eq!(37_500, set.as_micros());
+/// let get = budget.transaction_time(Operation::GetCurrent(1));
+/// assert_eq!(66_666, get.as_micros());
+/// ```
+///
+/// [SparseTelemetryScheduler]: struct.SparseTelemetryScheduler.html
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LinkBudget {
+    baud_rate: u32,
+    set_size: usize,
+    get_size: usize,
+    reply_size: usize,
+    half_duplex: bool,
+    gap: Duration,
+    setpoint_rate: f64,
+    telemetry_rate: f64,
+}
+
+impl LinkBudget {
+    /// Budget of a "Packet Serial" link at *baud_rate*, with *packet_type*
+    /// frames.
+    pub fn packet(baud_rate: u32, packet_type: PacketType) -> LinkBudget {
+        let (set_size, get_size, reply_size) = packet_type.frame_sizes();
+        LinkBudget::new(baud_rate, set_size, get_size, reply_size)
+    }
+
+    /// Budget of a "Plain Text" link at *baud_rate*, with the longest frames.
+    pub fn plain_text(baud_rate: u32) -> LinkBudget {
+        LinkBudget::new(baud_rate, TEXT_SET_SIZE, TEXT_GET_SIZE, TEXT_REPLY_SIZE)
+    }
+
+    fn new(baud_rate: u32, set_size: usize, get_size: usize, reply_size: usize) -> LinkBudget {
+        LinkBudget {
+            baud_rate,
+            set_size,
+            get_size,
+            reply_size,
+            half_duplex: false,
+            gap: Duration::ZERO,
+            setpoint_rate: 0.0,
+            telemetry_rate: 0.0,
+        }
+    }
+
+    /// Requests and replies share the same channel.
+    pub fn with_half_duplex(mut self) -> Self {
+        self.half_duplex = true;
+        self
+    }
+
+    /// Idle time after each frame, for example the turnaround of a radio
+    /// modem or the processing time of the device.
+    pub fn with_inter_frame_gap(mut self, gap: Duration) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// Number of set commands per second.
+    pub fn with_setpoint_rate(mut self, per_sec: f64) -> Self {
+        self.setpoint_rate = per_sec;
+        self
+    }
+
+    /// Number of get requests per second.
+    pub fn with_telemetry_rate(mut self, per_sec: f64) -> Self {
+        self.telemetry_rate = per_sec;
+        self
+    }
+
+    /// Estimated ratio of the link used by the requested rates, above 1.0
+    /// when they exceed its capacity.
+    pub fn utilization(&self) -> f64 {
+        let (tx, rx) = self.bytes_per_sec(self.telemetry_rate);
+        let used = if self.half_duplex {
+            tx + rx
+        } else {
+            tx.max(rx)
+        };
+        used / self.capacity()
+    }
+
+    /// Highest rate of get requests which fits in the link along with the
+    /// setpoints, or 0.0 if the setpoints alone exceed its capacity.
+    pub fn max_telemetry_rate(&self) -> f64 {
+        let capacity = self.capacity();
+        let (setpoints, _) = self.bytes_per_sec(0.0);
+        let gap = self.gap_bytes();
+        let (get, reply) = (self.get_size as f64 + gap, self.reply_size as f64 + gap);
+        let rate = if self.half_duplex {
+            (capacity - setpoints) / (get + reply)
+        } else {
+            ((capacity - setpoints) / get).min(capacity / reply)
+        };
+        rate.max(0.0)
+    }
+
+    /// Estimated time of a transaction of *operation*: the set command, or
+    /// the get request and its reply, each followed by the inter-frame gap.
+    pub fn transaction_time(&self, operation: Operation) -> Duration {
+        if operation.is_get() {
+            let bytes = self.get_size + self.reply_size;
+            transfer_time(self.baud_rate, bytes) + self.gap * 2
+        } else {
+            transfer_time(self.baud_rate, self.set_size) + self.gap
+        }
+    }
+
+    /// Return the utilization, or `Error::InvalidInput` with a warning in
+    /// the logs if the requested rates exceed the capacity of the link.
+    pub fn check(&self) -> Result<f64> {
+        let utilization = self.utilization();
+        if utilization > 1.0 {
+            let msg = format!(
+                "requested rates need {:.0}% of the link at {} baud, at most {:.1} requests/s fit",
+                utilization * 100.0,
+                self.baud_rate,
+                self.max_telemetry_rate()
+            );
+            log_warn!("{}", msg);
+            return Err(Error::InvalidInput(msg));
+        }
+        Ok(utilization)
+    }
+
+    /// Bytes per second sent and received with *telemetry_rate* requests.
+    fn bytes_per_sec(&self, telemetry_rate: f64) -> (f64, f64) {
+        let gap = self.gap_bytes();
+        let tx = self.setpoint_rate * (self.set_size as f64 + gap)
+            + telemetry_rate * (self.get_size as f64 + gap);
+        let rx = telemetry_rate * (self.reply_size as f64 + gap);
+        (tx, rx)
+    }
+
+    /// Number of bytes the link could carry during the inter-frame gap.
+    fn gap_bytes(&self) -> f64 {
+        self.gap.as_secs_f64() * self.capacity()
+    }
+
+    /// Bytes per second carried by the link.
+    fn capacity(&self) -> f64 {
+        f64::from(self.baud_rate) / BITS_PER_BYTE as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        // 240 bytes/s: 10 checksum setpoints (90 bytes) + 5 requests (35 + 45)
+        let budget = LinkBudget::packet(2400, PacketType::Checksum)
+            .with_setpoint_rate(10.0)
+            .with_telemetry_rate(5.0);
+        assert!((budget.utilization() - 125.0 / 240.0).abs() < 1e-9);
+        assert!((budget.with_half_duplex().utilization() - 170.0 / 240.0).abs() < 1e-9);
+        assert!((budget.with_half_duplex().max_telemetry_rate() - 150.0 / 16.0).abs() < 1e-9);
+        assert!(budget.check().is_ok());
+
+        // Plain text is larger
+        let text = LinkBudget::plain_text(2400)
+            .with_half_duplex()
+            .with_setpoint_rate(10.0)
+            .with_telemetry_rate(10.0);
+        assert!(text.check().is_err());
+
+        let saturated = LinkBudget::packet(2400, PacketType::CRC).with_setpoint_rate(30.0);
+        assert_eq!(0.0, saturated.max_telemetry_rate());
+    }
+
+    #[test]
+    fn test_inter_frame_gap() {
+        // The 10 ms gaps take 2.4 bytes each at 2400 baud
+        let budget = LinkBudget::packet(2400, PacketType::Checksum)
+            .with_inter_frame_gap(Duration::from_millis(10))
+            .with_setpoint_rate(10.0);
+        assert!((budget.utilization() - 114.0 / 240.0).abs() < 1e-9);
+        let set = budget.transaction_time(Operation::SetSpeed(1, 0.5));
+        assert_eq!(47_500, set.as_micros());
+        let get = budget.transaction_time(Operation::GetVoltage(1));
+        assert_eq!(86_666, get.as_micros());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::sabertooth2x32::commands::Command;
+use crate::sabertooth2x32::{Operation, Sabertooth2x32};
+use crate::Errors;
+
+/// Apply each of *operations* to every device in *devices*, like the same
+/// settings to all the devices of a robot, and return the errors of the
+/// failed operations, by index in *devices*. A failed operation does not
+/// prevent applying the next ones, nor applying them to the other devices.
+/// The values read by the getters are dropped.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::apply_all;
+/// use saberrs::sabertooth2x32::{Operation, PacketSerial};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut front = PacketSerial::new("/dev/ttyS0")?;
+/// let mut rear = PacketSerial::new("/dev/ttyS1")?;
+///
+/// let settings = [Operation::SetRamp(1, 0.2), Operation::SetRamp(2, 0.2)];
+/// if let Err(errors) = apply_all(&mut [&mut front, &mut rear], &settings) {
+///     eprintln!("{}", errors);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn apply_all<S>(devices: &mut [&mut S], operations: &[Operation]) -> Result<(), Errors>
+where
+    S: Sabertooth2x32 + ?Sized,
+{
+    let mut errors = Errors::new();
+    for (i, saber) in devices.iter_mut().enumerate() {
+        for operation in operations {
+            if let Err(e) = operation.apply(&mut **saber) {
+                log_warn!("{:?} failed on device {}: {}", operation, i, e);
+                let (_, channel, _) = Command::from_operation(*operation);
+                errors.push(i, channel, e);
+            }
+        }
+    }
+    errors.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+    use crate::Error;
+
+    #[test]
+    fn test_apply_all() {
+        let mut port1 = MockPort::new();
+        let mut port2 = MockPort::new();
+        let mut saber1 = PlainText::from(&port1);
+        let mut saber2 = PlainText::from(&port2).with_interlock();
+
+        let operations = [Operation::SetRamp(1, 0.5), Operation::SetSpeed(2, 0.5)];
+        let errors = apply_all(&mut [&mut saber1, &mut saber2], &operations).unwrap_err();
+        assert_eq!(1, errors.len());
+        assert!(matches!(errors.get(1, Some(2)), Some(Error::NotArmed)));
+        assert_eq!(port1.take_written(), b"R1: 1023\r\nM2: 1023\r\n");
+        assert_eq!(port2.take_written(), b"R1: 1023\r\n");
+
+        saber2.arm();
+        assert!(apply_all(&mut [&mut saber1, &mut saber2], &operations).is_ok());
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::VecDeque;
+
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::{Error, Result};
+
+/// Default margin above a threshold for leaving its level, in volts.
+pub const DEFAULT_HYSTERESIS: f32 = 0.2;
+
+/// Default total current under which the pack is considered at rest, in
+/// amperes.
+pub const DEFAULT_IDLE_CURRENT: f32 = 1.0;
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// Chemistry of the cells of a pack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Chemistry {
+    /// Lithium polymer or lithium ion, 3.7 V nominal.
+    LiPo,
+
+    /// Lithium iron phosphate, 3.2 V nominal.
+    LiFePO4,
+
+    /// Nickel-metal hydride, 1.2 V nominal.
+    NiMH,
+
+    /// Sealed lead-acid, 2.0 V nominal.
+    LeadAcid,
+}
+
+impl Chemistry {
+    /// Full, low and critical voltages of one cell, in volts.
+    fn cell_voltages(self) -> (f32, f32, f32) {
+        match self {
+            Chemistry::LiPo => (4.2, 3.5, 3.2),
+            Chemistry::LiFePO4 => (3.6, 3.0, 2.8),
+            Chemistry::NiMH => (1.45, 1.1, 1.0),
+            Chemistry::LeadAcid => (2.15, 1.95, 1.8),
+        }
+    }
+}
+
+/// Typed description of the pack powering the devices, from which the
+/// voltage thresholds are derived.
+///
+/// The low-voltage cutoff of the Sabertooth 2x32 itself is configured with
+/// the DIP switches and DEScribe, and cannot be read or written over the
+/// serial link. The pack lets the host apply the cutoffs matching the
+/// chemistry, with [monitor()](#method.monitor), and check at provisioning
+/// that the pack connected is the expected one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BatteryPack {
+    chemistry: Chemistry,
+    cells: u8,
+}
+
+impl BatteryPack {
+    /// Create a pack of *cells* cells in series of *chemistry*.
+    pub fn new(chemistry: Chemistry, cells: u8) -> BatteryPack {
+        BatteryPack {
+            chemistry,
+            cells: cells.max(1),
+        }
+    }
+
+    /// Return the chemistry of the cells.
+    pub fn chemistry(&self) -> Chemistry {
+        self.chemistry
+    }
+
+    /// Return the number of cells in series.
+    pub fn cells(&self) -> u8 {
+        self.cells
+    }
+
+    /// Voltage of the fully charged pack, in volts.
+    pub fn full_voltage(&self) -> f32 {
+        self.chemistry.cell_voltages().0 * self.cells as f32
+    }
+
+    /// Voltage under which the pack should be recharged, in volts.
+    pub fn low_voltage(&self) -> f32 {
+        self.chemistry.cell_voltages().1 * self.cells as f32
+    }
+
+    /// Voltage under which the motors should be stopped for protecting the
+    /// cells, in volts.
+    pub fn critical_voltage(&self) -> f32 {
+        self.chemistry.cell_voltages().2 * self.cells as f32
+    }
+
+    /// Whether *voltage* is plausible for this pack at rest: between the
+    /// critical voltage and the full voltage, with the default hysteresis
+    /// above it.
+    pub fn matches(&self, voltage: f32) -> bool {
+        (self.critical_voltage()..=self.full_voltage() + DEFAULT_HYSTERESIS).contains(&voltage)
+    }
+
+    /// Create a monitor with the low and critical thresholds of the pack.
+    pub fn monitor(&self) -> BatteryMonitor {
+        BatteryMonitor::new(self.low_voltage(), self.critical_voltage())
+    }
+}
+
+/// Charge level of the pack, from its lowest voltage reading.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BatteryLevel {
+    /// The voltage is above the low threshold.
+    Normal,
+
+    /// The voltage is below the low threshold.
+    Low,
+
+    /// The voltage is below the critical threshold.
+    Critical,
+}
+
+/// Event emitted by [BatteryMonitor](struct.BatteryMonitor.html) when the
+/// level of the pack changes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BatteryEvent {
+    pub from: BatteryLevel,
+    pub to: BatteryLevel,
+
+    /// Lowest voltage of the sample causing the change, in volts.
+    pub voltage: f32,
+}
+
+/// Consolidated state of a pack shared by several devices.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BatteryState {
+    /// Number of devices which answered.
+    pub devices: usize,
+
+    /// Lowest voltage read, in volts.
+    pub min_voltage: f32,
+
+    /// Mean of the voltages read, in volts.
+    pub mean_voltage: f32,
+
+    /// Sum of the currents of all the channels, in amperes.
+    pub total_current: f32,
+
+    /// Mean voltage of the last sample at rest, if any, in volts.
+    pub rest_voltage: Option<f32>,
+
+    /// Drop of the mean voltage from the rest voltage, when under load.
+    pub sag: Option<f32>,
+
+    /// Internal resistance of the pack and its wiring, from the sag and the
+    /// total current, in ohms.
+    pub resistance: Option<f32>,
+}
+
+/// Aggregation of the battery readings of the devices powered by the same
+/// pack, as on a power distribution board.
+///
+/// The devices each measure the pack voltage, slightly differently because
+/// of the wiring. The monitor polls all of them and consolidates their
+/// voltages and the currents of all their channels into a [BatteryState].
+/// The mean voltage read while the total current is under the idle current
+/// is kept as the rest voltage, from which the sag under load and the
+/// internal resistance are derived. The level of the pack follows the lowest
+/// voltage, and a [BatteryEvent] is emitted when it crosses a threshold,
+/// with some hysteresis for not flapping under varying loads.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{BatteryLevel, BatteryMonitor};
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut front = PacketSerial::new("/dev/ttyS0")?;
+/// let mut rear = PacketSerial::new("/dev/ttyS1")?;
+/// let mut monitor = BatteryMonitor::new(22.0, 20.5);
+///
+/// // In the control loop
+/// let state = monitor.poll(&mut [&mut front as &mut dyn Sabertooth2x32, &mut rear])?;
+/// println!("{:.1} V, {:.1} A", state.min_voltage, state.total_current);
+/// for event in monitor.drain_events() {
+///     if event.to == BatteryLevel::Critical {
+///         front.stop_motors()?;
+///         rear.stop_motors()?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [BatteryState]: struct.BatteryState.html
+/// [BatteryEvent]: struct.BatteryEvent.html
+#[derive(Clone, Debug)]
+pub struct BatteryMonitor {
+    low: f32,
+    critical: f32,
+    hysteresis: f32,
+    idle_current: f32,
+    rest_voltage: Option<f32>,
+    level: BatteryLevel,
+    state: Option<BatteryState>,
+    events: VecDeque<BatteryEvent>,
+}
+
+impl BatteryMonitor {
+    /// Create a monitor with the *low* and *critical* voltage thresholds, in
+    /// volts.
+    pub fn new(low: f32, critical: f32) -> BatteryMonitor {
+        BatteryMonitor {
+            low,
+            critical: critical.min(low),
+            hysteresis: DEFAULT_HYSTERESIS,
+            idle_current: DEFAULT_IDLE_CURRENT,
+            rest_voltage: None,
+            level: BatteryLevel::Normal,
+            state: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Set the margin above a threshold the voltage must reach for leaving
+    /// its level.
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis.abs();
+        self
+    }
+
+    /// Set the total current under which the pack is considered at rest.
+    pub fn with_idle_current(mut self, current: f32) -> Self {
+        self.idle_current = current.abs();
+        self
+    }
+
+    /// Return the current level of the pack.
+    pub fn level(&self) -> BatteryLevel {
+        self.level
+    }
+
+    /// Return the last consolidated state, if any.
+    pub fn state(&self) -> Option<BatteryState> {
+        self.state
+    }
+
+    /// Remove and return the pending events, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = BatteryEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Read the voltage and the currents of both channels of each of
+    /// *devices*, and consolidate them. The devices failing to answer are
+    /// left out, and the first error is returned only if none answered.
+    pub fn poll<S>(&mut self, devices: &mut [&mut S]) -> Result<BatteryState>
+    where
+        S: Sabertooth2x32 + ?Sized,
+    {
+        let mut samples = Vec::with_capacity(devices.len());
+        let mut first_error = None;
+        for (i, saber) in devices.iter_mut().enumerate() {
+            match read_device(&mut **saber) {
+                Ok(sample) => samples.push(sample),
+                Err(e) => {
+                    log_warn!("battery: device {} failed: {}", i, e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match (self.update(&samples), first_error) {
+            (Some(state), _) => Ok(state),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(Error::InvalidInput("no device".to_string())),
+        }
+    }
+
+    /// Consolidate the (*voltage*, *current*) *samples* of the devices,
+    /// measured by other means than [poll()](#method.poll), with the current
+    /// summed over the channels of each device. Return `None` if there is no
+    /// sample.
+    pub fn update(&mut self, samples: &[(f32, f32)]) -> Option<BatteryState> {
+        if samples.is_empty() {
+            return None;
+        }
+        let min_voltage = samples.iter().map(|s| s.0).fold(f32::INFINITY, f32::min);
+        let mean_voltage = samples.iter().map(|s| s.0).sum::<f32>() / samples.len() as f32;
+        let total_current: f32 = samples.iter().map(|s| s.1).sum();
+
+        let (sag, resistance) = if total_current.abs() <= self.idle_current {
+            self.rest_voltage = Some(mean_voltage);
+            (None, None)
+        } else {
+            match self.rest_voltage {
+                Some(rest) => {
+                    let sag = rest - mean_voltage;
+                    (Some(sag), Some(sag / total_current))
+                }
+                None => (None, None),
+            }
+        };
+
+        let state = BatteryState {
+            devices: samples.len(),
+            min_voltage,
+            mean_voltage,
+            total_current,
+            rest_voltage: self.rest_voltage,
+            sag,
+            resistance,
+        };
+        self.state = Some(state);
+        self.set_level(min_voltage);
+        Some(state)
+    }
+
+    fn set_level(&mut self, voltage: f32) {
+        let below = |threshold: f32, level: BatteryLevel| {
+            let margin = if self.level == level || self.level == BatteryLevel::Critical {
+                self.hysteresis
+            } else {
+                0.0
+            };
+            voltage < threshold + margin
+        };
+        let level = if below(self.critical, BatteryLevel::Critical) {
+            BatteryLevel::Critical
+        } else if below(self.low, BatteryLevel::Low) {
+            BatteryLevel::Low
+        } else {
+            BatteryLevel::Normal
+        };
+
+        if level != self.level {
+            log_warn!("battery level changed from {:?} to {:?}", self.level, level);
+            if self.events.len() == MAX_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(BatteryEvent {
+                from: self.level,
+                to: level,
+                voltage,
+            });
+            self.level = level;
+        }
+    }
+}
+
+/// Voltage and total current of *saber*.
+fn read_device<S: Sabertooth2x32 + ?Sized>(saber: &mut S) -> Result<(f32, f32)> {
+    let voltage = saber.get_voltage(1)?;
+    let current = saber.get_current(1)? + saber.get_current(2)?;
+    Ok((voltage, current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::MockPort;
+
+    #[test]
+    fn test_levels() {
+        let mut monitor = BatteryMonitor::new(22.0, 20.5);
+        monitor.update(&[(24.0, 0.0)]);
+        monitor.update(&[(21.9, 0.0)]);
+        assert_eq!(BatteryLevel::Low, monitor.level());
+
+        // Within the hysteresis
+        monitor.update(&[(22.1, 0.0)]);
+        assert_eq!(BatteryLevel::Low, monitor.level());
+
+        monitor.update(&[(20.0, 0.0)]);
+        monitor.update(&[(20.6, 0.0)]);
+        assert_eq!(BatteryLevel::Critical, monitor.level());
+
+        monitor.update(&[(22.3, 0.0)]);
+        assert_eq!(BatteryLevel::Normal, monitor.level());
+
+        let levels: Vec<_> = monitor.drain_events().map(|e| e.to).collect();
+        assert_eq!(
+            vec![
+                BatteryLevel::Low,
+                BatteryLevel::Critical,
+                BatteryLevel::Normal
+            ],
+            levels
+        );
+    }
+
+    #[test]
+    fn test_battery_pack() {
+        let lipo = BatteryPack::new(Chemistry::LiPo, 6);
+        assert!((lipo.full_voltage() - 25.2).abs() < 1e-4);
+        assert!((lipo.critical_voltage() - 19.2).abs() < 1e-4);
+        assert!(lipo.matches(22.2));
+        assert!(!lipo.matches(26.0));
+
+        // A 20-cell NiMH pack has the same nominal voltage, but other cutoffs
+        let nimh = BatteryPack::new(Chemistry::NiMH, 20);
+        assert!((nimh.low_voltage() - 22.0).abs() < 1e-4);
+        assert!(nimh.matches(20.5));
+        assert!(!lipo.matches(19.0));
+
+        let mut monitor = lipo.monitor();
+        monitor.update(&[(20.0, 0.0)]);
+        assert_eq!(BatteryLevel::Low, monitor.level());
+        let mut monitor = nimh.monitor();
+        monitor.update(&[(19.5, 0.0)]);
+        assert_eq!(BatteryLevel::Critical, monitor.level());
+    }
+
+    #[test]
+    fn test_sag() {
+        let mut monitor = BatteryMonitor::new(22.0, 20.5);
+        let state = monitor.update(&[(25.0, 0.5), (24.8, 0.0)]).unwrap();
+        assert_eq!(None, state.sag);
+        assert_eq!(Some(24.9), state.rest_voltage);
+
+        let state = monitor.update(&[(24.0, 10.0), (23.8, 10.0)]).unwrap();
+        assert_eq!(23.8, state.min_voltage);
+        assert_eq!(20.0, state.total_current);
+        assert!((state.sag.unwrap() - 1.0).abs() < 1e-4);
+        assert!((state.resistance.unwrap() - 0.05).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_poll() {
+        let reply = |frame: &[u8]| match frame {
+            b"M1: getb\r\n" => b"M1:B240\r\n".to_vec(),
+            b"M1: getc\r\n" => b"M1:C25\r\n".to_vec(),
+            b"M2: getc\r\n" => b"M2:C15\r\n".to_vec(),
+            _ => Vec::new(),
+        };
+        let mut front = PlainText::from(MockPort::with_responder(reply));
+        let mut rear = PlainText::from(MockPort::with_responder(reply));
+        let mut dead = PlainText::from(MockPort::new());
+        let mut monitor = BatteryMonitor::new(22.0, 20.5);
+
+        let state = monitor
+            .poll(&mut [&mut front, &mut rear, &mut dead])
+            .unwrap();
+        assert_eq!(2, state.devices);
+        assert_eq!(24.0, state.mean_voltage);
+        assert!((state.total_current - 8.0).abs() < 1e-4);
+
+        assert!(monitor.poll(&mut [&mut dead]).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::sabertooth2x32::{PacketSerial, PacketType, Sabertooth2x32, DEFAULT_PACKET_TYPE};
+use crate::{Error, FailsafeAction, Result, SabertoothSerial};
+
+/// Lock *mutex*, ignoring the poisoning: a thread panicking during a
+/// transaction does not invalidate the line.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// State of a bus shared by its devices.
+struct Shared<T> {
+    port: Mutex<T>,
+    transaction: Mutex<()>,
+    addresses: Mutex<Vec<u8>>,
+}
+
+/// Multi-drop serial line shared by several drivers in "Packet Serial" mode,
+/// each with its own address.
+///
+/// The bus owns the port, and hands out a [BusDevice] per address with
+/// [device_2x32()](#method.device_2x32). Unlike handles sharing a
+/// [SabertoothPortSync], the devices serialize their whole transactions on
+/// the bus: a request and its reply are never interleaved with the traffic
+/// of another device, so the devices can be used from different threads
+/// without further locking. An address is handed out once at a time.
+///
+/// # Example
+///
+/// ```rust
+/// use std::thread;
+/// use saberrs::{SabertoothBus, SabertoothPort};
+/// use saberrs::sabertooth2x32::Sabertooth2x32;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let bus = SabertoothBus::new(SabertoothPort::new("/dev/ttyUSB0")?);
+/// let mut front = bus.device_2x32(128)?;
+/// let mut rear = bus.device_2x32(129)?;
+///
+/// thread::spawn(move || front.set_speed(1, 0.5)).join().unwrap()?;
+/// let voltage = rear.get_voltage(1)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [BusDevice]: struct.BusDevice.html
+/// [SabertoothPortSync]: struct.SabertoothPortSync.html
+pub struct SabertoothBus<T> {
+    shared: Arc<Shared<T>>,
+    packet_type: PacketType,
+}
+
+impl<T: SabertoothSerial> SabertoothBus<T> {
+    /// Create a bus on *port*.
+    pub fn new(port: T) -> SabertoothBus<T> {
+        SabertoothBus {
+            shared: Arc::new(Shared {
+                port: Mutex::new(port),
+                transaction: Mutex::new(()),
+                addresses: Mutex::new(Vec::new()),
+            }),
+            packet_type: DEFAULT_PACKET_TYPE,
+        }
+    }
+
+    /// Set the packet type of the devices handed out afterwards.
+    pub fn with_packet_type(mut self, packet_type: PacketType) -> Self {
+        self.packet_type = packet_type;
+        self
+    }
+
+    /// Hand out the Sabertooth 2x32 at *address*. Return
+    /// `Error::InvalidInput` if the address is already handed out, until its
+    /// device is dropped.
+    pub fn device_2x32(&self, address: u8) -> Result<BusDevice<T>> {
+        let mut addresses = lock(&self.shared.addresses);
+        if addresses.contains(&address) {
+            return Err(Error::InvalidInput(format!(
+                "address {} already on the bus",
+                address
+            )));
+        }
+        addresses.push(address);
+        let port = BusPort {
+            shared: self.shared.clone(),
+        };
+        Ok(BusDevice {
+            saber: PacketSerial::from(port)
+                .with_address(address)
+                .with_packet_type(self.packet_type),
+            shared: self.shared.clone(),
+            address,
+        })
+    }
+
+    /// Addresses of the devices handed out, in the order they were.
+    pub fn addresses(&self) -> Vec<u8> {
+        lock(&self.shared.addresses).clone()
+    }
+
+    /// Run *f* on the port, between the transactions of the devices.
+    pub fn with_port<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        let _transaction = lock(&self.shared.transaction);
+        f(&mut lock(&self.shared.port))
+    }
+}
+
+/// Port of the [BusDevice](struct.BusDevice.html)s, giving access to the
+/// port of their bus.
+pub struct BusPort<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: SabertoothSerial> io::Read for BusPort<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        lock(&self.shared.port).read(buf)
+    }
+}
+
+impl<T: SabertoothSerial> io::Write for BusPort<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        lock(&self.shared.port).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        lock(&self.shared.port).flush()
+    }
+}
+
+impl<T: SabertoothSerial> SabertoothSerial for BusPort<T> {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        lock(&self.shared.port).set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Duration {
+        lock(&self.shared.port).timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        lock(&self.shared.port).set_baud_rate(baud_rate)
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        lock(&self.shared.port).baud_rate()
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        lock(&self.shared.port).clear_all()
+    }
+
+    fn clear_input(&self) -> Result<()> {
+        lock(&self.shared.port).clear_input()
+    }
+
+    fn is_line_condition(&self, byte: u8) -> bool {
+        lock(&self.shared.port).is_line_condition(byte)
+    }
+}
+
+/// Device of a [SabertoothBus](struct.SabertoothBus.html), running each
+/// operation as a transaction on the bus. Its address is released when it
+/// is dropped.
+///
+/// The settings and the diagnostics of its "Packet Serial" handle are
+/// available with [handle_mut()](#method.handle_mut).
+pub struct BusDevice<T: SabertoothSerial> {
+    saber: PacketSerial<BusPort<T>>,
+    shared: Arc<Shared<T>>,
+    address: u8,
+}
+
+impl<T: SabertoothSerial> BusDevice<T> {
+    /// Address of the device.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// "Packet Serial" handle of the device.
+    pub fn handle(&self) -> &PacketSerial<BusPort<T>> {
+        &self.saber
+    }
+
+    /// Mutable "Packet Serial" handle of the device, for its settings.
+    /// Its operations are not serialized on the bus.
+    pub fn handle_mut(&mut self) -> &mut PacketSerial<BusPort<T>> {
+        &mut self.saber
+    }
+
+    /// Run *f* on the handle as a single transaction on the bus.
+    pub fn transaction<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut PacketSerial<BusPort<T>>) -> R,
+    {
+        let _transaction = lock(&self.shared.transaction);
+        f(&mut self.saber)
+    }
+}
+
+impl<T: SabertoothSerial> Drop for BusDevice<T> {
+    fn drop(&mut self) {
+        lock(&self.shared.addresses).retain(|&address| address != self.address);
+    }
+}
+
+impl<T: SabertoothSerial> Sabertooth2x32 for BusDevice<T> {
+    fn startup(&mut self, channel: usize) -> Result<()> {
+        self.transaction(|saber| saber.startup(channel))
+    }
+
+    fn shutdown(&mut self, channel: usize) -> Result<()> {
+        self.transaction(|saber| saber.shutdown(channel))
+    }
+
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.transaction(|saber| saber.set_speed(channel, ratio))
+    }
+
+    fn get_speed(&mut self, channel: usize) -> Result<f32> {
+        self.transaction(|saber| saber.get_speed(channel))
+    }
+
+    fn stop_motors(&mut self) -> Result<()> {
+        self.transaction(|saber| saber.stop_motors())
+    }
+
+    fn emergency_stop(&mut self, action: FailsafeAction) -> Result<()> {
+        self.transaction(|saber| saber.emergency_stop(action))
+    }
+
+    fn all_stop(&mut self) -> Result<()> {
+        self.transaction(|saber| saber.all_stop())
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        self.transaction(|saber| saber.set_drive(ratio))
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        self.transaction(|saber| saber.set_turn(ratio))
+    }
+
+    fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.transaction(|saber| saber.set_power(channel, ratio))
+    }
+
+    fn get_power(&mut self, channel: usize) -> Result<f32> {
+        self.transaction(|saber| saber.get_power(channel))
+    }
+
+    fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.transaction(|saber| saber.set_ramp(channel, ratio))
+    }
+
+    fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.transaction(|saber| saber.set_aux(channel, ratio))
+    }
+
+    fn get_voltage(&mut self, channel: usize) -> Result<f32> {
+        self.transaction(|saber| saber.get_voltage(channel))
+    }
+
+    fn get_current(&mut self, channel: usize) -> Result<f32> {
+        self.transaction(|saber| saber.get_current(channel))
+    }
+
+    fn get_temperature(&mut self, channel: usize) -> Result<f32> {
+        self.transaction(|saber| saber.get_temperature(channel))
+    }
+
+    fn arm(&mut self) {
+        self.saber.arm()
+    }
+
+    fn disarm(&mut self) {
+        self.saber.disarm()
+    }
+
+    fn is_armed(&self) -> bool {
+        self.saber.is_armed()
+    }
+
+    fn shutdown_commanded(&self, channel: usize) -> bool {
+        self.saber.shutdown_commanded(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FrameTap, MockPort};
+    use std::thread;
+
+    #[test]
+    fn test_bus() {
+        let mut port = MockPort::new();
+        let bus = SabertoothBus::new(port.clone()).with_packet_type(PacketType::Checksum);
+        let mut front = bus.device_2x32(128).unwrap();
+        let mut rear = bus.device_2x32(129).unwrap();
+        assert!(bus.device_2x32(128).is_err());
+        assert_eq!(vec![128, 129], bus.addresses());
+
+        front.set_speed(1, 0.5).unwrap();
+        thread::spawn(move || rear.set_speed(1, 0.5))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            port.take_written(),
+            b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04\x81\x28\x00\x29\x7f\x07\x4d\x31\x04"
+        );
+        assert_eq!(vec![128], bus.addresses());
+
+        port.queue_reply(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76");
+        assert_eq!(12.0, front.get_voltage(1).unwrap());
+        drop(front);
+        assert!(bus.device_2x32(128).is_ok());
+    }
+}
@@ -0,0 +1,441 @@
+//! Declarative command tables for the devices.
+//!
+//! A device declares its command set once with `command_table!`, and the
+//! handles encode frames and scale replies from it instead of repeating the
+//! tokens, opcodes and scaling factors in each method. The table is checked
+//! at compile time for inconsistent entries and for commands producing the
+//! same frame, and the macro generates unit tests for the value encoding.
+//!
+//! The table does not generate the typed methods of the handles: they stay
+//! declared by the device trait, like
+//! [Sabertooth2x32](../sabertooth2x32/trait.Sabertooth2x32.html), and map
+//! their arguments to a command with a hand-written conversion, for example
+//! from an [Operation](../sabertooth2x32/enum.Operation.html). A new device
+//! therefore declares its trait and that conversion next to its table.
+//!
+//! ```ignore
+//! command_table! {
+//!     /// Commands of the device.
+//!     pub(crate) enum Command {
+//!         SetSpeed {
+//!             access: Access::Set,
+//!             token: b'M',
+//!             target: Target::Channel,
+//!             opcode: 0,
+//!             value: Value::Ratio(2047),
+//!             keyword: "",
+//!             prefix: None,
+//!         },
+//!     }
+//! }
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use crate::error::Result;
+use crate::protocol::addressing;
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use crate::protocol::addressing::{DataSource, DataTarget};
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use crate::utils;
+
+/// Whether a command writes to the device or reads from it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Access {
+    Set,
+    Get,
+}
+
+/// Second byte of the target of a command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Target {
+    /// Motor channel, `b'1'` or `b'2'`.
+    Channel,
+
+    /// Fixed byte, for example `b'D'` for the drive of mixed mode.
+    Fixed(u8),
+}
+
+/// Value sent by a set command, or returned by a get command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Value {
+    /// Ratio from -1.0 to 1.0, mapped to the raw values from -*max* to *max*.
+    Ratio(i32),
+
+    /// Raw value of a command without argument, sent as the keyword in
+    /// plain text.
+    Fixed(i32),
+
+    /// Physical quantity, equal to the raw value divided by the first factor
+    /// in packetized serial, and by the second one in plain text.
+    Scaled(i32, i32),
+}
+
+/// Protocol of a handle, for the scaling of the replies.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Protocol {
+    Packetized,
+    #[cfg(feature = "std")]
+    PlainText,
+}
+
+/// Description of a command, declared with `command_table!`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct CommandSpec {
+    /// Name of the command, which is the name of its enum variant.
+    pub name: &'static str,
+
+    pub access: Access,
+
+    /// First byte of the target, for example `b'M'` for the motors.
+    pub token: u8,
+
+    pub target: Target,
+
+    /// Command value in packetized serial.
+    pub opcode: u8,
+
+    pub value: Value,
+
+    /// Word sent instead of the value in plain text, for example `"getb"`.
+    /// Empty for the set commands taking an argument.
+    pub keyword: &'static str,
+
+    /// Prefix of the value in plain text replies.
+    pub prefix: Option<u8>,
+}
+
+impl CommandSpec {
+    /// Target bytes of the command for *channel*, checking that it exists
+    /// and that it is a documented source or target for the access.
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub fn target(&self, channel: Option<usize>) -> Result<[u8; 2]> {
+        let second = match self.target {
+            Target::Channel => match_channel_to!(channel.unwrap_or(0), b'1', b'2'),
+            Target::Fixed(byte) => byte,
+        };
+        let bytes = [self.token, second];
+        match self.access {
+            Access::Set => DataTarget::from_bytes(bytes).map(|target| target.bytes()),
+            Access::Get => DataSource::from_bytes(bytes).map(|source| source.bytes()),
+        }
+    }
+
+    /// Raw value sent by a set command for *arg*, checking its range.
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub fn raw_value(&self, arg: Option<f32>) -> Result<i32> {
+        match self.value {
+            Value::Ratio(max) => utils::ratio_to_range(arg.unwrap_or(0.0), max),
+            Value::Fixed(value) => Ok(value),
+            Value::Scaled(..) => Ok(0),
+        }
+    }
+
+    /// Raw value of a reply for *value*, in the unit of the getters, for
+    /// emulating a device.
+    #[cfg(feature = "emulator")]
+    pub fn unscale(&self, value: f32, protocol: Protocol) -> i32 {
+        let raw = match (self.value, protocol) {
+            (Value::Ratio(max), _) => value * max as f32,
+            (Value::Fixed(_), _) => value,
+            (Value::Scaled(mul, _), Protocol::Packetized) => value * mul as f32,
+            (Value::Scaled(_, mul), Protocol::PlainText) => value * mul as f32,
+        };
+        raw.round() as i32
+    }
+
+    /// Convert the *raw* value of a reply to the unit of the getters.
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub fn scale(&self, raw: i32, protocol: Protocol) -> f32 {
+        match (self.value, protocol) {
+            (Value::Ratio(max), _) => raw as f32 / max as f32,
+            (Value::Fixed(_), _) => raw as f32,
+            (Value::Scaled(div, _), Protocol::Packetized) => raw as f32 / div as f32,
+            #[cfg(feature = "std")]
+            (Value::Scaled(_, div), Protocol::PlainText) => raw as f32 / div as f32,
+        }
+    }
+
+    /// Plain text frame of the command for *channel* and *arg*.
+    #[cfg(feature = "std")]
+    pub fn text_frame(&self, channel: Option<usize>, arg: Option<f32>) -> Result<String> {
+        let target = self.target(channel)?;
+        let (token, target) = (char::from(target[0]), char::from(target[1]));
+        if self.keyword.is_empty() {
+            let value = self.raw_value(arg)?;
+            Ok(format!("{}{}: {}\r\n", token, target, value))
+        } else {
+            Ok(format!("{}{}: {}\r\n", token, target, self.keyword))
+        }
+    }
+}
+
+/// Version of the layout of the documents of [conformance_json()],
+/// incremented on any incompatible change.
+pub(crate) const CONFORMANCE_FORMAT: u32 = 1;
+
+/// Command numbers of the "Packet Serial" frames, for
+/// [conformance_json()].
+pub(crate) struct PacketCommands {
+    pub set: u8,
+    pub get: u8,
+    pub reply: u8,
+}
+
+/// Machine-readable description of the command set *table* of *device*, in
+/// JSON, with one line per command so that the changes are readable in a
+/// diff.
+pub(crate) fn conformance_json(
+    device: &str,
+    packet: &PacketCommands,
+    table: &[&CommandSpec],
+) -> String {
+    let mut json = String::from("{\n");
+    json += &format!("  \"format\": {},\n", CONFORMANCE_FORMAT);
+    json += &format!(
+        "  \"crate_version\": {},\n",
+        json_str(env!("CARGO_PKG_VERSION"))
+    );
+    json += &format!("  \"device\": {},\n", json_str(device));
+    json += &format!(
+        "  \"packet_serial\": {{\"set\": {}, \"get\": {}, \"reply\": {}}},\n",
+        packet.set, packet.get, packet.reply
+    );
+    json += "  \"commands\": [\n";
+    for (i, spec) in table.iter().enumerate() {
+        let sep = if i + 1 < table.len() { "," } else { "" };
+        json += &format!("    {}{}\n", spec_json(spec), sep);
+    }
+    json += "  ]\n}\n";
+    json
+}
+
+fn spec_json(spec: &CommandSpec) -> String {
+    let access = match spec.access {
+        Access::Set => "set",
+        Access::Get => "get",
+    };
+    let targets: Vec<String> = match spec.target {
+        Target::Channel => vec![[spec.token, b'1'], [spec.token, b'2']],
+        Target::Fixed(byte) => vec![[spec.token, byte]],
+    }
+    .iter()
+    .map(|target| json_str(&String::from_utf8_lossy(target)))
+    .collect();
+    let value = match spec.value {
+        Value::Ratio(max) => format!("{{\"kind\": \"ratio\", \"max\": {}}}", max),
+        Value::Fixed(raw) => format!("{{\"kind\": \"fixed\", \"raw\": {}}}", raw),
+        Value::Scaled(packet, text) => format!(
+            "{{\"kind\": \"scaled\", \"packet_serial_divisor\": {}, \"plain_text_divisor\": {}}}",
+            packet, text
+        ),
+    };
+    let prefix = match spec.prefix {
+        Some(prefix) => json_str(&char::from(prefix).to_string()),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"name\": {}, \"access\": \"{}\", \"targets\": [{}], \"opcode\": {}, \"value\": {}, \"keyword\": {}, \"prefix\": {}}}",
+        json_str(spec.name),
+        access,
+        targets.join(", "),
+        spec.opcode,
+        value,
+        json_str(spec.keyword),
+        prefix
+    )
+}
+
+/// Quote and escape *s* as a JSON string.
+pub(crate) fn json_str(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            c if (c as u32) < 0x20 => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Declare the command set of a device.
+///
+/// Generate an enum with one variant per command, its `ALL` list and its
+/// `spec()` method, a compile-time check of the table and unit tests for the
+/// value encoding. Every field of [CommandSpec] except `name` must be given.
+/// The typed methods of the handles are not generated, see the module
+/// documentation.
+macro_rules! command_table {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $enum:ident {
+            $(
+                $name:ident { $($field:ident : $value:expr),* $(,)? }
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        $vis enum $enum {
+            $($name),*
+        }
+
+        #[allow(dead_code)]
+        impl $enum {
+            /// All the commands, in declaration order.
+            $vis const ALL: &'static [$enum] = &[$($enum::$name),*];
+
+            const TABLE: &'static [$crate::command_table::CommandSpec] = &[
+                $($crate::command_table::CommandSpec {
+                    name: stringify!($name),
+                    $($field: $value),*
+                }),*
+            ];
+
+            /// Description of the command.
+            $vis fn spec(self) -> &'static $crate::command_table::CommandSpec {
+                &Self::TABLE[self as usize]
+            }
+        }
+
+        const _: () = $crate::command_table::check($enum::TABLE);
+
+        #[cfg(all(test, feature = "std"))]
+        mod command_table_tests {
+            use super::*;
+
+            #[test]
+            fn test_command_table() {
+                for command in $enum::ALL {
+                    $crate::command_table::check_encoding(command.spec());
+                }
+            }
+        }
+    };
+}
+
+/// Check a command table, panicking at compile time on the first error.
+pub(crate) const fn check(table: &[CommandSpec]) {
+    let mut i = 0;
+    while i < table.len() {
+        let spec = &table[i];
+        match (spec.access, spec.value) {
+            (_, Value::Ratio(max)) if max <= 0 => panic!("ratio maximum must be positive"),
+            (_, Value::Scaled(a, b)) if a <= 0 || b <= 0 => panic!("scale must be positive"),
+            (Access::Set, Value::Scaled(..)) => panic!("set commands take a ratio or no value"),
+            (Access::Set, Value::Fixed(_)) if spec.keyword.is_empty() => {
+                panic!("set commands without value need a keyword")
+            }
+            (Access::Set, Value::Ratio(_)) if !spec.keyword.is_empty() => {
+                panic!("set commands with a value have no keyword")
+            }
+            (Access::Set, _) if spec.prefix.is_some() => panic!("set commands have no reply"),
+            (Access::Get, Value::Fixed(_)) => panic!("get commands return a value"),
+            (Access::Get, _) if spec.keyword.is_empty() => panic!("get commands need a keyword"),
+            _ => {}
+        }
+
+        let set = matches!(spec.access, Access::Set);
+        let documented = match spec.target {
+            Target::Channel => {
+                addressing::is_valid([spec.token, b'1'], set)
+                    && addressing::is_valid([spec.token, b'2'], set)
+            }
+            Target::Fixed(byte) => addressing::is_valid([spec.token, byte], set),
+        };
+        if !documented {
+            panic!("undocumented source or target");
+        }
+
+        let mut j = i + 1;
+        while j < table.len() {
+            let other = &table[j];
+            if same_request(spec, other) && same_packet_value(spec, other) {
+                panic!("two commands have the same packetized frame");
+            }
+            if same_request(spec, other) && str_eq(spec.keyword, other.keyword) {
+                panic!("two commands have the same plain text frame");
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+const fn same_request(a: &CommandSpec, b: &CommandSpec) -> bool {
+    let same_target = match (a.target, b.target) {
+        (Target::Channel, Target::Channel) => true,
+        (Target::Fixed(x), Target::Fixed(y)) => x == y,
+        _ => false,
+    };
+    let same_access = matches!(
+        (a.access, b.access),
+        (Access::Set, Access::Set) | (Access::Get, Access::Get)
+    );
+    same_access && same_target && a.token == b.token
+}
+
+const fn same_packet_value(a: &CommandSpec, b: &CommandSpec) -> bool {
+    if a.opcode != b.opcode {
+        return false;
+    }
+    match (a.access, a.value, b.value) {
+        (Access::Set, Value::Fixed(x), Value::Fixed(y)) => x == y,
+        (Access::Set, Value::Fixed(_), _) | (Access::Set, _, Value::Fixed(_)) => false,
+        _ => true,
+    }
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Check the value encoding of *spec*, used by the tests generated by
+/// `command_table!`.
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn check_encoding(spec: &CommandSpec) {
+    if spec.target == Target::Channel {
+        assert!(spec.target(Some(3)).is_err(), "{}", spec.name);
+    }
+    if let Value::Ratio(max) = spec.value {
+        if spec.access == Access::Set {
+            assert_eq!(max, spec.raw_value(Some(1.0)).unwrap(), "{}", spec.name);
+            assert_eq!(-max, spec.raw_value(Some(-1.0)).unwrap(), "{}", spec.name);
+            assert!(spec.raw_value(Some(1.5)).is_err(), "{}", spec.name);
+        }
+        for &protocol in &[Protocol::Packetized, Protocol::PlainText] {
+            assert_eq!(1.0, spec.scale(max, protocol), "{}", spec.name);
+        }
+    }
+    let frame = spec.text_frame(Some(1), Some(0.0)).unwrap();
+    assert!(frame.ends_with("\r\n"), "{}", spec.name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_str() {
+        assert_eq!(r#""getb""#, json_str("getb"));
+        assert_eq!(r#""a\"b\\c\u000a""#, json_str("a\"b\\c\n"));
+    }
+}
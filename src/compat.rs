@@ -0,0 +1,45 @@
+//! Stable import path for the current API, for migrating incrementally.
+//!
+//! Application code importing the handles, the traits and the errors from
+//! this module keeps compiling while the crate root evolves: the module
+//! re-exports the API with its current shape, the channels given as `usize`
+//! and the errors as [Error], for one release cycle after a change of the
+//! root API.
+//!
+//! Code which only reports the errors can turn them into their message with
+//! [CompatResult::compat()], and does not depend on the variants of [Error].
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::compat::{CompatResult, PlainText, Sabertooth2x32};
+//! use saberrs::testing::{FrameTap, MockPort};
+//!
+//! fn battery(saber: &mut impl Sabertooth2x32) -> Result<f32, String> {
+//!     saber.get_voltage(1).compat()
+//! }
+//!
+//! let mut port = MockPort::new();
+//! let mut saber = PlainText::from(&port);
+//! port.queue_reply(b"M1:B240\r\n");
+//! assert_eq!(Ok(24.0), battery(&mut saber));
+//! assert!(saber.set_speed(3, 0.5).compat().unwrap_err().contains("channel"));
+//! ```
+
+pub use crate::error::{Error, Result};
+pub use crate::port::SabertoothSerial;
+pub use crate::sabertooth2x32::{PacketSerial, PacketType, PlainText, Sabertooth2x32};
+#[cfg(feature = "serialport")]
+pub use crate::SabertoothPort;
+
+/// Conversion of a [Result] into a result with the message of its error.
+pub trait CompatResult<T> {
+    /// Return the value, or the message of the error as displayed.
+    fn compat(self) -> std::result::Result<T, String>;
+}
+
+impl<T> CompatResult<T> for Result<T> {
+    fn compat(self) -> std::result::Result<T, String> {
+        self.map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::{Duration, SystemTime};
+
+use crate::command_table::json_str;
+use crate::history::{Outcome, Transaction};
+use crate::port::ReadStrategy;
+use crate::stats::Stats;
+
+/// Version of the layout of [Diagnostics::to_json()], incremented on any
+/// incompatible change.
+///
+/// [Diagnostics::to_json()]: struct.Diagnostics.html#method.to_json
+pub const DIAGNOSTICS_FORMAT: u32 = 1;
+
+/// Maximum number of errors kept in a bundle, the most recent ones.
+pub(crate) const MAX_ERRORS: usize = 16;
+
+/// Settings of the port of a handle, in a [Diagnostics] bundle.
+///
+/// [Diagnostics]: struct.Diagnostics.html
+#[derive(Clone, PartialEq, Debug)]
+pub struct PortSettings {
+    /// Baud rate of the port, if known.
+    pub baud_rate: Option<u32>,
+
+    /// Timeout of the port.
+    pub timeout: Duration,
+
+    /// Timeout of the replies, in place of the timeout of the port.
+    pub reply_timeout: Option<Duration>,
+
+    /// Deadline of the writes.
+    pub write_deadline: Option<Duration>,
+
+    /// Strategy of the reads.
+    pub read_strategy: ReadStrategy,
+}
+
+/// Self-describing snapshot of a handle, for attaching to bug reports.
+///
+/// The bundle gathers everything needed to reproduce a protocol-level issue:
+/// the port settings, the traffic statistics, the transactions of the
+/// history and their last errors, what the handle knows of the device, and
+/// its configuration. The history is only recorded when enabled with
+/// `with_history()` on the handle. [to_json()](#method.to_json) serializes
+/// the bundle, with the crate version.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+/// use saberrs::testing::MockPort;
+///
+/// let mut saber = PacketSerial::from(MockPort::new()).with_history(64);
+/// assert!(saber.get_voltage(1).is_err());
+///
+/// let bundle = saber.export_diagnostics();
+/// assert_eq!(1, bundle.errors.len());
+/// let json = bundle.to_json();
+/// assert!(json.contains(r#""device": "sabertooth-2x32 packet serial""#));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    /// Model of the device and protocol of the handle.
+    pub device: &'static str,
+
+    /// Name of the handle, if any.
+    pub name: Option<String>,
+
+    /// Settings of the port.
+    pub port: PortSettings,
+
+    /// Traffic statistics.
+    pub stats: Stats,
+
+    /// Recorded transactions, oldest first.
+    pub history: Vec<Transaction>,
+
+    /// Descriptions of the last failed transactions, oldest first.
+    pub errors: Vec<String>,
+
+    /// What the handle knows of the device, like its packet type. The
+    /// devices do not report their firmware revision.
+    pub capabilities: Vec<(&'static str, String)>,
+
+    /// Configuration of the handle, and the settings it wrote.
+    pub configuration: Vec<(&'static str, String)>,
+
+    /// State of the link, as seen by the handle.
+    pub status: Vec<(&'static str, String)>,
+}
+
+impl Diagnostics {
+    /// Serialize the bundle to JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        json += &format!("  \"format\": {},\n", DIAGNOSTICS_FORMAT);
+        json += &format!(
+            "  \"crate_version\": {},\n",
+            json_str(env!("CARGO_PKG_VERSION"))
+        );
+        json += &format!("  \"device\": {},\n", json_str(self.device));
+        json += &format!("  \"name\": {},\n", opt_json(self.name.as_deref()));
+        json += &format!("  \"port\": {},\n", port_json(&self.port));
+        json += &format!("  \"stats\": {},\n", stats_json(&self.stats));
+        json += &format!("  \"capabilities\": {},\n", pairs_json(&self.capabilities));
+        json += &format!(
+            "  \"configuration\": {},\n",
+            pairs_json(&self.configuration)
+        );
+        json += &format!("  \"status\": {},\n", pairs_json(&self.status));
+        let errors: Vec<_> = self.errors.iter().map(|e| json_str(e)).collect();
+        json += &format!("  \"errors\": [{}],\n", errors.join(", "));
+        json += "  \"history\": [\n";
+        for (i, tx) in self.history.iter().enumerate() {
+            let sep = if i + 1 < self.history.len() { "," } else { "" };
+            json += &format!("    {}{}\n", transaction_json(tx), sep);
+        }
+        json += "  ]\n}\n";
+        json
+    }
+}
+
+/// Descriptions of the last failed transactions of *history*, oldest first.
+pub(crate) fn last_errors<'a, I>(history: I) -> Vec<String>
+where
+    I: DoubleEndedIterator<Item = &'a Transaction>,
+{
+    let mut errors: Vec<_> = history
+        .rev()
+        .filter_map(|tx| match &tx.outcome {
+            Outcome::Failed(e) => Some(e.clone()),
+            _ => None,
+        })
+        .take(MAX_ERRORS)
+        .collect();
+    errors.reverse();
+    errors
+}
+
+fn opt_json(s: Option<&str>) -> String {
+    s.map_or_else(|| "null".to_string(), json_str)
+}
+
+/// Duration in milliseconds, or null.
+fn ms_json(duration: Option<Duration>) -> String {
+    match duration {
+        Some(d) => format!("{:.3}", d.as_secs_f64() * 1000.0),
+        None => "null".to_string(),
+    }
+}
+
+fn port_json(port: &PortSettings) -> String {
+    format!(
+        "{{\"baud_rate\": {}, \"timeout_ms\": {}, \"reply_timeout_ms\": {}, \"write_deadline_ms\": {}, \"read_strategy\": {}}}",
+        port.baud_rate.map_or_else(|| "null".to_string(), |b| b.to_string()),
+        ms_json(Some(port.timeout)),
+        ms_json(port.reply_timeout),
+        ms_json(port.write_deadline),
+        json_str(&format!("{:?}", port.read_strategy))
+    )
+}
+
+fn stats_json(stats: &Stats) -> String {
+    format!(
+        "{{\"elapsed_ms\": {}, \"frames_sent\": {}, \"frames_received\": {}, \"bytes_sent\": {}, \"bytes_received\": {}, \"bytes_discarded\": {}, \"line_conditions\": {}, \"resyncs\": {}, \"crc_errors\": {}, \"deadline_misses\": {}, \"min_latency_ms\": {}, \"mean_latency_ms\": {}, \"max_latency_ms\": {}}}",
+        ms_json(Some(stats.elapsed())),
+        stats.frames_sent(),
+        stats.frames_received(),
+        stats.bytes_sent(),
+        stats.bytes_received(),
+        stats.bytes_discarded(),
+        stats.line_conditions(),
+        stats.resyncs(),
+        stats.crc_errors(),
+        stats.deadline_misses(),
+        ms_json(stats.min_latency()),
+        ms_json(stats.mean_latency()),
+        ms_json(stats.max_latency())
+    )
+}
+
+fn pairs_json(pairs: &[(&'static str, String)]) -> String {
+    let fields: Vec<_> = pairs
+        .iter()
+        .map(|(key, value)| format!("{}: {}", json_str(key), json_str(value)))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn transaction_json(tx: &Transaction) -> String {
+    let time = tx
+        .time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+    let outcome = match &tx.outcome {
+        Outcome::Sent => "\"sent\"".to_string(),
+        Outcome::Replied => "\"replied\"".to_string(),
+        Outcome::Failed(e) => format!("{{\"failed\": {}}}", json_str(e)),
+    };
+    format!(
+        "{{\"time\": {}.{:06}, \"tx\": \"{}\", \"rx\": \"{}\", \"outcome\": {}}}",
+        time.as_secs(),
+        time.subsec_micros(),
+        hex(&tx.tx),
+        hex(&tx.rx),
+        outcome
+    )
+}
@@ -1,22 +1,66 @@
-use std::convert::From;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+#[cfg(feature = "std")]
 use std::error;
-use std::fmt;
+#[cfg(feature = "std")]
 use std::io;
 
 /// Result type used in the crate.
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
+/// Error type used in the crate. Some variants only exist with some
+/// features, so it is not exhaustive.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// IO error
+    #[cfg(feature = "std")]
     Io(io::Error),
 
+    /// Error of an `embedded-io` transport, see `EmbeddedPacketSerial`.
+    #[cfg(feature = "embedded-io")]
+    Transport(embedded_io::ErrorKind),
+
     /// Invalid input.
     InvalidInput(String),
 
     /// The response from the Sabertooth is invalid.
     Response(String),
 
+    /// The operation is not supported by the device or the protocol.
+    Unsupported(String),
+
+    /// The command was sent, but it took *elapsed* which is longer than the
+    /// *deadline*.
+    DeadlineMissed {
+        elapsed: Duration,
+        deadline: Duration,
+    },
+
+    /// A motion command was rejected because the handle has an interlock and
+    /// is not armed, see `Sabertooth2x32::arm()`. Nothing was sent.
+    NotArmed,
+
+    /// The command was denied by the command filter of the handle for the
+    /// given reason, see `CommandFilter`. Nothing was sent.
+    CommandDenied(String),
+
+    /// The command was rejected because *channel* is owned by *owner*, see
+    /// `MotorChannels`. Nothing was sent.
+    ChannelOwned { channel: usize, owner: String },
+
+    /// The reply received answers an earlier get request, which timed out or
+    /// was cancelled, instead of the current one. It was discarded, and the
+    /// reply of the current request may arrive late in turn.
+    StaleReply,
+
+    /// The frame could not be written before the write deadline, only
+    /// *written* of its *len* bytes were accepted by the port. A partial
+    /// frame may have reached the device.
+    TxTimeout { written: usize, len: usize },
+
     /// Other error
     Other,
 
@@ -26,11 +70,29 @@ pub enum Error {
 }
 
 impl fmt::Display for Error {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            #[cfg(feature = "std")]
             Error::Io(e) => write!(fmt, "IO error: {}", e),
+            #[cfg(feature = "embedded-io")]
+            Error::Transport(kind) => write!(fmt, "Transport error: {:?}", kind),
             Error::InvalidInput(msg) => write!(fmt, "Invalid input: {}", msg),
             Error::Response(msg) => write!(fmt, "Invalid response from Sabertooth: {}", msg),
+            Error::Unsupported(msg) => write!(fmt, "Unsupported operation: {}", msg),
+            Error::DeadlineMissed { elapsed, deadline } => write!(
+                fmt,
+                "Deadline missed: took {:?}, deadline {:?}",
+                elapsed, deadline
+            ),
+            Error::NotArmed => write!(fmt, "Motion command rejected: not armed"),
+            Error::CommandDenied(reason) => write!(fmt, "Command denied: {}", reason),
+            Error::ChannelOwned { channel, owner } => {
+                write!(fmt, "Channel {} owned by {}", channel, owner)
+            }
+            Error::StaleReply => write!(fmt, "Stale reply to an earlier request"),
+            Error::TxTimeout { written, len } => {
+                write!(fmt, "TX timeout: wrote {} of {} bytes", written, len)
+            }
             Error::Other => write!(fmt, "Other saberrs error"),
 
             #[cfg(feature = "serialport")]
@@ -39,26 +101,133 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "{}", defmt::Display2Format(self))
+    }
+}
+
+#[cfg(feature = "std")]
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
             Error::Io(e) => Some(e),
+            #[cfg(feature = "embedded-io")]
+            Error::Transport(_) => None,
             Error::InvalidInput(_) => None,
             Error::Response(_) => None,
+            Error::Unsupported(_) => None,
+            Error::DeadlineMissed { .. } => None,
+            Error::NotArmed => None,
+            Error::CommandDenied(_) => None,
+            Error::ChannelOwned { .. } => None,
+            Error::StaleReply => None,
+            Error::TxTimeout { .. } => None,
             Error::Other => None,
+
+            #[cfg(feature = "serialport")]
             Error::Serial(e) => Some(e),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         Self::Io(e)
     }
 }
 
+#[cfg(feature = "serialport")]
 impl From<serialport::Error> for Error {
     fn from(e: serialport::Error) -> Self {
         Self::Serial(e)
     }
 }
+
+/// Errors of a group operation on several devices, each with the index of
+/// the failed device in the group and its channel, if the failed operation
+/// addressed one. The members missing from it succeeded, so a caller can
+/// retry only the failed ones.
+#[derive(Debug, Default)]
+pub struct Errors {
+    errors: Vec<(usize, Option<usize>, Error)>,
+}
+
+impl Errors {
+    /// Create an empty aggregate.
+    pub fn new() -> Errors {
+        Errors::default()
+    }
+
+    /// Add the *error* of *channel* of the *device*.
+    pub fn push(&mut self, device: usize, channel: Option<usize>, error: Error) {
+        self.errors.push((device, channel, error));
+    }
+
+    /// Number of errors.
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Return true if no member failed.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Error of *channel* of the *device*, if it failed.
+    pub fn get(&self, device: usize, channel: Option<usize>) -> Option<&Error> {
+        self.errors
+            .iter()
+            .find(|(d, ch, _)| (*d, *ch) == (device, channel))
+            .map(|(_, _, e)| e)
+    }
+
+    /// Iterate over the device indexes, channels and errors, in the order
+    /// of the failures.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Option<usize>, &Error)> {
+        self.errors.iter().map(|(d, ch, e)| (*d, *ch, e))
+    }
+
+    /// Indexes of the failed devices, in increasing order and without
+    /// duplicates.
+    pub fn devices(&self) -> Vec<usize> {
+        let mut devices: Vec<_> = self.errors.iter().map(|(d, _, _)| *d).collect();
+        devices.sort_unstable();
+        devices.dedup();
+        devices
+    }
+
+    /// Return `Ok` if no member failed, or the errors otherwise.
+    pub fn into_result(self) -> core::result::Result<(), Errors> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+}
+
+impl fmt::Display for Errors {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} operation(s) failed", self.errors.len())?;
+        for (i, (device, channel, error)) in self.errors.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { "; " };
+            match channel {
+                Some(ch) => write!(fmt, "{}device {} channel {}: {}", sep, device, ch, error)?,
+                None => write!(fmt, "{}device {}: {}", sep, device, error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Errors {
+    /// The first error.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.errors
+            .first()
+            .map(|(_, _, e)| e as &(dyn error::Error + 'static))
+    }
+}
@@ -0,0 +1,211 @@
+use crate::sabertooth2x32::commissioning::Direction;
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::Result;
+
+/// Speed below which the direction of rotation is considered unknown.
+const DIRECTION_THRESHOLD: f32 = 0.02;
+
+/// Telemetry of one motor channel used by the
+/// [SpeedEstimator](struct.SpeedEstimator.html).
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct SpeedSample {
+    /// Commanded speed ratio, as given to `set_speed()` or read by
+    /// `get_speed()`.
+    pub commanded: f32,
+
+    /// Output power ratio, as read by `get_power()`.
+    pub power: f32,
+
+    /// Battery voltage in volts.
+    pub voltage: f32,
+
+    /// Motor current in amperes, positive when driving forward.
+    pub current: f32,
+}
+
+/// Speed estimated by the [SpeedEstimator](struct.SpeedEstimator.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpeedEstimate {
+    /// Estimated speed as a ratio of the no-load speed at the battery
+    /// voltage, between -1.0 and 1.0.
+    pub speed: f32,
+
+    /// Confidence in the estimate, between 0.0 (meaningless) and 1.0.
+    pub confidence: f32,
+}
+
+impl SpeedEstimate {
+    /// Estimated direction of rotation, or `None` if the motor is about
+    /// stopped.
+    pub fn direction(&self) -> Option<Direction> {
+        if self.speed > DIRECTION_THRESHOLD {
+            Some(Direction::Forward)
+        } else if self.speed < -DIRECTION_THRESHOLD {
+            Some(Direction::Backward)
+        } else {
+            None
+        }
+    }
+}
+
+/// Best-effort speed estimator for motors without encoders.
+///
+/// The speed of a brushed DC motor is roughly proportional to its back-EMF,
+/// which is the voltage applied to the motor minus the drop in its winding:
+///
+/// ```text
+/// speed = (power * voltage - current * resistance) / voltage
+/// ```
+///
+/// **This is an approximation.** It ignores the brush drop, the inductance
+/// and the temperature of the winding, and it is only as good as the winding
+/// resistance given with
+/// [with_winding_resistance()](#method.with_winding_resistance). The
+/// confidence decreases when the resistive drop dominates the applied voltage
+/// (stall, heavy load) and when the output power lags the command (ramping or
+/// current limiting). Use it for monitoring and coarse decisions, not for
+/// closing a speed loop.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::SpeedEstimator;
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// let mut estimator = SpeedEstimator::new().with_winding_resistance(0.3);
+///
+/// saber.set_speed(1, 0.5)?;
+/// let estimate = estimator.poll(&mut saber, 1)?;
+/// println!("speed {:.2} ({:.0}%)", estimate.speed, estimate.confidence * 100.0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SpeedEstimator {
+    resistance: f32,
+    smoothing: f32,
+    speed: Option<f32>,
+}
+
+impl SpeedEstimator {
+    /// Create a new estimator with no winding resistance and no smoothing.
+    pub fn new() -> SpeedEstimator {
+        SpeedEstimator {
+            resistance: 0.0,
+            smoothing: 1.0,
+            speed: None,
+        }
+    }
+
+    /// Set the resistance of the motor winding, in ohms.
+    pub fn with_winding_resistance(mut self, ohms: f32) -> Self {
+        self.resistance = ohms.max(0.0);
+        self
+    }
+
+    /// Set the weight of a new sample in the estimate, between 0.0
+    /// (excluded) and 1.0 for no smoothing.
+    pub fn with_smoothing(mut self, weight: f32) -> Self {
+        self.smoothing = weight.clamp(f32::EPSILON, 1.0);
+        self
+    }
+
+    /// Forget the previous samples.
+    pub fn reset(&mut self) {
+        self.speed = None;
+    }
+
+    /// Update the estimate with *sample*.
+    pub fn update(&mut self, sample: SpeedSample) -> SpeedEstimate {
+        if sample.voltage <= 0.0 {
+            return SpeedEstimate {
+                speed: self.speed.unwrap_or(0.0),
+                confidence: 0.0,
+            };
+        }
+
+        let applied = sample.power * sample.voltage;
+        let drop = sample.current * self.resistance;
+        let raw = ((applied - drop) / sample.voltage).clamp(-1.0, 1.0);
+
+        let speed = match self.speed {
+            Some(previous) => previous + self.smoothing * (raw - previous),
+            None => raw,
+        };
+        self.speed = Some(speed);
+
+        let load = if applied.abs() > f32::EPSILON {
+            (drop.abs() / applied.abs()).min(1.0)
+        } else {
+            0.0
+        };
+        let lag = (sample.commanded - sample.power).abs().min(1.0);
+
+        SpeedEstimate {
+            speed,
+            confidence: (1.0 - load) * (1.0 - lag),
+        }
+    }
+
+    /// Read the telemetry of *channel* from *saber* and update the estimate.
+    pub fn poll<S>(&mut self, saber: &mut S, channel: usize) -> Result<SpeedEstimate>
+    where
+        S: Sabertooth2x32 + ?Sized,
+    {
+        let sample = SpeedSample {
+            commanded: saber.get_speed(channel)?,
+            power: saber.get_power(channel)?,
+            voltage: saber.get_voltage(channel)?,
+            current: saber.get_current(channel)?,
+        };
+        Ok(self.update(sample))
+    }
+}
+
+impl Default for SpeedEstimator {
+    fn default() -> Self {
+        SpeedEstimator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(commanded: f32, power: f32, current: f32) -> SpeedSample {
+        SpeedSample {
+            commanded,
+            power,
+            voltage: 24.0,
+            current,
+        }
+    }
+
+    #[test]
+    fn test_estimate() {
+        let mut estimator = SpeedEstimator::new().with_winding_resistance(0.5);
+
+        // 12 V applied, 2.4 V lost in the winding
+        let estimate = estimator.update(sample(0.5, 0.5, 4.8));
+        assert!((estimate.speed - 0.4).abs() < 1e-4);
+        assert!((estimate.confidence - 0.8).abs() < 1e-4);
+        assert_eq!(Some(Direction::Forward), estimate.direction());
+
+        // Stall: everything is lost in the winding
+        let estimate = estimator.update(sample(-0.5, -0.5, -24.0));
+        assert!(estimate.speed.abs() < 1e-4);
+        assert_eq!(0.0, estimate.confidence);
+        assert_eq!(None, estimate.direction());
+    }
+
+    #[test]
+    fn test_smoothing_and_lag() {
+        let mut estimator = SpeedEstimator::new().with_smoothing(0.5);
+        estimator.update(sample(0.0, 0.0, 0.0));
+        let estimate = estimator.update(sample(1.0, 0.5, 0.0));
+        assert!((estimate.speed - 0.25).abs() < 1e-4);
+        assert!((estimate.confidence - 0.5).abs() < 1e-4);
+    }
+}
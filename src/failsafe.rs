@@ -0,0 +1,17 @@
+/// End state to reach when a failsafe or emergency stop is triggered.
+///
+/// Different robots want different behaviors when something goes wrong: a
+/// heavy platform on a slope must brake, while a manipulator may be safer when
+/// it can be moved by hand.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FailsafeAction {
+    /// Shut down the motor outputs, which puts the motors in a hard brake
+    /// state. The outputs must be started up again before moving.
+    HardBrake,
+
+    /// Let the motors spin freely (freewheel).
+    Coast,
+
+    /// Command a zero speed on every motor, the driver keeps regulating.
+    ZeroSpeed,
+}
@@ -0,0 +1,358 @@
+use std::collections::VecDeque;
+
+use crate::sabertooth2x32::Operation;
+use crate::{Error, Result};
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// Decision of a [CommandFilter](trait.CommandFilter.html) on an operation.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Verdict {
+    /// Send the operation unchanged.
+    Allow,
+
+    /// Reject the operation for *reason*: nothing is sent, and the handle
+    /// returns `Error::CommandDenied`.
+    Deny(String),
+
+    /// Send the given operation instead, for example with a limited speed.
+    /// It must not be a getter.
+    Replace(Operation),
+}
+
+/// Event emitted by a handle when its [CommandFilter] denies or modifies an
+/// operation, returned by `drain_filter_events()` on the handles.
+///
+/// [CommandFilter]: trait.CommandFilter.html
+#[derive(Clone, PartialEq, Debug)]
+pub enum FilterEvent {
+    /// *operation* was rejected for *reason*.
+    Denied {
+        operation: Operation,
+        reason: String,
+    },
+
+    /// *original* was replaced by *replacement*.
+    Modified {
+        original: Operation,
+        replacement: Operation,
+    },
+}
+
+/// Authorization hook inspecting the commands of a handle before they are
+/// sent, installed with `with_command_filter()` on the handles.
+///
+/// It lets a safety partition enforce its limits on the commands generated
+/// by less trusted application code: the filter sees every operation
+/// written by the handle, including the ones of the helpers and of the
+/// monitors, and may let it through, deny it or replace it. The getters are
+/// not filtered. [Envelope] is a filter enforcing the usual limits.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{CommandFilter, Verdict};
+/// use saberrs::sabertooth2x32::{Operation, PlainText, Sabertooth2x32};
+/// use saberrs::testing::MockPort;
+///
+/// /// Filter keeping the auxiliary outputs for the safety partition.
+/// struct NoAux;
+///
+/// impl CommandFilter for NoAux {
+///     fn inspect(&mut self, operation: Operation) -> Verdict {
+///         match operation {
+///             Operation::SetAux(..) => Verdict::Deny("reserved".to_string()),
+///             _ => Verdict::Allow,
+///         }
+///     }
+/// }
+///
+/// let mut saber = PlainText::from(MockPort::new()).with_command_filter(NoAux);
+/// assert!(saber.set_aux(1, 1.0).is_err());
+/// ```
+///
+/// [Envelope]: struct.Envelope.html
+pub trait CommandFilter {
+    /// Decide the fate of *operation*, which is not a getter.
+    fn inspect(&mut self, operation: Operation) -> Verdict;
+}
+
+/// Filter of a handle, with the events of its decisions.
+#[derive(Default)]
+pub(crate) struct FilterSlot {
+    filter: Option<Box<dyn CommandFilter + Send>>,
+    events: VecDeque<FilterEvent>,
+}
+
+impl FilterSlot {
+    pub(crate) fn set<F>(&mut self, filter: F)
+    where
+        F: CommandFilter + Send + 'static,
+    {
+        self.filter = Some(Box::new(filter));
+    }
+
+    /// Whether a filter is set.
+    pub(crate) fn is_set(&self) -> bool {
+        self.filter.is_some()
+    }
+
+    /// Return the operation to send for *operation*, or the error of its
+    /// denial.
+    pub(crate) fn check(
+        &mut self,
+        name: &Option<String>,
+        operation: Operation,
+    ) -> Result<Operation> {
+        let filter = match self.filter.as_mut() {
+            Some(filter) if !operation.is_get() => filter,
+            _ => return Ok(operation),
+        };
+        let prefix = name
+            .as_ref()
+            .map(|n| format!("{}: ", n))
+            .unwrap_or_default();
+        match filter.inspect(operation) {
+            Verdict::Allow => Ok(operation),
+            Verdict::Deny(reason) => {
+                log_warn!("{}{:?} denied: {}", prefix, operation, reason);
+                self.push_event(FilterEvent::Denied {
+                    operation,
+                    reason: reason.clone(),
+                });
+                Err(Error::CommandDenied(reason))
+            }
+            Verdict::Replace(replacement) if replacement.is_get() => Err(Error::InvalidInput(
+                format!("{:?} replaced by the getter {:?}", operation, replacement),
+            )),
+            Verdict::Replace(replacement) => {
+                log_info!("{}{:?} replaced by {:?}", prefix, operation, replacement);
+                self.push_event(FilterEvent::Modified {
+                    original: operation,
+                    replacement,
+                });
+                Ok(replacement)
+            }
+        }
+    }
+
+    pub(crate) fn drain_events(&mut self) -> impl Iterator<Item = FilterEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    fn push_event(&mut self, event: FilterEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+/// [CommandFilter] enforcing an operating envelope: maximum speed, maximum
+/// change between consecutive setpoints and allowed channels.
+///
+/// - The setpoints of the speed, power, drive and turn commands beyond the
+///   maximum speed are limited to it.
+/// - A setpoint further than the maximum step from the previous one of the
+///   same channel, or of the drive or the turn, is limited to the maximum step, so that a steady control
+///   loop can only accelerate by the maximum step per period.
+/// - The commands on a channel which is not allowed are denied. The drive
+///   and turn commands drive both channels, so they need both allowed.
+///
+/// The commands stopping a motor, the shutdowns and the zero setpoints, are
+/// always let through, so that the envelope never delays a stop.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{Envelope, FilterEvent};
+/// use saberrs::sabertooth2x32::{Operation, PlainText, Sabertooth2x32};
+/// use saberrs::testing::{FrameTap, MockPort};
+///
+/// let mut port = MockPort::new();
+/// let envelope = Envelope::new().with_max_speed(0.5).with_channels(&[1]);
+/// let mut saber = PlainText::from(&port).with_command_filter(envelope);
+///
+/// saber.set_speed(1, 1.0).unwrap();
+/// assert!(saber.set_speed(2, 0.2).is_err());
+/// assert_eq!(port.take_written(), b"M1: 1023\r\n");
+/// assert_eq!(2, saber.drain_filter_events().count());
+/// ```
+///
+/// [CommandFilter]: trait.CommandFilter.html
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Envelope {
+    max_speed: Option<f32>,
+    max_step: Option<f32>,
+    channels: Option<Vec<usize>>,
+    last: [f32; 4],
+}
+
+impl Envelope {
+    /// Create an envelope without any limit.
+    pub fn new() -> Envelope {
+        Envelope::default()
+    }
+
+    /// Limit the setpoints to ±*max_speed*, as a ratio.
+    pub fn with_max_speed(mut self, max_speed: f32) -> Self {
+        self.max_speed = Some(max_speed.abs());
+        self
+    }
+
+    /// Limit the change between consecutive setpoints of a channel to
+    /// *max_step*, as a ratio.
+    pub fn with_max_step(mut self, max_step: f32) -> Self {
+        self.max_step = Some(max_step.abs());
+        self
+    }
+
+    /// Deny the commands on the channels other than *channels*.
+    pub fn with_channels(mut self, channels: &[usize]) -> Self {
+        self.channels = Some(channels.to_vec());
+        self
+    }
+
+    fn allowed(&self, channel: usize) -> bool {
+        match &self.channels {
+            Some(channels) => channels.contains(&channel),
+            None => true,
+        }
+    }
+
+    /// Limit the setpoint *ratio* of *axis*, and record it.
+    fn limit(&mut self, axis: usize, ratio: f32) -> f32 {
+        let mut ratio = match self.max_speed {
+            Some(max) => ratio.clamp(-max, max),
+            None => ratio,
+        };
+        if let Some(step) = self.max_step {
+            let last = self.last[axis];
+            ratio = ratio.clamp(last - step, last + step);
+        }
+        self.last[axis] = ratio;
+        ratio
+    }
+}
+
+impl CommandFilter for Envelope {
+    fn inspect(&mut self, operation: Operation) -> Verdict {
+        // The setpoints are limited per axis: the channels, then the drive
+        // and the turn of the mixed mode
+        let (channels, setpoint) = match operation {
+            Operation::SetSpeed(ch, r) | Operation::SetPower(ch, r) => {
+                (ch..=ch, Some((ch.wrapping_sub(1), r)))
+            }
+            Operation::SetDrive(r) => (1..=2, Some((2, r))),
+            Operation::SetTurn(r) => (1..=2, Some((3, r))),
+            Operation::Startup(ch) | Operation::SetRamp(ch, _) | Operation::SetAux(ch, _) => {
+                (ch..=ch, None)
+            }
+            _ => return Verdict::Allow,
+        };
+        if !(1..=2).contains(channels.start()) {
+            // Rejected by the handle
+            return Verdict::Allow;
+        }
+        if let Some((axis, 0.0)) = setpoint {
+            self.last[axis] = 0.0;
+            return Verdict::Allow;
+        }
+        if !channels.clone().all(|channel| self.allowed(channel)) {
+            return Verdict::Deny("channel not allowed by the envelope".to_string());
+        }
+        let (axis, ratio) = match setpoint {
+            Some(setpoint) => setpoint,
+            None => return Verdict::Allow,
+        };
+        let limited = self.limit(axis, ratio);
+        if limited == ratio {
+            return Verdict::Allow;
+        }
+        Verdict::Replace(match operation {
+            Operation::SetSpeed(ch, _) => Operation::SetSpeed(ch, limited),
+            Operation::SetPower(ch, _) => Operation::SetPower(ch, limited),
+            Operation::SetDrive(_) => Operation::SetDrive(limited),
+            _ => Operation::SetTurn(limited),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope() {
+        let mut envelope = Envelope::new()
+            .with_max_speed(0.8)
+            .with_max_step(0.5)
+            .with_channels(&[1]);
+        assert_eq!(
+            Verdict::Replace(Operation::SetSpeed(1, 0.5)),
+            envelope.inspect(Operation::SetSpeed(1, 1.0))
+        );
+        assert_eq!(
+            Verdict::Replace(Operation::SetSpeed(1, 0.8)),
+            envelope.inspect(Operation::SetSpeed(1, 1.0))
+        );
+        assert_eq!(
+            Verdict::Allow,
+            envelope.inspect(Operation::SetSpeed(1, 0.0))
+        );
+        assert_eq!(
+            Verdict::Replace(Operation::SetSpeed(1, -0.5)),
+            envelope.inspect(Operation::SetSpeed(1, -0.9))
+        );
+        assert!(matches!(
+            envelope.inspect(Operation::SetSpeed(2, 0.1)),
+            Verdict::Deny(_)
+        ));
+        assert!(matches!(
+            envelope.inspect(Operation::SetDrive(0.1)),
+            Verdict::Deny(_)
+        ));
+        assert_eq!(
+            Verdict::Allow,
+            envelope.inspect(Operation::SetSpeed(2, 0.0))
+        );
+        assert_eq!(Verdict::Allow, envelope.inspect(Operation::Shutdown(2)));
+    }
+
+    #[test]
+    fn test_filter_slot() {
+        let mut slot = FilterSlot::default();
+        assert_eq!(
+            Operation::SetSpeed(1, 1.0),
+            slot.check(&None, Operation::SetSpeed(1, 1.0)).unwrap()
+        );
+        slot.set(Envelope::new().with_max_speed(0.5).with_channels(&[1]));
+        assert_eq!(
+            Operation::GetSpeed(2),
+            slot.check(&None, Operation::GetSpeed(2)).unwrap()
+        );
+        assert_eq!(
+            Operation::SetSpeed(1, 0.5),
+            slot.check(&None, Operation::SetSpeed(1, 1.0)).unwrap()
+        );
+        assert!(matches!(
+            slot.check(&None, Operation::SetAux(2, 1.0)),
+            Err(Error::CommandDenied(_))
+        ));
+        let events: Vec<_> = slot.drain_events().collect();
+        assert_eq!(
+            vec![
+                FilterEvent::Modified {
+                    original: Operation::SetSpeed(1, 1.0),
+                    replacement: Operation::SetSpeed(1, 0.5),
+                },
+                FilterEvent::Denied {
+                    operation: Operation::SetAux(2, 1.0),
+                    reason: "channel not allowed by the envelope".to_string(),
+                },
+            ],
+            events
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+
+#[allow(unused_imports)]
+use log::debug;
+
+/// Verbosity of the frame dumps of a handle, emitted at debug level.
+///
+/// The default is [Full](#variant.Full) in debug builds and
+/// [Off](#variant.Off) in release builds. It is set at runtime with
+/// `with_frame_logging()` or `set_frame_logging()` on the handles, for
+/// example for keeping the dumps of a release build, or for taming them in
+/// a control loop running at kHz rates.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::FrameLogging;
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?.with_frame_logging(FrameLogging::Sampled(100));
+///
+/// // Investigating a fault
+/// saber.set_frame_logging(FrameLogging::Full);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameLogging {
+    /// No frame is logged.
+    Off,
+
+    /// Only the discarded bytes and the frames of the failed transactions
+    /// are logged, with the error.
+    ErrorsOnly,
+
+    /// The frames of one transaction in *N* are logged, and so are the
+    /// discarded bytes. `Sampled(0)` logs no transaction.
+    Sampled(u32),
+
+    /// Every frame is logged.
+    Full,
+}
+
+impl Default for FrameLogging {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            FrameLogging::Full
+        } else {
+            FrameLogging::Off
+        }
+    }
+}
+
+/// Rendering of the frames in the logs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum FrameFormat {
+    /// Lines of text, like the "Plain Text" protocol.
+    Text,
+
+    /// Bytes, like the "Packet Serial" protocol.
+    Binary,
+}
+
+/// Frame dump handed over to the consumer of a
+/// [FrameSink](struct.FrameSink.html). It is displayed like the dumps
+/// logged by the handles.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FrameRecord {
+    name: Option<String>,
+    head: &'static str,
+    data: Vec<u8>,
+    format: FrameFormat,
+}
+
+impl FrameRecord {
+    /// Name of the handle, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Kind of record: `"tx"`, `"rx"`, `"discarded"` or `"error"`.
+    pub fn head(&self) -> &str {
+        self.head
+    }
+
+    /// Bytes of the frame, or text of the error.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl fmt::Display for FrameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.name.as_deref().unwrap_or("");
+        let sep = if name.is_empty() { "" } else { ": " };
+        match self.format {
+            FrameFormat::Text => {
+                let data = std::str::from_utf8(&self.data)
+                    .unwrap_or("<decode error>")
+                    .trim_matches(char::from(0))
+                    .trim(); // avoid empty lines caused by '\n' in logs
+                write!(f, "{}{}{} = {}", name, sep, self.head, data)
+            }
+            FrameFormat::Binary => write!(f, "{}{}{} = {:?}", name, sep, self.head, self.data),
+        }
+    }
+}
+
+/// Hand-over of the frame dumps from the handles to a consumer thread, so
+/// that formatting and writing the logs never delays a transaction.
+///
+/// The handles given the sink with `with_frame_sink()` push their dumps in
+/// a bounded channel without ever blocking: when the consumer cannot keep
+/// up and the channel is full, the dump is dropped and counted instead. The
+/// consumer thread stops when the last clone of the sink and the handles
+/// using it are dropped.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{FrameLogging, FrameSink};
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let sink = FrameSink::spawn(1024);
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?
+///     .with_frame_logging(FrameLogging::Full)
+///     .with_frame_sink(sink.clone());
+///
+/// // Later
+/// println!("{} dumps dropped", sink.dropped());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct FrameSink {
+    tx: SyncSender<FrameRecord>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl FrameSink {
+    /// Start a thread logging the dumps at debug level, like the handles do
+    /// without a sink, with room for *capacity* dumps waiting.
+    pub fn spawn(capacity: usize) -> FrameSink {
+        FrameSink::with_consumer(capacity, |record| {
+            debug!("{}", record);
+            #[cfg(feature = "defmt")]
+            {
+                let record = record.to_string();
+                defmt::debug!("{=str}", record.as_str());
+            }
+        })
+    }
+
+    /// Start a thread calling *consumer* with each dump, with room for
+    /// *capacity* dumps waiting, for example for writing them to a file.
+    pub fn with_consumer<F>(capacity: usize, mut consumer: F) -> FrameSink
+    where
+        F: FnMut(FrameRecord) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(capacity.max(1));
+        thread::spawn(move || {
+            for record in rx {
+                consumer(record);
+            }
+        });
+        FrameSink {
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of dumps dropped because the consumer was behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn push(&self, record: FrameRecord) {
+        match self.tx.try_send(record) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for FrameSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameSink")
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// Frame dumps of a handle, filtered according to a
+/// [FrameLogging](enum.FrameLogging.html).
+#[derive(Clone, Debug)]
+pub(crate) struct FrameLog {
+    logging: FrameLogging,
+    format: FrameFormat,
+    skipped: u32,
+    sampled: bool,
+    frames: Vec<(&'static str, Vec<u8>)>,
+    sink: Option<FrameSink>,
+}
+
+impl FrameLog {
+    pub(crate) fn new(format: FrameFormat) -> FrameLog {
+        FrameLog {
+            logging: FrameLogging::default(),
+            format,
+            skipped: 0,
+            sampled: false,
+            frames: Vec::new(),
+            sink: None,
+        }
+    }
+
+    pub(crate) fn set_sink(&mut self, sink: FrameSink) {
+        self.sink = Some(sink);
+    }
+
+    pub(crate) fn logging(&self) -> FrameLogging {
+        self.logging
+    }
+
+    pub(crate) fn set_logging(&mut self, logging: FrameLogging) {
+        self.logging = logging;
+        self.skipped = 0;
+        self.sampled = false;
+        self.frames.clear();
+    }
+
+    /// Record the command *frame* starting a transaction.
+    pub(crate) fn record_tx(&mut self, name: &Option<String>, frame: &[u8]) {
+        self.frames.clear();
+        if let FrameLogging::Sampled(n) = self.logging {
+            self.sampled = n > 0 && self.skipped == 0;
+            self.skipped = if self.sampled {
+                n - 1
+            } else {
+                self.skipped.saturating_sub(1)
+            };
+        }
+        self.record("tx", name, frame);
+    }
+
+    /// Record the reply *frame* of the current transaction.
+    pub(crate) fn record_rx(&mut self, name: &Option<String>, frame: &[u8]) {
+        self.record("rx", name, frame);
+    }
+
+    /// Record *bytes* dropped while looking for a reply, which are logged
+    /// unless the logging is off.
+    pub(crate) fn record_discarded(&mut self, name: &Option<String>, bytes: &[u8]) {
+        if self.logging != FrameLogging::Off {
+            self.emit(name, "discarded", bytes);
+        }
+    }
+
+    /// Record the outcome of the current transaction, logging its frames if
+    /// it failed with errors-only logging. The frames are kept until the
+    /// next transaction otherwise.
+    pub(crate) fn record_outcome<T, E: fmt::Display>(
+        &mut self,
+        name: &Option<String>,
+        res: &std::result::Result<T, E>,
+    ) {
+        if self.logging != FrameLogging::ErrorsOnly {
+            return;
+        }
+        if let Err(e) = res {
+            for (head, frame) in std::mem::take(&mut self.frames) {
+                self.emit(name, head, &frame);
+            }
+            self.emit_error(name, e);
+        }
+    }
+
+    fn record(&mut self, head: &'static str, name: &Option<String>, frame: &[u8]) {
+        match self.logging {
+            FrameLogging::Off => (),
+            FrameLogging::ErrorsOnly => self.frames.push((head, frame.to_vec())),
+            FrameLogging::Sampled(_) if !self.sampled => (),
+            FrameLogging::Sampled(_) | FrameLogging::Full => self.emit(name, head, frame),
+        }
+    }
+
+    fn emit(&self, name: &Option<String>, head: &'static str, frame: &[u8]) {
+        if let Some(sink) = &self.sink {
+            sink.push(FrameRecord {
+                name: name.clone(),
+                head,
+                data: frame.to_vec(),
+                format: self.format,
+            });
+            return;
+        }
+        let name = name.as_deref().unwrap_or("");
+        let sep = if name.is_empty() { "" } else { ": " };
+        match self.format {
+            FrameFormat::Text => {
+                let frame = std::str::from_utf8(frame)
+                    .unwrap_or("<decode error>")
+                    .trim_matches(char::from(0))
+                    .trim(); // avoid empty lines caused by '\n' in logs
+                debug!("{}{}{} = {}", name, sep, head, frame);
+                #[cfg(feature = "defmt")]
+                defmt::debug!("{=str}{=str}{=str} = {=str}", name, sep, head, frame);
+            }
+            FrameFormat::Binary => {
+                debug!("{}{}{} = {:?}", name, sep, head, frame);
+                #[cfg(feature = "defmt")]
+                defmt::debug!("{=str}{=str}{=str} = {=[u8]:#04x}", name, sep, head, frame);
+            }
+        }
+    }
+
+    fn emit_error<E: fmt::Display>(&self, name: &Option<String>, error: &E) {
+        if let Some(sink) = &self.sink {
+            sink.push(FrameRecord {
+                name: name.clone(),
+                head: "error",
+                data: error.to_string().into_bytes(),
+                format: FrameFormat::Text,
+            });
+            return;
+        }
+        let name = name.as_deref().unwrap_or("");
+        let sep = if name.is_empty() { "" } else { ": " };
+        debug!("{}{}error = {}", name, sep, error);
+        #[cfg(feature = "defmt")]
+        {
+            let error = error.to_string();
+            defmt::debug!("{=str}{=str}error = {=str}", name, sep, error.as_str());
+        }
+    }
+
+    #[cfg(test)]
+    fn pending(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampled() {
+        let mut log = FrameLog::new(FrameFormat::Binary);
+        log.set_logging(FrameLogging::Sampled(3));
+        let sampled: Vec<bool> = (0..6)
+            .map(|_| {
+                log.record_tx(&None, b"\x80");
+                log.sampled
+            })
+            .collect();
+        assert_eq!(vec![true, false, false, true, false, false], sampled);
+
+        log.set_logging(FrameLogging::Sampled(0));
+        log.record_tx(&None, b"\x80");
+        assert!(!log.sampled);
+    }
+
+    #[test]
+    fn test_errors_only() {
+        let mut log = FrameLog::new(FrameFormat::Text);
+        log.set_logging(FrameLogging::ErrorsOnly);
+        log.record_tx(&None, b"M1: get\r\n");
+        log.record_rx(&None, b"M1: 0\r\n");
+        assert_eq!(2, log.pending());
+        log.record_outcome(&None, &Ok::<(), String>(()));
+        assert_eq!(2, log.pending());
+
+        log.record_tx(&None, b"M1: get\r\n");
+        assert_eq!(1, log.pending());
+        log.record_outcome(&None, &Err::<(), _>("timed out"));
+        assert_eq!(0, log.pending());
+
+        log.set_logging(FrameLogging::Full);
+        log.record_tx(&None, b"M1: get\r\n");
+        assert_eq!(0, log.pending());
+    }
+
+    #[test]
+    fn test_sink() {
+        let (tx, rx) = mpsc::channel();
+        let (release, blocked) = mpsc::channel::<()>();
+        let sink = FrameSink::with_consumer(2, move |record| {
+            let _ = blocked.recv();
+            tx.send(record.to_string()).unwrap();
+        });
+        let mut log = FrameLog::new(FrameFormat::Text);
+        log.set_logging(FrameLogging::Full);
+        log.set_sink(sink.clone());
+
+        // The consumer holds one record, and the channel two more
+        let name = Some("front".to_string());
+        for _ in 0..5 {
+            log.record_tx(&name, b"M1: get\r\n");
+        }
+        for _ in 0..3 {
+            release.send(()).unwrap();
+        }
+        let records: Vec<_> = rx.iter().take(2).collect();
+        assert_eq!("front: tx = M1: get", records[0]);
+        assert!((2..=3).contains(&sink.dropped()));
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::{Error, FailsafeAction, Result};
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// Longest time step integrated at once, so that a stalled control loop does
+/// not trip the fuse on a single sample.
+const MAX_STEP: Duration = Duration::from_secs(1);
+
+/// Event emitted by [SoftFuse](struct.SoftFuse.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FuseEvent {
+    /// The accumulated I²t of the channel exceeded the limit, and the trip
+    /// action was applied.
+    Tripped { channel: usize, current: f32 },
+
+    /// The channel was re-armed with [SoftFuse::reset()].
+    ///
+    /// [SoftFuse::reset()]: struct.SoftFuse.html#method.reset
+    Reset { channel: usize },
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ChannelState {
+    heat: f32,
+    tripped: bool,
+}
+
+/// Software fuse, integrating the I²t of the motor current of each channel.
+///
+/// It protects small motors driven by a controller whose own current limit
+/// is far above what they can stand. The fuse accumulates the square of the
+/// current above the rated current over time, and cools down at the same rate
+/// below it. When the accumulated value exceeds the I²t limit, the channel
+/// trips: the trip action is applied to it and a
+/// [FuseEvent](enum.FuseEvent.html) is emitted. A tripped channel stays
+/// tripped until it is reset.
+///
+/// The trip action is `FailsafeAction::HardBrake` by default, which shuts
+/// down the channel. `ZeroSpeed` sets its speed to zero instead, and `Coast`
+/// is not available on the Sabertooth 2x32.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{FuseEvent, SoftFuse};
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+///
+/// // 3 A continuous, 10 A for about 1 s
+/// let mut fuse = SoftFuse::new(3.0, 91.0);
+///
+/// // In the control loop
+/// fuse.poll(&mut saber)?;
+/// for event in fuse.drain_events() {
+///     if let FuseEvent::Tripped { channel, .. } = event {
+///         eprintln!("channel {} tripped", channel);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SoftFuse {
+    rated_current: f32,
+    i2t_limit: f32,
+    action: FailsafeAction,
+    channels: [ChannelState; 2],
+    last_poll: Option<Instant>,
+    events: VecDeque<FuseEvent>,
+}
+
+impl SoftFuse {
+    /// Create a new fuse for motors standing *rated_current* amperes
+    /// continuously, and tripping when the accumulated I²t above it exceeds
+    /// *i2t_limit*, in A²s.
+    pub fn new(rated_current: f32, i2t_limit: f32) -> SoftFuse {
+        SoftFuse {
+            rated_current: rated_current.abs(),
+            i2t_limit,
+            action: FailsafeAction::HardBrake,
+            channels: [ChannelState::default(); 2],
+            last_poll: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Set the action applied to a channel when it trips.
+    pub fn with_action(mut self, action: FailsafeAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Accumulated I²t of *channel*, as a ratio of the limit: the channel
+    /// trips above 1.0.
+    pub fn load(&self, channel: usize) -> f32 {
+        match self.channels.get(channel.wrapping_sub(1)) {
+            Some(state) if self.i2t_limit > 0.0 => state.heat / self.i2t_limit,
+            _ => 0.0,
+        }
+    }
+
+    /// Return true if *channel* is tripped.
+    pub fn is_tripped(&self, channel: usize) -> bool {
+        self.channels
+            .get(channel.wrapping_sub(1))
+            .is_some_and(|state| state.tripped)
+    }
+
+    /// Re-arm *channel* and clear its accumulated I²t. The channel must be
+    /// started up again if the trip action shut it down.
+    pub fn reset(&mut self, channel: usize) {
+        if let Some(state) = self.channels.get_mut(channel.wrapping_sub(1)) {
+            *state = ChannelState::default();
+            self.push_event(FuseEvent::Reset { channel });
+        }
+    }
+
+    /// Remove and return the pending events, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = FuseEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Integrate the *current* of *channel*, in amperes, over *dt*. Return
+    /// true if the channel trips with this sample. The trip action is not
+    /// applied, this is for current measured by other means than
+    /// [poll()](#method.poll).
+    pub fn update(&mut self, channel: usize, current: f32, dt: Duration) -> bool {
+        let (rated, limit) = (self.rated_current, self.i2t_limit);
+        let state = match self.channels.get_mut(channel.wrapping_sub(1)) {
+            Some(state) if !state.tripped => state,
+            _ => return false,
+        };
+
+        let dt = dt.min(MAX_STEP).as_secs_f32();
+        state.heat = (state.heat + (current * current - rated * rated) * dt).max(0.0);
+        if state.heat <= limit {
+            return false;
+        }
+
+        state.tripped = true;
+        log_warn!("channel {} tripped the fuse at {:.1} A", channel, current);
+        self.push_event(FuseEvent::Tripped { channel, current });
+        true
+    }
+
+    /// Read the current of both channels of *saber*, integrate it since the
+    /// previous poll, and apply the trip action to the channels tripping.
+    pub fn poll<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S) -> Result<()> {
+        self.poll_at(saber, Instant::now())
+    }
+
+    fn poll_at<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S, now: Instant) -> Result<()> {
+        let dt = match self.last_poll {
+            Some(last) => now.saturating_duration_since(last),
+            None => Duration::from_secs(0),
+        };
+        self.last_poll = Some(now);
+
+        for channel in 1..=2 {
+            if self.is_tripped(channel) {
+                continue;
+            }
+            let current = saber.get_current(channel)?;
+            if self.update(channel, current, dt) {
+                self.trip(saber, channel)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn trip<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S, channel: usize) -> Result<()> {
+        saber.disarm();
+        match self.action {
+            FailsafeAction::HardBrake => saber.shutdown(channel),
+            FailsafeAction::ZeroSpeed => saber.set_speed(channel, 0.0),
+            FailsafeAction::Coast => Err(Error::Unsupported(
+                "the Sabertooth 2x32 cannot freewheel".to_string(),
+            )),
+        }
+    }
+
+    fn push_event(&mut self, event: FuseEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_update() {
+        let mut fuse = SoftFuse::new(2.0, 12.0);
+        let s = Duration::from_secs;
+
+        // Rated current never trips
+        assert!(!fuse.update(1, 2.0, s(1)));
+        assert_eq!(0.0, fuse.load(1));
+
+        // (4² - 2²) A² for 1 s is 12 A²s, the limit
+        assert!(!fuse.update(1, 4.0, s(1)));
+        assert!((fuse.load(1) - 1.0).abs() < 1e-6);
+
+        // Cooling down below the rated current
+        assert!(!fuse.update(1, 0.0, s(1)));
+        assert!(fuse.update(1, -5.0, s(1)));
+        assert!(fuse.is_tripped(1));
+        assert!(!fuse.is_tripped(2));
+        assert!(!fuse.update(1, 5.0, s(1)));
+
+        fuse.reset(1);
+        assert!(!fuse.is_tripped(1));
+        let events: Vec<_> = fuse.drain_events().collect();
+        assert_eq!(
+            vec![
+                FuseEvent::Tripped {
+                    channel: 1,
+                    current: -5.0
+                },
+                FuseEvent::Reset { channel: 1 },
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn test_poll() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port).with_interlock();
+        saber.arm();
+        let mut fuse = SoftFuse::new(1.0, 10.0);
+        let t0 = Instant::now();
+
+        port.queue_reply(b"M1:C100\r\n");
+        port.queue_reply(b"M2:C0\r\n");
+        fuse.poll_at(&mut saber, t0).unwrap();
+        port.take_written();
+
+        port.queue_reply(b"M1:C100\r\n");
+        port.queue_reply(b"");
+        port.queue_reply(b"M2:C0\r\n");
+        fuse.poll_at(&mut saber, t0 + Duration::from_millis(200))
+            .unwrap();
+        assert_eq!(
+            port.take_written(),
+            b"M1: getc\r\nM1: shutdown\r\nM2: getc\r\n"
+        );
+        assert!(fuse.is_tripped(1));
+        assert!(!saber.is_armed());
+    }
+}
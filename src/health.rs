@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Default duration after which persistent telemetry failures are reported.
+pub const DEFAULT_TELEMETRY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// State of the link with a device, as seen from its handle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LinkState {
+    /// Commands are written and replies are received.
+    Healthy,
+
+    /// Commands are still written, but no valid reply was received for longer
+    /// than the telemetry timeout, for example because the RX wire is loose.
+    /// Motion commands keep flowing.
+    TelemetryLost,
+
+    /// Commands cannot be written to the port.
+    Failed,
+}
+
+/// Event emitted when the link state changes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HealthEvent {
+    pub from: LinkState,
+    pub to: LinkState,
+}
+
+/// Link health monitor of a handle.
+///
+/// It distinguishes a fully failed link from a link where only the telemetry
+/// (the replies to the get requests) is lost.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{LinkState, Result};
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// saber.set_speed(1, 0.5)?;
+/// let _ = saber.get_current(1);
+///
+/// for event in saber.health_mut().drain_events() {
+///     if event.to == LinkState::TelemetryLost {
+///         println!("telemetry lost, still driving");
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Health {
+    telemetry_timeout: Duration,
+    tx_failed: bool,
+    rx_failing_since: Option<Instant>,
+    state: LinkState,
+    events: VecDeque<HealthEvent>,
+}
+
+impl Health {
+    /// Create a new monitor in the `Healthy` state.
+    pub fn new() -> Health {
+        Health {
+            telemetry_timeout: DEFAULT_TELEMETRY_TIMEOUT,
+            tx_failed: false,
+            rx_failing_since: None,
+            state: LinkState::Healthy,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Set how long the telemetry must fail before the state becomes
+    /// `TelemetryLost`.
+    pub fn set_telemetry_timeout(&mut self, timeout: Duration) {
+        self.telemetry_timeout = timeout;
+    }
+
+    /// Return the telemetry timeout.
+    pub fn telemetry_timeout(&self) -> Duration {
+        self.telemetry_timeout
+    }
+
+    /// Return the current state of the link.
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// Remove and return the pending state change events, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = HealthEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    pub(crate) fn record_tx(&mut self, ok: bool) {
+        self.record_tx_at(ok, Instant::now())
+    }
+
+    pub(crate) fn record_rx(&mut self, ok: bool) {
+        self.record_rx_at(ok, Instant::now())
+    }
+
+    fn record_tx_at(&mut self, ok: bool, now: Instant) {
+        self.tx_failed = !ok;
+        self.update(now);
+    }
+
+    fn record_rx_at(&mut self, ok: bool, now: Instant) {
+        if ok {
+            self.rx_failing_since = None;
+        } else if self.rx_failing_since.is_none() {
+            self.rx_failing_since = Some(now);
+        }
+        self.update(now);
+    }
+
+    fn update(&mut self, now: Instant) {
+        let state = if self.tx_failed {
+            LinkState::Failed
+        } else {
+            match self.rx_failing_since {
+                Some(t) if now.duration_since(t) >= self.telemetry_timeout => {
+                    LinkState::TelemetryLost
+                }
+                _ => LinkState::Healthy,
+            }
+        };
+
+        if state != self.state {
+            log_warn!("link state changed from {:?} to {:?}", self.state, state);
+            if self.events.len() == MAX_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(HealthEvent {
+                from: self.state,
+                to: state,
+            });
+            self.state = state;
+        }
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_telemetry_lost() {
+        let mut health = Health::new();
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+
+        health.record_tx_at(true, t0);
+        health.record_rx_at(false, t0);
+        assert_eq!(LinkState::Healthy, health.state());
+
+        // Still within the timeout
+        health.record_tx_at(true, t0 + ms(500));
+        health.record_rx_at(false, t0 + ms(500));
+        assert_eq!(LinkState::Healthy, health.state());
+
+        health.record_tx_at(true, t0 + ms(1000));
+        health.record_rx_at(false, t0 + ms(1000));
+        assert_eq!(LinkState::TelemetryLost, health.state());
+
+        // Commands keep flowing without changing the state
+        health.record_tx_at(true, t0 + ms(1100));
+        assert_eq!(LinkState::TelemetryLost, health.state());
+
+        health.record_rx_at(true, t0 + ms(1200));
+        assert_eq!(LinkState::Healthy, health.state());
+
+        let events: Vec<_> = health.drain_events().collect();
+        assert_eq!(
+            vec![
+                HealthEvent {
+                    from: LinkState::Healthy,
+                    to: LinkState::TelemetryLost
+                },
+                HealthEvent {
+                    from: LinkState::TelemetryLost,
+                    to: LinkState::Healthy
+                },
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn test_link_failed() {
+        let mut health = Health::new();
+        let t0 = Instant::now();
+
+        health.record_rx_at(false, t0);
+        health.record_tx_at(false, t0 + Duration::from_secs(2));
+        assert_eq!(LinkState::Failed, health.state());
+
+        health.record_tx_at(true, t0 + Duration::from_secs(3));
+        assert_eq!(LinkState::TelemetryLost, health.state());
+        assert_eq!(2, health.drain_events().count());
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{vec_deque, VecDeque};
+use std::fmt;
+use std::time::SystemTime;
+
+/// Outcome of a recorded transaction.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// The command was written. Setters are not acknowledged by the device.
+    Sent,
+
+    /// A valid reply was received.
+    Replied,
+
+    /// The transaction failed, with the description of the error.
+    Failed(String),
+}
+
+/// One transaction recorded in a [History](struct.History.html).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Transaction {
+    /// Time at which the command was written.
+    pub time: SystemTime,
+
+    /// Bytes of the command, before any frame transformation.
+    pub tx: Vec<u8>,
+
+    /// Bytes of the reply, empty for the setters.
+    pub rx: Vec<u8>,
+
+    /// Outcome of the transaction.
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "{}.{:06} tx={:02x?} rx={:02x?} {:?}",
+            time.as_secs(),
+            time.subsec_micros(),
+            self.tx,
+            self.rx,
+            self.outcome
+        )
+    }
+}
+
+/// Bounded history of the last transactions of a handle, for post-mortem
+/// analysis.
+///
+/// The history is disabled by default, and enabled with `with_history()` on
+/// the handles. When full, the oldest transaction is dropped.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?.with_history(50);
+/// saber.set_speed(1, 0.5)?;
+///
+/// // Something unexpected happened
+/// eprintln!("{}", saber.history());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    capacity: usize,
+    transactions: VecDeque<Transaction>,
+}
+
+impl History {
+    /// Create a new history keeping at most *capacity* transactions. A
+    /// capacity of 0 disables it.
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            transactions: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Maximum number of transactions kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of transactions kept.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Return true if no transaction is kept.
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Iterate over the transactions, oldest first.
+    pub fn iter(&self) -> vec_deque::Iter<'_, Transaction> {
+        self.transactions.iter()
+    }
+
+    /// Remove all the transactions.
+    pub fn clear(&mut self) {
+        self.transactions.clear()
+    }
+
+    pub(crate) fn record_tx<E: fmt::Display>(&mut self, tx: &[u8], res: &Result<(), E>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.transactions.len() == self.capacity {
+            self.transactions.pop_front();
+        }
+        self.transactions.push_back(Transaction {
+            time: SystemTime::now(),
+            tx: tx.to_vec(),
+            rx: Vec::new(),
+            outcome: match res {
+                Ok(()) => Outcome::Sent,
+                Err(e) => Outcome::Failed(e.to_string()),
+            },
+        });
+    }
+
+    pub(crate) fn record_rx(&mut self, rx: &[u8]) {
+        if let Some(last) = self.transactions.back_mut() {
+            last.rx.extend_from_slice(rx);
+        }
+    }
+
+    pub(crate) fn record_outcome<T, E: fmt::Display>(&mut self, res: &Result<T, E>) {
+        if let Some(last) = self.transactions.back_mut() {
+            last.outcome = match res {
+                Ok(_) => Outcome::Replied,
+                Err(e) => Outcome::Failed(e.to_string()),
+            };
+        }
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for transaction in self.iter() {
+            writeln!(f, "{}", transaction)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a History {
+    type Item = &'a Transaction;
+    type IntoIter = vec_deque::Iter<'a, Transaction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded() {
+        let mut history = History::new(2);
+        let ok: Result<(), String> = Ok(());
+        history.record_tx(b"a", &ok);
+        history.record_tx(b"b", &ok);
+        history.record_rx(b"B");
+        history.record_outcome(&Err::<(), _>("bad reply"));
+        history.record_tx(b"c", &ok);
+
+        let tx: Vec<_> = history.iter().map(|t| t.tx.clone()).collect();
+        assert_eq!(vec![b"b".to_vec(), b"c".to_vec()], tx);
+        let b = history.iter().next().unwrap();
+        assert_eq!(b"B", &b.rx[..]);
+        assert_eq!(Outcome::Failed("bad reply".to_string()), b.outcome);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut history = History::default();
+        history.record_tx(b"a", &Ok::<(), String>(()));
+        history.record_rx(b"A");
+        assert!(history.is_empty());
+    }
+}
@@ -1,11 +1,15 @@
 //! `saberrs` is a library for interfacing with [Dimension Engineering]
 //! Sabertooth motor driver.
 //!
-//! Currently only the Sabertooth 2x32 is supported.
+//! The Sabertooth 2x32 is supported with all its serial protocols, the older
+//! Sabertooth 2x12 and 2x25 with their "Packetized Serial" protocol (see
+//! [sabertooth2x12]), and the Sabertooth 2x5 with its single-byte
+//! "Simplified Serial" protocol (see [sabertooth2x5]).
 //!
 //! # Simple usage
 //!
-//! ```rust
+#![cfg_attr(feature = "serialport", doc = " ```rust")]
+#![cfg_attr(not(feature = "serialport"), doc = " ```ignore")]
 //! # use saberrs::Result;
 //! use saberrs::sabertooth2x32::{Sabertooth2x32, PacketSerial};
 //!
@@ -29,7 +33,8 @@
 //!
 //! Other protocol variants can be used:
 //!
-//! ```rust
+#![cfg_attr(feature = "serialport", doc = " ```rust")]
+#![cfg_attr(not(feature = "serialport"), doc = " ```ignore")]
 //! use saberrs::sabertooth2x32::{Sabertooth2x32, PacketSerial, PacketType, PlainText};
 //! # use saberrs::Result;
 //!
@@ -50,15 +55,16 @@
 //! The handles rely on the trait [SabertoothSerial], which abstract the
 //! low-level IO communication with the device.
 //!
-//! By default, the library provides [SabertoothPort] and
-//! [SabertoothPortShared]. In most cases the application writer shouldn't need
+//! By default, the library provides [SabertoothPort], [SabertoothPortShared]
+//! and [SabertoothPortSync]. In most cases the application writer shouldn't need
 //! to care about those, but they may be used for applying custom baud rates or
 //! timeout values for example.
 //!
 //! [SabertoothSerial] can be implemented manually for even more customization.
 //! For example stubs can be implemented for debugging purpose:
 //!
-//! ```rust
+#![cfg_attr(feature = "std", doc = " ```rust")]
+#![cfg_attr(not(feature = "std"), doc = " ```ignore")]
 //! use std::time::Duration;
 //! use std::io::{self, Read, Write};
 //! use saberrs::{SabertoothSerial};
@@ -121,19 +127,112 @@
 //! ```
 //!
 //!
+//! # Threads
+//!
+//! The handles own their port, and are `Send` when the port is:
+//!
+//! | Port                   | `Send` | `Sync` | Clonable |
+//! |------------------------|--------|--------|----------|
+//! | [SabertoothPort]       | yes    | no     | no       |
+//! | [SabertoothPortShared] | no     | no     | yes      |
+//! | [SabertoothPortSync]   | yes    | yes    | yes      |
+//! | `testing::MockPort`    | yes    | yes    | yes      |
+//!
+//! Several drivers in "Packet Serial" mode on one multi-drop line are best
+//! driven through a [SabertoothBus], whose devices serialize their
+//! transactions on the line and are `Send` when the port is.
+//!
+//! `PacketSerial` and `PlainText` are never `Sync`, since their frame
+//! transform and scaling only need to be `Send`: a handle used from several
+//! threads goes behind a `Mutex`. The subsystems (`CommandQueue`,
+//! `TelemetryLog`, `SoftFuse`, `AutoRestart`, ...) and [Error] are `Send` and
+//! `Sync`, and the telemetry stream of the `async` feature is `Send` when its
+//! handle is. These guarantees are checked at compile time by the tests.
+//!
+//! The state shared between the telemetry sampling thread and its stream is
+//! built on primitives which are replaced by the ones of [loom] when the
+//! crate is compiled with `--cfg loom`, and its hand-over is model-checked
+//! with:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --features async --lib loom
+//! ```
+//!
+//! # Layers
+//!
+//! The protocol code (frames, parsing, validation and scaling) is kept apart
+//! from the IO code (ports, handles and subsystems), and does not depend on
+//! it. The public API re-exports both, so the layering is invisible to the
+//! applications, but it keeps the protocol code reusable by other transports.
+//!
+//! # Unsafe code
+//!
+//! The crate contains no `unsafe` code, and forbids it with
+//! `#![forbid(unsafe_code)]`: any addition using it fails to compile, and the
+//! attribute cannot be overridden further down in the crate. The unsafe code
+//! left to audit is in the dependencies enabled by the features.
+//!
 //! # Features and dependencies
 //!
 //! Features:
 //!
+//! - `std`, enabled by default, adds the IO layer: the [SabertoothSerial]
+//!   interface, the handles on it and the subsystems, which all the features
+//!   below except `defmt` and `embedded-io` enable. Without it the crate is
+//!   `no_std`, and only needs `alloc` for the messages of [Error]: it keeps
+//!   the [frame] and [opcodes] modules, the value conversions and the
+//!   `Sabertooth2x32` and `Sabertooth2x12` traits, for microcontrollers.
+//! - `embedded-io`, disabled by default, adds
+//!   `sabertooth2x32::EmbeddedPacketSerial` and
+//!   `sabertooth2x12::EmbeddedPacketizedSerial`, the handles implementing
+//!   the traits over the [embedded-io] `Read` and `Write` transports, with
+//!   or without `std`.
 //! - `serialport`, enabled by default, allows the usage of the crate
-//! [serialport] for providing [SabertoothPort] and [SabertoothPortShared].
-//! If this feature is disabled [SabertoothSerial] needs to be implemented
-//! manually.
+//!   [serialport] for providing [SabertoothPort], [SabertoothPortShared] and
+//!   [SabertoothPortSync].
+//!   If this feature is disabled [SabertoothSerial] needs to be implemented
+//!   manually.
+//! - `telemetry`, enabled by default, adds the recording of the telemetry in
+//!   the [telemetry] module.
+//! - `control`, enabled by default, adds the control helpers built on the
+//!   handles: the [ramp] module, the command queue, the mailbox, the
+//!   supervisor and its retry budget, the monitors and the skid steering.
+//! - `emulator`, disabled by default, adds `testing::DryRun`, emulating
+//!   devices without hardware, the `dry_run()` constructors of the handles,
+//!   and the fault injection of `testing::FaultInjector`.
+//! - `defmt`, disabled by default, mirrors the logs emitted with [log] using
+//!   [defmt], for example for logging over RTT on microcontrollers. [Error]
+//!   also implements `defmt::Format`.
+//! - `mio`, disabled by default, adds `register()` on the handles for
+//!   registering their port in a [mio] event loop, on Unix.
+//! - `metrics`, disabled by default, adds the [metrics](metrics/index.html)
+//!   module, publishing the traffic statistics and the telemetry to the
+//!   [metrics] facade.
+//! - `async`, disabled by default, adds the asynchronous handles
+//!   `sabertooth2x32::AsyncPacketSerial` and `sabertooth2x32::AsyncPlainText`
+//!   over any `AsyncSabertoothSerial` port, without depending on a runtime,
+//!   and `telemetry::telemetry_stream()`, which yields the telemetry as a
+//!   [futures-core] `Stream`, with the `telemetry` feature.
+//! - `tokio`, disabled by default, enables `async` and adds `TokioPort`,
+//!   the `AsyncSabertoothSerial` port on [tokio] streams like the ones of
+//!   tokio-serial.
+//!
+//! The subsystem features are independent of each other and need no
+//! dependency, so that the minimal build, with `default-features = false`,
+//! only has the protocols and the traits, and only depends on [log]. Adding
+//! the `std` feature brings the [SabertoothSerial] interface without any
+//! other dependency.
 //!
 //! Dependencies:
 //!
 //! - [serialport] for the `serialport` feature.
 //! - [log] for emitting logs.
+//! - [defmt] for the `defmt` feature.
+//! - [embedded-io] for the `embedded-io` feature.
+//! - [mio] for the `mio` feature.
+//! - [metrics] for the `metrics` feature.
+//! - [futures-core] for the `async` feature.
+//! - [tokio] for the `tokio` feature.
 //!
 //! # Disclaimer
 //!
@@ -148,22 +247,200 @@
 //! [SabertoothSerial]: trait.SabertoothSerial.html
 //! [SabertoothPort]: struct.SabertoothPort.html
 //! [SabertoothPortShared]: struct.SabertoothPortShared.html
+//! [SabertoothPortSync]: struct.SabertoothPortSync.html
+//! [SabertoothBus]: struct.SabertoothBus.html
 //! [serialport]: https://crates.io/crates/serialport
 //! [log]: https://crates.io/crates/log
+//! [defmt]: https://crates.io/crates/defmt
+//! [embedded-io]: https://crates.io/crates/embedded-io
+//! [frame]: frame/index.html
+//! [opcodes]: opcodes/index.html
+//! [mio]: https://crates.io/crates/mio
+//! [futures-core]: https://crates.io/crates/futures-core
+//! [metrics]: https://crates.io/crates/metrics
+//! [tokio]: https://crates.io/crates/tokio
+//! [loom]: https://crates.io/crates/loom
+//! [sabertooth2x12]: sabertooth2x12/index.html
+//! [sabertooth2x5]: sabertooth2x5/index.html
+//! [Error]: enum.Error.html
 
-pub use error::{Error, Result};
-pub use port::SabertoothSerial;
+#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub use crate::protocol::scaling::{Scaling, ScalingProfile};
+pub use crate::protocol::transform::{EscapedAddress, FrameTransform, Passthrough};
+#[cfg(feature = "std")]
+pub use addressbook::AddressBook;
+#[cfg(feature = "control")]
+pub use asymmetry::{AsymmetryEvent, AsymmetryMonitor};
+#[cfg(feature = "async")]
+pub use asyncport::{AsyncSabertoothSerial, BridgePort};
+#[cfg(feature = "tokio")]
+pub use asyncport::{TokioPort, DEFAULT_ASYNC_TIMEOUT};
+#[cfg(feature = "std")]
+pub use bandwidth::LinkBudget;
+#[cfg(feature = "std")]
+pub use batch::apply_all;
+#[cfg(feature = "control")]
+pub use battery::{
+    BatteryEvent, BatteryLevel, BatteryMonitor, BatteryPack, BatteryState, Chemistry,
+};
+#[cfg(feature = "std")]
+pub use bus::{BusDevice, BusPort, SabertoothBus};
+#[cfg(feature = "std")]
+pub use diagnostics::{Diagnostics, PortSettings, DIAGNOSTICS_FORMAT};
+pub use error::{Error, Errors, Result};
+#[cfg(feature = "control")]
+pub use estimator::{SpeedEstimate, SpeedEstimator, SpeedSample};
+pub use failsafe::FailsafeAction;
+#[cfg(feature = "std")]
+pub use filter::{CommandFilter, Envelope, FilterEvent, Verdict};
+#[cfg(feature = "std")]
+pub use framelog::{FrameLogging, FrameRecord, FrameSink};
+#[cfg(feature = "control")]
+pub use fuse::{FuseEvent, SoftFuse};
+#[cfg(feature = "std")]
+pub use health::{Health, HealthEvent, LinkState};
+#[cfg(feature = "std")]
+pub use history::{History, Outcome, Transaction};
+#[cfg(feature = "control")]
+pub use mailbox::Mailbox;
+#[cfg(feature = "control")]
+pub use ownership::{MotorChannel, MotorChannels, OwnershipEvent};
+#[cfg(feature = "std")]
+pub use port::{ReadStrategy, SabertoothSerial, WaitPoint, Yield};
+#[cfg(feature = "std")]
+pub use profile::{DeviceProfile, LimitViolation, ProfileRegistry};
+#[cfg(feature = "control")]
+pub use queue::{
+    Command, CommandQueue, Priority, PriorityScheduler, QueueStats, Scheduler,
+    SparseTelemetryScheduler,
+};
+#[cfg(feature = "control")]
+pub use reboot::{RebootEvent, RebootMonitor};
+#[cfg(feature = "control")]
+pub use restart::{AutoRestart, RestartEvent};
+#[cfg(feature = "control")]
+pub use retry::RetryBudget;
+#[cfg(feature = "control")]
+pub use skidsteer::{SkidSteer4, Wheel};
+#[cfg(feature = "std")]
+pub use state::ChannelState;
+#[cfg(feature = "std")]
+pub use stats::{LatencySummary, Stats};
+#[cfg(feature = "std")]
+pub use stop::{stop_all, stop_all_confirmed, ChannelStop, StopReport};
+#[cfg(feature = "control")]
+pub use supervisor::{Supervisor, SupervisorEvent, SupervisorState};
+#[cfg(feature = "std")]
+pub use timeout::{AdaptiveTimeout, DEFAULT_MIN_SAMPLES, DEFAULT_TIMEOUT_FACTOR};
+pub use units::{Percent, Ratio, RatioSetters};
+#[cfg(feature = "control")]
+pub use watchdog::{WatchdogFeeder, WatchdogGuard, DEFAULT_FEED_PERIOD};
 
 #[cfg(feature = "serialport")]
-pub use port::sabertoothport::{SabertoothPort, SabertoothPortShared};
+pub use port::sabertoothport::{SabertoothPort, SabertoothPortShared, SabertoothPortSync};
 
 #[macro_use]
 mod utils;
+#[macro_use]
+mod command_table;
+#[cfg(feature = "std")]
+mod diagnostics;
 
+mod protocol;
+
+#[cfg(feature = "std")]
+mod addressbook;
+#[cfg(feature = "control")]
+mod asymmetry;
+#[cfg(feature = "async")]
+mod asyncport;
+#[cfg(feature = "std")]
+mod bandwidth;
+#[cfg(feature = "std")]
+mod batch;
+#[cfg(feature = "control")]
+mod battery;
+#[cfg(feature = "std")]
+mod bus;
 mod error;
+#[cfg(feature = "control")]
+mod estimator;
+mod failsafe;
+#[cfg(feature = "std")]
+mod filter;
+#[cfg(feature = "std")]
+mod framelog;
+#[cfg(feature = "control")]
+mod fuse;
+#[cfg(feature = "std")]
+mod health;
+#[cfg(feature = "std")]
+mod history;
+#[cfg(feature = "control")]
+mod mailbox;
+#[cfg(feature = "control")]
+mod ownership;
+#[cfg(feature = "std")]
 mod port;
+#[cfg(feature = "std")]
+mod profile;
+#[cfg(feature = "control")]
+mod queue;
+#[cfg(feature = "control")]
+mod reboot;
+#[cfg(feature = "control")]
+mod restart;
+#[cfg(feature = "control")]
+mod retry;
+#[cfg(feature = "control")]
+mod skidsteer;
+#[cfg(feature = "std")]
+mod state;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod stop;
+#[cfg(feature = "control")]
+mod supervisor;
+#[cfg(all(feature = "async", feature = "telemetry"))]
+mod sync;
+#[cfg(feature = "std")]
+mod timeout;
+mod units;
+#[cfg(feature = "control")]
+mod watchdog;
+
+pub use crate::protocol::frame;
+pub use crate::protocol::opcodes;
+#[cfg(feature = "std")]
+pub mod compat;
+#[cfg(feature = "control")]
+pub mod ramp;
 
 /// Interface for the [Sabertooth 2x32].
 ///
 /// [Sabertooth 2x32]: https://www.dimensionengineering.com/products/sabertooth2x32
 pub mod sabertooth2x32;
+
+/// Interface for the [Sabertooth 2x12] and [Sabertooth 2x25].
+///
+/// [Sabertooth 2x12]: https://www.dimensionengineering.com/products/sabertooth2x12
+/// [Sabertooth 2x25]: https://www.dimensionengineering.com/products/sabertooth2x25
+pub mod sabertooth2x12;
+
+/// Interface for the [Sabertooth 2x5].
+///
+/// [Sabertooth 2x5]: https://www.dimensionengineering.com/products/sabertooth2x5
+#[cfg(feature = "std")]
+pub mod sabertooth2x5;
+
+pub mod telemetry;
+#[cfg(feature = "std")]
+pub mod testing;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
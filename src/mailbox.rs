@@ -0,0 +1,320 @@
+use std::time::{Duration, Instant};
+
+#[allow(unused_imports)]
+use log::debug;
+
+use crate::queue::target;
+use crate::sabertooth2x32::{Operation, Sabertooth2x32, Support};
+use crate::units::Ratio;
+use crate::{Error, FailsafeAction, Result};
+
+/// Default time between two transmissions of a
+/// [Mailbox](struct.Mailbox.html).
+pub const DEFAULT_TRANSMIT_PERIOD: Duration = Duration::from_millis(10);
+
+/// Handle wrapper where the setpoints are "latest value wins": the setters
+/// only store their value, and the values are written at a fixed transmit
+/// rate.
+///
+/// A setter replaces the value waiting for the same target, ie. the same
+/// operation on the same channel, so a planner calling `set_speed()` faster
+/// than the link can carry never builds up a backlog: the device receives
+/// the latest setpoint of each target at most one transmit period late. The
+/// waiting setpoints are written by the setters themselves when the period
+/// has elapsed since the last transmission, and by [poll()](#method.poll),
+/// which must be called regularly for the last setpoints to go out when the
+/// setters stop.
+///
+/// The arguments are checked when a setpoint is stored, but the errors of
+/// the device are only returned by the call writing it. The getters, the
+/// startup and the shutdown are sent right away, and the stops discard the
+/// waiting setpoints before being sent right away.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::Mailbox;
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyS0")?;
+/// let mut saber = Mailbox::new(saber).with_period(Duration::from_millis(20));
+///
+/// // In the planner loop, at any rate
+/// saber.set_speed(1, 0.5)?;
+/// saber.set_speed(2, 0.4)?;
+/// saber.poll()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Mailbox<S> {
+    saber: S,
+    period: Duration,
+    pending: Vec<Operation>,
+    last_transmit: Option<Instant>,
+    coalesced: u64,
+}
+
+impl<S: Sabertooth2x32> Mailbox<S> {
+    /// Wrap *saber*, with a transmission every 10 ms at most, for 100 Hz.
+    pub fn new(saber: S) -> Mailbox<S> {
+        Mailbox {
+            saber,
+            period: DEFAULT_TRANSMIT_PERIOD,
+            pending: Vec::new(),
+            last_transmit: None,
+            coalesced: 0,
+        }
+    }
+
+    /// Set the time between two transmissions.
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Number of setpoints waiting for the next transmission.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Number of setpoints replaced by a newer one before being written.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+
+    /// Write the waiting setpoints if the transmit period has elapsed since
+    /// the last transmission, and return the number of setpoints written.
+    pub fn poll(&mut self) -> Result<usize> {
+        self.poll_at(Instant::now())
+    }
+
+    /// Write the waiting setpoints now, whatever the transmit period.
+    ///
+    /// On error the transmission stops, the failed setpoint is discarded and
+    /// the other setpoints are kept for the next one.
+    pub fn flush(&mut self) -> Result<usize> {
+        self.transmit(Instant::now())
+    }
+
+    /// Return a reference to the wrapped handle.
+    pub fn get_ref(&self) -> &S {
+        &self.saber
+    }
+
+    /// Return a mutable reference to the wrapped handle. Writing setpoints
+    /// through it bypasses the mailbox.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.saber
+    }
+
+    /// Return the wrapped handle, discarding the waiting setpoints.
+    pub fn into_inner(self) -> S {
+        self.saber
+    }
+
+    fn poll_at(&mut self, now: Instant) -> Result<usize> {
+        match self.last_transmit {
+            Some(last) if now.saturating_duration_since(last) < self.period => Ok(0),
+            _ => self.transmit(now),
+        }
+    }
+
+    fn transmit(&mut self, now: Instant) -> Result<usize> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        self.last_transmit = Some(now);
+        let mut sent = 0;
+        while !self.pending.is_empty() {
+            let operation = self.pending.remove(0);
+            operation.apply(&mut self.saber)?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Store the setpoint *operation* in place of the waiting one for the
+    /// same target, and transmit if due.
+    fn post(&mut self, operation: Operation, now: Instant) -> Result<()> {
+        if operation.is_motion() && !self.saber.is_armed() {
+            return Err(Error::NotArmed);
+        }
+        let key = target(operation);
+        match self.pending.iter().position(|&op| target(op) == key) {
+            Some(i) => {
+                self.pending[i] = operation;
+                self.coalesced += 1;
+            }
+            None => self.pending.push(operation),
+        }
+        self.poll_at(now).map(|_| ())
+    }
+
+    fn post_channel(&mut self, channel: usize, ratio: f32, operation: Operation) -> Result<()> {
+        match_channel_to!(channel, (), ());
+        Ratio::new(ratio)?;
+        self.post(operation, Instant::now())
+    }
+
+    /// Discard the waiting setpoints of the motors, overridden by a stop.
+    fn discard_motion(&mut self) {
+        let before = self.pending.len();
+        self.pending.retain(|op| {
+            !matches!(
+                op,
+                Operation::SetSpeed(..)
+                    | Operation::SetDrive(_)
+                    | Operation::SetTurn(_)
+                    | Operation::SetPower(..)
+            )
+        });
+        if self.pending.len() < before {
+            debug!("discarded {} setpoints", before - self.pending.len());
+        }
+    }
+}
+
+impl<S: Sabertooth2x32> Sabertooth2x32 for Mailbox<S> {
+    fn startup(&mut self, channel: usize) -> Result<()> {
+        self.saber.startup(channel)
+    }
+
+    fn shutdown(&mut self, channel: usize) -> Result<()> {
+        self.saber.shutdown(channel)
+    }
+
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.post_channel(channel, ratio, Operation::SetSpeed(channel, ratio))
+    }
+
+    fn get_speed(&mut self, channel: usize) -> Result<f32> {
+        self.saber.get_speed(channel)
+    }
+
+    fn stop_motors(&mut self) -> Result<()> {
+        self.discard_motion();
+        self.saber.stop_motors()
+    }
+
+    fn emergency_stop(&mut self, action: FailsafeAction) -> Result<()> {
+        self.discard_motion();
+        self.saber.emergency_stop(action)
+    }
+
+    fn all_stop(&mut self) -> Result<()> {
+        self.discard_motion();
+        self.saber.all_stop()
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        Ratio::new(ratio)?;
+        self.post(Operation::SetDrive(ratio), Instant::now())
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        Ratio::new(ratio)?;
+        self.post(Operation::SetTurn(ratio), Instant::now())
+    }
+
+    fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.post_channel(channel, ratio, Operation::SetPower(channel, ratio))
+    }
+
+    fn get_power(&mut self, channel: usize) -> Result<f32> {
+        self.saber.get_power(channel)
+    }
+
+    fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.post_channel(channel, ratio, Operation::SetRamp(channel, ratio))
+    }
+
+    fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.post_channel(channel, ratio, Operation::SetAux(channel, ratio))
+    }
+
+    fn get_voltage(&mut self, channel: usize) -> Result<f32> {
+        self.saber.get_voltage(channel)
+    }
+
+    fn get_current(&mut self, channel: usize) -> Result<f32> {
+        self.saber.get_current(channel)
+    }
+
+    fn get_temperature(&mut self, channel: usize) -> Result<f32> {
+        self.saber.get_temperature(channel)
+    }
+
+    fn arm(&mut self) {
+        self.saber.arm()
+    }
+
+    fn disarm(&mut self) {
+        self.saber.disarm()
+    }
+
+    fn is_armed(&self) -> bool {
+        self.saber.is_armed()
+    }
+
+    fn shutdown_commanded(&self, channel: usize) -> bool {
+        self.saber.shutdown_commanded(channel)
+    }
+
+    fn supports(&self, operation: Operation) -> Support {
+        self.saber.supports(operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_latest_value_wins() {
+        let mut port = MockPort::new();
+        let mut saber = Mailbox::new(PlainText::from(&port)).with_period(Duration::from_secs(1));
+        let now = Instant::now();
+
+        // The first setpoint goes out right away
+        saber.post(Operation::SetSpeed(1, 0.5), now).unwrap();
+        assert_eq!(port.take_written(), b"M1: 1023\r\n");
+
+        for i in 1..=10 {
+            let ratio = -0.1 * i as f32;
+            saber.post(Operation::SetSpeed(1, ratio), now).unwrap();
+            saber.post(Operation::SetSpeed(2, ratio), now).unwrap();
+        }
+        assert_eq!(2, saber.pending());
+        assert_eq!(18, saber.coalesced());
+        assert_eq!(0, saber.poll_at(now + Duration::from_millis(500)).unwrap());
+        assert!(port.take_written().is_empty());
+
+        assert_eq!(2, saber.poll_at(now + Duration::from_secs(1)).unwrap());
+        assert_eq!(port.take_written(), b"M1: -2047\r\nM2: -2047\r\n");
+    }
+
+    #[test]
+    fn test_stop_and_errors() {
+        let mut port = MockPort::new();
+        let mut saber = Mailbox::new(PlainText::from(&port)).with_period(Duration::from_secs(1));
+        saber.set_aux(1, 0.0).unwrap();
+        saber.set_speed(1, 0.5).unwrap();
+        saber.set_drive(0.5).unwrap();
+        assert_eq!(port.take_written(), b"Q1: 0\r\n");
+
+        saber.stop_motors().unwrap();
+        assert_eq!(0, saber.pending());
+        assert_eq!(port.take_written(), b"M1: 0\r\nM2: 0\r\n");
+
+        assert!(saber.set_speed(3, 0.0).is_err());
+        assert!(saber.set_turn(1.5).is_err());
+        assert_eq!(0, saber.pending());
+
+        let mut saber = Mailbox::new(PlainText::from(&port).with_interlock());
+        assert!(matches!(saber.set_speed(1, 0.5), Err(Error::NotArmed)));
+    }
+}
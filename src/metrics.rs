@@ -0,0 +1,281 @@
+//! Publishing of the traffic statistics and of the telemetry to the
+//! [metrics] facade, so that the exporter installed by the application
+//! (Prometheus, StatsD, ...) picks them up.
+//!
+//! **Requires** the "metrics" feature.
+//!
+//! | Metric                          | Type    | Source                    |
+//! |---------------------------------|---------|---------------------------|
+//! | `saberrs_frames_sent_total`     | counter | [Stats::frames_sent()]    |
+//! | `saberrs_frames_received_total` | counter | [Stats::frames_received()]|
+//! | `saberrs_bytes_sent_total`      | counter | [Stats::bytes_sent()]     |
+//! | `saberrs_bytes_received_total`  | counter | [Stats::bytes_received()] |
+//! | `saberrs_crc_errors_total`      | counter | [Stats::crc_errors()]     |
+//! | `saberrs_resyncs_total`         | counter | [Stats::resyncs()]        |
+//! | `saberrs_speed_ratio`           | gauge   | `get_speed()`             |
+//! | `saberrs_power_ratio`           | gauge   | `get_power()`             |
+//! | `saberrs_voltage_volts`         | gauge   | `get_voltage()`           |
+//! | `saberrs_current_amperes`       | gauge   | `get_current()`           |
+//! | `saberrs_temperature_celsius`   | gauge   | `get_temperature()`       |
+//!
+//! The gauges have a `channel` label. The counters are published with the
+//! absolute values of the [Stats], so they restart from zero when the
+//! statistics are reset, like after a process restart.
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::metrics::MetricsPublisher;
+//! use saberrs::sabertooth2x32::PacketSerial;
+//!
+//! # fn example() -> saberrs::Result<()> {
+//! let mut saber = PacketSerial::new("/dev/ttyS0")?;
+//! let publisher = MetricsPublisher::new().with_label("device", "left");
+//! MetricsPublisher::describe();
+//!
+//! // In the control loop
+//! publisher.poll(&mut saber, 1)?;
+//! publisher.poll(&mut saber, 2)?;
+//! publisher.publish_stats(saber.stats());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [metrics]: https://crates.io/crates/metrics
+//! [Stats]: ../struct.Stats.html
+//! [Stats::frames_sent()]: ../struct.Stats.html#method.frames_sent
+//! [Stats::frames_received()]: ../struct.Stats.html#method.frames_received
+//! [Stats::bytes_sent()]: ../struct.Stats.html#method.bytes_sent
+//! [Stats::bytes_received()]: ../struct.Stats.html#method.bytes_received
+//! [Stats::crc_errors()]: ../struct.Stats.html#method.crc_errors
+//! [Stats::resyncs()]: ../struct.Stats.html#method.resyncs
+
+use ::metrics::{counter, describe_counter, describe_gauge, gauge, Label, Unit};
+
+use crate::error::Result;
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::stats::Stats;
+use crate::telemetry::{Quantity, Record};
+
+const FRAMES_SENT: &str = "saberrs_frames_sent_total";
+const FRAMES_RECEIVED: &str = "saberrs_frames_received_total";
+const BYTES_SENT: &str = "saberrs_bytes_sent_total";
+const BYTES_RECEIVED: &str = "saberrs_bytes_received_total";
+const CRC_ERRORS: &str = "saberrs_crc_errors_total";
+const RESYNCS: &str = "saberrs_resyncs_total";
+
+/// Name of the gauge of *quantity*.
+fn gauge_name(quantity: Quantity) -> &'static str {
+    match quantity {
+        Quantity::Speed => "saberrs_speed_ratio",
+        Quantity::Power => "saberrs_power_ratio",
+        Quantity::Voltage => "saberrs_voltage_volts",
+        Quantity::Current => "saberrs_current_amperes",
+        Quantity::Temperature => "saberrs_temperature_celsius",
+    }
+}
+
+/// Publisher of the metrics of one device. See the
+/// [module documentation](index.html).
+#[derive(Clone, Debug, Default)]
+pub struct MetricsPublisher {
+    labels: Vec<Label>,
+}
+
+impl MetricsPublisher {
+    /// Create a new publisher without labels.
+    pub fn new() -> MetricsPublisher {
+        MetricsPublisher { labels: Vec::new() }
+    }
+
+    /// Add a label to all the metrics, for telling several devices apart.
+    pub fn with_label(mut self, key: &str, value: &str) -> Self {
+        self.labels
+            .push(Label::new(key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Describe the metrics to the installed recorder, with their units.
+    /// This is optional, and only needs to be done once.
+    pub fn describe() {
+        describe_counter!(FRAMES_SENT, Unit::Count, "Frames written to the port");
+        describe_counter!(
+            FRAMES_RECEIVED,
+            Unit::Count,
+            "Reply frames read from the port"
+        );
+        describe_counter!(BYTES_SENT, Unit::Bytes, "Bytes written to the port");
+        describe_counter!(BYTES_RECEIVED, Unit::Bytes, "Bytes read from the port");
+        describe_counter!(
+            CRC_ERRORS,
+            Unit::Count,
+            "Replies with an invalid checksum or CRC"
+        );
+        describe_counter!(
+            RESYNCS,
+            Unit::Count,
+            "Resynchronizations on the start of a reply"
+        );
+        describe_gauge!(gauge_name(Quantity::Speed), "Speed ratio of the motor");
+        describe_gauge!(
+            gauge_name(Quantity::Power),
+            "Power output ratio of the motor"
+        );
+        describe_gauge!(gauge_name(Quantity::Voltage), "Battery voltage in volts");
+        describe_gauge!(gauge_name(Quantity::Current), "Motor current in amperes");
+        describe_gauge!(
+            gauge_name(Quantity::Temperature),
+            "Temperature of the output transistors in degrees Celsius"
+        );
+    }
+
+    /// Publish the traffic statistics of a handle.
+    pub fn publish_stats(&self, stats: &Stats) {
+        let counters = [
+            (FRAMES_SENT, stats.frames_sent()),
+            (FRAMES_RECEIVED, stats.frames_received()),
+            (BYTES_SENT, stats.bytes_sent()),
+            (BYTES_RECEIVED, stats.bytes_received()),
+            (CRC_ERRORS, stats.crc_errors()),
+            (RESYNCS, stats.resyncs()),
+        ];
+        for (name, value) in counters.iter() {
+            counter!(*name, self.labels.clone()).absolute(*value);
+        }
+    }
+
+    /// Publish a telemetry record, for example from a
+    /// [TelemetryLog](../telemetry/struct.TelemetryLog.html).
+    pub fn publish_record(&self, record: &Record) {
+        self.publish(usize::from(record.channel), record.quantity, record.value);
+    }
+
+    /// Read the voltage, the current and the temperature of *channel* of
+    /// *saber*, and publish them.
+    pub fn poll<S>(&self, saber: &mut S, channel: usize) -> Result<()>
+    where
+        S: Sabertooth2x32 + ?Sized,
+    {
+        let voltage = saber.get_voltage(channel)?;
+        self.publish(channel, Quantity::Voltage, voltage);
+        let current = saber.get_current(channel)?;
+        self.publish(channel, Quantity::Current, current);
+        let temperature = saber.get_temperature(channel)?;
+        self.publish(channel, Quantity::Temperature, temperature);
+        Ok(())
+    }
+
+    fn publish(&self, channel: usize, quantity: Quantity, value: f32) {
+        let mut labels = self.labels.clone();
+        labels.push(Label::new("channel", channel.to_string()));
+        gauge!(gauge_name(quantity), labels).set(f64::from(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+    use ::metrics::{
+        with_local_recorder, Counter, CounterFn, Gauge, GaugeFn, Histogram, Key, KeyName, Metadata,
+        Recorder, SharedString,
+    };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    type Values = Arc<Mutex<HashMap<String, f64>>>;
+
+    /// Recorder keeping the last value of every metric, by "name{labels}".
+    #[derive(Default)]
+    struct TestRecorder(Values);
+
+    struct Handle(Values, String);
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            *self.0.lock().unwrap().entry(self.1.clone()).or_default() += value as f64;
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0.lock().unwrap().insert(self.1.clone(), value as f64);
+        }
+    }
+
+    impl GaugeFn for Handle {
+        fn increment(&self, value: f64) {
+            *self.0.lock().unwrap().entry(self.1.clone()).or_default() += value;
+        }
+
+        fn decrement(&self, value: f64) {
+            GaugeFn::increment(self, -value);
+        }
+
+        fn set(&self, value: f64) {
+            self.0.lock().unwrap().insert(self.1.clone(), value);
+        }
+    }
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            Arc::new(Handle(self.0.clone(), name))
+        }
+
+        fn get(&self, name: &str) -> Option<f64> {
+            self.0.lock().unwrap().get(name).copied()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.handle(key))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn test_publish() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        port.queue_reply(b"M2:B240\r\n");
+        port.queue_reply(b"M2:C-15\r\n");
+        port.queue_reply(b"M2:T31\r\n");
+
+        let recorder = TestRecorder::default();
+        let publisher = MetricsPublisher::new().with_label("device", "left");
+        with_local_recorder(&recorder, || {
+            MetricsPublisher::describe();
+            publisher.poll(&mut saber, 2).unwrap();
+            publisher.publish_stats(saber.stats());
+        });
+
+        let gauge = |name| recorder.get(&format!("{}{{device=left,channel=2}}", name));
+        assert_eq!(Some(24.0), gauge("saberrs_voltage_volts"));
+        assert_eq!(Some(-1.5), gauge("saberrs_current_amperes"));
+        assert_eq!(Some(31.0), gauge("saberrs_temperature_celsius"));
+        assert_eq!(
+            Some(3.0),
+            recorder.get("saberrs_frames_sent_total{device=left}")
+        );
+        assert_eq!(
+            Some(0.0),
+            recorder.get("saberrs_crc_errors_total{device=left}")
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::sabertooth2x32::{Operation, Sabertooth2x32};
+use crate::{Error, Result};
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// Event emitted by [MotorChannels](struct.MotorChannels.html) when the
+/// owner of a channel changes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum OwnershipEvent {
+    /// *owner* claimed the free *channel*.
+    Claimed { channel: usize, owner: String },
+
+    /// *owner* released *channel* by dropping its handle.
+    Released { channel: usize, owner: String },
+
+    /// *owner* took *channel* over from *previous*, whose handle is revoked.
+    TakenOver {
+        channel: usize,
+        previous: String,
+        owner: String,
+    },
+}
+
+/// Current owner of a channel, with the token of its handle.
+#[derive(Clone, Debug)]
+struct Claim {
+    owner: String,
+    token: u64,
+}
+
+/// State shared by the registry and the channel handles.
+struct Registry<S> {
+    saber: S,
+    claims: [Option<Claim>; 2],
+    next_token: u64,
+    events: VecDeque<OwnershipEvent>,
+}
+
+impl<S> Registry<S> {
+    fn push_event(&mut self, event: OwnershipEvent) {
+        log_info!("{:?}", event);
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Error for *channel*, owned by a claim other than *token*.
+    fn owned_error(&self, channel: usize, token: u64) -> Option<Error> {
+        match &self.claims[channel - 1] {
+            Some(claim) if claim.token == token => None,
+            Some(claim) => Some(Error::ChannelOwned {
+                channel,
+                owner: claim.owner.clone(),
+            }),
+            None => Some(Error::ChannelOwned {
+                channel,
+                owner: "nobody".to_string(),
+            }),
+        }
+    }
+}
+
+/// Lock *registry*, ignoring the poisoning: a panic while commanding a
+/// channel leaves the claims consistent.
+fn lock<S>(registry: &Mutex<Registry<S>>) -> MutexGuard<'_, Registry<S>> {
+    registry.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Registry handing out the channels of a handle to exclusive owners, so
+/// that two subsystems cannot command the same motor by accident.
+///
+/// A channel is commanded through the [MotorChannel] returned by
+/// [claim()](#method.claim), which fails with `Error::ChannelOwned` while
+/// another owner holds the channel. [take_over()](#method.take_over)
+/// overrides the claim explicitly, for example for a safety supervisor: the
+/// setters of the previous handle then fail with `Error::ChannelOwned`. A
+/// channel is released when its handle is dropped. The changes of owner are
+/// reported as [OwnershipEvent]s.
+///
+/// The registry is cheap to clone, and the clones share the claims, so the
+/// subsystems can each get one, from different threads if the handle is
+/// `Send`. The operations involving both channels, like the drive, the turn
+/// and the stops, are run with [with_handle()](#method.with_handle), which
+/// does not check the claims.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{Error, MotorChannels};
+/// use saberrs::sabertooth2x32::PlainText;
+/// use saberrs::testing::MockPort;
+///
+/// let channels = MotorChannels::new(PlainText::from(MockPort::new()));
+/// let mut lift = channels.claim(1, "lift").unwrap();
+/// assert!(matches!(channels.claim(1, "drive"), Err(Error::ChannelOwned { .. })));
+///
+/// lift.set_speed(0.5).unwrap();
+/// let mut estop = channels.take_over(1, "estop").unwrap();
+/// assert!(lift.set_speed(0.5).is_err());
+/// estop.set_speed(0.0).unwrap();
+/// assert_eq!(2, channels.drain_events().count());
+/// ```
+///
+/// [MotorChannel]: struct.MotorChannel.html
+/// [OwnershipEvent]: enum.OwnershipEvent.html
+pub struct MotorChannels<S> {
+    registry: Arc<Mutex<Registry<S>>>,
+}
+
+impl<S> Clone for MotorChannels<S> {
+    fn clone(&self) -> Self {
+        MotorChannels {
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<S: Sabertooth2x32> MotorChannels<S> {
+    /// Create a registry for the channels of *saber*, all free.
+    pub fn new(saber: S) -> MotorChannels<S> {
+        MotorChannels {
+            registry: Arc::new(Mutex::new(Registry {
+                saber,
+                claims: [None, None],
+                next_token: 0,
+                events: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Claim *channel* for *owner*. Return `Error::ChannelOwned` if it is
+    /// already claimed.
+    pub fn claim(&self, channel: usize, owner: &str) -> Result<MotorChannel<S>> {
+        self.acquire(channel, owner, false)
+    }
+
+    /// Claim *channel* for *owner*, revoking the handle of its current owner
+    /// if any.
+    pub fn take_over(&self, channel: usize, owner: &str) -> Result<MotorChannel<S>> {
+        self.acquire(channel, owner, true)
+    }
+
+    /// Current owner of *channel*, if any.
+    pub fn owner(&self, channel: usize) -> Option<String> {
+        let registry = lock(&self.registry);
+        let claim = registry.claims.get(channel.wrapping_sub(1))?;
+        claim.as_ref().map(|claim| claim.owner.clone())
+    }
+
+    /// Run *f* on the handle, without checking the claims.
+    pub fn with_handle<R, F: FnOnce(&mut S) -> R>(&self, f: F) -> R {
+        f(&mut lock(&self.registry).saber)
+    }
+
+    /// Return the pending ownership events, oldest first.
+    pub fn drain_events(&self) -> impl Iterator<Item = OwnershipEvent> {
+        let events: Vec<_> = lock(&self.registry).events.drain(..).collect();
+        events.into_iter()
+    }
+
+    fn acquire(&self, channel: usize, owner: &str, take_over: bool) -> Result<MotorChannel<S>> {
+        let index = match_channel_to!(channel, 0, 1);
+        let mut registry = lock(&self.registry);
+        let token = registry.next_token;
+        let claim = Claim {
+            owner: owner.to_string(),
+            token,
+        };
+        let event = match registry.claims[index].take() {
+            Some(previous) if !take_over => {
+                registry.claims[index] = Some(previous.clone());
+                return Err(Error::ChannelOwned {
+                    channel,
+                    owner: previous.owner,
+                });
+            }
+            Some(previous) => OwnershipEvent::TakenOver {
+                channel,
+                previous: previous.owner,
+                owner: owner.to_string(),
+            },
+            None => OwnershipEvent::Claimed {
+                channel,
+                owner: owner.to_string(),
+            },
+        };
+        registry.claims[index] = Some(claim);
+        registry.next_token += 1;
+        registry.push_event(event);
+        Ok(MotorChannel {
+            registry: self.registry.clone(),
+            channel,
+            owner: owner.to_string(),
+            token,
+        })
+    }
+}
+
+/// Exclusive handle on a channel, returned by [MotorChannels::claim()]. The
+/// channel is released when it is dropped.
+///
+/// The setters fail with `Error::ChannelOwned` once the channel was taken
+/// over, while the getters keep working.
+///
+/// [MotorChannels::claim()]: struct.MotorChannels.html#method.claim
+pub struct MotorChannel<S> {
+    registry: Arc<Mutex<Registry<S>>>,
+    channel: usize,
+    owner: String,
+    token: u64,
+}
+
+impl<S: Sabertooth2x32> MotorChannel<S> {
+    /// Channel of the handle.
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Owner given when claiming the channel.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Whether the handle still owns its channel.
+    pub fn is_owner(&self) -> bool {
+        lock(&self.registry)
+            .owned_error(self.channel, self.token)
+            .is_none()
+    }
+
+    /// Start the channel up, see [Sabertooth2x32::startup()].
+    ///
+    /// [Sabertooth2x32::startup()]: sabertooth2x32/trait.Sabertooth2x32.html#tymethod.startup
+    pub fn startup(&mut self) -> Result<()> {
+        self.set(Operation::Startup(self.channel))
+    }
+
+    /// Shut the channel down, see [Sabertooth2x32::shutdown()].
+    ///
+    /// [Sabertooth2x32::shutdown()]: sabertooth2x32/trait.Sabertooth2x32.html#tymethod.shutdown
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.set(Operation::Shutdown(self.channel))
+    }
+
+    /// Set the speed of the channel, as a ratio.
+    pub fn set_speed(&mut self, ratio: f32) -> Result<()> {
+        self.set(Operation::SetSpeed(self.channel, ratio))
+    }
+
+    /// Set the power of the channel, as a ratio.
+    pub fn set_power(&mut self, ratio: f32) -> Result<()> {
+        self.set(Operation::SetPower(self.channel, ratio))
+    }
+
+    /// Set the ramping of the channel, as a ratio.
+    pub fn set_ramp(&mut self, ratio: f32) -> Result<()> {
+        self.set(Operation::SetRamp(self.channel, ratio))
+    }
+
+    /// Speed of the channel, as a ratio.
+    pub fn get_speed(&mut self) -> Result<f32> {
+        lock(&self.registry).saber.get_speed(self.channel)
+    }
+
+    /// Output power of the channel, as a ratio.
+    pub fn get_power(&mut self) -> Result<f32> {
+        lock(&self.registry).saber.get_power(self.channel)
+    }
+
+    /// Current of the channel, in amperes.
+    pub fn get_current(&mut self) -> Result<f32> {
+        lock(&self.registry).saber.get_current(self.channel)
+    }
+
+    /// Temperature of the channel, in degrees Celsius.
+    pub fn get_temperature(&mut self) -> Result<f32> {
+        lock(&self.registry).saber.get_temperature(self.channel)
+    }
+
+    fn set(&mut self, operation: Operation) -> Result<()> {
+        let mut registry = lock(&self.registry);
+        if let Some(e) = registry.owned_error(self.channel, self.token) {
+            return Err(e);
+        }
+        operation.apply(&mut registry.saber).map(|_| ())
+    }
+}
+
+impl<S> Drop for MotorChannel<S> {
+    fn drop(&mut self) {
+        let mut registry = lock(&self.registry);
+        let index = self.channel - 1;
+        if matches!(&registry.claims[index], Some(claim) if claim.token == self.token) {
+            registry.claims[index] = None;
+            registry.push_event(OwnershipEvent::Released {
+                channel: self.channel,
+                owner: self.owner.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_motor_channels() {
+        let mut port = MockPort::new();
+        let channels = MotorChannels::new(PlainText::from(&port));
+        assert!(channels.claim(3, "lift").is_err());
+        let mut lift = channels.claim(1, "lift").unwrap();
+        let mut drive = channels.clone().claim(2, "drive").unwrap();
+        assert_eq!(Some("lift".to_string()), channels.owner(1));
+
+        lift.set_speed(0.5).unwrap();
+        drive.set_speed(-0.5).unwrap();
+        assert_eq!(port.take_written(), b"M1: 1023\r\nM2: -1023\r\n");
+
+        let mut estop = channels.take_over(1, "estop").unwrap();
+        assert!(!lift.is_owner());
+        assert!(matches!(
+            lift.set_speed(1.0),
+            Err(Error::ChannelOwned { channel: 1, ref owner }) if owner == "estop"
+        ));
+        estop.set_speed(0.0).unwrap();
+        assert_eq!(port.take_written(), b"M1: 0\r\n");
+
+        drop(lift);
+        assert_eq!(Some("estop".to_string()), channels.owner(1));
+        drop(estop);
+        assert!(channels.claim(1, "lift").is_ok());
+
+        let events: Vec<_> = channels.drain_events().collect();
+        assert_eq!(6, events.len());
+        assert_eq!(
+            OwnershipEvent::TakenOver {
+                channel: 1,
+                previous: "lift".to_string(),
+                owner: "estop".to_string(),
+            },
+            events[2]
+        );
+        assert_eq!(
+            OwnershipEvent::Released {
+                channel: 1,
+                owner: "estop".to_string(),
+            },
+            events[3]
+        );
+    }
+}
@@ -3,6 +3,98 @@ use std::time::Duration;
 
 use crate::error::Result;
 
+/// How the handles wait for the bytes of a reply.
+///
+/// Blocking reads let the OS wake the thread when data arrives, which adds
+/// the scheduling latency to every reply. On a dedicated core, spinning on
+/// the port cuts it, at the cost of keeping the core busy.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::ReadStrategy;
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let strategy = ReadStrategy::Hybrid {
+///     spin: Duration::from_micros(500),
+/// };
+/// let saber = PacketSerial::new("/dev/ttyS0")?.with_read_strategy(strategy);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ReadStrategy {
+    /// Block in the port until data arrives or its timeout elapses.
+    #[default]
+    Blocking,
+
+    /// Poll the port without blocking for up to *spin*, then fail with
+    /// `io::ErrorKind::TimedOut`. The port timeout is not used.
+    BusyPoll { spin: Duration },
+
+    /// Poll the port without blocking for up to *spin*, then block until
+    /// data arrives or the port timeout elapses.
+    Hybrid { spin: Duration },
+}
+
+/// Internal wait point of a handle, passed to a [Yield] hook.
+///
+/// [Yield]: trait.Yield.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitPoint {
+    /// Polling the port for the bytes of a reply.
+    Reply,
+
+    /// Between a failed operation and its retry.
+    Retry,
+}
+
+/// Hook giving back control to a cooperative scheduler while a handle
+/// waits, installed with `with_yield_hook()` on `PacketSerial`, `PlainText`
+/// and `Supervisor`.
+///
+/// Without threads or async runtime, a transaction blocked in the port
+/// stalls the whole loop until its reply arrives or the port timeout
+/// elapses. With a hook, the handles poll the port without blocking, for
+/// up to the port timeout or the spin of the [ReadStrategy], and call the
+/// hook between the polls, where the executor can run its other tasks. The
+/// hook is also called before each retry of a `Supervisor`.
+///
+/// It is implemented for the closures taking a [WaitPoint].
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::WaitPoint;
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn run_other_tasks() {}
+/// # fn example() -> saberrs::Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyS0")?
+///     .with_yield_hook(|_point: WaitPoint| run_other_tasks());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [ReadStrategy]: enum.ReadStrategy.html
+/// [WaitPoint]: enum.WaitPoint.html
+pub trait Yield: Send {
+    /// Called at each iteration of the wait at *point*. It should return
+    /// quickly: the wait is not over until it returns.
+    fn yield_now(&mut self, point: WaitPoint);
+}
+
+impl<F> Yield for F
+where
+    F: FnMut(WaitPoint) + Send,
+{
+    fn yield_now(&mut self, point: WaitPoint) {
+        self(point)
+    }
+}
+
 /// Minimal serial port trait.
 ///
 /// The Sabertooth interfaces will rely on this trait for low level
@@ -45,20 +137,45 @@ pub trait SabertoothSerial: io::Write + io::Read {
 
     /// Clear the tx and rx buffer, remaining bytes will be lost.
     fn clear_all(&self) -> Result<()>;
+
+    /// Clear the rx buffer, remaining bytes will be lost. Unlike
+    /// `clear_all()`, the bytes written but not transmitted yet, like a
+    /// previous command, are kept. The handles call it before a request.
+    ///
+    /// The default implementation calls `clear_all()`, which is correct for
+    /// the ports transmitting on write.
+    fn clear_input(&self) -> Result<()> {
+        self.clear_all()
+    }
+
+    /// Return true if *byte*, received outside of a reply frame, stands for
+    /// a line condition (break or framing error) rather than data.
+    ///
+    /// The `serialport` crate does not report these conditions. With the
+    /// default terminal settings on Unix, the driver delivers each of them as
+    /// a NUL byte, which is what the default implementation detects. Ports
+    /// with other conventions can override it.
+    fn is_line_condition(&self, byte: u8) -> bool {
+        byte == 0
+    }
 }
 
-/// `SabertoothPort` and `SabertoothPortShared` are optional concrete
-/// implementations of the trait `SabertoothSerial`. Thay can be disabled for
-/// cutting the dependency on the `serialport` external crate.
+/// `SabertoothPort`, `SabertoothPortShared` and `SabertoothPortSync` are
+/// optional concrete implementations of the trait `SabertoothSerial`. Thay can
+/// be disabled for cutting the dependency on the `serialport` external crate.
 /// In this case the trait `SabertoothSerial` will need to be implemented
 /// manually by the application.
 #[cfg(feature = "serialport")]
 pub mod sabertoothport {
     use std::cell::RefCell;
     use std::io;
+    #[cfg(unix)]
+    use std::os::unix::io::{AsRawFd, RawFd};
     use std::rc::Rc;
+    use std::sync::{Arc, Mutex, MutexGuard};
     use std::time::Duration;
 
+    use serialport::SerialPortBuilder;
     use serialport::{self, ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
 
     use crate::{Result, SabertoothSerial};
@@ -81,15 +198,26 @@ pub mod sabertoothport {
     /// Default stop bits setting when opening a `SabertoothPort`
     const DEFAULT_STOP_BITS: StopBits = StopBits::One;
 
-    fn open_default_serialport(port: &str) -> Result<Box<dyn SerialPort>> {
-        let ser = serialport::new(port, DEFAULT_BAUDRATE)
+    fn default_serialport_builder(port: &str) -> SerialPortBuilder {
+        serialport::new(port, DEFAULT_BAUDRATE)
             .timeout(DEFAULT_TIMEOUT)
             .data_bits(DEFAULT_DATA_BITS)
             .flow_control(DEFAULT_FLOW_CONTROL)
             .parity(DEFAULT_PARITY)
             .stop_bits(DEFAULT_STOP_BITS)
-            .open()?;
-        Ok(ser)
+    }
+
+    /// Open the port, and return it with its file descriptor.
+    #[cfg(unix)]
+    fn open_default_serialport(port: &str) -> Result<(Box<dyn SerialPort>, RawFd)> {
+        let ser = default_serialport_builder(port).open_native()?;
+        let fd = ser.as_raw_fd();
+        Ok((Box::new(ser), fd))
+    }
+
+    #[cfg(not(unix))]
+    fn open_default_serialport(port: &str) -> Result<(Box<dyn SerialPort>, ())> {
+        Ok((default_serialport_builder(port).open()?, ()))
     }
 
     /// Raw Sabertooth controller.
@@ -100,13 +228,38 @@ pub mod sabertoothport {
     /// **Requires** the "serialport" feature (enabled by default).
     pub struct SabertoothPort {
         dev: Box<dyn SerialPort>,
+        #[cfg(unix)]
+        fd: RawFd,
     }
 
     impl SabertoothPort {
-        /// Create a new `SabertoothPort` with a default configuration
+        /// Create a new `SabertoothPort` with a default configuration.
         pub fn new(port: &str) -> Result<SabertoothPort> {
-            let ser = open_default_serialport(port)?;
-            Ok(SabertoothPort { dev: ser })
+            #[allow(clippy::let_unit_value)]
+            let (ser, _fd) = open_default_serialport(port)?;
+            Ok(SabertoothPort {
+                dev: ser,
+                #[cfg(unix)]
+                fd: _fd,
+            })
+        }
+
+        /// Create a new `SabertoothPort` like [new()](#method.new), with
+        /// *baud_rate* and *timeout* instead of the defaults.
+        pub fn open_with(port: &str, baud_rate: u32, timeout: Duration) -> Result<SabertoothPort> {
+            let mut ser = SabertoothPort::new(port)?;
+            ser.set_baud_rate(baud_rate)?;
+            ser.set_timeout(timeout)?;
+            Ok(ser)
+        }
+    }
+
+    /// The file descriptor can be registered in an event loop for being
+    /// notified when a reply arrives.
+    #[cfg(unix)]
+    impl AsRawFd for SabertoothPort {
+        fn as_raw_fd(&self) -> RawFd {
+            self.fd
         }
     }
 
@@ -130,6 +283,10 @@ pub mod sabertoothport {
         fn clear_all(&self) -> Result<()> {
             Ok(self.dev.clear(ClearBuffer::All)?)
         }
+
+        fn clear_input(&self) -> Result<()> {
+            Ok(self.dev.clear(ClearBuffer::Input)?)
+        }
     }
 
     impl io::Read for SabertoothPort {
@@ -167,6 +324,8 @@ pub mod sabertoothport {
     /// The downside of `SabertoothPortShared`, besides possible performance loss,
     /// is that it is not
     /// [Send](https://doc.rust-lang.org/std/marker/trait.Send.html).
+    /// [SabertoothPortSync](struct.SabertoothPortSync.html) is the variant
+    /// for sharing a port between threads.
     ///
     /// # Example
     ///
@@ -193,18 +352,32 @@ pub mod sabertoothport {
     #[derive(Clone)]
     pub struct SabertoothPortShared {
         dev: Rc<RefCell<Box<dyn SerialPort>>>,
+        #[cfg(unix)]
+        fd: RawFd,
     }
 
     impl SabertoothPortShared {
         /// Create a new `SabertoothPortShared` with a default configuration
         pub fn new(port: &str) -> Result<SabertoothPortShared> {
-            let ser = open_default_serialport(port)?;
+            #[allow(clippy::let_unit_value)]
+            let (ser, _fd) = open_default_serialport(port)?;
             Ok(SabertoothPortShared {
                 dev: Rc::new(RefCell::new(ser)),
+                #[cfg(unix)]
+                fd: _fd,
             })
         }
     }
 
+    /// The file descriptor can be registered in an event loop for being
+    /// notified when a reply arrives.
+    #[cfg(unix)]
+    impl AsRawFd for SabertoothPortShared {
+        fn as_raw_fd(&self) -> RawFd {
+            self.fd
+        }
+    }
+
     impl SabertoothSerial for SabertoothPortShared {
         fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
             Ok(self.dev.borrow_mut().set_timeout(timeout)?)
@@ -225,6 +398,10 @@ pub mod sabertoothport {
         fn clear_all(&self) -> Result<()> {
             Ok(self.dev.borrow_mut().clear(ClearBuffer::All)?)
         }
+
+        fn clear_input(&self) -> Result<()> {
+            Ok(self.dev.borrow_mut().clear(ClearBuffer::Input)?)
+        }
     }
 
     impl io::Read for SabertoothPortShared {
@@ -255,4 +432,120 @@ pub mod sabertoothport {
             )
         }
     }
+
+    /// Clonable variant of [SabertoothPort](struct.SabertoothPort.html)
+    /// which is `Send` and `Sync`, for handles sharing a port from different
+    /// threads.
+    ///
+    /// Each read, write or setting locks the port, so the frames of the
+    /// handles can interleave: a thread sending a command while another one
+    /// waits for a reply may consume it. The transactions of each handle
+    /// must therefore be serialized by the application, for example by
+    /// putting the handles behind a common `Mutex` or by driving them from a
+    /// single thread at a time.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use saberrs::{Result, SabertoothPortSync};
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+    ///
+    /// # fn example() -> Result<()> {
+    /// let dev = SabertoothPortSync::new("/dev/ttyS2")?;
+    /// let mut front = PacketSerial::from(&dev).with_address(128);
+    /// let mut rear = PacketSerial::from(&dev).with_address(129);
+    ///
+    /// thread::spawn(move || front.stop_motors()).join().unwrap()?;
+    /// rear.stop_motors()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// **Requires** the "serialport" feature (enabled by default).
+    #[derive(Clone)]
+    pub struct SabertoothPortSync {
+        dev: Arc<Mutex<Box<dyn SerialPort>>>,
+        #[cfg(unix)]
+        fd: RawFd,
+    }
+
+    impl SabertoothPortSync {
+        /// Create a new `SabertoothPortSync` with a default configuration
+        pub fn new(port: &str) -> Result<SabertoothPortSync> {
+            #[allow(clippy::let_unit_value)]
+            let (ser, _fd) = open_default_serialport(port)?;
+            Ok(SabertoothPortSync {
+                dev: Arc::new(Mutex::new(ser)),
+                #[cfg(unix)]
+                fd: _fd,
+            })
+        }
+
+        fn lock(&self) -> MutexGuard<'_, Box<dyn SerialPort>> {
+            // A thread panicking during an IO does not invalidate the port.
+            self.dev.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    /// The file descriptor can be registered in an event loop for being
+    /// notified when a reply arrives.
+    #[cfg(unix)]
+    impl AsRawFd for SabertoothPortSync {
+        fn as_raw_fd(&self) -> RawFd {
+            self.fd
+        }
+    }
+
+    impl SabertoothSerial for SabertoothPortSync {
+        fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+            Ok(self.lock().set_timeout(timeout)?)
+        }
+
+        fn timeout(&self) -> Duration {
+            self.lock().timeout()
+        }
+
+        fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+            Ok(self.lock().set_baud_rate(baud_rate)?)
+        }
+
+        fn baud_rate(&self) -> Result<u32> {
+            Ok(self.lock().baud_rate()?)
+        }
+
+        fn clear_all(&self) -> Result<()> {
+            Ok(self.lock().clear(ClearBuffer::All)?)
+        }
+
+        fn clear_input(&self) -> Result<()> {
+            Ok(self.lock().clear(ClearBuffer::Input)?)
+        }
+    }
+
+    impl io::Read for SabertoothPortSync {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.lock().read(buf)
+        }
+    }
+
+    impl io::Write for SabertoothPortSync {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.lock().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.lock().flush()
+        }
+    }
+
+    impl std::fmt::Debug for SabertoothPortSync {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "SabertoothPortSync({:?})",
+                self.lock().name().unwrap_or_else(|| String::from("_"))
+            )
+        }
+    }
 }
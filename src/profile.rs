@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "control")]
+use crate::fuse::SoftFuse;
+use crate::protocol::scaling::{Scaling, ScalingProfile};
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::telemetry::Quantity;
+use crate::Result;
+
+/// Time the peak current can be drawn from the continuous current, used for
+/// deriving the I²t limit of a [SoftFuse](struct.SoftFuse.html).
+#[cfg(feature = "control")]
+const PEAK_SECONDS: f32 = 1.0;
+
+/// Range below the temperature limit over which the continuous current is
+/// derated linearly down to zero, in degrees Celsius.
+const DERATING_RANGE: f32 = 20.0;
+
+/// Limit of a [DeviceProfile](struct.DeviceProfile.html) exceeded by a
+/// reading.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LimitViolation {
+    /// The current is above the peak current, in amperes.
+    Overcurrent { current: f32, peak: f32 },
+
+    /// The battery voltage is below the operating range, in volts.
+    Undervoltage { voltage: f32, min: f32 },
+
+    /// The battery voltage is above the operating range, in volts.
+    Overvoltage { voltage: f32, max: f32 },
+
+    /// The temperature is above the limit, in degrees Celsius.
+    Overtemperature { temperature: f32, max: f32 },
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitViolation::Overcurrent { current, peak } => {
+                write!(fmt, "current {:.1} A above {:.1} A", current, peak)
+            }
+            LimitViolation::Undervoltage { voltage, min } => {
+                write!(fmt, "voltage {:.1} V below {:.1} V", voltage, min)
+            }
+            LimitViolation::Overvoltage { voltage, max } => {
+                write!(fmt, "voltage {:.1} V above {:.1} V", voltage, max)
+            }
+            LimitViolation::Overtemperature { temperature, max } => {
+                write!(fmt, "temperature {:.0} °C above {:.0} °C", temperature, max)
+            }
+        }
+    }
+}
+
+/// Ratings and value scalings of a motor driver model.
+///
+/// The built-in profiles hold the ratings published by Dimension Engineering.
+/// Profiles for other models, or derated ones for a given installation, are
+/// built with [new()](#method.new) and the `with_*` methods, without touching
+/// the protocol code. A profile is used:
+///
+/// - as the [Scaling](trait.Scaling.html) of a handle, with its value
+///   scalings;
+/// - as a guard, with [check()](#method.check) on the readings, or with the
+///   [SoftFuse](struct.SoftFuse.html) returned by
+///   [soft_fuse()](#method.soft_fuse);
+/// - by simulated devices, for answering within the ratings.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{DeviceProfile, LimitViolation};
+/// use saberrs::sabertooth2x32::PacketSerial;
+/// use saberrs::telemetry::Quantity;
+///
+/// let profile = DeviceProfile::sabertooth_2x32();
+/// assert_eq!(32.0, profile.max_current());
+/// assert!(matches!(
+///     profile.check(Quantity::Voltage, 40.0),
+///     Some(LimitViolation::Overvoltage { .. })
+/// ));
+///
+/// # fn example(profile: DeviceProfile) -> saberrs::Result<()> {
+/// let mut fuse = profile.soft_fuse();
+/// let saber = PacketSerial::new("/dev/ttyS0")?.with_scaling(profile);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct DeviceProfile {
+    name: String,
+    max_current: f32,
+    peak_current: f32,
+    min_voltage: f32,
+    max_voltage: f32,
+    max_temperature: f32,
+    scaling: ScalingProfile,
+}
+
+impl DeviceProfile {
+    /// Create a profile named *name* without limits and leaving the values
+    /// unchanged.
+    pub fn new(name: &str) -> DeviceProfile {
+        DeviceProfile {
+            name: name.to_string(),
+            max_current: f32::INFINITY,
+            peak_current: f32::INFINITY,
+            min_voltage: 0.0,
+            max_voltage: f32::INFINITY,
+            max_temperature: f32::INFINITY,
+            scaling: ScalingProfile::new(),
+        }
+    }
+
+    /// Sabertooth 2x32: 32 A continuous and 64 A peak per channel, from 6 V
+    /// to 33.6 V, with a conservative temperature limit of 85 °C.
+    pub fn sabertooth_2x32() -> DeviceProfile {
+        DeviceProfile::new("sabertooth-2x32")
+            .with_current(32.0, 64.0)
+            .with_voltage_range(6.0, 33.6)
+            .with_max_temperature(85.0)
+    }
+
+    /// Sabertooth 2x60: 60 A continuous and 120 A peak per channel, from 6 V
+    /// to 30 V, with a conservative temperature limit of 85 °C.
+    pub fn sabertooth_2x60() -> DeviceProfile {
+        DeviceProfile::new("sabertooth-2x60")
+            .with_current(60.0, 120.0)
+            .with_voltage_range(6.0, 30.0)
+            .with_max_temperature(85.0)
+    }
+
+    /// Set the continuous and the peak current per channel, in amperes.
+    pub fn with_current(mut self, max_current: f32, peak_current: f32) -> Self {
+        self.max_current = max_current.abs();
+        self.peak_current = peak_current.abs().max(self.max_current);
+        self
+    }
+
+    /// Set the operating range of the battery voltage, in volts.
+    pub fn with_voltage_range(mut self, min: f32, max: f32) -> Self {
+        self.min_voltage = min;
+        self.max_voltage = max;
+        self
+    }
+
+    /// Set the highest temperature, in degrees Celsius.
+    pub fn with_max_temperature(mut self, max_temperature: f32) -> Self {
+        self.max_temperature = max_temperature;
+        self
+    }
+
+    /// Set the scalings applied to the values of the getters.
+    pub fn with_scaling(mut self, scaling: ScalingProfile) -> Self {
+        self.scaling = scaling;
+        self
+    }
+
+    /// Name of the profile.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Continuous current per channel, in amperes.
+    pub fn max_current(&self) -> f32 {
+        self.max_current
+    }
+
+    /// Peak current per channel, in amperes.
+    pub fn peak_current(&self) -> f32 {
+        self.peak_current
+    }
+
+    /// Operating range of the battery voltage, in volts.
+    pub fn voltage_range(&self) -> (f32, f32) {
+        (self.min_voltage, self.max_voltage)
+    }
+
+    /// Highest temperature, in degrees Celsius.
+    pub fn max_temperature(&self) -> f32 {
+        self.max_temperature
+    }
+
+    /// Scalings applied to the values of the getters.
+    pub fn scaling(&self) -> &ScalingProfile {
+        &self.scaling
+    }
+
+    /// Check the scaled *value* of *quantity* against the ratings, and return
+    /// the limit it exceeds, if any. Speeds and powers are not checked.
+    pub fn check(&self, quantity: Quantity, value: f32) -> Option<LimitViolation> {
+        match quantity {
+            Quantity::Current if value.abs() > self.peak_current => {
+                Some(LimitViolation::Overcurrent {
+                    current: value,
+                    peak: self.peak_current,
+                })
+            }
+            Quantity::Voltage if value < self.min_voltage => Some(LimitViolation::Undervoltage {
+                voltage: value,
+                min: self.min_voltage,
+            }),
+            Quantity::Voltage if value > self.max_voltage => Some(LimitViolation::Overvoltage {
+                voltage: value,
+                max: self.max_voltage,
+            }),
+            Quantity::Temperature if value > self.max_temperature => {
+                Some(LimitViolation::Overtemperature {
+                    temperature: value,
+                    max: self.max_temperature,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Estimate the load of a channel drawing *current* at *temperature*, in
+    /// percent of its continuous current. The continuous current is derated
+    /// linearly over the 20 °C below the temperature limit, so a hot channel
+    /// shows a higher load for the same current, and a channel at the limit
+    /// a load of at least 100 %. The load is above 100 % when the channel
+    /// draws more than its continuous current.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::DeviceProfile;
+    ///
+    /// let profile = DeviceProfile::sabertooth_2x32();
+    /// assert_eq!(50.0, profile.load(16.0, 40.0));
+    /// assert_eq!(100.0, profile.load(16.0, 75.0));
+    /// ```
+    pub fn load(&self, current: f32, temperature: f32) -> f32 {
+        let derating = ((self.max_temperature - temperature) / DERATING_RANGE).clamp(0.0, 1.0);
+        let load = 100.0 * current.abs() / (self.max_current * derating);
+        match derating > 0.0 {
+            true => load,
+            false => load.max(100.0),
+        }
+    }
+
+    /// Read the current and the temperature of *channel* of *saber*, and
+    /// return its [load()](#method.load), in percent.
+    pub fn get_load<S>(&self, saber: &mut S, channel: usize) -> Result<f32>
+    where
+        S: Sabertooth2x32 + ?Sized,
+    {
+        let current = saber.get_current(channel)?;
+        let temperature = saber.get_temperature(channel)?;
+        Ok(self.load(current, temperature))
+    }
+
+    /// Software fuse for the ratings: rated for the continuous current, and
+    /// tripping after about one second at the peak current.
+    ///
+    /// **Requires** the "control" feature (enabled by default).
+    #[cfg(feature = "control")]
+    pub fn soft_fuse(&self) -> SoftFuse {
+        let (max, peak) = (self.max_current, self.peak_current);
+        SoftFuse::new(max, (peak * peak - max * max) * PEAK_SECONDS)
+    }
+}
+
+impl Scaling for DeviceProfile {
+    #[inline]
+    fn scale(&self, quantity: Quantity, value: f32) -> f32 {
+        self.scaling.scale(quantity, value)
+    }
+}
+
+/// Collection of [DeviceProfile]s by name, with the built-in profiles.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{DeviceProfile, ProfileRegistry};
+///
+/// let mut registry = ProfileRegistry::new();
+/// registry.insert(DeviceProfile::new("syren-25").with_current(25.0, 50.0));
+///
+/// assert!(registry.get("sabertooth-2x60").is_some());
+/// assert_eq!(Some(25.0), registry.get("syren-25").map(|p| p.max_current()));
+/// ```
+///
+/// [DeviceProfile]: struct.DeviceProfile.html
+#[derive(Clone, Debug)]
+pub struct ProfileRegistry {
+    profiles: BTreeMap<String, DeviceProfile>,
+}
+
+impl ProfileRegistry {
+    /// Create a registry holding the built-in profiles.
+    pub fn new() -> ProfileRegistry {
+        let mut registry = ProfileRegistry {
+            profiles: BTreeMap::new(),
+        };
+        registry.insert(DeviceProfile::sabertooth_2x32());
+        registry.insert(DeviceProfile::sabertooth_2x60());
+        registry
+    }
+
+    /// Add *profile*, replacing a profile with the same name.
+    pub fn insert(&mut self, profile: DeviceProfile) {
+        self.profiles.insert(profile.name.clone(), profile);
+    }
+
+    /// Return the profile named *name*.
+    pub fn get(&self, name: &str) -> Option<&DeviceProfile> {
+        self.profiles.get(name)
+    }
+
+    /// Iterate over the profiles, in the order of their names.
+    pub fn iter(&self) -> impl Iterator<Item = &DeviceProfile> {
+        self.profiles.values()
+    }
+}
+
+impl Default for ProfileRegistry {
+    fn default() -> Self {
+        ProfileRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::{PlainText, Sabertooth2x32};
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_check() {
+        let profile = DeviceProfile::sabertooth_2x32();
+        assert_eq!(None, profile.check(Quantity::Current, -64.0));
+        assert_eq!(
+            Some(LimitViolation::Overcurrent {
+                current: -70.0,
+                peak: 64.0
+            }),
+            profile.check(Quantity::Current, -70.0)
+        );
+        assert!(profile.check(Quantity::Voltage, 5.0).is_some());
+        assert_eq!(None, profile.check(Quantity::Voltage, 24.0));
+        assert!(profile.check(Quantity::Temperature, 90.0).is_some());
+        assert_eq!(None, profile.check(Quantity::Speed, 2.0));
+        assert_eq!(None, DeviceProfile::new("x").check(Quantity::Current, 1e6));
+    }
+
+    #[cfg(feature = "control")]
+    #[test]
+    fn test_soft_fuse() {
+        let mut fuse = DeviceProfile::new("x").with_current(3.0, 5.0).soft_fuse();
+        assert!(!fuse.update(1, 5.0, std::time::Duration::from_millis(900)));
+        assert!(fuse.update(1, 5.0, std::time::Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn test_load() {
+        let profile = DeviceProfile::sabertooth_2x60();
+        assert_eq!(0.0, profile.load(0.0, 30.0));
+        assert_eq!(150.0, profile.load(-90.0, 30.0));
+        assert_eq!(100.0, profile.load(0.0, 90.0));
+        assert_eq!(f32::INFINITY, profile.load(1.0, 85.0));
+        assert_eq!(0.0, DeviceProfile::new("x").load(100.0, 200.0));
+
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        port.queue_reply(b"M2:C150\r\n");
+        port.queue_reply(b"M2:T75\r\n");
+        assert_eq!(50.0, profile.get_load(&mut saber, 2).unwrap());
+    }
+
+    #[test]
+    fn test_scaling() {
+        let mut port = MockPort::new();
+        let scaling = ScalingProfile::new().with_gain(Quantity::Current, 2.0);
+        let profile = DeviceProfile::sabertooth_2x60().with_scaling(scaling);
+        let mut saber = PlainText::from(&port).with_scaling(profile);
+        port.queue_reply(b"M1:C15\r\n");
+        assert_eq!(3.0, saber.get_current(1).unwrap());
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+
+use crate::error::{Error, Result};
+
+/// Kind of data addressed by the first byte of a [DataSource] or a
+/// [DataTarget].
+///
+/// [DataSource]: struct.DataSource.html
+/// [DataTarget]: struct.DataTarget.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataKind {
+    /// Motor speed, `M`.
+    Motor,
+
+    /// Motor power output, `P`.
+    Power,
+
+    /// Auxiliary output, `Q`.
+    Aux,
+
+    /// Speed ramping, `R`.
+    Ramp,
+
+    /// Signal input S1 or S2, `S`.
+    Signal,
+}
+
+/// Addressing of a kind of data: its token and the second bytes valid when
+/// reading it and when writing it.
+struct KindSpec {
+    kind: DataKind,
+    token: u8,
+    get: &'static [u8],
+    set: &'static [u8],
+}
+
+/// Sources and targets documented for the Sabertooth 2x32. A newly
+/// documented one is added here, and becomes valid for the handles and the
+/// command tables.
+const KINDS: &[KindSpec] = &[
+    KindSpec {
+        kind: DataKind::Motor,
+        token: b'M',
+        get: b"12",
+        set: b"12DT",
+    },
+    KindSpec {
+        kind: DataKind::Power,
+        token: b'P',
+        get: b"12",
+        set: b"12",
+    },
+    KindSpec {
+        kind: DataKind::Aux,
+        token: b'Q',
+        get: b"",
+        set: b"12",
+    },
+    KindSpec {
+        kind: DataKind::Ramp,
+        token: b'R',
+        get: b"",
+        set: b"12",
+    },
+    KindSpec {
+        kind: DataKind::Signal,
+        token: b'S',
+        get: b"12",
+        set: b"",
+    },
+];
+
+impl DataKind {
+    /// First byte of the sources and targets of this kind.
+    pub fn token(self) -> u8 {
+        KINDS[self as usize].token
+    }
+
+    fn from_token(token: u8) -> Option<DataKind> {
+        KINDS.iter().find(|k| k.token == token).map(|k| k.kind)
+    }
+}
+
+/// Whether the source or target *bytes* is documented, for reading it if
+/// *set* is false and for writing it otherwise.
+pub(crate) const fn is_valid(bytes: [u8; 2], set: bool) -> bool {
+    let mut i = 0;
+    while i < KINDS.len() {
+        if KINDS[i].token == bytes[0] {
+            let seconds = if set { KINDS[i].set } else { KINDS[i].get };
+            let mut j = 0;
+            while j < seconds.len() {
+                if seconds[j] == bytes[1] {
+                    return true;
+                }
+                j += 1;
+            }
+            return false;
+        }
+        i += 1;
+    }
+    false
+}
+
+fn check(bytes: [u8; 2], set: bool) -> Result<DataKind> {
+    match DataKind::from_token(bytes[0]) {
+        Some(kind) if is_valid(bytes, set) => Ok(kind),
+        _ => {
+            let what = if set { "target" } else { "source" };
+            let msg = format!("invalid {} {}", what, String::from_utf8_lossy(&bytes));
+            Err(Error::InvalidInput(msg))
+        }
+    }
+}
+
+fn channel_byte(channel: usize) -> u8 {
+    match channel {
+        1 => b'1',
+        2 => b'2',
+        _ => 0,
+    }
+}
+
+/// Source of the value read by a get request, like `M1` or `S2`.
+///
+/// Only the documented combinations of kind and channel can be built, so an
+/// invalid request is rejected before being written.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{DataKind, DataSource};
+///
+/// let source = DataSource::new(DataKind::Signal, 1).unwrap();
+/// assert_eq!(*b"S1", source.bytes());
+///
+/// // The ramping cannot be read
+/// assert!(DataSource::new(DataKind::Ramp, 1).is_err());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DataSource {
+    kind: DataKind,
+    bytes: [u8; 2],
+}
+
+impl DataSource {
+    /// Source of *kind* for *channel*, 1 or 2.
+    pub fn new(kind: DataKind, channel: usize) -> Result<DataSource> {
+        DataSource::from_bytes([kind.token(), channel_byte(channel)])
+    }
+
+    /// Source of the two bytes of a frame.
+    pub fn from_bytes(bytes: [u8; 2]) -> Result<DataSource> {
+        let kind = check(bytes, false)?;
+        Ok(DataSource { kind, bytes })
+    }
+
+    /// Kind of the data read.
+    pub fn kind(&self) -> DataKind {
+        self.kind
+    }
+
+    /// Bytes of the source in the frames.
+    pub fn bytes(&self) -> [u8; 2] {
+        self.bytes
+    }
+}
+
+impl fmt::Display for DataSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            char::from(self.bytes[0]),
+            char::from(self.bytes[1])
+        )
+    }
+}
+
+/// Target of the value written by a set request, like `M1` or `MD`.
+///
+/// Only the documented combinations of kind and channel can be built, so an
+/// invalid request is rejected before being written.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{DataKind, DataTarget};
+///
+/// let target = DataTarget::from_bytes(*b"MD").unwrap();
+/// assert_eq!(DataKind::Motor, target.kind());
+///
+/// // The signal inputs cannot be written
+/// assert!(DataTarget::new(DataKind::Signal, 1).is_err());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DataTarget {
+    kind: DataKind,
+    bytes: [u8; 2],
+}
+
+impl DataTarget {
+    /// Target of *kind* for *channel*, 1 or 2.
+    pub fn new(kind: DataKind, channel: usize) -> Result<DataTarget> {
+        DataTarget::from_bytes([kind.token(), channel_byte(channel)])
+    }
+
+    /// Target of the two bytes of a frame.
+    pub fn from_bytes(bytes: [u8; 2]) -> Result<DataTarget> {
+        let kind = check(bytes, true)?;
+        Ok(DataTarget { kind, bytes })
+    }
+
+    /// Kind of the data written.
+    pub fn kind(&self) -> DataKind {
+        self.kind
+    }
+
+    /// Bytes of the target in the frames.
+    pub fn bytes(&self) -> [u8; 2] {
+        self.bytes
+    }
+}
+
+impl fmt::Display for DataTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            char::from(self.bytes[0]),
+            char::from(self.bytes[1])
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_kinds() {
+        for (i, spec) in KINDS.iter().enumerate() {
+            assert_eq!(i, spec.kind as usize);
+            assert_eq!(Some(spec.kind), DataKind::from_token(spec.token));
+        }
+    }
+
+    #[test]
+    fn test_validity() {
+        assert!(DataSource::from_bytes(*b"M1").is_ok());
+        assert!(DataSource::from_bytes(*b"S2").is_ok());
+        assert!(DataSource::from_bytes(*b"MD").is_err());
+        assert!(DataSource::from_bytes(*b"Q1").is_err());
+        assert!(DataSource::new(DataKind::Motor, 3).is_err());
+
+        assert!(DataTarget::from_bytes(*b"MT").is_ok());
+        assert!(DataTarget::from_bytes(*b"R2").is_ok());
+        assert!(DataTarget::from_bytes(*b"S1").is_err());
+        assert!(DataTarget::from_bytes(*b"X1").is_err());
+        assert_eq!("Q2", DataTarget::new(DataKind::Aux, 2).unwrap().to_string());
+    }
+}
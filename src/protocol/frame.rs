@@ -0,0 +1,290 @@
+//! Frame encoding and decoding, decoupled from the ports.
+//!
+//! The functions below build and check the frames of the "Packet Serial"
+//! protocol of the Sabertooth 2x32 and of the "Packetized Serial" protocol
+//! of the Sabertooth 2x12 and 2x25, in byte slices, without any
+//! [SabertoothSerial] involved. They are meant for shipping the frames over
+//! a custom transport, like a radio link with its own framing, with the
+//! constants of the [opcodes] module.
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::frame::{self, PacketType};
+//! use saberrs::opcodes::sabertooth2x32 as op;
+//!
+//! let mut buf = [0u8; frame::MAX_FRAME_SIZE];
+//! let len = frame::encode_get(PacketType::Checksum, 128, op::GET_BATTERY, *b"M1", &mut buf)?;
+//! assert_eq!(&[128, 41, 16, 57, 77, 49, 126], &buf[..len]);
+//!
+//! // Reply received over the custom transport
+//! let reply = frame::decode_reply(PacketType::Checksum, b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76")?;
+//! assert_eq!(128, reply.address);
+//! assert_eq!(op::GET_BATTERY, reply.command);
+//! assert_eq!(120, reply.value);
+//! assert_eq!(*b"M1", reply.source);
+//! # Ok::<(), saberrs::Error>(())
+//! ```
+//!
+//! [SabertoothSerial]: ../trait.SabertoothSerial.html
+//! [opcodes]: ../opcodes/index.html
+
+use alloc::format;
+use alloc::string::ToString;
+
+use crate::error::{Error, Result};
+use crate::protocol::opcodes::{sabertooth2x12 as op12, sabertooth2x32 as op};
+use crate::protocol::packet::quirks::QuirksProfile;
+use crate::protocol::packet::{
+    crc, match_frame, packet_is_valid, unpack_data_value, PacketFrame, ParseError,
+};
+
+pub use crate::protocol::packet::PacketType;
+
+/// Size of the largest "Packet Serial" frame, in bytes.
+pub const MAX_FRAME_SIZE: usize = crc::PACKET_SET_SIZE;
+
+/// Size of a "Packetized Serial" frame, in bytes.
+pub const PACKETIZED_FRAME_SIZE: usize = 4;
+
+/// Largest magnitude of the data value of a "Packet Serial" frame.
+pub const DATA_VALUE_MAX: i32 = 16383;
+
+/// Reply of a Sabertooth 2x32 to a get frame, decoded by
+/// [decode_reply()](fn.decode_reply.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Reply {
+    /// Address of the device.
+    pub address: u8,
+
+    /// Command value of the get frame answered, like `GET_BATTERY`.
+    pub command: u8,
+
+    /// Signed data value.
+    pub value: i32,
+
+    /// Source of the value, like `*b"M1"`.
+    pub source: [u8; 2],
+}
+
+/// Write to *buf* the set frame of *command*, like `SET_VALUE`, with the
+/// signed *value* for *target*, like `*b"M1"`. Return the size of the frame.
+///
+/// `Error::InvalidInput` is returned for an argument out of range, or when
+/// *buf* is smaller than the frame.
+pub fn encode_set(
+    packet_type: PacketType,
+    address: u8,
+    command: u8,
+    value: i32,
+    target: [u8; 2],
+    buf: &mut [u8],
+) -> Result<usize> {
+    check_header(address, command, target)?;
+    if value.abs() > DATA_VALUE_MAX {
+        return Err(Error::InvalidInput(format!(
+            "value ({}) out of range -{}~{}",
+            value, DATA_VALUE_MAX, DATA_VALUE_MAX
+        )));
+    }
+    let frame = PacketFrame::new_set_frame(packet_type, address, command, value, target)?;
+    copy_frame(frame.as_ref(), buf)
+}
+
+/// Write to *buf* the get frame of *command*, like `GET_BATTERY`, for
+/// *source*, like `*b"M1"`. Return the size of the frame.
+///
+/// `Error::InvalidInput` is returned for an argument out of range, or when
+/// *buf* is smaller than the frame.
+pub fn encode_get(
+    packet_type: PacketType,
+    address: u8,
+    command: u8,
+    source: [u8; 2],
+    buf: &mut [u8],
+) -> Result<usize> {
+    check_header(address, command, source)?;
+    let frame = PacketFrame::new_get_frame(packet_type, address, command, source)?;
+    copy_frame(frame.as_ref(), buf)
+}
+
+/// Decode the reply at the start of *buf*, which may hold more bytes.
+///
+/// `Error::Response` is returned if *buf* does not start with a complete
+/// reply of *packet_type* with a valid checksum or CRC.
+pub fn decode_reply(packet_type: PacketType, buf: &[u8]) -> Result<Reply> {
+    let error = |s: &str| Err(Error::Response(s.to_string()));
+
+    let size = packet_type.frame_sizes().2;
+    let resp = match buf.get(..size) {
+        Some(resp) => resp,
+        None => return error("invalid packet size"),
+    };
+    let address = match packet_type {
+        PacketType::Checksum => resp[0],
+        PacketType::CRC => resp[0].wrapping_sub(op::CRC_ADDRESS_OFFSET),
+    };
+    match packet_is_valid(packet_type, resp, address, &QuirksProfile::default()) {
+        Ok(_) => (),
+        Err(ParseError::PacketSize) => return error("invalid packet size"),
+        Err(ParseError::ChecksumError) => return error("invalid checksum or CRC"),
+        Err(ParseError::AddressError) => return error("invalid address"),
+    }
+    if resp[1] != op::CMD_REPLY {
+        return error("invalid command num");
+    }
+
+    // The device adds one to the command value of the negative values
+    let magnitude = i32::from(unpack_data_value(&resp[4..6]));
+    let (command, value) = match resp[2] & 1 {
+        0 => (resp[2], magnitude),
+        _ => (resp[2] - 1, -magnitude),
+    };
+    Ok(Reply {
+        address,
+        command,
+        value,
+        source: [resp[6], resp[7]],
+    })
+}
+
+/// Size of the valid set, get or reply frame at the start of *buf*, and its
+/// packet type, if any, whatever its address. Useful for splitting a stream
+/// into frames.
+pub fn frame_size(buf: &[u8]) -> Option<(PacketType, usize)> {
+    match_frame(buf)
+}
+
+/// "Packetized Serial" frame of *command* with *data*, for the device at
+/// *address*.
+///
+/// `Error::InvalidInput` is returned if the address is out of 128~135, or
+/// if the command or the data is above 127.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::frame::encode_packetized;
+///
+/// assert_eq!([128, 0, 64, 64], encode_packetized(128, 0, 64).unwrap());
+/// assert!(encode_packetized(128, 0, 128).is_err());
+/// ```
+pub fn encode_packetized(
+    address: u8,
+    command: u8,
+    data: u8,
+) -> Result<[u8; PACKETIZED_FRAME_SIZE]> {
+    if !(op12::MIN_ADDRESS..=op12::MAX_ADDRESS).contains(&address) {
+        return Err(Error::InvalidInput(format!(
+            "address should be between {} and {} (was {})",
+            op12::MIN_ADDRESS,
+            op12::MAX_ADDRESS,
+            address
+        )));
+    }
+    if command > 127 || i32::from(data) > op12::DATA_MAX {
+        return Err(Error::InvalidInput(format!(
+            "command ({}) and data ({}) should be at most 127",
+            command, data
+        )));
+    }
+    let checksum = address.wrapping_add(command).wrapping_add(data) & 0x7f;
+    Ok([address, command, data, checksum])
+}
+
+/// Check the address, the command value and the target or source of a
+/// "Packet Serial" frame.
+fn check_header(address: u8, command: u8, target: [u8; 2]) -> Result<()> {
+    if !(op::MIN_ADDRESS..=op::MAX_ADDRESS).contains(&address) {
+        return Err(Error::InvalidInput(format!(
+            "address should be between {} and {} (was {})",
+            op::MIN_ADDRESS,
+            op::MAX_ADDRESS,
+            address
+        )));
+    }
+    if command > 126 || target.iter().any(|&byte| byte > 127) {
+        return Err(Error::InvalidInput(format!(
+            "command value ({}) or target ({:?}) out of range",
+            command, target
+        )));
+    }
+    Ok(())
+}
+
+fn copy_frame(frame: &[u8], buf: &mut [u8]) -> Result<usize> {
+    match buf.get_mut(..frame.len()) {
+        Some(dest) => {
+            dest.copy_from_slice(frame);
+            Ok(frame.len())
+        }
+        None => Err(Error::InvalidInput(format!(
+            "buffer too small for the frame ({} < {} bytes)",
+            buf.len(),
+            frame.len()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut buf = [0u8; MAX_FRAME_SIZE];
+        let len = encode_set(
+            PacketType::Checksum,
+            128,
+            op::SET_VALUE,
+            -2047,
+            *b"M1",
+            &mut buf,
+        );
+        assert_eq!(9, len.unwrap());
+        assert_eq!(b"\x80\x28\x01\x29\x7f\x0f\x4d\x31\x0c", &buf[..9]);
+        let len = encode_set(
+            PacketType::Checksum,
+            128,
+            op::SET_KEEP_ALIVE,
+            0,
+            *b"M1",
+            &mut buf,
+        );
+        assert_eq!(
+            b"\x80\x28\x10\x38\x00\x00\x4d\x31\x7e",
+            &buf[..len.unwrap()]
+        );
+
+        let len = encode_get(PacketType::CRC, 128, op::GET_BATTERY, *b"M1", &mut buf);
+        assert_eq!(b"\xf0\x29\x10\x2e\x4d\x31\x06\x24", &buf[..len.unwrap()]);
+
+        assert!(encode_get(PacketType::CRC, 128, 0, *b"M1", &mut buf[..7]).is_err());
+        assert!(encode_get(PacketType::CRC, 127, 0, *b"M1", &mut buf).is_err());
+        assert!(encode_set(PacketType::CRC, 128, 0, 16384, *b"M1", &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_decode_reply() {
+        let reply = decode_reply(
+            PacketType::Checksum,
+            b"\x80\x49\x01\x4a\x7f\x07\x4d\x32\x05\x80",
+        )
+        .unwrap();
+        assert_eq!(
+            Reply {
+                address: 128,
+                command: op::GET_VALUE,
+                value: -1023,
+                source: *b"M2"
+            },
+            reply
+        );
+        let mut buf = [0u8; MAX_FRAME_SIZE];
+        let len = encode_get(PacketType::Checksum, 128, op::GET_VALUE, *b"M2", &mut buf);
+        assert!(decode_reply(PacketType::Checksum, &buf[..len.unwrap()]).is_err());
+        assert!(decode_reply(PacketType::CRC, b"\x80\x49\x01\x4a").is_err());
+
+        assert_eq!(Some((PacketType::Checksum, 7)), frame_size(&buf));
+    }
+}
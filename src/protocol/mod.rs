@@ -0,0 +1,18 @@
+//! Protocol layer of the crate: frames, parsing, validation and scaling.
+//!
+//! The modules below perform no IO and keep no time: they build and check
+//! bytes, and convert values. Everything outside of `protocol` belongs to the IO
+//! layer (ports, handles and subsystems), which depends on this layer and
+//! never the other way around. Keeping the layers apart is what allows
+//! reusing the protocol code in the async and device-side implementations.
+//!
+//! The public items are re-exported at their historical paths.
+
+pub(crate) mod addressing;
+pub(crate) mod packet;
+pub(crate) mod quantity;
+pub(crate) mod scaling;
+pub(crate) mod transform;
+
+pub mod frame;
+pub mod opcodes;
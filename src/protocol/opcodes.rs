@@ -0,0 +1,133 @@
+//! Protocol constants of the supported devices.
+//!
+//! The handles build their frames from these constants, so tools decoding or
+//! generating the traffic, like gateways and protocol analyzers, can use them
+//! instead of copying the values from the manuals. They follow the
+//! documentation of Dimension Engineering and are stable: a change of value
+//! is a breaking change of the crate.
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::opcodes::sabertooth2x32 as op;
+//!
+//! // Decode the command of a "Packet Serial" frame
+//! let frame = [128u8, 41, 16, 57, 77, 49, 126];
+//! assert_eq!(op::CMD_GET, frame[1]);
+//! assert_eq!(op::GET_BATTERY, frame[2]);
+//! ```
+
+/// "Packet Serial" and "Plain Text" protocols of the Sabertooth 2x32.
+pub mod sabertooth2x32 {
+    /// Smallest address of a "Packet Serial" device.
+    pub const MIN_ADDRESS: u8 = 128;
+
+    /// Largest address of a "Packet Serial" device.
+    pub const MAX_ADDRESS: u8 = 135;
+
+    /// Offset added to the address byte of the frames protected by a CRC.
+    pub const CRC_ADDRESS_OFFSET: u8 = 112;
+
+    /// Command number of the set frames.
+    pub const CMD_SET: u8 = 40;
+
+    /// Command number of the get frames.
+    pub const CMD_GET: u8 = 41;
+
+    /// Command number of the replies to the get frames.
+    pub const CMD_REPLY: u8 = 73;
+
+    /// Command value of a set frame writing a value, like a speed.
+    pub const SET_VALUE: u8 = 0;
+
+    /// Command value of a set frame resetting the serial timeout.
+    pub const SET_KEEP_ALIVE: u8 = 16;
+
+    /// Command value of a set frame shutting down (data 1) or starting up
+    /// (data 0) an output.
+    pub const SET_SHUTDOWN: u8 = 32;
+
+    /// Command value of a set frame setting the serial timeout.
+    pub const SET_TIMEOUT: u8 = 64;
+
+    /// Command value of a get frame reading a value, like a speed.
+    pub const GET_VALUE: u8 = 0;
+
+    /// Command value of a get frame reading the battery voltage.
+    pub const GET_BATTERY: u8 = 16;
+
+    /// Command value of a get frame reading the current.
+    pub const GET_CURRENT: u8 = 32;
+
+    /// Command value of a get frame reading the temperature.
+    pub const GET_TEMPERATURE: u8 = 64;
+
+    /// Raw value of a full forward command, the opposite being full reverse.
+    pub const RANGE_MAX: i32 = 2047;
+}
+
+/// "Simplified Serial" protocol of the Sabertooth 2x5.
+pub mod sabertooth2x5 {
+    /// Byte stopping both motors.
+    pub const STOP_ALL: u8 = 0;
+
+    /// Byte stopping motor 1.
+    pub const MOTOR1_STOP: u8 = 64;
+
+    /// Byte stopping motor 2.
+    pub const MOTOR2_STOP: u8 = 192;
+
+    /// Largest offset from the stop byte, for full forward or full reverse.
+    pub const RANGE: i32 = 63;
+}
+
+/// "Packetized Serial" protocol of the Sabertooth 2x12 and 2x25.
+pub mod sabertooth2x12 {
+    /// Smallest address of a "Packetized Serial" device.
+    pub const MIN_ADDRESS: u8 = 128;
+
+    /// Largest address of a "Packetized Serial" device.
+    pub const MAX_ADDRESS: u8 = 135;
+
+    /// Command driving motor 1 forward.
+    pub const DRIVE_FORWARD_1: u8 = 0;
+
+    /// Command driving motor 1 backward.
+    pub const DRIVE_BACKWARD_1: u8 = 1;
+
+    /// Command setting the minimum battery voltage.
+    pub const MIN_VOLTAGE: u8 = 2;
+
+    /// Command setting the maximum battery voltage.
+    pub const MAX_VOLTAGE: u8 = 3;
+
+    /// Command driving motor 2 forward.
+    pub const DRIVE_FORWARD_2: u8 = 4;
+
+    /// Command driving motor 2 backward.
+    pub const DRIVE_BACKWARD_2: u8 = 5;
+
+    /// Command driving forward in mixed mode.
+    pub const DRIVE_FORWARD_MIXED: u8 = 8;
+
+    /// Command driving backward in mixed mode.
+    pub const DRIVE_BACKWARD_MIXED: u8 = 9;
+
+    /// Command turning right in mixed mode.
+    pub const TURN_RIGHT_MIXED: u8 = 10;
+
+    /// Command turning left in mixed mode.
+    pub const TURN_LEFT_MIXED: u8 = 11;
+
+    /// Command setting the ramping.
+    pub const RAMPING: u8 = 16;
+
+    /// Command setting the deadband.
+    pub const DEADBAND: u8 = 17;
+
+    /// Largest data value, for full speed.
+    pub const DATA_MAX: i32 = 127;
+
+    /// Largest ramping value.
+    pub const RAMPING_MAX: u8 = 80;
+}
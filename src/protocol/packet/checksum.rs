@@ -15,11 +15,32 @@ pub struct PacketSet([u8; PACKET_SET_SIZE]);
 impl PacketSet {
     pub fn new(
         address: u8,
-        command_value: CommandSet,
+        command_value: u8,
         data_value: i32,
         target: [u8; 2],
     ) -> Result<PacketSet> {
-        let mut command_value = command_value as u8;
+        PacketSet::with_command_num(CMD_NUM_SET, address, command_value, data_value, target)
+    }
+
+    /// Reply of the device, which has the layout of a set frame.
+    #[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+    pub fn reply(
+        address: u8,
+        command_value: u8,
+        data_value: i32,
+        source: [u8; 2],
+    ) -> Result<PacketSet> {
+        PacketSet::with_command_num(CMD_NUM_REPLY, address, command_value, data_value, source)
+    }
+
+    fn with_command_num(
+        command_num: u8,
+        address: u8,
+        command_value: u8,
+        data_value: i32,
+        target: [u8; 2],
+    ) -> Result<PacketSet> {
+        let mut command_value = command_value;
         let mut data_value = data_value;
 
         if data_value < 0 {
@@ -29,8 +50,8 @@ impl PacketSet {
 
         let mut buf = [0u8; PACKET_SET_SIZE];
         buf[0] = address;
-        buf[1] = CMD_NUM_SET;
-        buf[2] = command_value as u8;
+        buf[1] = command_num;
+        buf[2] = command_value;
         buf[3] = checksum(&buf[..3]);
         buf[4..6].copy_from_slice(&pack_data_value(data_value as u16));
         buf[6..8].copy_from_slice(&target[..2]);
@@ -51,11 +72,11 @@ impl AsRef<[u8]> for PacketSet {
 pub struct PacketGet([u8; PACKET_GET_SIZE]);
 
 impl PacketGet {
-    pub fn new(address: u8, command_value: CommandGet, source: [u8; 2]) -> Result<PacketGet> {
+    pub fn new(address: u8, command_value: u8, source: [u8; 2]) -> Result<PacketGet> {
         let mut buf = [0u8; PACKET_GET_SIZE];
         buf[0] = address;
         buf[1] = CMD_NUM_GET;
-        buf[2] = command_value as u8;
+        buf[2] = command_value;
         buf[3] = checksum(&buf[..3]);
         buf[4..6].copy_from_slice(&source[..2]);
         buf[6] = checksum(&buf[4..6]);
@@ -71,10 +92,32 @@ impl AsRef<[u8]> for PacketGet {
     }
 }
 
-pub fn packet_is_valid(resp: &[u8], address: u8) -> std::result::Result<(), ParseError> {
-    if resp.len() != PACKET_REPLY_SIZE {
+/// Size of the set, get or reply frame at the start of *buf*, if its
+/// checksums are valid.
+pub fn frame_size(buf: &[u8]) -> Option<usize> {
+    let size = match buf.get(1)? {
+        &CMD_NUM_GET => PACKET_GET_SIZE,
+        _ => PACKET_SET_SIZE,
+    };
+    let frame = buf.get(..size)?;
+    let data_valid = frame[size - 1] == checksum(&frame[4..size - 1]);
+    if is_framed(frame) && frame[3] == checksum(&frame[..3]) && data_valid {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+pub fn packet_is_valid(
+    resp: &[u8],
+    address: u8,
+    quirks: &QuirksProfile,
+) -> core::result::Result<(), ParseError> {
+    if resp.len() != quirks.frame_size(PacketType::Checksum) {
         Err(ParseError::PacketSize)
-    } else if resp[3] != checksum(&resp[..3]) || resp[8] != checksum(&resp[4..8]) {
+    } else if (quirks.checks_header() && resp[3] != checksum(&resp[..3]))
+        || (!quirks.truncated_replies() && resp[8] != checksum(&resp[4..8]))
+    {
         Err(ParseError::ChecksumError)
     } else if resp[0] != address {
         Err(ParseError::AddressError)
@@ -3,7 +3,7 @@ use super::*;
 pub const PACKET_SET_SIZE: usize = 10;
 pub const PACKET_GET_SIZE: usize = 8;
 pub const PACKET_REPLY_SIZE: usize = 10;
-pub const PACKET_ADDR_OFFSET: u8 = 112;
+pub const PACKET_ADDR_OFFSET: u8 = crate::protocol::opcodes::sabertooth2x32::CRC_ADDRESS_OFFSET;
 
 fn crc7(data: &[u8]) -> u8 {
     let mut crc = 0x7fu8;
@@ -54,11 +54,32 @@ pub struct PacketSet([u8; PACKET_SET_SIZE]);
 impl PacketSet {
     pub fn new(
         address: u8,
-        command_value: CommandSet,
+        command_value: u8,
         data_value: i32,
         target: [u8; 2],
     ) -> Result<PacketSet> {
-        let mut command_value = command_value as u8;
+        PacketSet::with_command_num(CMD_NUM_SET, address, command_value, data_value, target)
+    }
+
+    /// Reply of the device, which has the layout of a set frame.
+    #[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+    pub fn reply(
+        address: u8,
+        command_value: u8,
+        data_value: i32,
+        source: [u8; 2],
+    ) -> Result<PacketSet> {
+        PacketSet::with_command_num(CMD_NUM_REPLY, address, command_value, data_value, source)
+    }
+
+    fn with_command_num(
+        command_num: u8,
+        address: u8,
+        command_value: u8,
+        data_value: i32,
+        target: [u8; 2],
+    ) -> Result<PacketSet> {
+        let mut command_value = command_value;
         let mut data_value = data_value;
 
         if data_value < 0 {
@@ -68,8 +89,8 @@ impl PacketSet {
 
         let mut buf = [0u8; PACKET_SET_SIZE];
         buf[0] = address + PACKET_ADDR_OFFSET;
-        buf[1] = CMD_NUM_SET;
-        buf[2] = command_value as u8;
+        buf[1] = command_num;
+        buf[2] = command_value;
         buf[3] = crc7(&buf[..3]);
         buf[4..6].copy_from_slice(&pack_data_value(data_value as u16));
         buf[6..8].copy_from_slice(&target[..2]);
@@ -92,11 +113,11 @@ impl AsRef<[u8]> for PacketSet {
 pub struct PacketGet([u8; PACKET_GET_SIZE]);
 
 impl PacketGet {
-    pub fn new(address: u8, command_value: CommandGet, source: [u8; 2]) -> Result<PacketGet> {
+    pub fn new(address: u8, command_value: u8, source: [u8; 2]) -> Result<PacketGet> {
         let mut buf = [0u8; PACKET_GET_SIZE];
         buf[0] = address + PACKET_ADDR_OFFSET;
         buf[1] = CMD_NUM_GET;
-        buf[2] = command_value as u8;
+        buf[2] = command_value;
         buf[3] = crc7(&buf[..3]);
         buf[4..6].copy_from_slice(&source[..2]);
         let crcdata = crc14_to_buf(&buf[4..6]);
@@ -114,10 +135,32 @@ impl AsRef<[u8]> for PacketGet {
     }
 }
 
-pub fn packet_is_valid(resp: &[u8], address: u8) -> std::result::Result<(), ParseError> {
-    if resp.len() != PACKET_REPLY_SIZE {
+/// Size of the set, get or reply frame at the start of *buf*, if its CRCs
+/// are valid.
+pub fn frame_size(buf: &[u8]) -> Option<usize> {
+    let size = match buf.get(1)? {
+        &CMD_NUM_GET => PACKET_GET_SIZE,
+        _ => PACKET_SET_SIZE,
+    };
+    let frame = buf.get(..size)?;
+    let data_valid = frame[size - 2..] == crc14_to_buf(&frame[4..size - 2]);
+    if is_framed(frame) && frame[3] == crc7(&frame[..3]) && data_valid {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+pub fn packet_is_valid(
+    resp: &[u8],
+    address: u8,
+    quirks: &QuirksProfile,
+) -> core::result::Result<(), ParseError> {
+    if resp.len() != quirks.frame_size(PacketType::CRC) {
         Err(ParseError::PacketSize)
-    } else if resp[3] != crc7(&resp[..3]) || resp[8..10] != crc14_to_buf(&resp[4..8]) {
+    } else if (quirks.checks_header() && resp[3] != crc7(&resp[..3]))
+        || (!quirks.truncated_replies() && resp[8..10] != crc14_to_buf(&resp[4..8]))
+    {
         Err(ParseError::ChecksumError)
     } else if resp[0] != address + PACKET_ADDR_OFFSET {
         Err(ParseError::AddressError)
@@ -152,7 +195,7 @@ mod tests {
     fn test_crc_packet() {
         assert_eq!(
             &[240, 41, 0, 109, 77, 49, 6, 36],
-            PacketFrame::new_get_frame(PacketType::CRC, 128, CommandGet::Value, [77, 49])
+            PacketFrame::new_get_frame(PacketType::CRC, 128, CommandGet::Value as u8, [77, 49])
                 .unwrap()
                 .as_ref()
         );
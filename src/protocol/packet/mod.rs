@@ -0,0 +1,249 @@
+//! Frames of the "Packet Serial" protocol of the Sabertooth 2x32.
+//!
+//! Building the request frames, validating the replies and the packet type
+//! definitions, with no port involved.
+
+#[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+use alloc::vec::Vec;
+
+use crate::error::Result;
+use crate::protocol::opcodes::sabertooth2x32 as op;
+
+pub(crate) mod checksum;
+pub(crate) mod crc;
+pub(crate) mod quirks;
+pub(crate) mod strictness;
+
+use quirks::QuirksProfile;
+#[cfg(feature = "std")]
+use quirks::MAX_REPLY_PADDING;
+
+pub(crate) const CMD_NUM_SET: u8 = op::CMD_SET;
+pub(crate) const CMD_NUM_GET: u8 = op::CMD_GET;
+pub(crate) const CMD_NUM_REPLY: u8 = op::CMD_REPLY;
+
+/// Size of the largest reply, including the padding.
+#[cfg(feature = "std")]
+pub(crate) const PACKET_MAX_REPLY_SIZE: usize = crc::PACKET_REPLY_SIZE + MAX_REPLY_PADDING;
+
+/// Type of frame protection for
+/// [PacketSerial](../sabertooth2x32/struct.PacketSerial.html).
+///
+/// The packet type is selected at runtime, but it costs a single `match` per
+/// frame: frames are built on the stack and there is no dynamic dispatch nor
+/// allocation involved.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PacketType {
+    /// Manual extract:
+    /// > * good for most applications
+    /// > * easier to implement
+    /// > * faster updates (uses one less byte per command than a CRC)
+    Checksum,
+
+    /// Manual extract:
+    /// > * good for safety-critical applications with noisier wiring
+    /// > * harder to implement
+    /// > * slower updates (uses one more byte per command than a checksum)
+    /// > * provides a Hamming distance of 4
+    CRC,
+}
+
+impl PacketType {
+    #[cfg(feature = "std")]
+    pub(crate) fn other(self) -> PacketType {
+        match self {
+            PacketType::Checksum => PacketType::CRC,
+            PacketType::CRC => PacketType::Checksum,
+        }
+    }
+
+    /// Packet type and address of a frame starting with *byte*.
+    #[cfg(feature = "emulator")]
+    pub(crate) fn from_address_byte(byte: u8) -> (PacketType, u8) {
+        match byte.checked_sub(crc::PACKET_ADDR_OFFSET) {
+            Some(address) if address >= 128 => (PacketType::CRC, address),
+            _ => (PacketType::Checksum, byte),
+        }
+    }
+
+    /// Size of the checksum or CRC of the data of a frame, in bytes.
+    pub(crate) fn data_check_size(self) -> usize {
+        match self {
+            PacketType::Checksum => 1,
+            PacketType::CRC => 2,
+        }
+    }
+
+    /// Sizes of the set frames, get frames and replies, in bytes.
+    pub(crate) fn frame_sizes(self) -> (usize, usize, usize) {
+        match self {
+            PacketType::Checksum => (
+                checksum::PACKET_SET_SIZE,
+                checksum::PACKET_GET_SIZE,
+                checksum::PACKET_REPLY_SIZE,
+            ),
+            PacketType::CRC => (
+                crc::PACKET_SET_SIZE,
+                crc::PACKET_GET_SIZE,
+                crc::PACKET_REPLY_SIZE,
+            ),
+        }
+    }
+}
+
+pub(crate) enum ParseError {
+    PacketSize,
+    ChecksumError,
+    AddressError,
+}
+
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(u8)]
+pub enum CommandSet {
+    Value = op::SET_VALUE,
+    KeepAlive = op::SET_KEEP_ALIVE,
+    Shutdown = op::SET_SHUTDOWN, // can also be used for startup (name follows the doc)
+    Timeout = op::SET_TIMEOUT,
+}
+
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(u8)]
+pub enum CommandGet {
+    Value = op::GET_VALUE,
+    Battery = op::GET_BATTERY,
+    Current = op::GET_CURRENT,
+    Temperature = op::GET_TEMPERATURE,
+}
+
+pub(crate) fn pack_data_value(value: u16) -> [u8; 2] {
+    [(value & 127) as u8, ((value >> 7) & 127) as u8]
+}
+
+pub(crate) fn unpack_data_value(buf: &[u8]) -> u16 {
+    u16::from(buf[0] & 127) + (u16::from(buf[1] & 127) << 7)
+}
+
+/// Check the size, the checksum or CRC and the address of the reply *resp*
+/// with *packet_type*, without its padding.
+pub(crate) fn packet_is_valid(
+    packet_type: PacketType,
+    resp: &[u8],
+    address: u8,
+    quirks: &QuirksProfile,
+) -> core::result::Result<(), ParseError> {
+    match packet_type {
+        PacketType::Checksum => checksum::packet_is_valid(resp, address, quirks),
+        PacketType::CRC => crc::packet_is_valid(resp, address, quirks),
+    }
+}
+
+/// Reply of the device at *address* to the get request of *cmd_value* and
+/// *source*, for emulating a device.
+#[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+pub(crate) fn encode_reply(
+    packet_type: PacketType,
+    address: u8,
+    cmd_value: u8,
+    data_value: i32,
+    source: [u8; 2],
+) -> Result<Vec<u8>> {
+    let reply = match packet_type {
+        PacketType::Checksum => checksum::PacketSet::reply(address, cmd_value, data_value, source)?
+            .as_ref()
+            .to_vec(),
+        PacketType::CRC => crc::PacketSet::reply(address, cmd_value, data_value, source)?
+            .as_ref()
+            .to_vec(),
+    };
+    Ok(reply)
+}
+
+/// Packet type and size of the well-formed frame at the start of *buf*, if
+/// any, whatever its address.
+pub(crate) fn match_frame(buf: &[u8]) -> Option<(PacketType, usize)> {
+    if let Some(size) = checksum::frame_size(buf) {
+        return Some((PacketType::Checksum, size));
+    }
+    crc::frame_size(buf).map(|size| (PacketType::CRC, size))
+}
+
+/// Whether only the first byte of *frame*, its address, has its most
+/// significant bit set.
+fn is_framed(frame: &[u8]) -> bool {
+    frame[0] & 0x80 != 0 && frame[1..].iter().all(|&byte| byte & 0x80 == 0)
+}
+
+/// Position of the first byte of a reply frame in *buf*, or its length if
+/// there is none. Only the address byte has its most significant bit set.
+#[cfg(feature = "std")]
+pub(crate) fn frame_start(buf: &[u8]) -> usize {
+    buf.iter()
+        .position(|&byte| byte & 0x80 != 0)
+        .unwrap_or(buf.len())
+}
+
+/// Request frame of either packet type.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum PacketFrame {
+    ChecksumSet(checksum::PacketSet),
+    ChecksumGet(checksum::PacketGet),
+    CRCSet(crc::PacketSet),
+    CRCGet(crc::PacketGet),
+}
+
+impl PacketFrame {
+    pub(crate) fn new_set_frame(
+        packet_type: PacketType,
+        address: u8,
+        command_value: u8,
+        data_value: i32,
+        target: [u8; 2],
+    ) -> Result<PacketFrame> {
+        let frame = match packet_type {
+            PacketType::Checksum => PacketFrame::ChecksumSet(checksum::PacketSet::new(
+                address,
+                command_value,
+                data_value,
+                target,
+            )?),
+            PacketType::CRC => PacketFrame::CRCSet(crc::PacketSet::new(
+                address,
+                command_value,
+                data_value,
+                target,
+            )?),
+        };
+        Ok(frame)
+    }
+
+    pub(crate) fn new_get_frame(
+        packet_type: PacketType,
+        address: u8,
+        command_value: u8,
+        source: [u8; 2],
+    ) -> Result<PacketFrame> {
+        let frame = match packet_type {
+            PacketType::Checksum => {
+                PacketFrame::ChecksumGet(checksum::PacketGet::new(address, command_value, source)?)
+            }
+            PacketType::CRC => {
+                PacketFrame::CRCGet(crc::PacketGet::new(address, command_value, source)?)
+            }
+        };
+        Ok(frame)
+    }
+}
+
+impl AsRef<[u8]> for PacketFrame {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            PacketFrame::ChecksumSet(ar) => ar.as_ref(),
+            PacketFrame::ChecksumGet(ar) => ar.as_ref(),
+            PacketFrame::CRCSet(ar) => ar.as_ref(),
+            PacketFrame::CRCGet(ar) => ar.as_ref(),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+/// Largest padding of the replies accepted by
+/// [QuirksProfile::with_reply_padding()](struct.QuirksProfile.html#method.with_reply_padding).
+pub const MAX_REPLY_PADDING: usize = 8;
+
+/// Deviations of the reply frames from the specification, for working
+/// around firmware quirks in the field.
+///
+/// The default profile follows the specification. The sizes and the
+/// validation rules of the replies are derived from the profile and the
+/// packet type of the handle, so a quirk applies to both packet types.
+///
+/// # Example
+///
+#[cfg_attr(feature = "std", doc = " ```rust")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use saberrs::sabertooth2x32::{PacketSerial, PacketType, QuirksProfile, Sabertooth2x32};
+/// use saberrs::testing::{FrameTap, MockPort};
+///
+/// // Firmware sending a trailing byte after each reply
+/// let quirks = QuirksProfile::new().with_reply_padding(1);
+/// assert_eq!(10, quirks.reply_size(PacketType::Checksum));
+///
+/// let mut port = MockPort::new();
+/// let mut saber = PacketSerial::from(&port)
+///     .with_packet_type(PacketType::Checksum)
+///     .with_quirks(quirks);
+/// port.queue_reply(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76\x00");
+/// assert_eq!(12.0, saber.get_voltage(1).unwrap());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct QuirksProfile {
+    padding: usize,
+    truncated: bool,
+    unchecked_header: bool,
+}
+
+impl QuirksProfile {
+    /// Create a profile following the specification.
+    pub fn new() -> QuirksProfile {
+        QuirksProfile::default()
+    }
+
+    /// Expect *padding* extra bytes after each reply, which are read and
+    /// ignored. It is capped to [MAX_REPLY_PADDING].
+    ///
+    /// [MAX_REPLY_PADDING]: constant.MAX_REPLY_PADDING.html
+    pub fn with_reply_padding(mut self, padding: usize) -> Self {
+        self.padding = padding.min(MAX_REPLY_PADDING);
+        self
+    }
+
+    /// Expect replies ending after the source, without the checksum or CRC
+    /// of their data, which is therefore not verified.
+    pub fn with_truncated_replies(mut self) -> Self {
+        self.truncated = true;
+        self
+    }
+
+    /// Do not verify the checksum or CRC of the header of the replies.
+    pub fn with_unchecked_header(mut self) -> Self {
+        self.unchecked_header = true;
+        self
+    }
+
+    /// Number of extra bytes expected after each reply.
+    pub fn reply_padding(&self) -> usize {
+        self.padding
+    }
+
+    /// True if the replies have no checksum or CRC for their data.
+    pub fn truncated_replies(&self) -> bool {
+        self.truncated
+    }
+
+    /// True if the checksum or CRC of the header is verified.
+    pub fn checks_header(&self) -> bool {
+        !self.unchecked_header
+    }
+
+    /// Number of bytes read for each reply with *packet_type*, including the
+    /// padding.
+    pub fn reply_size(&self, packet_type: super::PacketType) -> usize {
+        self.frame_size(packet_type) + self.padding
+    }
+
+    /// Size of a reply frame with *packet_type*, without the padding.
+    pub(crate) fn frame_size(&self, packet_type: super::PacketType) -> usize {
+        let (_, _, size) = packet_type.frame_sizes();
+        if self.truncated {
+            size - packet_type.data_check_size()
+        } else {
+            size
+        }
+    }
+}
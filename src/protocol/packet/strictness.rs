@@ -0,0 +1,44 @@
+use core::fmt;
+
+/// Handling of the replies which pass the integrity check but do not match
+/// the request: another address, from a nearby master talking to another
+/// device, or another source.
+///
+/// The default is `Strict`. The right choice depends on the wiring topology.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Strictness {
+    /// Return `Error::Response`.
+    #[default]
+    Strict,
+
+    /// Discard the reply and read the next one, until the read timeout.
+    Skip,
+
+    /// Accept the reply, log a warning and record a [Mismatch] event.
+    ///
+    /// [Mismatch]: enum.Mismatch.html
+    Lenient,
+}
+
+/// Mismatch between a reply and its request, recorded by the `Lenient`
+/// [Strictness](enum.Strictness.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mismatch {
+    /// The reply comes from another address.
+    Address { expected: u8, received: u8 },
+
+    /// The reply is for another source (channel) than the request.
+    Source {
+        expected: [u8; 2],
+        received: [u8; 2],
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Address { .. } => f.write_str("invalid address"),
+            Mismatch::Source { .. } => f.write_str("invalid source"),
+        }
+    }
+}
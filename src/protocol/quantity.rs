@@ -0,0 +1,46 @@
+/// Quantity measured by a getter, and recorded by a
+/// [telemetry::Record](../telemetry/struct.Record.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Quantity {
+    /// Commanded speed ratio, from `get_speed()`.
+    Speed,
+    /// Output power ratio, from `get_power()`.
+    Power,
+    /// Battery voltage in volts.
+    Voltage,
+    /// Motor current in amperes.
+    Current,
+    /// Temperature in degrees Celsius.
+    Temperature,
+}
+
+impl Quantity {
+    /// Name of the quantity, as used in the exports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantity::Speed => "speed",
+            Quantity::Power => "power",
+            Quantity::Voltage => "voltage",
+            Quantity::Current => "current",
+            Quantity::Temperature => "temperature",
+        }
+    }
+
+    #[cfg(feature = "telemetry")]
+    pub(crate) fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    #[cfg(feature = "telemetry")]
+    pub(crate) fn from_byte(b: u8) -> Option<Quantity> {
+        [
+            Quantity::Speed,
+            Quantity::Power,
+            Quantity::Voltage,
+            Quantity::Current,
+            Quantity::Temperature,
+        ]
+        .get(usize::from(b))
+        .cloned()
+    }
+}
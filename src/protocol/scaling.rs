@@ -0,0 +1,160 @@
+use crate::protocol::quantity::Quantity;
+
+/// Number of quantities, ie. of get commands.
+const QUANTITIES: usize = 5;
+
+/// Conversion applied to the values returned by the getters, after the
+/// scaling defined by the protocol.
+///
+/// It allows adjusting the readings of calibrated hardware, or of model
+/// variants and firmware revisions reporting the current with another scale,
+/// without post-processing every call. [ScalingProfile] covers the linear
+/// corrections, and the trait can be implemented for anything else.
+///
+/// [ScalingProfile]: struct.ScalingProfile.html
+pub trait Scaling {
+    /// Return the value of *quantity* read as *value*.
+    fn scale(&self, quantity: Quantity, value: f32) -> f32;
+}
+
+/// Linear correction of each quantity, `gain * value + offset`, followed by
+/// an optional deadzone.
+///
+/// The default profile leaves the values unchanged. The deadzone reads the
+/// corrected values closer to zero than its width as 0.0, for example for
+/// keeping the noise of a stopped motor from flipping the sign of its speed.
+///
+/// # Example
+///
+#[cfg_attr(feature = "serialport", doc = " ```rust")]
+#[cfg_attr(not(feature = "serialport"), doc = " ```ignore")]
+/// use saberrs::{Scaling, ScalingProfile};
+/// use saberrs::sabertooth2x32::PacketSerial;
+/// use saberrs::telemetry::Quantity;
+///
+/// // Current sensor reading 5% high, battery voltage read 0.3 V low
+/// let profile = ScalingProfile::new()
+///     .with_gain(Quantity::Current, 1.0 / 1.05)
+///     .with_offset(Quantity::Voltage, 0.3)
+///     .with_deadzone(Quantity::Speed, 0.01);
+/// assert_eq!(24.3, profile.scale(Quantity::Voltage, 24.0));
+/// assert_eq!(0.0, profile.scale(Quantity::Speed, -0.005));
+///
+/// # fn example(profile: ScalingProfile) -> saberrs::Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyS0")?.with_scaling(profile);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ScalingProfile {
+    gains: [f32; QUANTITIES],
+    offsets: [f32; QUANTITIES],
+    deadzones: [f32; QUANTITIES],
+}
+
+impl ScalingProfile {
+    /// Create a profile leaving the values unchanged.
+    pub fn new() -> ScalingProfile {
+        ScalingProfile {
+            gains: [1.0; QUANTITIES],
+            offsets: [0.0; QUANTITIES],
+            deadzones: [0.0; QUANTITIES],
+        }
+    }
+
+    /// Multiply the values of *quantity* by *gain*.
+    pub fn with_gain(mut self, quantity: Quantity, gain: f32) -> Self {
+        self.gains[quantity as usize] = gain;
+        self
+    }
+
+    /// Add *offset* to the values of *quantity*, after the gain.
+    pub fn with_offset(mut self, quantity: Quantity, offset: f32) -> Self {
+        self.offsets[quantity as usize] = offset;
+        self
+    }
+
+    /// Read the values of *quantity* closer to zero than *width*, after the
+    /// gain and the offset, as 0.0.
+    pub fn with_deadzone(mut self, quantity: Quantity, width: f32) -> Self {
+        self.deadzones[quantity as usize] = width.abs();
+        self
+    }
+
+    /// Gain of *quantity*.
+    pub fn gain(&self, quantity: Quantity) -> f32 {
+        self.gains[quantity as usize]
+    }
+
+    /// Offset of *quantity*.
+    pub fn offset(&self, quantity: Quantity) -> f32 {
+        self.offsets[quantity as usize]
+    }
+
+    /// Width of the deadzone of *quantity*, 0.0 if there is none.
+    pub fn deadzone(&self, quantity: Quantity) -> f32 {
+        self.deadzones[quantity as usize]
+    }
+}
+
+impl Default for ScalingProfile {
+    fn default() -> Self {
+        ScalingProfile::new()
+    }
+}
+
+impl Scaling for ScalingProfile {
+    #[inline]
+    fn scale(&self, quantity: Quantity, value: f32) -> f32 {
+        let value = self.gain(quantity) * value + self.offset(quantity);
+        if value.abs() < self.deadzone(quantity) {
+            0.0
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::{PlainText, Sabertooth2x32};
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_profile() {
+        let profile = ScalingProfile::new().with_gain(Quantity::Current, 2.0);
+        assert_eq!(-3.0, profile.scale(Quantity::Current, -1.5));
+        assert_eq!(-1.5, profile.scale(Quantity::Voltage, -1.5));
+        assert_eq!(ScalingProfile::default(), ScalingProfile::new());
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut port = MockPort::new();
+        let profile = ScalingProfile::new()
+            .with_gain(Quantity::Current, 0.5)
+            .with_offset(Quantity::Temperature, -2.0);
+        let mut saber = PlainText::from(&port).with_scaling(profile);
+        port.queue_reply(b"M2:C-15\r\n");
+        assert_eq!(-0.75, saber.get_current(2).unwrap());
+        port.queue_reply(b"M2:T31\r\n");
+        assert_eq!(29.0, saber.get_temperature(2).unwrap());
+        port.queue_reply(b"M1:B240\r\n");
+        assert_eq!(24.0, saber.get_voltage(1).unwrap());
+    }
+
+    #[test]
+    fn test_deadzone() {
+        let mut port = MockPort::new();
+        let profile = ScalingProfile::new().with_deadzone(Quantity::Speed, -0.01);
+        assert_eq!(0.01, profile.deadzone(Quantity::Speed));
+        assert_eq!(-0.005, profile.scale(Quantity::Current, -0.005));
+        let mut saber = PlainText::from(&port).with_scaling(profile);
+        port.queue_reply(b"M1: -10\r\n");
+        let speed = saber.get_speed(1).unwrap();
+        assert!(speed == 0.0 && speed.is_sign_positive());
+        port.queue_reply(b"M1: -21\r\n");
+        assert_eq!(-21.0 / 2047.0, saber.get_speed(1).unwrap());
+    }
+}
@@ -0,0 +1,103 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+/// Transformation applied to every frame just before it is written to the
+/// port.
+///
+/// It allows using adapters which need the frames wrapped or escaped, like
+/// RS-485 multi-drop adapters flagging the address byte with a 9th bit,
+/// without changing the handles. The frames shown in the logs are the frames
+/// before the transformation, while the traffic statistics count the bytes
+/// actually written.
+///
+/// # Example
+///
+#[cfg_attr(feature = "serialport", doc = " ```rust")]
+#[cfg_attr(not(feature = "serialport"), doc = " ```ignore")]
+/// use std::borrow::Cow;
+/// use saberrs::FrameTransform;
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// /// Adapter framing every packet with STX and ETX bytes.
+/// struct StxEtx;
+///
+/// impl FrameTransform for StxEtx {
+///     fn transform<'a>(&mut self, frame: &'a [u8]) -> Cow<'a, [u8]> {
+///         let mut out = Vec::with_capacity(frame.len() + 2);
+///         out.push(0x02);
+///         out.extend_from_slice(frame);
+///         out.push(0x03);
+///         Cow::Owned(out)
+///     }
+/// }
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_frame_transform(StxEtx);
+/// # Ok(())
+/// # }
+/// ```
+pub trait FrameTransform {
+    /// Return the bytes to write for *frame*.
+    fn transform<'a>(&mut self, frame: &'a [u8]) -> Cow<'a, [u8]>;
+}
+
+/// Default transformation, writing the frames unchanged.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Passthrough;
+
+impl FrameTransform for Passthrough {
+    #[inline]
+    fn transform<'a>(&mut self, frame: &'a [u8]) -> Cow<'a, [u8]> {
+        Cow::Borrowed(frame)
+    }
+}
+
+/// Transformation for adapters which take the 9th bit from an escape byte.
+///
+/// The first byte of every frame, which is the address for the "Packet
+/// Serial" protocol, is preceded by *escape* so that the adapter sends it
+/// with the 9th bit set. Other occurrences of *escape* in the frame are
+/// doubled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EscapedAddress {
+    escape: u8,
+}
+
+impl EscapedAddress {
+    /// Create a new transformation using the *escape* byte.
+    pub fn new(escape: u8) -> EscapedAddress {
+        EscapedAddress { escape }
+    }
+}
+
+impl FrameTransform for EscapedAddress {
+    fn transform<'a>(&mut self, frame: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut out = Vec::with_capacity(frame.len() + 2);
+        if let Some((address, data)) = frame.split_first() {
+            out.push(self.escape);
+            out.push(*address);
+            for &b in data {
+                if b == self.escape {
+                    out.push(self.escape);
+                }
+                out.push(b);
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escaped_address() {
+        let mut t = EscapedAddress::new(0x1b);
+        assert_eq!(
+            &t.transform(b"\x80\x28\x1b\x29")[..],
+            b"\x1b\x80\x28\x1b\x1b\x29"
+        );
+        assert!(t.transform(b"").is_empty());
+    }
+}
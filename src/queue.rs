@@ -0,0 +1,476 @@
+use std::time::{Duration, Instant};
+
+#[allow(unused_imports)]
+use log::debug;
+
+use crate::sabertooth2x32::commands;
+use crate::sabertooth2x32::{Operation, Sabertooth2x32};
+use crate::Result;
+
+/// Priority of a queued command. Commands with a higher priority are sent
+/// first by the default [PriorityScheduler](struct.PriorityScheduler.html).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Priority {
+    /// Telemetry requests, deferred when the link is loaded.
+    Low,
+    /// Setpoints and configuration.
+    Normal,
+    /// Commands which must go out before anything else, like a stop.
+    High,
+}
+
+/// Command waiting in a [CommandQueue](struct.CommandQueue.html).
+#[derive(Clone, PartialEq, Debug)]
+pub struct Command {
+    operation: Operation,
+    priority: Priority,
+    stale_after: Option<Duration>,
+    queued_at: Instant,
+}
+
+impl Command {
+    /// Create a new command. Getters have the `Low` priority, the other
+    /// operations have the `Normal` priority.
+    pub fn new(operation: Operation) -> Command {
+        let priority = if operation.is_get() {
+            Priority::Low
+        } else {
+            Priority::Normal
+        };
+        Command {
+            operation,
+            priority,
+            stale_after: None,
+            queued_at: Instant::now(),
+        }
+    }
+
+    /// Set the priority of the command.
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Drop the command instead of sending it if it is still queued after
+    /// *stale_after*. Useful for motion setpoints, which are worse than
+    /// useless when they arrive late.
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = Some(stale_after);
+        self
+    }
+
+    /// Operation sent by the command.
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// Priority of the command.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Time at which the command was created.
+    pub fn queued_at(&self) -> Instant {
+        self.queued_at
+    }
+
+    /// Whether the command is too old for being sent at *now*.
+    pub fn is_stale(&self, now: Instant) -> bool {
+        match self.stale_after {
+            Some(stale_after) => now.saturating_duration_since(self.queued_at) > stale_after,
+            None => false,
+        }
+    }
+}
+
+/// Policy choosing which pending command is sent next.
+pub trait Scheduler {
+    /// Return the index in *pending* of the next command to send. *pending*
+    /// is in queuing order and contains no stale command.
+    fn select(&mut self, pending: &[Command], now: Instant) -> Option<usize>;
+}
+
+/// Default scheduler: highest priority first, then oldest first.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PriorityScheduler;
+
+impl Scheduler for PriorityScheduler {
+    fn select(&mut self, pending: &[Command], _now: Instant) -> Option<usize> {
+        // max_by_key() returns the last maximum, so reverse for the oldest
+        pending
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, cmd)| cmd.priority)
+            .map(|(i, _)| i)
+    }
+}
+
+/// Scheduler for low-bandwidth links: like [PriorityScheduler], but the
+/// getters are only sent one at a time, at least *interval* apart. The other
+/// commands are never delayed by the telemetry.
+///
+/// [PriorityScheduler]: struct.PriorityScheduler.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SparseTelemetryScheduler {
+    interval: Duration,
+    last_get: Option<Instant>,
+}
+
+impl SparseTelemetryScheduler {
+    /// Create a new scheduler sending a getter every *interval* at most.
+    pub fn new(interval: Duration) -> SparseTelemetryScheduler {
+        SparseTelemetryScheduler {
+            interval,
+            last_get: None,
+        }
+    }
+}
+
+impl Scheduler for SparseTelemetryScheduler {
+    fn select(&mut self, pending: &[Command], now: Instant) -> Option<usize> {
+        let get_allowed = match self.last_get {
+            Some(last) => now.saturating_duration_since(last) >= self.interval,
+            None => true,
+        };
+        let i = pending
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, cmd)| get_allowed || !cmd.operation.is_get())
+            .max_by_key(|(_, cmd)| cmd.priority)
+            .map(|(i, _)| i)?;
+        if pending[i].operation.is_get() {
+            self.last_get = Some(now);
+        }
+        Some(i)
+    }
+}
+
+/// Metrics of a [CommandQueue](struct.CommandQueue.html).
+#[derive(Clone, Debug, Default)]
+pub struct QueueStats {
+    depth: usize,
+    max_depth: usize,
+    sent: u64,
+    dropped: u64,
+    deferred: u64,
+    coalesced: u64,
+}
+
+impl QueueStats {
+    /// Number of commands currently waiting.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Highest number of commands waiting at the same time.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Number of commands sent to the device.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Number of commands dropped because they were stale.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Number of times a command was left in the queue at the end of a flush
+    /// because the frame budget was exhausted.
+    pub fn deferred(&self) -> u64 {
+        self.deferred
+    }
+
+    /// Number of commands replaced by a newer command for the same target
+    /// before being sent, see
+    /// [CommandQueue::with_coalescing()](struct.CommandQueue.html#method.with_coalescing).
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+}
+
+/// Queue of commands sent to a device by batches.
+///
+/// Commands are sent by [flush()](#method.flush), in the order given by the
+/// scheduler. Stale commands are dropped rather than sent late, and when a
+/// frame budget is set the remaining commands, usually the `Low` priority
+/// telemetry requests, are kept for the next flush.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::{Command, CommandQueue};
+/// use saberrs::sabertooth2x32::{Operation, PacketSerial};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// let mut queue = CommandQueue::new().with_frame_budget(4);
+///
+/// // In the control loop
+/// let setpoint = Duration::from_millis(50);
+/// queue.push(Command::new(Operation::SetDrive(0.5)).with_stale_after(setpoint));
+/// queue.push(Command::new(Operation::SetTurn(0.0)).with_stale_after(setpoint));
+/// queue.push(Command::new(Operation::GetVoltage(1)));
+/// for (operation, value) in queue.flush(&mut saber)? {
+///     println!("{:?} = {}", operation, value);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CommandQueue<S: Scheduler = PriorityScheduler> {
+    pending: Vec<Command>,
+    scheduler: S,
+    frame_budget: Option<usize>,
+    coalescing: bool,
+    stats: QueueStats,
+}
+
+impl CommandQueue<PriorityScheduler> {
+    /// Create a new empty queue with the default scheduler and no frame budget.
+    pub fn new() -> CommandQueue<PriorityScheduler> {
+        CommandQueue {
+            pending: Vec::new(),
+            scheduler: PriorityScheduler,
+            frame_budget: None,
+            coalescing: false,
+            stats: QueueStats::default(),
+        }
+    }
+}
+
+impl Default for CommandQueue<PriorityScheduler> {
+    fn default() -> Self {
+        CommandQueue::new()
+    }
+}
+
+impl<S: Scheduler> CommandQueue<S> {
+    /// Use *scheduler* for ordering the commands.
+    pub fn with_scheduler<T: Scheduler>(self, scheduler: T) -> CommandQueue<T> {
+        CommandQueue {
+            pending: self.pending,
+            scheduler,
+            frame_budget: self.frame_budget,
+            coalescing: self.coalescing,
+            stats: self.stats,
+        }
+    }
+
+    /// Send at most *frame_budget* commands per flush.
+    pub fn with_frame_budget(mut self, frame_budget: usize) -> Self {
+        self.frame_budget = Some(frame_budget);
+        self
+    }
+
+    /// Replace a waiting command by a newer one for the same target, ie.
+    /// the same operation on the same channel, instead of queuing both: only
+    /// the latest setpoint is sent, and a getter already waiting is not
+    /// requested twice. The newer command takes the place of the older one.
+    pub fn with_coalescing(mut self) -> Self {
+        self.coalescing = true;
+        self
+    }
+
+    /// Add a command to the queue.
+    pub fn push(&mut self, command: Command) {
+        if self.coalescing {
+            let key = target(command.operation);
+            if let Some(i) = self.pending.iter().position(|c| target(c.operation) == key) {
+                self.pending[i] = command;
+                self.stats.coalesced += 1;
+                return;
+            }
+        }
+        self.pending.push(command);
+        self.update_depth();
+    }
+
+    /// Number of commands waiting.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Return true if no command is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Remove all the waiting commands.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.update_depth();
+    }
+
+    /// Metrics of the queue.
+    pub fn stats(&self) -> &QueueStats {
+        &self.stats
+    }
+
+    /// Reset the metrics of the queue, except the current depth.
+    pub fn reset_stats(&mut self) {
+        self.stats = QueueStats::default();
+        self.update_depth();
+    }
+
+    /// Send the waiting commands to *saber*, and return the values read by
+    /// the getters.
+    ///
+    /// On error the flush stops, the failed command is discarded and the
+    /// other commands are kept in the queue.
+    pub fn flush<T>(&mut self, saber: &mut T) -> Result<Vec<(Operation, f32)>>
+    where
+        T: Sabertooth2x32 + ?Sized,
+    {
+        self.flush_at(saber, Instant::now())
+    }
+
+    fn flush_at<T>(&mut self, saber: &mut T, now: Instant) -> Result<Vec<(Operation, f32)>>
+    where
+        T: Sabertooth2x32 + ?Sized,
+    {
+        let budget = self.frame_budget.unwrap_or(usize::MAX);
+        let mut values = Vec::new();
+        let mut sent = 0;
+
+        let before = self.pending.len();
+        self.pending.retain(|cmd| !cmd.is_stale(now));
+        let dropped = before - self.pending.len();
+        if dropped > 0 {
+            debug!("dropped {} stale commands", dropped);
+            self.stats.dropped += dropped as u64;
+        }
+
+        while sent < budget {
+            let i = match self.scheduler.select(&self.pending, now) {
+                Some(i) => i,
+                None => break,
+            };
+            let command = self.pending.remove(i);
+            sent += 1;
+            self.stats.sent += 1;
+            let res = command.operation.apply(saber);
+            self.update_depth();
+            if let Some(value) = res? {
+                values.push((command.operation, value));
+            }
+        }
+
+        self.stats.deferred += self.pending.len() as u64;
+        self.update_depth();
+        Ok(values)
+    }
+
+    fn update_depth(&mut self) {
+        self.stats.depth = self.pending.len();
+        self.stats.max_depth = self.stats.max_depth.max(self.stats.depth);
+    }
+}
+
+/// Command and channel addressed by *operation*.
+pub(crate) fn target(operation: Operation) -> (commands::Command, Option<usize>) {
+    let (command, channel, _) = commands::Command::from_operation(operation);
+    (command, channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_priority_and_budget() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        let mut queue = CommandQueue::new().with_frame_budget(2);
+
+        queue.push(Command::new(Operation::GetVoltage(1)));
+        queue.push(Command::new(Operation::SetSpeed(1, 0.0)));
+        queue.push(Command::new(Operation::SetSpeed(2, 0.0)).with_priority(Priority::High));
+        assert_eq!(queue.stats().depth(), 3);
+
+        assert!(queue.flush(&mut saber).unwrap().is_empty());
+        assert_eq!(port.take_written(), b"M2: 0\r\nM1: 0\r\n");
+        assert_eq!(queue.stats().deferred(), 1);
+
+        port.queue_reply(b"M1: B120\r\n");
+        let values = queue.flush(&mut saber).unwrap();
+        assert_eq!(values, vec![(Operation::GetVoltage(1), 12.0)]);
+        assert_eq!(queue.stats().sent(), 3);
+        assert_eq!(queue.stats().depth(), 0);
+        assert_eq!(queue.stats().max_depth(), 3);
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        let mut queue = CommandQueue::new().with_coalescing();
+
+        queue.push(Command::new(Operation::SetSpeed(1, 0.5)));
+        queue.push(Command::new(Operation::SetSpeed(2, 0.5)));
+        queue.push(Command::new(Operation::GetVoltage(1)));
+        queue.push(Command::new(Operation::SetSpeed(1, -1.0)));
+        queue.push(Command::new(Operation::GetVoltage(1)));
+        assert_eq!(3, queue.len());
+        assert_eq!(2, queue.stats().coalesced());
+
+        port.queue_reply(b"M1: 0\r\n");
+        port.queue_reply(b"M1: 0\r\n");
+        port.queue_reply(b"M1:B240\r\n");
+        queue.flush(&mut saber).unwrap();
+        assert_eq!(
+            port.take_written(),
+            b"M1: -2047\r\nM2: 1023\r\nM1: getb\r\n"
+        );
+    }
+
+    #[test]
+    fn test_sparse_telemetry() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        let interval = Duration::from_secs(1);
+        let mut queue = CommandQueue::new()
+            .with_scheduler(SparseTelemetryScheduler::new(interval))
+            .with_frame_budget(3);
+
+        queue.push(Command::new(Operation::GetVoltage(1)));
+        queue.push(Command::new(Operation::GetVoltage(2)));
+        queue.push(Command::new(Operation::SetSpeed(1, 0.0)));
+        port.queue_reply(b"");
+        port.queue_reply(b"M1:B240\r\n");
+        let now = Instant::now();
+        let values = queue.flush_at(&mut saber, now).unwrap();
+        assert_eq!(values, vec![(Operation::GetVoltage(1), 24.0)]);
+        assert_eq!(port.take_written(), b"M1: 0\r\nM1: getb\r\n");
+
+        // Too early for the next getter
+        queue.flush_at(&mut saber, now + interval / 2).unwrap();
+        assert!(port.take_written().is_empty());
+        port.queue_reply(b"M2:B240\r\n");
+        queue.flush_at(&mut saber, now + interval).unwrap();
+        assert_eq!(port.take_written(), b"M2: getb\r\n");
+    }
+
+    #[test]
+    fn test_stale() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        let mut queue = CommandQueue::new();
+
+        let stale_after = Duration::from_millis(50);
+        queue.push(Command::new(Operation::SetDrive(0.5)).with_stale_after(stale_after));
+        queue.push(Command::new(Operation::SetAux(1, 0.0)));
+
+        let later = Instant::now() + Duration::from_secs(1);
+        queue.flush_at(&mut saber, later).unwrap();
+        assert_eq!(port.take_written(), b"Q1: 0\r\n");
+        assert_eq!(queue.stats().dropped(), 1);
+    }
+}
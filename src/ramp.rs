@@ -0,0 +1,104 @@
+//! Ramp presets and conversion tables.
+//!
+//! The Sabertooth 2x12, 2x25 and 2x60 take the ramping as a raw value of the
+//! "Packetized Serial" protocol (command 16), whose meaning is documented:
+//!
+//! | Value    | Ramp type    | Time from full reverse to full forward |
+//! |----------|--------------|----------------------------------------|
+//! | 0        | default      | set with the DIP switches / DEScribe   |
+//! | 1 to 10  | fast         | 256 / *value* milliseconds             |
+//! | 11 to 20 | slow         | 256 / (15.25 × (*value* − 10)) seconds |
+//! | 21 to 80 | intermediate | 256 / (15.25 × (*value* − 10)) seconds |
+//!
+//! [ramp_time()] applies this table and [table()] lists it, for displaying
+//! what a raw value means.
+//!
+//! The Sabertooth 2x32 takes the ramping as a ratio (see
+//! [Sabertooth2x32::set_ramp()]), and the duration it stands for is not
+//! published. The presets therefore only give raw values for the packetized
+//! protocol.
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::ramp::{ramp_time, RampPreset};
+//!
+//! let value = RampPreset::Gentle.packetized_value();
+//! println!("{} -> {:?}", value, ramp_time(value));
+//! ```
+//!
+//! [ramp_time()]: fn.ramp_time.html
+//! [table()]: fn.table.html
+//! [Sabertooth2x32::set_ramp()]: ../sabertooth2x32/trait.Sabertooth2x32.html#tymethod.set_ramp
+
+use std::time::Duration;
+
+/// Highest valid raw ramp value.
+pub const MAX_RAMP_VALUE: u8 = 80;
+
+/// Named ramp settings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RampPreset {
+    /// About 4.2 s from full reverse to full forward.
+    Gentle,
+
+    /// About 0.84 s from full reverse to full forward.
+    Standard,
+
+    /// About 64 ms from full reverse to full forward.
+    Aggressive,
+}
+
+impl RampPreset {
+    /// Raw value of the preset for the "Packetized Serial" protocol.
+    pub fn packetized_value(self) -> u8 {
+        match self {
+            RampPreset::Gentle => 14,
+            RampPreset::Standard => 30,
+            RampPreset::Aggressive => 4,
+        }
+    }
+
+    /// Time from full reverse to full forward of the preset.
+    pub fn ramp_time(self) -> Duration {
+        // Presets only use valid values
+        ramp_time(self.packetized_value()).unwrap()
+    }
+}
+
+/// Time from full reverse to full forward for the raw ramp *value*.
+///
+/// Return `None` for 0, which selects the ramp configured on the device, and
+/// for values above [MAX_RAMP_VALUE](constant.MAX_RAMP_VALUE.html).
+pub fn ramp_time(value: u8) -> Option<Duration> {
+    match value {
+        1..=10 => Some(Duration::from_micros(256_000 / u64::from(value))),
+        11..=MAX_RAMP_VALUE => {
+            let secs = 256.0 / (15.25 * f64::from(value - 10));
+            Some(Duration::from_micros((secs * 1e6).round() as u64))
+        }
+        _ => None,
+    }
+}
+
+/// List all the raw ramp values with their time from full reverse to full
+/// forward.
+pub fn table() -> impl Iterator<Item = (u8, Duration)> {
+    (1..=MAX_RAMP_VALUE).filter_map(|value| ramp_time(value).map(|t| (value, t)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramp_time() {
+        assert_eq!(None, ramp_time(0));
+        assert_eq!(Some(Duration::from_millis(256)), ramp_time(1));
+        assert_eq!(Some(Duration::from_micros(25_600)), ramp_time(10));
+        assert_eq!(Some(Duration::from_micros(16_786_885)), ramp_time(11));
+        assert_eq!(Some(Duration::from_micros(239_813)), ramp_time(80));
+        assert_eq!(None, ramp_time(81));
+        assert_eq!(80, table().count());
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::queue::target;
+use crate::sabertooth2x32::{Operation, Sabertooth2x32};
+use crate::Result;
+
+/// Default time the device must stay silent for its return to count as a
+/// reboot.
+pub const DEFAULT_MIN_OUTAGE: Duration = Duration::from_millis(500);
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// Event emitted by [RebootMonitor](struct.RebootMonitor.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RebootEvent {
+    /// The device answers again after staying silent for *outage*: it was
+    /// likely power-cycled, and lost its volatile settings.
+    Detected { outage: Duration },
+
+    /// The cached settings were re-applied.
+    Reapplied { count: usize },
+
+    /// Re-applying the cached settings failed, it is retried at the next
+    /// poll.
+    ReapplyFailed,
+}
+
+/// Monitor detecting a device power-cycled in the middle of a session, and
+/// re-applying its volatile settings.
+///
+/// The settings written with the setters, like the ramping, are lost when
+/// the device reboots, while the handle believes they are applied. The
+/// device does not report its reboots, so the monitor polls it with a
+/// voltage request: a device answering again after staying silent for the
+/// minimum outage is considered rebooted. A reboot known by other means,
+/// like a startup banner, can be reported with
+/// [notify_reboot()](#method.notify_reboot).
+///
+/// The monitor caches the volatile configuration given with
+/// [with_setting()](#method.with_setting), and with
+/// [with_reapply()](#method.with_reapply) re-applies it automatically after a
+/// reboot. A spurious detection, for example after a loose connector, only
+/// writes the same settings again.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{RebootEvent, RebootMonitor};
+/// use saberrs::sabertooth2x32::{Operation, PacketSerial};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// let mut monitor = RebootMonitor::new()
+///     .with_setting(Operation::SetRamp(1, 0.2))
+///     .with_setting(Operation::SetRamp(2, 0.2))
+///     .with_reapply();
+/// monitor.apply(&mut saber)?;
+///
+/// // In the control loop
+/// monitor.poll(&mut saber)?;
+/// for event in monitor.drain_events() {
+///     println!("{:?}", event);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RebootMonitor {
+    settings: Vec<Operation>,
+    reapply: bool,
+    min_outage: Duration,
+    silent_since: Option<Instant>,
+    pending: bool,
+    events: VecDeque<RebootEvent>,
+}
+
+impl RebootMonitor {
+    /// Create a new monitor with the default minimum outage, caching no
+    /// setting.
+    pub fn new() -> RebootMonitor {
+        RebootMonitor {
+            settings: Vec::new(),
+            reapply: false,
+            min_outage: DEFAULT_MIN_OUTAGE,
+            silent_since: None,
+            pending: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Set how long the device must stay silent for its return to count as
+    /// a reboot.
+    pub fn with_min_outage(mut self, min_outage: Duration) -> Self {
+        self.min_outage = min_outage;
+        self
+    }
+
+    /// Cache the volatile setting *operation*, see
+    /// [set_setting()](#method.set_setting).
+    pub fn with_setting(mut self, operation: Operation) -> Self {
+        self.set_setting(operation);
+        self
+    }
+
+    /// Re-apply the cached settings automatically after a reboot.
+    pub fn with_reapply(mut self) -> Self {
+        self.reapply = true;
+        self
+    }
+
+    /// Cache the volatile setting *operation*, replacing the one for the same
+    /// operation and channel. The getters are ignored.
+    pub fn set_setting(&mut self, operation: Operation) {
+        if operation.is_get() {
+            return;
+        }
+        let key = target(operation);
+        match self.settings.iter().position(|&op| target(op) == key) {
+            Some(i) => self.settings[i] = operation,
+            None => self.settings.push(operation),
+        }
+    }
+
+    /// Cached settings, in the order they are applied.
+    pub fn settings(&self) -> &[Operation] {
+        &self.settings
+    }
+
+    /// Write the cached settings to *saber*, stopping at the first error.
+    pub fn apply<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S) -> Result<()> {
+        for operation in &self.settings {
+            operation.apply(saber)?;
+        }
+        Ok(())
+    }
+
+    /// Report a reboot detected by other means. The settings are re-applied
+    /// at the next poll if enabled.
+    pub fn notify_reboot(&mut self) {
+        self.detected(Duration::from_secs(0));
+    }
+
+    /// Remove and return the pending events, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = RebootEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Poll the device of *saber*, and re-apply the cached settings if a
+    /// reboot is detected and re-applying is enabled. Return whether a
+    /// reboot was detected. The failed requests are not errors, they are
+    /// the silence of the device.
+    pub fn poll<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S) -> Result<bool> {
+        self.poll_at(saber, Instant::now())
+    }
+
+    fn poll_at<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S, now: Instant) -> Result<bool> {
+        if saber.get_voltage(1).is_err() {
+            self.silent_since.get_or_insert(now);
+            return Ok(false);
+        }
+        let rebooted = match self.silent_since.take() {
+            Some(since) if now.saturating_duration_since(since) >= self.min_outage => {
+                self.detected(now - since);
+                true
+            }
+            _ => false,
+        };
+        if self.pending && self.reapply {
+            self.reapply(saber);
+        }
+        Ok(rebooted)
+    }
+
+    fn detected(&mut self, outage: Duration) {
+        log_warn!("device rebooted after {:?} of silence", outage);
+        self.push_event(RebootEvent::Detected { outage });
+        self.pending = true;
+    }
+
+    fn reapply<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S) {
+        match self.apply(saber) {
+            Ok(()) => {
+                log_info!("re-applied {} setting(s)", self.settings.len());
+                self.push_event(RebootEvent::Reapplied {
+                    count: self.settings.len(),
+                });
+                self.pending = false;
+            }
+            Err(e) => {
+                log_warn!("re-applying the settings failed: {}", e);
+                self.push_event(RebootEvent::ReapplyFailed);
+            }
+        }
+    }
+
+    fn push_event(&mut self, event: RebootEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl Default for RebootMonitor {
+    fn default() -> Self {
+        RebootMonitor::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_reboot() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        let mut monitor = RebootMonitor::new()
+            .with_min_outage(Duration::from_secs(1))
+            .with_setting(Operation::SetRamp(1, 0.5))
+            .with_setting(Operation::GetVoltage(1))
+            .with_setting(Operation::SetRamp(1, 0.0))
+            .with_reapply();
+        assert_eq!(&[Operation::SetRamp(1, 0.0)], monitor.settings());
+        let t0 = Instant::now();
+        let s = Duration::from_secs;
+
+        port.queue_reply(b"M1:B240\r\n");
+        assert!(!monitor.poll_at(&mut saber, t0).unwrap());
+
+        // Short glitch
+        assert!(!monitor.poll_at(&mut saber, t0 + s(1)).unwrap());
+        port.queue_reply(b"M1:B240\r\n");
+        assert!(!monitor.poll_at(&mut saber, t0 + s(1)).unwrap());
+        assert_eq!(0, monitor.drain_events().count());
+
+        // Power cycle
+        assert!(!monitor.poll_at(&mut saber, t0 + s(2)).unwrap());
+        assert!(!monitor.poll_at(&mut saber, t0 + s(3)).unwrap());
+        port.take_written();
+        port.queue_reply(b"M1:B240\r\n");
+        assert!(monitor.poll_at(&mut saber, t0 + s(4)).unwrap());
+        assert_eq!(port.take_written(), b"M1: getb\r\nR1: 0\r\n");
+        let events: Vec<_> = monitor.drain_events().collect();
+        assert_eq!(
+            vec![
+                RebootEvent::Detected { outage: s(2) },
+                RebootEvent::Reapplied { count: 1 },
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn test_reapply_failed() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port).with_interlock();
+        let mut monitor = RebootMonitor::new()
+            .with_setting(Operation::SetSpeed(1, 0.5))
+            .with_reapply();
+        monitor.notify_reboot();
+
+        port.queue_reply(b"M1:B240\r\n");
+        assert!(!monitor.poll_at(&mut saber, Instant::now()).unwrap());
+        saber.arm();
+        port.queue_reply(b"M1:B240\r\n");
+        monitor.poll_at(&mut saber, Instant::now()).unwrap();
+        let events: Vec<_> = monitor.drain_events().collect();
+        assert_eq!(
+            vec![
+                RebootEvent::Detected {
+                    outage: Duration::from_secs(0)
+                },
+                RebootEvent::ReapplyFailed,
+                RebootEvent::Reapplied { count: 1 },
+            ],
+            events
+        );
+    }
+}
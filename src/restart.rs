@@ -0,0 +1,306 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::sabertooth2x32::{is_shut_down, Sabertooth2x32};
+use crate::Result;
+
+/// Default time a channel must stay shut down before it is restarted.
+pub const DEFAULT_COOL_DOWN: Duration = Duration::from_secs(2);
+
+/// Default number of restart attempts before giving up.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// Event emitted by [AutoRestart](struct.AutoRestart.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RestartEvent {
+    /// The channel looks shut down.
+    Detected { channel: usize },
+
+    /// `startup()` was sent to the channel.
+    Attempt { channel: usize, attempt: u32 },
+
+    /// The channel outputs power again.
+    Recovered { channel: usize },
+
+    /// The maximum number of attempts is reached, the channel is left alone
+    /// until it recovers or [AutoRestart::reset()] is called.
+    ///
+    /// [AutoRestart::reset()]: struct.AutoRestart.html#method.reset
+    GaveUp { channel: usize },
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ChannelState {
+    shut_since: Option<Instant>,
+    attempts: u32,
+    gave_up: bool,
+}
+
+/// Policy restarting the motor channels after a transient fault shut them
+/// down.
+///
+/// The Sabertooth 2x32 does not report its shutdown state, so a channel is
+/// considered shut down when its commanded speed is not zero while its output
+/// power stays at zero. The condition must hold for the cool-down time before
+/// `startup()` is sent, which also filters the short lag of the output after
+/// a new command. A channel shut down on purpose with `shutdown()` would be
+/// restarted as well, so stop polling or set its speed to zero before.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::{AutoRestart, RestartEvent};
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// let mut restart = AutoRestart::new()
+///     .with_cool_down(Duration::from_secs(5))
+///     .with_max_retries(2);
+///
+/// // In the control loop
+/// restart.poll(&mut saber)?;
+/// for event in restart.drain_events() {
+///     println!("{:?}", event);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AutoRestart {
+    cool_down: Duration,
+    max_retries: u32,
+    channels: [ChannelState; 2],
+    events: VecDeque<RestartEvent>,
+}
+
+impl AutoRestart {
+    /// Create a new policy with the default cool-down and number of retries.
+    pub fn new() -> AutoRestart {
+        AutoRestart {
+            cool_down: DEFAULT_COOL_DOWN,
+            max_retries: DEFAULT_MAX_RETRIES,
+            channels: [ChannelState::default(); 2],
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Set how long a channel must stay shut down before each restart
+    /// attempt.
+    pub fn with_cool_down(mut self, cool_down: Duration) -> Self {
+        self.cool_down = cool_down;
+        self
+    }
+
+    /// Set the maximum number of consecutive restart attempts.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Forget the past attempts, so that channels given up on can be
+    /// restarted again.
+    pub fn reset(&mut self) {
+        self.channels = [ChannelState::default(); 2];
+    }
+
+    /// Remove and return the pending events, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = RestartEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Read the state of both channels of *saber*, and restart them if
+    /// needed.
+    pub fn poll<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S) -> Result<()> {
+        self.poll_at(saber, Instant::now())
+    }
+
+    fn poll_at<S: Sabertooth2x32 + ?Sized>(&mut self, saber: &mut S, now: Instant) -> Result<()> {
+        for channel in 1..=2 {
+            let commanded = saber.get_speed(channel)?;
+            let power = saber.get_power(channel)?;
+            let shut = is_shut_down(commanded, power);
+
+            let state = self.channels[channel - 1];
+            if !shut {
+                if state.shut_since.is_some() {
+                    if state.attempts > 0 {
+                        log_info!("channel {} recovered", channel);
+                        self.push_event(RestartEvent::Recovered { channel });
+                    }
+                    self.channels[channel - 1] = ChannelState::default();
+                }
+                continue;
+            }
+
+            let since = match state.shut_since {
+                Some(since) => since,
+                None => {
+                    log_warn!("channel {} looks shut down", channel);
+                    self.push_event(RestartEvent::Detected { channel });
+                    self.channels[channel - 1].shut_since = Some(now);
+                    now
+                }
+            };
+
+            if state.gave_up || now.duration_since(since) < self.cool_down {
+                continue;
+            }
+
+            if state.attempts >= self.max_retries {
+                log_warn!("channel {} still shut down, giving up", channel);
+                self.push_event(RestartEvent::GaveUp { channel });
+                self.channels[channel - 1].gave_up = true;
+                continue;
+            }
+
+            let attempt = state.attempts + 1;
+            log_info!("restarting channel {} (attempt {})", channel, attempt);
+            self.push_event(RestartEvent::Attempt { channel, attempt });
+            self.channels[channel - 1].attempts = attempt;
+            self.channels[channel - 1].shut_since = Some(now);
+            saber.startup(channel)?;
+        }
+        Ok(())
+    }
+
+    fn push_event(&mut self, event: RestartEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl Default for AutoRestart {
+    fn default() -> Self {
+        AutoRestart::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Device with channel 1 shut down until started up *fail_startups* + 1
+    /// times.
+    struct FakeSaber {
+        speed: [f32; 2],
+        running: [bool; 2],
+        fail_startups: u32,
+        startups: u32,
+    }
+
+    impl Sabertooth2x32 for FakeSaber {
+        fn startup(&mut self, channel: usize) -> Result<()> {
+            self.startups += 1;
+            if self.startups > self.fail_startups {
+                self.running[channel - 1] = true;
+            }
+            Ok(())
+        }
+        fn shutdown(&mut self, channel: usize) -> Result<()> {
+            self.running[channel - 1] = false;
+            Ok(())
+        }
+        fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+            self.speed[channel - 1] = ratio;
+            Ok(())
+        }
+        fn get_speed(&mut self, channel: usize) -> Result<f32> {
+            Ok(self.speed[channel - 1])
+        }
+        fn set_drive(&mut self, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn set_turn(&mut self, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn set_power(&mut self, _: usize, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn get_power(&mut self, channel: usize) -> Result<f32> {
+            match self.running[channel - 1] {
+                true => Ok(self.speed[channel - 1]),
+                false => Ok(0.0),
+            }
+        }
+        fn set_ramp(&mut self, _: usize, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn set_aux(&mut self, _: usize, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn get_voltage(&mut self, _: usize) -> Result<f32> {
+            Ok(24.0)
+        }
+        fn get_current(&mut self, _: usize) -> Result<f32> {
+            Ok(0.0)
+        }
+        fn get_temperature(&mut self, _: usize) -> Result<f32> {
+            Ok(25.0)
+        }
+    }
+
+    fn fake_saber(fail_startups: u32) -> FakeSaber {
+        FakeSaber {
+            speed: [0.5, 0.5],
+            running: [false, true],
+            fail_startups,
+            startups: 0,
+        }
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut saber = fake_saber(1);
+        let mut restart = AutoRestart::new().with_cool_down(Duration::from_secs(1));
+        let t0 = Instant::now();
+        let s = Duration::from_secs;
+
+        restart.poll_at(&mut saber, t0).unwrap();
+        restart.poll_at(&mut saber, t0 + s(1)).unwrap();
+        restart.poll_at(&mut saber, t0 + s(2)).unwrap();
+        restart.poll_at(&mut saber, t0 + s(3)).unwrap();
+
+        let events: Vec<_> = restart.drain_events().collect();
+        assert_eq!(
+            vec![
+                RestartEvent::Detected { channel: 1 },
+                RestartEvent::Attempt {
+                    channel: 1,
+                    attempt: 1
+                },
+                RestartEvent::Attempt {
+                    channel: 1,
+                    attempt: 2
+                },
+                RestartEvent::Recovered { channel: 1 },
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn test_give_up() {
+        let mut saber = fake_saber(u32::MAX);
+        let mut restart = AutoRestart::new()
+            .with_cool_down(Duration::from_secs(1))
+            .with_max_retries(1);
+        let t0 = Instant::now();
+        for i in 0..5 {
+            restart
+                .poll_at(&mut saber, t0 + Duration::from_secs(i))
+                .unwrap();
+        }
+        assert_eq!(1, saber.startups);
+        assert_eq!(
+            Some(RestartEvent::GaveUp { channel: 1 }),
+            restart.drain_events().last()
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// State of a budget, shared by its clones.
+#[derive(Debug)]
+struct Budget {
+    max_retries: u32,
+    max_time: Option<Duration>,
+    window: Option<Duration>,
+    start: Instant,
+    retries: u32,
+    time: Duration,
+    denied: u64,
+}
+
+impl Budget {
+    /// Start a new window if the current one is over.
+    fn roll(&mut self, now: Instant) {
+        if let Some(window) = self.window {
+            if now.duration_since(self.start) >= window {
+                self.reset(now);
+            }
+        }
+    }
+
+    fn reset(&mut self, now: Instant) {
+        self.start = now;
+        self.retries = 0;
+        self.time = Duration::from_secs(0);
+    }
+
+    fn exhausted(&self) -> bool {
+        self.retries >= self.max_retries || matches!(self.max_time, Some(max) if self.time >= max)
+    }
+}
+
+/// Bound on the retries of all the operations of a control cycle, shared
+/// by the [Supervisor]s of the handles, and by the packet type fallback of
+/// `PacketSerial` with its `with_retry_budget()`.
+///
+/// Each supervisor retries a failed operation up to its own number of
+/// retries. When every device starts failing at once, the retries of all
+/// the operations of a cycle add up and the cycle overruns its period. A
+/// budget caps their total number and, optionally, the total time spent in
+/// them: once it is exhausted, the failed operations are returned without
+/// retry, and count as failures for the supervisors as usual. The budget is
+/// refilled when its window elapses, or explicitly with
+/// [reset()](#method.reset) at the start of each cycle.
+///
+/// The clones of a budget share the same state.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::{RetryBudget, Supervisor};
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let budget = RetryBudget::new(4).with_max_time(Duration::from_millis(20));
+/// let mut front = Supervisor::new(PacketSerial::new("/dev/ttyS0")?)
+///     .with_retries(2)
+///     .with_retry_budget(budget.clone());
+/// let mut rear = Supervisor::new(PacketSerial::new("/dev/ttyS1")?)
+///     .with_retries(2)
+///     .with_retry_budget(budget.clone());
+///
+/// // In the control loop
+/// budget.reset();
+/// front.set_speed(1, 0.5)?;
+/// rear.set_speed(1, 0.5)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [Supervisor]: struct.Supervisor.html
+#[derive(Clone, Debug)]
+pub struct RetryBudget {
+    budget: Arc<Mutex<Budget>>,
+}
+
+impl RetryBudget {
+    /// Create a budget of *max_retries* retries, without time limit, refilled
+    /// only by [reset()](#method.reset).
+    pub fn new(max_retries: u32) -> RetryBudget {
+        RetryBudget {
+            budget: Arc::new(Mutex::new(Budget {
+                max_retries,
+                max_time: None,
+                window: None,
+                start: Instant::now(),
+                retries: 0,
+                time: Duration::from_secs(0),
+                denied: 0,
+            })),
+        }
+    }
+
+    /// Also limit the total time spent in retries.
+    pub fn with_max_time(self, max_time: Duration) -> Self {
+        self.lock().max_time = Some(max_time);
+        self
+    }
+
+    /// Refill the budget automatically every *window*, for example the
+    /// period of the control loop.
+    pub fn with_window(self, window: Duration) -> Self {
+        {
+            let mut budget = self.lock();
+            budget.window = Some(window);
+            budget.reset(Instant::now());
+        }
+        self
+    }
+
+    /// Refill the budget, and start a new window.
+    pub fn reset(&self) {
+        self.lock().reset(Instant::now());
+    }
+
+    /// Number of retries left in the current window.
+    pub fn remaining(&self) -> u32 {
+        let mut budget = self.lock();
+        budget.roll(Instant::now());
+        match budget.exhausted() {
+            true => 0,
+            false => budget.max_retries - budget.retries,
+        }
+    }
+
+    /// Total number of retries denied because the budget was exhausted.
+    pub fn denied(&self) -> u64 {
+        self.lock().denied
+    }
+
+    /// Take one retry from the budget. Return false if it is exhausted.
+    pub(crate) fn acquire(&self) -> bool {
+        let mut budget = self.lock();
+        budget.roll(Instant::now());
+        if budget.exhausted() {
+            budget.denied = budget.denied.saturating_add(1);
+            return false;
+        }
+        budget.retries += 1;
+        true
+    }
+
+    /// Record *elapsed* spent in a retry.
+    pub(crate) fn spend(&self, elapsed: Duration) {
+        self.lock().time += elapsed;
+    }
+
+    /// Lock the state, ignoring the poisoning: the counters stay valid.
+    fn lock(&self) -> MutexGuard<'_, Budget> {
+        self.budget.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(2);
+        let shared = budget.clone();
+        assert!(budget.acquire());
+        assert!(shared.acquire());
+        assert_eq!(0, budget.remaining());
+        assert!(!budget.acquire());
+        assert_eq!(1, shared.denied());
+
+        budget.reset();
+        assert_eq!(2, shared.remaining());
+
+        let budget = RetryBudget::new(10).with_max_time(Duration::from_millis(5));
+        assert!(budget.acquire());
+        budget.spend(Duration::from_millis(5));
+        assert!(!budget.acquire());
+
+        let budget = RetryBudget::new(1).with_window(Duration::from_millis(0));
+        assert!(budget.acquire());
+        assert!(budget.acquire());
+    }
+}
@@ -0,0 +1,617 @@
+//! The Sabertooth 2x12 and 2x25 speak the "Packetized Serial" protocol, the
+//! predecessor of the "Packet Serial" protocol of the Sabertooth 2x32. Every
+//! command is a frame of four bytes, and the device never replies:
+//!
+//! | Byte | Content                                   |
+//! |------|-------------------------------------------|
+//! | 0    | address, from 128 to 135                  |
+//! | 1    | command                                   |
+//! | 2    | data, from 0 to 127                       |
+//! | 3    | checksum: sum of the 3 bytes, masked by 127 |
+//!
+//! Besides the speeds, the protocol has a native mixed mode, and settings
+//! for the battery voltage limits, the ramping and the deadband, exposed by
+//! the [Sabertooth2x12] trait. There are no getters. The commands of the
+//! mixed mode and of the independent mode must not be mixed without a
+//! restart of the device.
+//!
+//! # Example
+//!
+#![cfg_attr(feature = "serialport", doc = " ```rust")]
+#![cfg_attr(not(feature = "serialport"), doc = " ```ignore")]
+//! use saberrs::sabertooth2x12::{PacketizedSerial, Sabertooth2x12};
+//!
+//! # fn example() -> saberrs::Result<()> {
+//! let mut saber = PacketizedSerial::new("/dev/ttyS0")?.with_address(129);
+//! saber.set_min_voltage(10.5)?;
+//! saber.set_speed(1, 0.5)?;
+//! saber.set_speed(2, -0.5)?;
+//! saber.stop_motors()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [Sabertooth2x12]: trait.Sabertooth2x12.html
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use alloc::format;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use log::debug;
+
+#[cfg(feature = "std")]
+use crate::bandwidth;
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use crate::error::Error;
+use crate::error::Result;
+#[cfg(feature = "std")]
+use crate::port::SabertoothSerial;
+use crate::protocol::frame;
+#[cfg(feature = "std")]
+use crate::stats::Stats;
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use crate::utils;
+
+#[cfg(feature = "serialport")]
+use crate::port::sabertoothport::SabertoothPort;
+
+use crate::opcodes::sabertooth2x12 as op;
+
+/// Default address of a [PacketizedSerial](struct.PacketizedSerial.html).
+pub const DEFAULT_ADDRESS: u8 = op::MIN_ADDRESS;
+
+/// Size of a "Packetized Serial" frame.
+pub const FRAME_SIZE: usize = frame::PACKETIZED_FRAME_SIZE;
+
+/// Interface for the Sabertooth 2x12 and 2x25. See the [module
+/// documentation](index.html).
+pub trait Sabertooth2x12 {
+    /// Set the speed of motor *channel* (1 or 2). *ratio* is between -1.0
+    /// for full reverse and 1.0 for full forward.
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()>;
+
+    /// Set the drive of the mixed mode: *ratio* is between -1.0 for full
+    /// backward and 1.0 for full forward. The device only starts moving
+    /// once both the drive and the [turn](#tymethod.set_turn) are set.
+    fn set_drive(&mut self, ratio: f32) -> Result<()>;
+
+    /// Set the turn of the mixed mode: *ratio* is between -1.0 for full left
+    /// and 1.0 for full right.
+    fn set_turn(&mut self, ratio: f32) -> Result<()>;
+
+    /// Set the battery voltage under which the motors stop, from 6.0 to
+    /// 30.0 volts, for protecting the batteries.
+    fn set_min_voltage(&mut self, volts: f32) -> Result<()>;
+
+    /// Set the battery voltage over which the device stops regenerating,
+    /// from 0.0 to 24.8 volts, for power supplies which cannot absorb the
+    /// braking energy.
+    fn set_max_voltage(&mut self, volts: f32) -> Result<()>;
+
+    /// Set the ramping, from 0 to 80: 0 disables it, 1 to 10 are the fast
+    /// ramps, 11 to 20 the slow ramps and 21 to 80 the intermediate ramps.
+    fn set_ramp(&mut self, value: u8) -> Result<()>;
+
+    /// Set the deadband, from 0 to 127: the motors stop while the commanded
+    /// speed is within *value* out of 127 of zero. 0 restores the default.
+    fn set_deadband(&mut self, value: u8) -> Result<()>;
+
+    /// Stop both motors.
+    fn stop_motors(&mut self) -> Result<()> {
+        self.set_speed(1, 0.0)?;
+        self.set_speed(2, 0.0)
+    }
+}
+
+/// Encode the frame of *command* with *data* for the device at *address*.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x12::encode_frame;
+///
+/// assert_eq!([128, 0, 64, 64], encode_frame(128, 0, 64).unwrap());
+/// assert!(encode_frame(128, 0, 128).is_err());
+/// ```
+pub fn encode_frame(address: u8, command: u8, data: u8) -> Result<[u8; FRAME_SIZE]> {
+    frame::encode_packetized(address, command, data)
+}
+
+/// Command and data of the signed *ratio*, with the command *forward* for
+/// the positive values and *backward* for the negative ones.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn signed_command(ratio: f32, forward: u8, backward: u8) -> Result<(u8, u8)> {
+    let value = utils::ratio_to_range(ratio, op::DATA_MAX)?;
+    if value < 0 {
+        Ok((backward, (-value) as u8))
+    } else {
+        Ok((forward, value as u8))
+    }
+}
+
+/// Check that *value* is between *min* and *max*, for the setting *name*.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn check_setting(name: &str, value: f32, min: f32, max: f32) -> Result<()> {
+    if !(min..=max).contains(&value) {
+        return Err(Error::InvalidInput(format!(
+            "{} ({}) out of range {}~{}",
+            name, value, min, max
+        )));
+    }
+    Ok(())
+}
+
+// Command and data of each setter of the trait, shared by the handles.
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn speed_command(channel: usize, ratio: f32) -> Result<(u8, u8)> {
+    let (forward, backward) = match_channel_to!(
+        channel,
+        (op::DRIVE_FORWARD_1, op::DRIVE_BACKWARD_1),
+        (op::DRIVE_FORWARD_2, op::DRIVE_BACKWARD_2)
+    );
+    signed_command(ratio, forward, backward)
+}
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn drive_command(ratio: f32) -> Result<(u8, u8)> {
+    signed_command(ratio, op::DRIVE_FORWARD_MIXED, op::DRIVE_BACKWARD_MIXED)
+}
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn turn_command(ratio: f32) -> Result<(u8, u8)> {
+    signed_command(ratio, op::TURN_RIGHT_MIXED, op::TURN_LEFT_MIXED)
+}
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn min_voltage_command(volts: f32) -> Result<(u8, u8)> {
+    check_setting("minimum voltage", volts, 6.0, 30.0)?;
+    Ok((op::MIN_VOLTAGE, utils::round((volts - 6.0) * 5.0) as u8))
+}
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn max_voltage_command(volts: f32) -> Result<(u8, u8)> {
+    check_setting("maximum voltage", volts, 0.0, 24.8)?;
+    let data = utils::round(volts * 5.12).min(op::DATA_MAX as f32);
+    Ok((op::MAX_VOLTAGE, data as u8))
+}
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+fn ramp_command(value: u8) -> Result<(u8, u8)> {
+    check_setting("ramping", f32::from(value), 0.0, f32::from(op::RAMPING_MAX))?;
+    Ok((op::RAMPING, value))
+}
+
+/// Settings last sent by a [PacketizedSerial](struct.PacketizedSerial.html),
+/// returned by its `last_config()`. `None` for the settings it never sent.
+///
+/// The device cannot report its settings, so these are the values of the
+/// last successful writes of the handle, not a read of the device: they are
+/// lost by the device when it restarts, and other handles on the same line
+/// can change them. The 2x12 and 2x25 have no serial timeout nor baud rate
+/// command, so there is nothing to track for them.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PacketizedConfig {
+    /// Battery voltage under which the motors stop, in volts.
+    pub min_voltage: Option<f32>,
+
+    /// Battery voltage over which the device stops regenerating, in volts.
+    pub max_voltage: Option<f32>,
+
+    /// Ramping, from 0 to 80.
+    pub ramp: Option<u8>,
+
+    /// Deadband, from 0 to 127.
+    pub deadband: Option<u8>,
+}
+
+/// Interface using the "Packetized Serial" protocol of the Sabertooth 2x12
+/// and 2x25. See the [module documentation](index.html).
+#[cfg(feature = "std")]
+pub struct PacketizedSerial<T: SabertoothSerial> {
+    dev: T,
+    address: u8,
+    stats: Stats,
+    name: Option<String>,
+    baud_rate: Option<u32>,
+    write_deadline: Option<Duration>,
+    config: PacketizedConfig,
+}
+
+#[cfg(feature = "serialport")]
+impl PacketizedSerial<SabertoothPort> {
+    /// Open a serial port and return a new `PacketizedSerial` handle at the
+    /// address 128. The port is opened at 9600 baud; the device picks up the
+    /// rate from the autobauding byte, see
+    /// [send_autobaud()](#method.send_autobaud).
+    pub fn new(port: &str) -> Result<PacketizedSerial<SabertoothPort>> {
+        Ok(PacketizedSerial::from(SabertoothPort::new(port)?))
+    }
+
+    /// Open a serial port at *baud_rate*, and return a new
+    /// `PacketizedSerial` handle. The device never replies, so *timeout*
+    /// only bounds the writes.
+    pub fn open_with(
+        port: &str,
+        baud_rate: u32,
+        timeout: Duration,
+    ) -> Result<PacketizedSerial<SabertoothPort>> {
+        Ok(PacketizedSerial::from(SabertoothPort::open_with(
+            port, baud_rate, timeout,
+        )?))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: SabertoothSerial> PacketizedSerial<T> {
+    /// Set the address of the device, from 128 to 135 as set by its DIP
+    /// switches. An invalid address makes the commands fail with
+    /// `Error::InvalidInput`.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set a human-readable name for the device, used in the logs.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Re-assert *baud_rate* on the port before each transaction, for
+    /// handles sharing a port with devices at other rates. The port is only
+    /// reconfigured when its rate differs.
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// Give up writing a command after *deadline* with `Error::TxTimeout`,
+    /// instead of waiting for as long as the port lets `write_all()` block.
+    pub fn with_write_deadline(mut self, deadline: Duration) -> Self {
+        self.write_deadline = Some(deadline);
+        self
+    }
+
+    /// Return the address of the device.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Return the name of the device, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Traffic statistics since the creation of the handle or the last call
+    /// to [reset_stats()](#method.reset_stats).
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Reset the traffic statistics.
+    pub fn reset_stats(&mut self) {
+        self.stats.reset()
+    }
+
+    /// Settings last sent by the handle, see
+    /// [PacketizedConfig](struct.PacketizedConfig.html).
+    pub fn last_config(&self) -> &PacketizedConfig {
+        &self.config
+    }
+
+    /// Send the autobauding byte, from which the devices on the line pick up
+    /// the baud rate. It must be sent once after the devices power up,
+    /// before any command.
+    pub fn send_autobaud(&mut self) -> Result<()> {
+        self.write_frame(&[0xaa])
+    }
+
+    /// Estimate the time taken on the wire by a command, from the baud
+    /// rate.
+    pub fn estimate_transaction_time(&self) -> Result<Duration> {
+        let baud_rate = match self.baud_rate {
+            Some(baud_rate) => baud_rate,
+            None => self.dev.baud_rate()?,
+        };
+        Ok(bandwidth::transfer_time(baud_rate, FRAME_SIZE))
+    }
+
+    /// Return the serial port.
+    pub fn into_inner(self) -> T {
+        self.dev
+    }
+
+    fn send(&mut self, command: u8, data: u8) -> Result<()> {
+        let frame = encode_frame(self.address, command, data)?;
+        self.write_frame(&frame)
+    }
+
+    /// Set the baud rate given with [with_baud_rate()](#method.with_baud_rate)
+    /// on the port, if it differs.
+    fn assert_baud_rate(&mut self) -> Result<()> {
+        match self.baud_rate {
+            Some(baud_rate) if self.dev.baud_rate()? != baud_rate => {
+                self.dev.set_baud_rate(baud_rate)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
+        self.assert_baud_rate()?;
+        debug!(
+            "{}{}tx = {:?}",
+            self.name.as_deref().unwrap_or(""),
+            if self.name.is_some() { ": " } else { "" },
+            txdata
+        );
+        match self.write_deadline {
+            Some(deadline) => utils::write_bounded(&mut self.dev, txdata, deadline)?,
+            None => self.dev.write_all(txdata)?,
+        }
+        self.stats.record_tx(txdata.len());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: SabertoothSerial> Sabertooth2x12 for PacketizedSerial<T> {
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        let (command, data) = speed_command(channel, ratio)?;
+        self.send(command, data)
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        let (command, data) = drive_command(ratio)?;
+        self.send(command, data)
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        let (command, data) = turn_command(ratio)?;
+        self.send(command, data)
+    }
+
+    fn set_min_voltage(&mut self, volts: f32) -> Result<()> {
+        let (command, data) = min_voltage_command(volts)?;
+        self.send(command, data)?;
+        self.config.min_voltage = Some(volts);
+        Ok(())
+    }
+
+    fn set_max_voltage(&mut self, volts: f32) -> Result<()> {
+        let (command, data) = max_voltage_command(volts)?;
+        self.send(command, data)?;
+        self.config.max_voltage = Some(volts);
+        Ok(())
+    }
+
+    fn set_ramp(&mut self, value: u8) -> Result<()> {
+        let (command, data) = ramp_command(value)?;
+        self.send(command, data)?;
+        self.config.ramp = Some(value);
+        Ok(())
+    }
+
+    fn set_deadband(&mut self, value: u8) -> Result<()> {
+        self.send(op::DEADBAND, value)?;
+        self.config.deadband = Some(value);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: SabertoothSerial> From<T> for PacketizedSerial<T> {
+    fn from(dev: T) -> Self {
+        PacketizedSerial {
+            dev,
+            address: DEFAULT_ADDRESS,
+            stats: Stats::new(),
+            name: None,
+            baud_rate: None,
+            write_deadline: None,
+            config: PacketizedConfig::default(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> From<&T> for PacketizedSerial<T>
+where
+    T: SabertoothSerial + Clone,
+{
+    fn from(dev: &T) -> Self {
+        PacketizedSerial::from(dev.clone())
+    }
+}
+
+/// "Packetized Serial" interface over an [embedded-io] transport, like the
+/// UART of a microcontroller, available without `std`.
+///
+/// It sends the same frames as [PacketizedSerial](struct.PacketizedSerial.html),
+/// and returns the errors of the transport as `Error::Transport`.
+/// **Requires** the "embedded-io" feature.
+///
+/// [embedded-io]: https://crates.io/crates/embedded-io
+#[cfg(feature = "embedded-io")]
+pub struct EmbeddedPacketizedSerial<T> {
+    dev: T,
+    address: u8,
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T: embedded_io::Write> EmbeddedPacketizedSerial<T> {
+    /// Create a new interface on *dev*, at the address 128.
+    pub fn new(dev: T) -> EmbeddedPacketizedSerial<T> {
+        EmbeddedPacketizedSerial {
+            dev,
+            address: DEFAULT_ADDRESS,
+        }
+    }
+
+    /// Set the address of the device, from 128 to 135 as set by its DIP
+    /// switches. An invalid address makes the commands fail with
+    /// `Error::InvalidInput`.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Return the address of the device.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Send the autobauding byte, see
+    /// [PacketizedSerial::send_autobaud()](struct.PacketizedSerial.html#method.send_autobaud).
+    pub fn send_autobaud(&mut self) -> Result<()> {
+        self.write_frame(&[0xaa])
+    }
+
+    /// Mutable reference to the transport.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.dev
+    }
+
+    /// Return the transport.
+    pub fn into_inner(self) -> T {
+        self.dev
+    }
+
+    fn send(&mut self, command: u8, data: u8) -> Result<()> {
+        let frame = encode_frame(self.address, command, data)?;
+        self.write_frame(&frame)
+    }
+
+    fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
+        debug!("tx = {:?}", txdata);
+        utils::write_embedded(&mut self.dev, txdata)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T: embedded_io::Write> Sabertooth2x12 for EmbeddedPacketizedSerial<T> {
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        let (command, data) = speed_command(channel, ratio)?;
+        self.send(command, data)
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        let (command, data) = drive_command(ratio)?;
+        self.send(command, data)
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        let (command, data) = turn_command(ratio)?;
+        self.send(command, data)
+    }
+
+    fn set_min_voltage(&mut self, volts: f32) -> Result<()> {
+        let (command, data) = min_voltage_command(volts)?;
+        self.send(command, data)
+    }
+
+    fn set_max_voltage(&mut self, volts: f32) -> Result<()> {
+        let (command, data) = max_voltage_command(volts)?;
+        self.send(command, data)
+    }
+
+    fn set_ramp(&mut self, value: u8) -> Result<()> {
+        let (command, data) = ramp_command(value)?;
+        self.send(command, data)
+    }
+
+    fn set_deadband(&mut self, value: u8) -> Result<()> {
+        self.send(op::DEADBAND, value)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T: embedded_io::Write> From<T> for EmbeddedPacketizedSerial<T> {
+    fn from(dev: T) -> Self {
+        EmbeddedPacketizedSerial::new(dev)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_encode_frame() {
+        assert_eq!([130, 4, 127, 5], encode_frame(130, 4, 127).unwrap());
+        assert!(encode_frame(127, 0, 0).is_err());
+        assert!(encode_frame(136, 0, 0).is_err());
+        assert!(encode_frame(128, 0, 128).is_err());
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut port = MockPort::new();
+        let mut saber = PacketizedSerial::from(&port).with_address(129);
+        saber.set_speed(1, 0.5).unwrap();
+        saber.set_speed(2, -1.0).unwrap();
+        saber.stop_motors().unwrap();
+        assert_eq!(
+            port.take_written(),
+            [129, 0, 63, 64, 129, 5, 127, 5, 129, 0, 0, 1, 129, 4, 0, 5]
+        );
+        assert!(saber.set_speed(3, 0.0).is_err());
+        assert!(saber.set_speed(1, 1.5).is_err());
+
+        saber.set_drive(-0.5).unwrap();
+        saber.set_turn(1.0).unwrap();
+        assert_eq!(port.take_written(), [129, 9, 63, 73, 129, 10, 127, 10]);
+    }
+
+    #[test]
+    fn test_settings() {
+        let mut port = MockPort::new();
+        let mut saber = PacketizedSerial::from(&port);
+        saber.set_min_voltage(12.0).unwrap();
+        saber.set_max_voltage(24.8).unwrap();
+        saber.set_ramp(20).unwrap();
+        saber.set_deadband(3).unwrap();
+        assert_eq!(
+            port.take_written(),
+            [128, 2, 30, 32, 128, 3, 127, 2, 128, 16, 20, 36, 128, 17, 3, 20]
+        );
+        assert!(saber.set_min_voltage(5.0).is_err());
+        assert!(saber.set_max_voltage(30.0).is_err());
+        assert!(saber.set_ramp(81).is_err());
+        assert!(saber.set_deadband(128).is_err());
+        assert_eq!(4, saber.stats().frames_sent());
+    }
+
+    #[test]
+    fn test_last_config() {
+        let port = MockPort::new();
+        let mut saber = PacketizedSerial::from(&port);
+        assert_eq!(&PacketizedConfig::default(), saber.last_config());
+
+        saber.set_min_voltage(12.0).unwrap();
+        saber.set_ramp(20).unwrap();
+        saber.set_ramp(10).unwrap();
+        assert!(saber.set_deadband(128).is_err());
+        let expected = PacketizedConfig {
+            min_voltage: Some(12.0),
+            ramp: Some(10),
+            ..PacketizedConfig::default()
+        };
+        assert_eq!(&expected, saber.last_config());
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn test_embedded() {
+        let mut buf = [0u8; 8];
+        let mut saber = EmbeddedPacketizedSerial::new(&mut buf[..]).with_address(129);
+        saber.set_speed(2, -1.0).unwrap();
+        saber.set_min_voltage(12.0).unwrap();
+        assert!(saber.set_speed(1, 0.0).is_err());
+        assert_eq!([129, 5, 127, 5, 129, 2, 30, 33], buf);
+    }
+}
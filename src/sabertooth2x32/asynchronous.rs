@@ -0,0 +1,402 @@
+use std::future::Future;
+
+use super::{Operation, PacketSerial, PacketType, PlainText, Sabertooth2x32};
+use crate::asyncport::{AsyncSabertoothSerial, BridgePort};
+use crate::utils;
+use crate::Result;
+
+/// Size of the chunks read from the asynchronous port.
+const READ_CHUNK: usize = 32;
+
+/// Asynchronous counterpart of [Sabertooth2x32], implemented by
+/// [AsyncPacketSerial] and [AsyncPlainText].
+///
+/// The methods send the same frames as their synchronous versions, and wait
+/// for the replies of the getters without blocking the executor. The
+/// futures borrow the handle mutably, so the requests of a handle never
+/// interleave. **Requires** the "async" feature.
+///
+/// [Sabertooth2x32]: trait.Sabertooth2x32.html
+/// [AsyncPacketSerial]: struct.AsyncPacketSerial.html
+/// [AsyncPlainText]: struct.AsyncPlainText.html
+pub trait AsyncSabertooth2x32: Send {
+    /// Execute *operation*, and return the value for getters.
+    fn execute(&mut self, operation: Operation)
+        -> impl Future<Output = Result<Option<f32>>> + Send;
+
+    /// See [Sabertooth2x32::startup()](trait.Sabertooth2x32.html#tymethod.startup).
+    fn startup(&mut self, channel: usize) -> impl Future<Output = Result<()>> + Send {
+        async move { self.execute(Operation::Startup(channel)).await.map(drop) }
+    }
+
+    /// See [Sabertooth2x32::shutdown()](trait.Sabertooth2x32.html#tymethod.shutdown).
+    fn shutdown(&mut self, channel: usize) -> impl Future<Output = Result<()>> + Send {
+        async move { self.execute(Operation::Shutdown(channel)).await.map(drop) }
+    }
+
+    /// See [Sabertooth2x32::set_speed()](trait.Sabertooth2x32.html#tymethod.set_speed).
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            self.execute(Operation::SetSpeed(channel, ratio))
+                .await
+                .map(drop)
+        }
+    }
+
+    /// See [Sabertooth2x32::get_speed()](trait.Sabertooth2x32.html#tymethod.get_speed).
+    fn get_speed(&mut self, channel: usize) -> impl Future<Output = Result<f32>> + Send {
+        async move { value(self.execute(Operation::GetSpeed(channel)).await) }
+    }
+
+    /// See [Sabertooth2x32::stop_motors()](trait.Sabertooth2x32.html#method.stop_motors).
+    fn stop_motors(&mut self) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            self.set_speed(1, 0.0).await?;
+            self.set_speed(2, 0.0).await
+        }
+    }
+
+    /// See [Sabertooth2x32::set_drive()](trait.Sabertooth2x32.html#tymethod.set_drive).
+    fn set_drive(&mut self, ratio: f32) -> impl Future<Output = Result<()>> + Send {
+        async move { self.execute(Operation::SetDrive(ratio)).await.map(drop) }
+    }
+
+    /// See [Sabertooth2x32::set_turn()](trait.Sabertooth2x32.html#tymethod.set_turn).
+    fn set_turn(&mut self, ratio: f32) -> impl Future<Output = Result<()>> + Send {
+        async move { self.execute(Operation::SetTurn(ratio)).await.map(drop) }
+    }
+
+    /// See [Sabertooth2x32::set_power()](trait.Sabertooth2x32.html#tymethod.set_power).
+    fn set_power(&mut self, channel: usize, ratio: f32) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            self.execute(Operation::SetPower(channel, ratio))
+                .await
+                .map(drop)
+        }
+    }
+
+    /// See [Sabertooth2x32::get_power()](trait.Sabertooth2x32.html#tymethod.get_power).
+    fn get_power(&mut self, channel: usize) -> impl Future<Output = Result<f32>> + Send {
+        async move { value(self.execute(Operation::GetPower(channel)).await) }
+    }
+
+    /// See [Sabertooth2x32::set_ramp()](trait.Sabertooth2x32.html#tymethod.set_ramp).
+    fn set_ramp(&mut self, channel: usize, ratio: f32) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            self.execute(Operation::SetRamp(channel, ratio))
+                .await
+                .map(drop)
+        }
+    }
+
+    /// See [Sabertooth2x32::set_aux()](trait.Sabertooth2x32.html#tymethod.set_aux).
+    fn set_aux(&mut self, channel: usize, ratio: f32) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            self.execute(Operation::SetAux(channel, ratio))
+                .await
+                .map(drop)
+        }
+    }
+
+    /// See [Sabertooth2x32::get_voltage()](trait.Sabertooth2x32.html#tymethod.get_voltage).
+    fn get_voltage(&mut self, channel: usize) -> impl Future<Output = Result<f32>> + Send {
+        async move { value(self.execute(Operation::GetVoltage(channel)).await) }
+    }
+
+    /// See [Sabertooth2x32::get_current()](trait.Sabertooth2x32.html#tymethod.get_current).
+    fn get_current(&mut self, channel: usize) -> impl Future<Output = Result<f32>> + Send {
+        async move { value(self.execute(Operation::GetCurrent(channel)).await) }
+    }
+
+    /// See [Sabertooth2x32::get_temperature()](trait.Sabertooth2x32.html#tymethod.get_temperature).
+    fn get_temperature(&mut self, channel: usize) -> impl Future<Output = Result<f32>> + Send {
+        async move { value(self.execute(Operation::GetTemperature(channel)).await) }
+    }
+}
+
+/// Value of a getter executed with `execute()`.
+fn value(res: Result<Option<f32>>) -> Result<f32> {
+    res.map(|value| value.unwrap_or_default())
+}
+
+/// Split getter of the synchronous handles, used by the asynchronous ones
+/// for waiting for the replies outside of the handle.
+trait SplitGet: Sabertooth2x32 + Send {
+    fn start_get(&mut self, operation: Operation) -> Result<()>;
+    fn try_get(&mut self) -> Result<f32>;
+    fn cancel_get(&mut self);
+}
+
+impl SplitGet for PacketSerial<BridgePort> {
+    fn start_get(&mut self, operation: Operation) -> Result<()> {
+        PacketSerial::start_get(self, operation)
+    }
+
+    fn try_get(&mut self) -> Result<f32> {
+        PacketSerial::try_get(self)
+    }
+
+    fn cancel_get(&mut self) {
+        PacketSerial::cancel_get(self)
+    }
+}
+
+impl SplitGet for PlainText<BridgePort> {
+    fn start_get(&mut self, operation: Operation) -> Result<()> {
+        PlainText::start_get(self, operation)
+    }
+
+    fn try_get(&mut self) -> Result<f32> {
+        PlainText::try_get(self)
+    }
+
+    fn cancel_get(&mut self) {
+        PlainText::cancel_get(self)
+    }
+}
+
+/// Execute *operation* with the synchronous handle *saber* writing into
+/// *bridge*, and carry its frames and the replies over *port*.
+async fn execute<S, P>(
+    saber: &mut S,
+    bridge: &BridgePort,
+    port: &mut P,
+    operation: Operation,
+) -> Result<Option<f32>>
+where
+    S: SplitGet,
+    P: AsyncSabertoothSerial,
+{
+    if !operation.is_get() {
+        let res = operation.apply(saber);
+        flush(bridge, port).await?;
+        return res;
+    }
+    saber.start_get(operation)?;
+    if let Err(e) = flush(bridge, port).await {
+        saber.cancel_get();
+        return Err(e);
+    }
+    let mut buf = [0u8; READ_CHUNK];
+    loop {
+        match saber.try_get() {
+            Ok(value) => return Ok(Some(value)),
+            Err(crate::Error::Io(ref e)) if utils::would_block(e) => {}
+            Err(e) => return Err(e),
+        }
+        match port.read(&mut buf).await {
+            Ok(count) => bridge.push_rx(&buf[..count]),
+            Err(e) => {
+                saber.cancel_get();
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Write the frames collected by *bridge* to *port*.
+async fn flush<P: AsyncSabertoothSerial>(bridge: &BridgePort, port: &mut P) -> Result<()> {
+    let frames = bridge.take_tx();
+    if frames.is_empty() {
+        return Ok(());
+    }
+    port.write_all(&frames).await
+}
+
+/// Asynchronous "Packet Serial" interface, over an
+/// [AsyncSabertoothSerial](../trait.AsyncSabertoothSerial.html) port.
+///
+/// It runs a [PacketSerial](struct.PacketSerial.html) handle, available with
+/// [handle_mut()](#method.handle_mut) for its settings and diagnostics, and
+/// carries its frames over the asynchronous port. **Requires** the "async"
+/// feature.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{AsyncPacketSerial, AsyncSabertooth2x32};
+/// use saberrs::AsyncSabertoothSerial;
+///
+/// async fn drive<P: AsyncSabertoothSerial>(port: P) -> saberrs::Result<f32> {
+///     let mut saber = AsyncPacketSerial::new(port).with_address(129);
+///     saber.set_speed(1, 0.5).await?;
+///     saber.get_voltage(1).await
+/// }
+/// ```
+pub struct AsyncPacketSerial<P> {
+    saber: PacketSerial<BridgePort>,
+    bridge: BridgePort,
+    port: P,
+}
+
+impl<P: AsyncSabertoothSerial> AsyncPacketSerial<P> {
+    /// Create a new interface on *port*, with the default address and
+    /// packet type.
+    pub fn new(port: P) -> AsyncPacketSerial<P> {
+        let bridge = BridgePort::new();
+        AsyncPacketSerial {
+            saber: PacketSerial::from(&bridge),
+            bridge,
+            port,
+        }
+    }
+
+    /// Set the address of the Sabertooth.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.saber = self.saber.with_address(address);
+        self
+    }
+
+    /// Set the integrity protection type used for the frames.
+    pub fn with_packet_type(mut self, packet_type: PacketType) -> Self {
+        self.saber = self.saber.with_packet_type(packet_type);
+        self
+    }
+
+    /// Synchronous handle building the frames.
+    pub fn handle(&self) -> &PacketSerial<BridgePort> {
+        &self.saber
+    }
+
+    /// Mutable synchronous handle building the frames, for its settings.
+    pub fn handle_mut(&mut self) -> &mut PacketSerial<BridgePort> {
+        &mut self.saber
+    }
+
+    /// Mutable reference to the asynchronous port.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Return the asynchronous port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+}
+
+impl<P: AsyncSabertoothSerial> AsyncSabertooth2x32 for AsyncPacketSerial<P> {
+    fn execute(
+        &mut self,
+        operation: Operation,
+    ) -> impl Future<Output = Result<Option<f32>>> + Send {
+        execute(&mut self.saber, &self.bridge, &mut self.port, operation)
+    }
+}
+
+/// Asynchronous "Plain Text" interface, over an
+/// [AsyncSabertoothSerial](../trait.AsyncSabertoothSerial.html) port.
+///
+/// It runs a [PlainText](struct.PlainText.html) handle, available with
+/// [handle_mut()](#method.handle_mut) for its settings and diagnostics, and
+/// carries its frames over the asynchronous port. **Requires** the "async"
+/// feature.
+pub struct AsyncPlainText<P> {
+    saber: PlainText<BridgePort>,
+    bridge: BridgePort,
+    port: P,
+}
+
+impl<P: AsyncSabertoothSerial> AsyncPlainText<P> {
+    /// Create a new interface on *port*.
+    pub fn new(port: P) -> AsyncPlainText<P> {
+        let bridge = BridgePort::new();
+        AsyncPlainText {
+            saber: PlainText::from(&bridge),
+            bridge,
+            port,
+        }
+    }
+
+    /// Synchronous handle building the frames.
+    pub fn handle(&self) -> &PlainText<BridgePort> {
+        &self.saber
+    }
+
+    /// Mutable synchronous handle building the frames, for its settings.
+    pub fn handle_mut(&mut self) -> &mut PlainText<BridgePort> {
+        &mut self.saber
+    }
+
+    /// Mutable reference to the asynchronous port.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Return the asynchronous port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+}
+
+impl<P: AsyncSabertoothSerial> AsyncSabertooth2x32 for AsyncPlainText<P> {
+    fn execute(
+        &mut self,
+        operation: Operation,
+    ) -> impl Future<Output = Result<Option<f32>>> + Send {
+        execute(&mut self.saber, &self.bridge, &mut self.port, operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+    use std::collections::VecDeque;
+    use std::io;
+
+    /// Port replying with the queued chunks, one per read, and timing out
+    /// once they are exhausted.
+    #[derive(Default)]
+    struct ChunkPort {
+        written: Vec<u8>,
+        chunks: VecDeque<Vec<u8>>,
+    }
+
+    impl AsyncSabertoothSerial for ChunkPort {
+        async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+            self.written.extend_from_slice(buf);
+            Ok(())
+        }
+
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let chunk = self
+                .chunks
+                .pop_front()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut))?;
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn test_packet_serial() {
+        let mut saber =
+            AsyncPacketSerial::new(ChunkPort::default()).with_packet_type(PacketType::Checksum);
+        saber.set_speed(1, 0.5).now_or_never().unwrap().unwrap();
+        assert_eq!(
+            b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04",
+            &saber.get_mut().written[..]
+        );
+
+        let port = saber.get_mut();
+        port.written.clear();
+        port.chunks.push_back(b"\x80\x49\x10\x59".to_vec());
+        port.chunks.push_back(b"\x78\x00\x4d\x31\x76".to_vec());
+        let voltage = saber.get_voltage(1).now_or_never().unwrap().unwrap();
+        assert_eq!(12.0, voltage);
+        assert!(saber.get_voltage(1).now_or_never().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let mut saber = AsyncPlainText::new(ChunkPort::default());
+        saber.get_mut().chunks.push_back(b"M1:B240\r\n".to_vec());
+        let voltage = saber.get_voltage(1).now_or_never().unwrap().unwrap();
+        assert_eq!(24.0, voltage);
+        saber.stop_motors().now_or_never().unwrap().unwrap();
+        assert_eq!(
+            &b"M1: getb\r\nM1: 0\r\nM2: 0\r\n"[..],
+            &saber.into_inner().written[..]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+#[cfg(feature = "std")]
+use alloc::format;
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use super::Operation;
+use crate::command_table::{Access, Target, Value};
+#[cfg(feature = "std")]
+use crate::error::{Error, Result};
+use crate::protocol::packet::{CommandGet, CommandSet};
+#[cfg(feature = "std")]
+use crate::protocol::quantity::Quantity;
+use crate::utils::RANGE_MAX;
+
+command_table! {
+    /// Commands of the Sabertooth 2x32, named after the
+    /// [Operation](../enum.Operation.html) variants.
+    pub(crate) enum Command {
+        Startup {
+            access: Access::Set,
+            token: b'M',
+            target: Target::Channel,
+            opcode: CommandSet::Shutdown as u8,
+            value: Value::Fixed(0),
+            keyword: "startup",
+            prefix: None,
+        },
+        Shutdown {
+            access: Access::Set,
+            token: b'M',
+            target: Target::Channel,
+            opcode: CommandSet::Shutdown as u8,
+            value: Value::Fixed(1),
+            keyword: "shutdown",
+            prefix: None,
+        },
+        SetSpeed {
+            access: Access::Set,
+            token: b'M',
+            target: Target::Channel,
+            opcode: CommandSet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "",
+            prefix: None,
+        },
+        GetSpeed {
+            access: Access::Get,
+            token: b'M',
+            target: Target::Channel,
+            opcode: CommandGet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "get",
+            prefix: None,
+        },
+        SetDrive {
+            access: Access::Set,
+            token: b'M',
+            target: Target::Fixed(b'D'),
+            opcode: CommandSet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "",
+            prefix: None,
+        },
+        SetTurn {
+            access: Access::Set,
+            token: b'M',
+            target: Target::Fixed(b'T'),
+            opcode: CommandSet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "",
+            prefix: None,
+        },
+        SetPower {
+            access: Access::Set,
+            token: b'P',
+            target: Target::Channel,
+            opcode: CommandSet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "",
+            prefix: None,
+        },
+        GetPower {
+            access: Access::Get,
+            token: b'P',
+            target: Target::Channel,
+            opcode: CommandGet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "get",
+            prefix: None,
+        },
+        SetRamp {
+            access: Access::Set,
+            token: b'R',
+            target: Target::Channel,
+            opcode: CommandSet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "",
+            prefix: None,
+        },
+        SetAux {
+            access: Access::Set,
+            token: b'Q',
+            target: Target::Channel,
+            opcode: CommandSet::Value as u8,
+            value: Value::Ratio(RANGE_MAX),
+            keyword: "",
+            prefix: None,
+        },
+        GetVoltage {
+            access: Access::Get,
+            token: b'M',
+            target: Target::Channel,
+            opcode: CommandGet::Battery as u8,
+            value: Value::Scaled(10, 10),
+            keyword: "getb",
+            prefix: Some(b'B'),
+        },
+        GetCurrent {
+            access: Access::Get,
+            token: b'M',
+            target: Target::Channel,
+            opcode: CommandGet::Current as u8,
+            value: Value::Scaled(1, 10),
+            keyword: "getc",
+            prefix: Some(b'C'),
+        },
+        GetTemperature {
+            access: Access::Get,
+            token: b'M',
+            target: Target::Channel,
+            opcode: CommandGet::Temperature as u8,
+            value: Value::Scaled(1, 1),
+            keyword: "gett",
+            prefix: Some(b'T'),
+        },
+    }
+}
+
+/// Arguments of an operation: its command, channel and ratio.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+pub(crate) type Args = (Command, Option<usize>, Option<f32>);
+
+impl Command {
+    /// Split *operation* into its command and arguments.
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub fn from_operation(operation: Operation) -> Args {
+        match operation {
+            Operation::Startup(ch) => (Command::Startup, Some(ch), None),
+            Operation::Shutdown(ch) => (Command::Shutdown, Some(ch), None),
+            Operation::SetSpeed(ch, r) => (Command::SetSpeed, Some(ch), Some(r)),
+            Operation::GetSpeed(ch) => (Command::GetSpeed, Some(ch), None),
+            Operation::SetDrive(r) => (Command::SetDrive, None, Some(r)),
+            Operation::SetTurn(r) => (Command::SetTurn, None, Some(r)),
+            Operation::SetPower(ch, r) => (Command::SetPower, Some(ch), Some(r)),
+            Operation::GetPower(ch) => (Command::GetPower, Some(ch), None),
+            Operation::SetRamp(ch, r) => (Command::SetRamp, Some(ch), Some(r)),
+            Operation::SetAux(ch, r) => (Command::SetAux, Some(ch), Some(r)),
+            Operation::GetVoltage(ch) => (Command::GetVoltage, Some(ch), None),
+            Operation::GetCurrent(ch) => (Command::GetCurrent, Some(ch), None),
+            Operation::GetTemperature(ch) => (Command::GetTemperature, Some(ch), None),
+        }
+    }
+
+    /// Quantity read by the get command, if it is one.
+    #[cfg(feature = "std")]
+    pub fn quantity(self) -> Option<Quantity> {
+        match self {
+            Command::GetSpeed => Some(Quantity::Speed),
+            Command::GetPower => Some(Quantity::Power),
+            Command::GetVoltage => Some(Quantity::Voltage),
+            Command::GetCurrent => Some(Quantity::Current),
+            Command::GetTemperature => Some(Quantity::Temperature),
+            _ => None,
+        }
+    }
+
+    /// Split the get request *operation* into its command and arguments.
+    #[cfg(feature = "std")]
+    pub fn from_get(operation: Operation) -> Result<Args> {
+        let args = Command::from_operation(operation);
+        if args.0.spec().access != Access::Get {
+            let msg = format!("{:?} is not a get request", operation);
+            return Err(Error::InvalidInput(msg));
+        }
+        Ok(args)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_golden_coverage() {
+        let transcripts = [
+            testing::golden_checksum(),
+            testing::golden_crc(),
+            testing::golden_plain_text(),
+        ];
+        for transcript in &transcripts {
+            for &command in Command::ALL {
+                assert!(
+                    transcript
+                        .iter()
+                        .any(|step| Command::from_operation(step.operation).0 == command),
+                    "{:?} missing from a golden transcript",
+                    command
+                );
+            }
+        }
+        for step in &transcripts[0] {
+            let (command, ..) = Command::from_operation(step.operation);
+            assert_eq!(
+                step.operation.is_get(),
+                command.spec().access == Access::Get
+            );
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Acceptance test for the bring-up of a Sabertooth 2x32 and its motors.
+//!
+//! The test spins each channel forward then backward at low power, checks the
+//! current drawn by the motor, and checks that the battery voltage stays
+//! stable under load.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use saberrs::Result;
+//! use saberrs::sabertooth2x32::PacketSerial;
+//! use saberrs::sabertooth2x32::commissioning::Commissioning;
+//!
+//! # fn example() -> Result<()> {
+//! let mut saber = PacketSerial::new("/dev/ttyS0")?;
+//!
+//! let report = Commissioning::new()
+//!     .with_power(0.15)
+//!     .with_current_range(0.5, 8.0)
+//!     .with_settle_time(Duration::from_millis(800))
+//!     .run(&mut saber)?;
+//!
+//! for step in report.steps() {
+//!     println!("{:?}", step);
+//! }
+//! assert!(report.passed());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::thread;
+use std::time::Duration;
+
+use super::Sabertooth2x32;
+use crate::Result;
+
+/// Direction of rotation used during a commissioning step.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// Measurements and verdict of one commissioning step.
+#[derive(Clone, PartialEq, Debug)]
+pub struct StepReport {
+    /// Motor channel, 1 or 2.
+    pub channel: usize,
+
+    /// Direction of rotation.
+    pub direction: Direction,
+
+    /// Absolute motor current measured during the step, in amperes.
+    pub current: f32,
+
+    /// Battery voltage measured during the step, in volts.
+    pub voltage: f32,
+
+    /// True if the current is inside the expected range.
+    pub current_ok: bool,
+
+    /// True if the voltage did not drop more than allowed from idle.
+    pub voltage_ok: bool,
+}
+
+impl StepReport {
+    /// True if every check of the step passed.
+    pub fn passed(&self) -> bool {
+        self.current_ok && self.voltage_ok
+    }
+}
+
+/// Result of a commissioning run.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Report {
+    idle_voltage: f32,
+    steps: Vec<StepReport>,
+}
+
+impl Report {
+    /// Battery voltage measured before spinning the motors, in volts.
+    pub fn idle_voltage(&self) -> f32 {
+        self.idle_voltage
+    }
+
+    /// Reports of the individual steps, in execution order.
+    pub fn steps(&self) -> &[StepReport] {
+        &self.steps
+    }
+
+    /// True if every step passed.
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(StepReport::passed)
+    }
+}
+
+/// Commissioning test settings. See the [module documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct Commissioning {
+    power: f32,
+    settle_time: Duration,
+    min_current: f32,
+    max_current: f32,
+    max_voltage_drop: f32,
+}
+
+impl Commissioning {
+    /// Create commissioning settings with conservative defaults: 10% power,
+    /// 500 ms settle time, current between 0.2 A and 10 A, and at most 1 V of
+    /// battery voltage drop.
+    pub fn new() -> Commissioning {
+        Commissioning {
+            power: 0.1,
+            settle_time: Duration::from_millis(500),
+            min_current: 0.2,
+            max_current: 10.0,
+            max_voltage_drop: 1.0,
+        }
+    }
+
+    /// Set the power ratio applied during the steps, between 0.0 and 1.0.
+    pub fn with_power(mut self, power: f32) -> Self {
+        self.power = power;
+        self
+    }
+
+    /// Set the time to wait after applying power before measuring.
+    pub fn with_settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = settle_time;
+        self
+    }
+
+    /// Set the range of acceptable motor current, in amperes.
+    pub fn with_current_range(mut self, min: f32, max: f32) -> Self {
+        self.min_current = min;
+        self.max_current = max;
+        self
+    }
+
+    /// Set the maximum acceptable battery voltage drop under load, in volts.
+    pub fn with_max_voltage_drop(mut self, drop: f32) -> Self {
+        self.max_voltage_drop = drop;
+        self
+    }
+
+    /// Run the test on *saber*. The motors are stopped at the end of the run,
+    /// including when a communication error interrupts it.
+    pub fn run<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S) -> Result<Report> {
+        let res = self.run_steps(saber);
+        let stop = saber.stop_motors();
+        let report = res?;
+        stop?;
+        Ok(report)
+    }
+
+    fn run_steps<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S) -> Result<Report> {
+        let idle_voltage = saber.get_voltage(1)?;
+        let mut steps = Vec::new();
+
+        for &channel in [1, 2].iter() {
+            for &direction in [Direction::Forward, Direction::Backward].iter() {
+                let power = match direction {
+                    Direction::Forward => self.power,
+                    Direction::Backward => -self.power,
+                };
+
+                saber.set_power(channel, power)?;
+                thread::sleep(self.settle_time);
+                let current = saber.get_current(channel)?.abs();
+                let voltage = saber.get_voltage(channel)?;
+                saber.set_power(channel, 0.0)?;
+
+                steps.push(StepReport {
+                    channel,
+                    direction,
+                    current,
+                    voltage,
+                    current_ok: current >= self.min_current && current <= self.max_current,
+                    voltage_ok: idle_voltage - voltage <= self.max_voltage_drop,
+                });
+            }
+        }
+
+        Ok(Report {
+            idle_voltage,
+            steps,
+        })
+    }
+}
+
+impl Default for Commissioning {
+    fn default() -> Self {
+        Commissioning::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fake motor driver: the current follows the power, and the voltage sags
+    /// proportionally to the current.
+    struct FakeSaber {
+        power: [f32; 2],
+        amps_per_power: [f32; 2],
+    }
+
+    impl Sabertooth2x32 for FakeSaber {
+        fn startup(&mut self, _: usize) -> Result<()> {
+            Ok(())
+        }
+        fn shutdown(&mut self, _: usize) -> Result<()> {
+            Ok(())
+        }
+        fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+            self.set_power(channel, ratio)
+        }
+        fn get_speed(&mut self, channel: usize) -> Result<f32> {
+            self.get_power(channel)
+        }
+        fn set_drive(&mut self, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn set_turn(&mut self, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+            self.power[channel - 1] = ratio;
+            Ok(())
+        }
+        fn get_power(&mut self, channel: usize) -> Result<f32> {
+            Ok(self.power[channel - 1])
+        }
+        fn set_ramp(&mut self, _: usize, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn set_aux(&mut self, _: usize, _: f32) -> Result<()> {
+            Ok(())
+        }
+        fn get_voltage(&mut self, _: usize) -> Result<f32> {
+            let load: f32 = (0..2).map(|i| self.get_current(i + 1).unwrap().abs()).sum();
+            Ok(24.0 - 0.1 * load)
+        }
+        fn get_current(&mut self, channel: usize) -> Result<f32> {
+            Ok(self.power[channel - 1] * self.amps_per_power[channel - 1])
+        }
+        fn get_temperature(&mut self, _: usize) -> Result<f32> {
+            Ok(25.0)
+        }
+    }
+
+    fn commissioning() -> Commissioning {
+        Commissioning::new()
+            .with_power(0.2)
+            .with_settle_time(Duration::from_millis(0))
+            .with_current_range(1.0, 5.0)
+    }
+
+    #[test]
+    fn test_commissioning_pass() {
+        let mut saber = FakeSaber {
+            power: [0.0; 2],
+            amps_per_power: [10.0, 15.0],
+        };
+        let report = commissioning().run(&mut saber).unwrap();
+        assert_eq!(4, report.steps().len());
+        assert!(report.passed());
+        assert_eq!([0.0, 0.0], saber.power);
+    }
+
+    #[test]
+    fn test_commissioning_fail() {
+        // Channel 2 is disconnected, channel 1 draws too much current
+        let mut saber = FakeSaber {
+            power: [0.0; 2],
+            amps_per_power: [100.0, 0.0],
+        };
+        let report = commissioning()
+            .with_max_voltage_drop(5.0)
+            .run(&mut saber)
+            .unwrap();
+        assert!(!report.passed());
+        assert!(report.steps().iter().all(|s| !s.current_ok && s.voltage_ok));
+
+        let report = commissioning().run(&mut saber).unwrap();
+        assert!(!report.steps()[0].voltage_ok);
+    }
+}
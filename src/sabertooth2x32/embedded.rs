@@ -0,0 +1,303 @@
+use alloc::string::ToString;
+
+use embedded_io::{Read, ReadExactError, Write};
+use log::debug;
+
+use super::commands::Command;
+use super::{Operation, PacketType, Sabertooth2x32};
+use crate::command_table::Protocol;
+use crate::error::{Error, Result};
+use crate::protocol::frame::{self, MAX_FRAME_SIZE};
+use crate::protocol::opcodes::sabertooth2x32 as op;
+use crate::utils;
+
+/// "Packet Serial" interface over an [embedded-io] transport, like the UART
+/// of a microcontroller, available without `std`.
+///
+/// It sends the same frames as [PacketSerial](struct.PacketSerial.html),
+/// with the same default address and packet type, but has none of its
+/// subsystems. `embedded-io` transports have no timeout: the transport must
+/// return an error, for example of kind `TimedOut`, for the getters not to
+/// wait forever for a reply which never comes. Its errors are returned as
+/// `Error::Transport`. A `&mut` reference to a transport is a transport as
+/// well, so the handle may borrow it. **Requires** the "embedded-io"
+/// feature.
+///
+/// # Example
+///
+/// ```rust
+/// use embedded_io::{Read, Write};
+/// use saberrs::sabertooth2x32::{EmbeddedPacketSerial, Sabertooth2x32};
+///
+/// fn drive<T: Read + Write>(uart: &mut T) -> saberrs::Result<f32> {
+///     let mut saber = EmbeddedPacketSerial::new(uart).with_address(129);
+///     saber.set_speed(1, 0.5)?;
+///     saber.get_voltage(1)
+/// }
+/// ```
+///
+/// [embedded-io]: https://crates.io/crates/embedded-io
+pub struct EmbeddedPacketSerial<T> {
+    dev: T,
+    address: u8,
+    packet_type: PacketType,
+}
+
+impl<T: Read + Write> EmbeddedPacketSerial<T> {
+    /// Create a new interface on *dev*, with the default address and packet
+    /// type.
+    pub fn new(dev: T) -> EmbeddedPacketSerial<T> {
+        EmbeddedPacketSerial {
+            dev,
+            address: op::MIN_ADDRESS,
+            packet_type: PacketType::CRC,
+        }
+    }
+
+    /// Set the address of the Sabertooth.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set the integrity protection type used for the frames.
+    pub fn with_packet_type(mut self, packet_type: PacketType) -> Self {
+        self.packet_type = packet_type;
+        self
+    }
+
+    /// Return the address of the Sabertooth.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Return the integrity protection type used for the frames.
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    /// Mutable reference to the transport.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.dev
+    }
+
+    /// Return the transport.
+    pub fn into_inner(self) -> T {
+        self.dev
+    }
+
+    /// Execute *operation*, and return the value for getters.
+    fn execute(&mut self, operation: Operation) -> Result<Option<f32>> {
+        let (command, channel, arg) = Command::from_operation(operation);
+        let spec = command.spec();
+        let target = spec.target(channel)?;
+        let mut buf = [0u8; MAX_FRAME_SIZE];
+        if !operation.is_get() {
+            let value = spec.raw_value(arg)?;
+            let len = frame::encode_set(
+                self.packet_type,
+                self.address,
+                spec.opcode,
+                value,
+                target,
+                &mut buf,
+            )?;
+            self.write_frame(&buf[..len])?;
+            return Ok(None);
+        }
+
+        let len = frame::encode_get(
+            self.packet_type,
+            self.address,
+            spec.opcode,
+            target,
+            &mut buf,
+        )?;
+        self.write_frame(&buf[..len])?;
+        let raw = self.read_reply(spec.opcode, target)?;
+        Ok(Some(spec.scale(raw, Protocol::Packetized)))
+    }
+
+    fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
+        debug!("tx = {:?}", txdata);
+        utils::write_embedded(&mut self.dev, txdata)
+    }
+
+    fn read_reply(&mut self, command: u8, source: [u8; 2]) -> Result<i32> {
+        let mut buf = [0u8; MAX_FRAME_SIZE];
+        let resp = &mut buf[..self.packet_type.frame_sizes().2];
+        match self.dev.read_exact(resp) {
+            Ok(()) => (),
+            Err(ReadExactError::UnexpectedEof) => {
+                return Err(Error::Response("incomplete reply".to_string()))
+            }
+            Err(ReadExactError::Other(e)) => return Err(utils::transport_error(e)),
+        }
+        debug!("rx = {:?}", resp);
+
+        let reply = frame::decode_reply(self.packet_type, resp)?;
+        if reply.address != self.address {
+            return Err(Error::Response("invalid address".to_string()));
+        }
+        if reply.command != command {
+            return Err(Error::Response("unexpected command".to_string()));
+        }
+        if reply.source != source {
+            return Err(Error::Response("invalid source".to_string()));
+        }
+        Ok(reply.value)
+    }
+}
+
+impl<T: Read + Write> Sabertooth2x32 for EmbeddedPacketSerial<T> {
+    fn startup(&mut self, channel: usize) -> Result<()> {
+        self.execute(Operation::Startup(channel)).map(drop)
+    }
+
+    fn shutdown(&mut self, channel: usize) -> Result<()> {
+        self.execute(Operation::Shutdown(channel)).map(drop)
+    }
+
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.execute(Operation::SetSpeed(channel, ratio)).map(drop)
+    }
+
+    fn get_speed(&mut self, channel: usize) -> Result<f32> {
+        value(self.execute(Operation::GetSpeed(channel)))
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        self.execute(Operation::SetDrive(ratio)).map(drop)
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        self.execute(Operation::SetTurn(ratio)).map(drop)
+    }
+
+    fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.execute(Operation::SetPower(channel, ratio)).map(drop)
+    }
+
+    fn get_power(&mut self, channel: usize) -> Result<f32> {
+        value(self.execute(Operation::GetPower(channel)))
+    }
+
+    fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.execute(Operation::SetRamp(channel, ratio)).map(drop)
+    }
+
+    fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.execute(Operation::SetAux(channel, ratio)).map(drop)
+    }
+
+    fn get_voltage(&mut self, channel: usize) -> Result<f32> {
+        value(self.execute(Operation::GetVoltage(channel)))
+    }
+
+    fn get_current(&mut self, channel: usize) -> Result<f32> {
+        value(self.execute(Operation::GetCurrent(channel)))
+    }
+
+    fn get_temperature(&mut self, channel: usize) -> Result<f32> {
+        value(self.execute(Operation::GetTemperature(channel)))
+    }
+}
+
+/// Value of a getter executed with `execute()`.
+fn value(res: Result<Option<f32>>) -> Result<f32> {
+    res.map(|value| value.unwrap_or_default())
+}
+
+impl<T: Read + Write> From<T> for EmbeddedPacketSerial<T> {
+    fn from(dev: T) -> Self {
+        EmbeddedPacketSerial::new(dev)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    /// Transport replying with the queued bytes, and timing out once they
+    /// are exhausted.
+    #[derive(Default)]
+    struct Uart {
+        written: Vec<u8>,
+        rx: VecDeque<u8>,
+    }
+
+    impl embedded_io::ErrorType for Uart {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl Read for Uart {
+        fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, Self::Error> {
+            match self.rx.pop_front() {
+                Some(byte) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                None => Err(embedded_io::ErrorKind::TimedOut),
+            }
+        }
+    }
+
+    impl Write for Uart {
+        fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, Self::Error> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> core::result::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_set() {
+        let mut uart = Uart::default();
+        let mut saber = EmbeddedPacketSerial::new(&mut uart).with_packet_type(PacketType::Checksum);
+        saber.set_speed(1, 0.5).unwrap();
+        saber.startup(2).unwrap();
+        assert!(saber.set_speed(3, 0.5).is_err());
+        assert_eq!(
+            &b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04\x80\x28\x20\x48\x00\x00\x4d\x32\x7f"[..],
+            &uart.written[..]
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let mut saber =
+            EmbeddedPacketSerial::new(Uart::default()).with_packet_type(PacketType::Checksum);
+        let uart = saber.get_mut();
+        uart.rx.extend(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76");
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(
+            b"\x80\x29\x10\x39\x4d\x31\x7e",
+            &saber.get_mut().written[..]
+        );
+
+        assert!(matches!(
+            saber.get_voltage(1),
+            Err(Error::Transport(embedded_io::ErrorKind::TimedOut))
+        ));
+        saber
+            .get_mut()
+            .rx
+            .extend(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76");
+        assert!(matches!(saber.get_voltage(2), Err(Error::Response(_))));
+
+        // Reply to another command
+        saber
+            .get_mut()
+            .rx
+            .extend(b"\x80\x49\x20\x69\x02\x00\x4d\x31\x00");
+        match saber.get_voltage(1) {
+            Err(Error::Response(msg)) => assert_eq!("unexpected command", msg),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -1,71 +1,864 @@
-use crate::Result;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
+use crate::command_table;
+use crate::protocol::packet;
+use crate::{Error, FailsafeAction, Result};
+use commands::Command;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+pub(crate) mod commands;
+#[cfg(feature = "embedded-io")]
+mod embedded;
+#[cfg(feature = "std")]
 mod packetserial;
+#[cfg(feature = "std")]
 mod plaintext;
 
-pub use packetserial::{PacketSerial, PacketType, DEFAULT_ADDRESS, DEFAULT_PACKET_TYPE};
+#[cfg(feature = "std")]
+pub mod commissioning;
+#[cfg(feature = "std")]
+pub mod provision;
+#[cfg(feature = "std")]
+pub mod recovery;
+#[cfg(feature = "std")]
+pub mod settings;
+#[cfg(feature = "std")]
+pub mod soak;
+#[cfg(feature = "std")]
+pub mod sweep;
+
+pub use crate::protocol::addressing::{DataKind, DataSource, DataTarget};
+pub use crate::protocol::packet::quirks::{QuirksProfile, MAX_REPLY_PADDING};
+pub use crate::protocol::packet::strictness::{Mismatch, Strictness};
+pub use crate::protocol::packet::PacketType;
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncPacketSerial, AsyncPlainText, AsyncSabertooth2x32};
+#[cfg(feature = "embedded-io")]
+pub use embedded::EmbeddedPacketSerial;
+#[cfg(feature = "std")]
+pub use packetserial::{PacketSerial, DEFAULT_ADDRESS, DEFAULT_PACKET_TYPE, MAX_SERIAL_TIMEOUT};
+#[cfg(feature = "std")]
 pub use plaintext::PlainText;
 
+#[cfg(feature = "emulator")]
+pub(crate) use crate::protocol::packet::encode_reply;
+
 /// Trait exposing the available methods for controlling the Sabertooth 2x32.
 /// Note: implementors may also provide additional methods.
+///
+/// The setters take plain `f32` ratios. [RatioSetters] provides variants
+/// taking a [Ratio] or a [Percent], for catching unit mistakes.
+///
+/// [RatioSetters]: ../trait.RatioSetters.html
+/// [Ratio]: ../struct.Ratio.html
+/// [Percent]: ../struct.Percent.html
 pub trait Sabertooth2x32 {
     ///  Returns the motor channel from a shutdown state to normal operation.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::Startup(1);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x20\x48\x00\x00\x4d\x31\x7e");
+    /// assert_eq!(text.encode(op).unwrap(), b"M1: startup\r\n");
+    /// ```
     fn startup(&mut self, channel: usize) -> Result<()>;
 
     /// Shuts off the motor output. Using the shutdown command will put the motor
     /// in a hard brake state.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::Shutdown(2);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x20\x48\x01\x00\x4d\x32\x00");
+    /// assert_eq!(text.encode(op).unwrap(), b"M2: shutdown\r\n");
+    /// ```
     fn shutdown(&mut self, channel: usize) -> Result<()>;
 
     /// Set the speed of the selected motor.
     /// *channel* is 1 or 2, *ratio* is a ratio between -1.0 for full
     /// backward and 1.0 for full forward (so 0.0 stops the motor).
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::SetSpeed(2, 0.5);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x00\x28\x7f\x07\x4d\x32\x05");
+    /// assert_eq!(text.encode(op).unwrap(), b"M2: 1023\r\n");
+    /// ```
     fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
     /// Get the current speed of the motor. See set_motor() for the values range.
+    ///
+    /// This is the speed *commanded* to the channel, after the ramping of the
+    /// driver, and not a measure of the motor speed nor the output power.
+    /// [get_commanded_speed()](#method.get_commanded_speed) is the same
+    /// getter with an explicit name.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::GetSpeed(2);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x29\x00\x29\x4d\x32\x7f");
+    /// assert_eq!(text.encode(op).unwrap(), b"M2: get\r\n");
+    /// ```
     fn get_speed(&mut self, channel: usize) -> Result<f32>;
 
+    /// Get the speed commanded to the motor, as a ratio between -1.0 and
+    /// 1.0. Same as [get_speed()](#method.get_speed), read from the "M"
+    /// source of the channel.
+    ///
+    /// To know what the driver actually applies to the motor, for example
+    /// while limited by the current or the battery voltage, use
+    /// [get_output_power()](#method.get_output_power).
+    fn get_commanded_speed(&mut self, channel: usize) -> Result<f32> {
+        self.get_speed(channel)
+    }
+
     /// Stop the motors, ie. set both speeds to zero.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::testing::{FrameTap, MockPort};
+    /// # let mut port = MockPort::new();
+    /// # let mut saber = PlainText::from(&port);
+    /// saber.stop_motors().unwrap();
+    /// assert_eq!(port.take_written(), b"M1: 0\r\nM2: 0\r\n");
+    /// ```
     fn stop_motors(&mut self) -> Result<()> {
         self.set_speed(1, 0.0)?;
         self.set_speed(2, 0.0)?;
         Ok(())
     }
 
+    /// Bring both motors to the failsafe end state given by *action*. The
+    /// handle is [disarmed](#method.disarm) first.
+    ///
+    /// - `HardBrake` shuts down both channels, then sets both speeds to zero
+    ///   so that the motors do not jump when started up again.
+    /// - `ZeroSpeed` is the same as [stop_motors()](#method.stop_motors).
+    /// - `Coast` is not available on the Sabertooth 2x32, which has no
+    ///   freewheel command, and returns `Error::Unsupported` without sending
+    ///   anything.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::FailsafeAction;
+    /// # use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::testing::{FrameTap, MockPort};
+    /// # let mut port = MockPort::new();
+    /// # let mut saber = PlainText::from(&port);
+    /// saber.emergency_stop(FailsafeAction::HardBrake).unwrap();
+    /// assert_eq!(port.take_written(), b"M1: shutdown\r\nM2: shutdown\r\nM1: 0\r\nM2: 0\r\n");
+    /// ```
+    fn emergency_stop(&mut self, action: FailsafeAction) -> Result<()> {
+        self.disarm();
+        match action {
+            FailsafeAction::HardBrake => {
+                self.shutdown(1)?;
+                self.shutdown(2)?;
+                self.stop_motors()
+            }
+            FailsafeAction::ZeroSpeed => self.stop_motors(),
+            FailsafeAction::Coast => Err(Error::Unsupported(
+                "the Sabertooth 2x32 cannot freewheel".to_string(),
+            )),
+        }
+    }
+
+    /// Stop both motors like [stop_motors()](#method.stop_motors), but try
+    /// the second motor even when the first one fails. Return the first
+    /// error, if any.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::testing::{FrameTap, MockPort};
+    /// # let mut port = MockPort::new();
+    /// # let mut saber = PlainText::from(&port);
+    /// saber.all_stop().unwrap();
+    /// assert_eq!(port.take_written(), b"M1: 0\r\nM2: 0\r\n");
+    /// ```
+    fn all_stop(&mut self) -> Result<()> {
+        let res1 = self.set_speed(1, 0.0);
+        let res2 = self.set_speed(2, 0.0);
+        res1.and(res2)
+    }
+
+    /// Let both motors freewheel, like
+    /// [emergency_stop(FailsafeAction::Coast)](#method.emergency_stop).
+    /// Implementors for devices able to freewheel override `emergency_stop()`.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::testing::{FrameTap, MockPort};
+    /// # let mut port = MockPort::new();
+    /// # let mut saber = PlainText::from(&port);
+    /// assert!(saber.coast_all().is_err());
+    /// assert_eq!(port.take_written(), b"");
+    /// ```
+    fn coast_all(&mut self) -> Result<()> {
+        self.emergency_stop(FailsafeAction::Coast)
+    }
+
+    /// Drive in mixed mode, with a single call: *drive* is between -1.0 for
+    /// full backward and 1.0 for full forward, *turn* between -1.0 for full
+    /// left and 1.0 for full right. See [set_drive()](#tymethod.set_drive)
+    /// and [set_turn()](#tymethod.set_turn).
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::testing::{FrameTap, MockPort};
+    /// # let mut port = MockPort::new();
+    /// # let mut saber = PlainText::from(&port);
+    /// saber.drive_arcade(0.5, 0.25).unwrap();
+    /// assert_eq!(port.take_written(), b"MD: 1023\r\nMT: 511\r\n");
+    /// ```
+    fn drive_arcade(&mut self, drive: f32, turn: f32) -> Result<()> {
+        self.set_drive(drive)?;
+        self.set_turn(turn)
+    }
+
+    /// Set the speeds of both motors, *left* on channel 1 and *right* on
+    /// channel 2.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::testing::{FrameTap, MockPort};
+    /// # let mut port = MockPort::new();
+    /// # let mut saber = PlainText::from(&port);
+    /// saber.drive_tank(-1.0, 0.5).unwrap();
+    /// assert_eq!(port.take_written(), b"M1: -2047\r\nM2: 1023\r\n");
+    /// ```
+    fn drive_tank(&mut self, left: f32, right: f32) -> Result<()> {
+        self.set_speed(1, left)?;
+        self.set_speed(2, right)
+    }
+
     /// Set the drive. *ratio* is a ratio between -1.0 for full backward
     /// and 1.0 for full forward.
     /// Note: Both set_drive() and set_turn() must have been set at least once
     /// for having an effect.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::SetDrive(-0.5);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x01\x29\x7f\x07\x4d\x44\x17");
+    /// assert_eq!(text.encode(op).unwrap(), b"MD: -1023\r\n");
+    /// ```
     fn set_drive(&mut self, ratio: f32) -> Result<()>;
 
     /// Set the turn value. *ratio* is a ratio between -1.0 for full
     /// left and 1.0 for full right.
     /// Note: Both set_drive() and set_turn() must have been set at least once
     /// for having an effect.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::SetTurn(0.25);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x00\x28\x7f\x03\x4d\x54\x23");
+    /// assert_eq!(text.encode(op).unwrap(), b"MT: 511\r\n");
+    /// ```
     fn set_turn(&mut self, ratio: f32) -> Result<()>;
 
     /// Set the power output of the selected motor. *channel* is 1 or 2, and
     /// *ratio* is a ratio between -1.0 and 1.0.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::SetPower(1, -1.0);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x01\x29\x7f\x0f\x50\x31\x0f");
+    /// assert_eq!(text.encode(op).unwrap(), b"P1: -2047\r\n");
+    /// ```
     fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
     /// Return the current power output of the motor. *channel* is 1 or 2, and
     /// the returned value is a ratio between -1.0 and 1.0.
+    ///
+    /// This is the power the driver *outputs* to the motor, which differs
+    /// from the commanded speed while ramping, current limiting or braking.
+    /// [get_output_power()](#method.get_output_power) is the same getter with
+    /// an explicit name.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::GetPower(1);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x29\x00\x29\x50\x31\x01");
+    /// assert_eq!(text.encode(op).unwrap(), b"P1: get\r\n");
+    /// ```
     fn get_power(&mut self, channel: usize) -> Result<f32>;
 
+    /// Get the power output to the motor, as a ratio between -1.0 and 1.0.
+    /// Same as [get_power()](#method.get_power), read from the "P" source of
+    /// the channel.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// use saberrs::testing::{FrameTap, MockPort};
+    ///
+    /// # fn example() -> saberrs::Result<()> {
+    /// let mut port = MockPort::new();
+    /// let mut saber = PlainText::from(&port);
+    ///
+    /// // Channel 1 commanded at full speed, but limited to half power
+    /// port.queue_reply(b"M1: 2047\r\n");
+    /// port.queue_reply(b"P1: 1023\r\n");
+    /// assert_eq!(1.0, saber.get_commanded_speed(1)?);
+    /// assert!((saber.get_output_power(1)? - 0.5).abs() < 0.001);
+    /// assert_eq!(port.take_written(), b"M1: get\r\nP1: get\r\n");
+    /// # Ok(())
+    /// # }
+    /// # example().unwrap();
+    /// ```
+    fn get_output_power(&mut self, channel: usize) -> Result<f32> {
+        self.get_power(channel)
+    }
+
     /// Set the speed ramping of the motor.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::SetRamp(1, 0.25);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x00\x28\x7f\x03\x52\x31\x05");
+    /// assert_eq!(text.encode(op).unwrap(), b"R1: 511\r\n");
+    /// ```
     fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
+    /// Set the auxiliary output of the selected channel. *ratio* is a ratio
+    /// between -1.0 and 1.0.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::SetAux(2, 0.5);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x28\x00\x28\x7f\x07\x51\x32\x09");
+    /// assert_eq!(text.encode(op).unwrap(), b"Q2: 1023\r\n");
+    /// ```
     fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
     /// Get the battery voltage on the selected motor, in volts.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::GetVoltage(1);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x29\x10\x39\x4d\x31\x7e");
+    /// assert_eq!(text.encode(op).unwrap(), b"M1: getb\r\n");
+    /// ```
     fn get_voltage(&mut self, channel: usize) -> Result<f32>;
 
     /// Get the motor current in amperes. Positive current values mean energy is
     /// being drawn from the battery, and negative values indicate energy is
     /// being regenerated into the battery. Note: this noisy signal may vary by
     /// several amps, this is normal.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::GetCurrent(1);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x29\x20\x49\x4d\x31\x7e");
+    /// assert_eq!(text.encode(op).unwrap(), b"M1: getc\r\n");
+    /// ```
     fn get_current(&mut self, channel: usize) -> Result<f32>;
 
     /// Get the temperature of the output transistors for this channel, in
     /// degrees celsius.
+    ///
+    /// # Example
+    ///
+    /// Frames of `PacketSerial` with checksum and of `PlainText`:
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType, PlainText};
+    /// # use saberrs::testing::MockPort;
+    /// # let packet = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// # let text = PlainText::from(MockPort::new());
+    /// let op = Operation::GetTemperature(2);
+    /// assert_eq!(packet.encode(op).unwrap(), b"\x80\x29\x40\x69\x4d\x32\x7f");
+    /// assert_eq!(text.encode(op).unwrap(), b"M2: gett\r\n");
+    /// ```
     fn get_temperature(&mut self, channel: usize) -> Result<f32>;
+
+    /// Infer whether *channel* latched a fault which shut down its output,
+    /// like an overcurrent, an overheat or a battery cutoff.
+    ///
+    /// The Sabertooth 2x32 has no fault register, so this is not a read of
+    /// the device state but a heuristic on the telemetry, which may report
+    /// false positives and miss faults. The commanded speed and the output
+    /// power are read, and the channel is considered faulted when the
+    /// magnitude of the speed is above 1 % while the one of the power is at
+    /// most 1 %. The voltage and the temperature are then read into the
+    /// returned [Fault], for telling the causes apart.
+    ///
+    /// A channel commanded at zero is never reported, since it outputs
+    /// nothing anyway. Neither is a channel shut down on purpose, according
+    /// to [shutdown_commanded()](#method.shutdown_commanded), in which case
+    /// nothing is read.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// use saberrs::testing::MockPort;
+    ///
+    /// # fn example() -> saberrs::Result<()> {
+    /// // Channel 1 commanded at full speed, but outputting nothing
+    /// let port = MockPort::with_responder(|frame| match frame {
+    ///     b"M1: get\r\n" => b"M1: 2047\r\n".to_vec(),
+    ///     b"P1: get\r\n" => b"P1: 0\r\n".to_vec(),
+    ///     b"M1: getb\r\n" => b"M1:B240\r\n".to_vec(),
+    ///     b"M1: gett\r\n" => b"M1:T95\r\n".to_vec(),
+    ///     _ => Vec::new(),
+    /// });
+    /// let mut saber = PlainText::from(port);
+    ///
+    /// if let Some(fault) = saber.infer_fault(1)? {
+    ///     assert_eq!(95.0, fault.temperature);
+    ///     saber.restart_channel(1)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # example().unwrap();
+    /// ```
+    ///
+    /// [Fault]: struct.Fault.html
+    fn infer_fault(&mut self, channel: usize) -> Result<Option<Fault>> {
+        if self.shutdown_commanded(channel) {
+            return Ok(None);
+        }
+        let commanded = self.get_speed(channel)?;
+        let power = self.get_power(channel)?;
+        if !is_shut_down(commanded, power) {
+            return Ok(None);
+        }
+        Ok(Some(Fault {
+            commanded,
+            voltage: self.get_voltage(channel)?,
+            temperature: self.get_temperature(channel)?,
+        }))
+    }
+
+    /// Restart *channel* after an inferred fault, with the startup command
+    /// which resets the latch of the device. This is the same as
+    /// [startup()](#method.startup), with a name telling the intent, and
+    /// nothing checks that a fault was actually cleared.
+    fn restart_channel(&mut self, channel: usize) -> Result<()> {
+        self.startup(channel)
+    }
+
+    /// Whether the last of [startup()](#method.startup) and
+    /// [shutdown()](#method.shutdown) sent by the handle on *channel* was a
+    /// shutdown. Always false on the handles which do not track their
+    /// commands.
+    fn shutdown_commanded(&self, channel: usize) -> bool {
+        let _ = channel;
+        false
+    }
+
+    /// Allow the motion commands on a handle created with an interlock, like
+    /// `PacketSerial::with_interlock()`. Does nothing on the handles without
+    /// interlock, which are always armed.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::Error;
+    /// # use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::testing::{FrameTap, MockPort};
+    /// # let mut port = MockPort::new();
+    /// let mut saber = PlainText::from(&port).with_interlock();
+    /// assert!(matches!(saber.set_speed(1, 0.5), Err(Error::NotArmed)));
+    /// saber.arm();
+    /// saber.set_speed(1, 0.5).unwrap();
+    /// assert_eq!(port.take_written(), b"M1: 1023\r\n");
+    /// ```
+    fn arm(&mut self) {}
+
+    /// Reject the motion commands again until the next
+    /// [arm()](#method.arm). Stopping and reading stay allowed. Called by
+    /// the failsafe paths, like [emergency_stop()](#method.emergency_stop)
+    /// and a tripping [SoftFuse](../struct.SoftFuse.html). Does nothing on
+    /// the handles without interlock.
+    fn disarm(&mut self) {}
+
+    /// Whether the motion commands are allowed: always true on the handles
+    /// without interlock.
+    fn is_armed(&self) -> bool {
+        true
+    }
+
+    /// How the handle performs *operation*, for deciding at runtime whether
+    /// to call it, to emulate it, or to disable the corresponding control.
+    /// `PacketSerial` and `PlainText` support every operation natively.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```rust")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// # use saberrs::sabertooth2x32::{Operation, PlainText, Sabertooth2x32, Support};
+    /// # use saberrs::testing::MockPort;
+    /// let saber = PlainText::from(MockPort::new());
+    /// assert_eq!(Support::Native, saber.supports(Operation::GetTemperature(1)));
+    /// ```
+    fn supports(&self, operation: Operation) -> Support {
+        let _ = operation;
+        Support::Native
+    }
+}
+
+/// Ratio under which the output power or the commanded speed is considered
+/// to be zero.
+pub(crate) const POWER_THRESHOLD: f32 = 0.01;
+
+/// Whether a channel commanded at *commanded* and outputting *power* looks
+/// shut down.
+pub(crate) fn is_shut_down(commanded: f32, power: f32) -> bool {
+    commanded.abs() > POWER_THRESHOLD && power.abs() <= POWER_THRESHOLD
+}
+
+/// Fault inferred on a channel from its telemetry, returned by
+/// [Sabertooth2x32::infer_fault()](trait.Sabertooth2x32.html#method.infer_fault)
+/// with the readings made when it was detected. It is not read from the
+/// device, which has no fault register.
+///
+/// A temperature near the thermal limit points to an overheat, and a low
+/// voltage to the battery cutoff. Otherwise the cause is likely an
+/// overcurrent, or a shutdown sent by another handle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fault {
+    /// Speed commanded to the channel, as a ratio.
+    pub commanded: f32,
+
+    /// Battery voltage, in volts.
+    pub voltage: f32,
+
+    /// Temperature of the channel, in degrees Celsius.
+    pub temperature: f32,
+}
+
+/// Operation of the [Sabertooth2x32] trait, with its arguments.
+///
+/// It is used for describing commands ahead of sending them, like in
+/// [CommandQueue](../struct.CommandQueue.html) or in test transcripts.
+///
+/// [Sabertooth2x32]: trait.Sabertooth2x32.html
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operation {
+    Startup(usize),
+    Shutdown(usize),
+    SetSpeed(usize, f32),
+    GetSpeed(usize),
+    SetDrive(f32),
+    SetTurn(f32),
+    SetPower(usize, f32),
+    GetPower(usize),
+    SetRamp(usize, f32),
+    SetAux(usize, f32),
+    GetVoltage(usize),
+    GetCurrent(usize),
+    GetTemperature(usize),
+}
+
+impl Operation {
+    /// Execute the operation on *saber*, and return the value for getters.
+    pub fn apply<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S) -> Result<Option<f32>> {
+        match *self {
+            Operation::Startup(ch) => saber.startup(ch).map(|_| None),
+            Operation::Shutdown(ch) => saber.shutdown(ch).map(|_| None),
+            Operation::SetSpeed(ch, r) => saber.set_speed(ch, r).map(|_| None),
+            Operation::GetSpeed(ch) => saber.get_speed(ch).map(Some),
+            Operation::SetDrive(r) => saber.set_drive(r).map(|_| None),
+            Operation::SetTurn(r) => saber.set_turn(r).map(|_| None),
+            Operation::SetPower(ch, r) => saber.set_power(ch, r).map(|_| None),
+            Operation::GetPower(ch) => saber.get_power(ch).map(Some),
+            Operation::SetRamp(ch, r) => saber.set_ramp(ch, r).map(|_| None),
+            Operation::SetAux(ch, r) => saber.set_aux(ch, r).map(|_| None),
+            Operation::GetVoltage(ch) => saber.get_voltage(ch).map(Some),
+            Operation::GetCurrent(ch) => saber.get_current(ch).map(Some),
+            Operation::GetTemperature(ch) => saber.get_temperature(ch).map(Some),
+        }
+    }
+
+    /// Whether the operation can move a motor: a non-zero speed, drive, turn
+    /// or power, or a startup, which resumes the setpoint the channel had
+    /// before its shutdown. These are the operations rejected by a disarmed
+    /// interlock.
+    pub fn is_motion(&self) -> bool {
+        match *self {
+            Operation::Startup(_) => true,
+            Operation::SetSpeed(_, r)
+            | Operation::SetDrive(r)
+            | Operation::SetTurn(r)
+            | Operation::SetPower(_, r) => r != 0.0,
+            _ => false,
+        }
+    }
+
+    /// Whether the operation reads a value from the device.
+    pub fn is_get(&self) -> bool {
+        matches!(
+            self,
+            Operation::GetSpeed(_)
+                | Operation::GetPower(_)
+                | Operation::GetVoltage(_)
+                | Operation::GetCurrent(_)
+                | Operation::GetTemperature(_)
+        )
+    }
+}
+
+/// How a handle performs an [Operation], returned by the `supports()`
+/// methods of the handles.
+///
+/// [Operation]: enum.Operation.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Support {
+    /// The device has a command for the operation.
+    Native,
+
+    /// The handle performs the operation in software with other commands,
+    /// like the mixing of the drive and turn from the motor speeds.
+    Emulated,
+
+    /// The operation always fails with `Error::Unsupported`.
+    Unsupported,
+}
+
+/// Machine-readable description of the commands of both protocols, in JSON,
+/// generated from the command table of the handles, for keeping external
+/// tools like GUIs or protocol analyzers in sync with the crate.
+///
+/// The document holds the version of its layout in `"format"`, and the
+/// version of the crate in `"crate_version"`. Each command is described
+/// with its name, its target bytes, its "Packet Serial" opcode, its value
+/// scaling and its "Plain Text" keyword and reply prefix. A copy is kept in
+/// `docs/sabertooth2x32.json`, so that its changes appear in the history of
+/// the repository.
+///
+/// # Example
+///
+/// ```rust
+/// let json = saberrs::sabertooth2x32::conformance_json();
+/// assert!(json.contains(r#""name": "GetVoltage""#));
+/// ```
+pub fn conformance_json() -> String {
+    let packet = command_table::PacketCommands {
+        set: packet::CMD_NUM_SET,
+        get: packet::CMD_NUM_GET,
+        reply: packet::CMD_NUM_REPLY,
+    };
+    let table: Vec<_> = Command::ALL.iter().map(|command| command.spec()).collect();
+    command_table::conformance_json("sabertooth-2x32", &packet, &table)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_conveniences() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+
+        saber.drive_arcade(1.0, -1.0).unwrap();
+        saber.drive_tank(0.0, 1.0).unwrap();
+        saber.all_stop().unwrap();
+        assert_eq!(
+            port.take_written(),
+            &b"MD: 2047\r\nMT: -2047\r\nM1: 0\r\nM2: 2047\r\nM1: 0\r\nM2: 0\r\n"[..]
+        );
+
+        assert!(matches!(saber.coast_all(), Err(Error::Unsupported(_))));
+        assert!(port.take_written().is_empty());
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut port = MockPort::with_responder(|frame| match frame {
+            b"M1: get\r\n" => b"M1: 1023\r\n".to_vec(),
+            b"M2: get\r\n" => b"M2: 1023\r\n".to_vec(),
+            b"P1: get\r\n" => b"P1: 1000\r\n".to_vec(),
+            b"P2: get\r\n" => b"P2: 0\r\n".to_vec(),
+            b"M2: getb\r\n" => b"M2:B110\r\n".to_vec(),
+            b"M2: gett\r\n" => b"M2:T30\r\n".to_vec(),
+            _ => Vec::new(),
+        });
+        let mut saber = PlainText::from(&port);
+
+        assert_eq!(None, saber.infer_fault(1).unwrap());
+        assert_eq!(port.take_written(), b"M1: get\r\nP1: get\r\n");
+
+        let fault = saber.infer_fault(2).unwrap().unwrap();
+        assert_eq!(11.0, fault.voltage);
+        assert_eq!(30.0, fault.temperature);
+
+        saber.restart_channel(2).unwrap();
+        assert!(port.take_written().ends_with(b"M2: startup\r\n"));
+
+        // Shut down on purpose: nothing is read
+        saber.shutdown(2).unwrap();
+        port.take_written();
+        assert_eq!(None, saber.infer_fault(2).unwrap());
+        assert!(port.take_written().is_empty());
+    }
+
+    #[test]
+    fn test_fault_zero_command() {
+        let port = MockPort::with_responder(|frame| match frame {
+            b"M1: get\r\n" => b"M1: 0\r\n".to_vec(),
+            b"P1: get\r\n" => b"P1: 0\r\n".to_vec(),
+            _ => Vec::new(),
+        });
+        let mut saber = PlainText::from(&port);
+        assert_eq!(None, saber.infer_fault(1).unwrap());
+    }
+
+    #[test]
+    fn test_interlock() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_interlock();
+        assert!(!saber.is_armed());
+        assert!(matches!(saber.set_drive(0.5), Err(Error::NotArmed)));
+        assert!(matches!(saber.set_power(1, -0.1), Err(Error::NotArmed)));
+        assert!(port.take_written().is_empty());
+
+        // Stopping is always allowed
+        saber.stop_motors().unwrap();
+        saber.arm();
+        saber.set_drive(0.5).unwrap();
+        // Two stops and the drive, 10 bytes each
+        assert_eq!(30, port.take_written().len());
+
+        saber.emergency_stop(FailsafeAction::ZeroSpeed).unwrap();
+        assert_eq!(20, port.take_written().len());
+        assert!(!saber.is_armed());
+        assert!(matches!(saber.set_speed(2, 1.0), Err(Error::NotArmed)));
+
+        // A startup would resume the setpoint from before the shutdown
+        saber.disarm();
+        assert!(matches!(saber.startup(1), Err(Error::NotArmed)));
+        assert!(matches!(saber.restart_channel(1), Err(Error::NotArmed)));
+        assert!(port.take_written().is_empty());
+
+        // No interlock
+        let mut saber = PlainText::from(&port);
+        saber.disarm();
+        assert!(saber.is_armed());
+        saber.set_speed(2, 1.0).unwrap();
+    }
 }
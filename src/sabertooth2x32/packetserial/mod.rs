@@ -1,88 +1,89 @@
+use std::collections::VecDeque;
+use std::io;
+#[cfg(all(unix, feature = "mio"))]
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
 #[allow(unused_imports)]
 use log::debug;
+#[cfg(all(unix, feature = "mio"))]
+use mio::unix::SourceFd;
 
+use crate::bandwidth;
+use crate::command_table::Protocol;
+use crate::diagnostics::{self, Diagnostics, PortSettings};
 use crate::error::{Error, Result};
-use crate::port::SabertoothSerial;
-use crate::sabertooth2x32::Sabertooth2x32;
-use crate::utils;
+use crate::filter::{CommandFilter, FilterEvent, FilterSlot};
+use crate::framelog::{FrameFormat, FrameLog, FrameLogging, FrameSink};
+use crate::health::Health;
+use crate::history::History;
+use crate::port::{ReadStrategy, SabertoothSerial, Yield};
+use crate::protocol::addressing::DataSource;
+use crate::protocol::opcodes::sabertooth2x32 as op;
+use crate::protocol::packet::quirks::QuirksProfile;
+use crate::protocol::packet::strictness::{Mismatch, Strictness};
+use crate::protocol::packet::{
+    frame_start, packet_is_valid, unpack_data_value, PacketFrame, PacketType, ParseError,
+    CMD_NUM_REPLY, PACKET_MAX_REPLY_SIZE,
+};
+use crate::protocol::scaling::{Scaling, ScalingProfile};
+use crate::protocol::transform::{FrameTransform, Passthrough};
+#[cfg(feature = "control")]
+use crate::retry::RetryBudget;
+use crate::sabertooth2x32::commands::Command;
+use crate::sabertooth2x32::recovery::{Diagnosis, Recovery, SniffReport, SniffedPort, Sniffer};
+use crate::sabertooth2x32::settings::{Setting, SettingValue, Settings};
+use crate::sabertooth2x32::PlainText;
+use crate::sabertooth2x32::{Operation, Sabertooth2x32};
+use crate::state::{ChannelState, ChannelStates};
+use crate::stats::{LatencyAlert, Stats};
+use crate::telemetry::Quantity;
+#[cfg(feature = "emulator")]
+use crate::testing::DryRun;
+#[cfg(any(test, feature = "emulator"))]
+use crate::testing::FaultInjector;
+use crate::timeout::AdaptiveTimeout;
+use crate::units::Ratio;
+use crate::utils::{self, ReplyTiming};
 
+#[cfg(feature = "serialport")]
+use crate::addressbook::AddressBook;
 #[cfg(feature = "serialport")]
 use crate::port::sabertoothport::SabertoothPort;
 
-mod checksum;
-mod crc;
-
-#[cfg(debug_assertions)]
-macro_rules! dbg_frame {
-    ($head:ident, $frame:expr) => {
-        debug!("{} = {:?}", stringify!($head), $frame);
-    };
-}
-
-#[cfg(not(debug_assertions))]
-macro_rules! dbg_frame {
-    ($head:ident, $frame:expr) => {};
-}
-
 /// Default address for packet communication.
-pub const DEFAULT_ADDRESS: u8 = 128;
+pub const DEFAULT_ADDRESS: u8 = op::MIN_ADDRESS;
 
 /// Default packet type when creating a [PacketSerial](struct.PacketSerial.html)
 pub const DEFAULT_PACKET_TYPE: PacketType = PacketType::CRC;
 
-const CMD_NUM_SET: u8 = 40;
-const CMD_NUM_GET: u8 = 41;
-const CMD_NUM_REPLY: u8 = 73;
-
-const PACKET_MAX_REPLY_SIZE: usize = crc::PACKET_REPLY_SIZE;
-
-/// Type of frame protection for [PacketSerial](struct.PacketSerial.html).
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum PacketType {
-    /// Manual extract:
-    /// > * good for most applications
-    /// > * easier to implement
-    /// > * faster updates (uses one less byte per command than a CRC)
-    Checksum,
-
-    /// Manual extract:
-    /// > * good for safety-critical applications with noisier wiring
-    /// > * harder to implement
-    /// > * slower updates (uses one more byte per command than a checksum)
-    /// > * provides a Hamming distance of 4
-    CRC,
-}
+/// Longest serial timeout accepted by
+/// [PacketSerial::set_serial_timeout()](struct.PacketSerial.html#method.set_serial_timeout),
+/// the largest data value of a frame in milliseconds.
+pub const MAX_SERIAL_TIMEOUT: Duration = Duration::from_millis(16383);
 
-pub enum ParseError {
-    PacketSize,
-    ChecksumError,
-    AddressError,
-}
+/// Maximum number of bytes discarded while looking for the start of a reply.
+const MAX_RESYNC_BYTES: usize = 64;
 
-#[allow(unused)]
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum CommandSet {
-    Value = 0,
-    KeepAlive = 16,
-    Shutdown = 32, // can also be used for startup (name follows the doc)
-    Timeout = 64,
-}
+/// Maximum number of replies skipped for a single request.
+const MAX_SKIPPED_REPLIES: usize = 8;
 
-#[allow(unused)]
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum CommandGet {
-    Value = 0,
-    Battery = 16,
-    Current = 32,
-    Temperature = 64,
-}
+/// Maximum number of pending mismatch events, older events are dropped.
+const MAX_MISMATCHES: usize = 32;
 
-fn pack_data_value(value: u16) -> [u8; 2] {
-    [(value & 127) as u8, ((value >> 7) & 127) as u8]
+/// Get request waiting for its reply, see `PacketSerial::start_get()`.
+#[derive(Clone, Copy, Debug)]
+struct PendingGet {
+    command: Command,
+    source: [u8; 2],
+    buf: [u8; PACKET_MAX_REPLY_SIZE],
+    len: usize,
 }
 
-fn unpack_data_value(buf: &[u8]) -> u16 {
-    u16::from(buf[0] & 127) + (u16::from(buf[1] & 127) << 7)
+/// Command and source of the get request *operation*.
+fn get_request(operation: Operation) -> Result<(Command, [u8; 2])> {
+    let (command, channel, _) = Command::from_get(operation)?;
+    Ok((command, command.spec().target(channel)?))
 }
 
 /// Interface using the "Packet Serial" protocol with checksum or CRC.
@@ -90,6 +91,39 @@ pub struct PacketSerial<T: SabertoothSerial> {
     dev: T,
     address: u8,
     packet_type: PacketType,
+    stats: Stats,
+    health: Health,
+    history: History,
+    frame_log: FrameLog,
+    name: Option<String>,
+    transform: Box<dyn FrameTransform + Send>,
+    scaling: Box<dyn Scaling + Send>,
+    fallback_threshold: Option<u32>,
+    #[cfg(feature = "control")]
+    retry_budget: Option<RetryBudget>,
+    reply_failures: u32,
+    pending: Option<PendingGet>,
+    strictness: Strictness,
+    mismatches: VecDeque<Mismatch>,
+    quirks: QuirksProfile,
+    baud_rate: Option<u32>,
+    latency_alert: Option<LatencyAlert>,
+    write_deadline: Option<Duration>,
+    read_strategy: ReadStrategy,
+    #[cfg(any(test, feature = "emulator"))]
+    faults: utils::Faults,
+    yield_hook: utils::YieldHook,
+    armed: Option<bool>,
+    recovery: Option<(u32, Recovery)>,
+    recovery_failures: u32,
+    diagnosis: Option<Diagnosis>,
+    unanswered: Option<(u8, [u8; 2])>,
+    reply_barrier: Option<Duration>,
+    reply_timing: ReplyTiming,
+    channels: ChannelStates,
+    filter: FilterSlot,
+    sniffer: Option<Sniffer>,
+    settings: Settings,
 }
 
 #[cfg(feature = "serialport")]
@@ -107,6 +141,64 @@ impl PacketSerial<SabertoothPort> {
     pub fn new(port: &str) -> Result<PacketSerial<SabertoothPort>> {
         Ok(PacketSerial::from(SabertoothPort::new(port)?))
     }
+
+    /// Open a serial port at *baud_rate* with *timeout*, and return a new
+    /// instance of `PacketSerial` with default settings. For the other port
+    /// settings, configure a `SabertoothPort` and convert it with `from()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// let saber = PacketSerial::open_with("/dev/ttyUSB0", 115_200, Duration::from_millis(20));
+    /// ```
+    pub fn open_with(
+        port: &str,
+        baud_rate: u32,
+        timeout: Duration,
+    ) -> Result<PacketSerial<SabertoothPort>> {
+        Ok(PacketSerial::from(SabertoothPort::open_with(
+            port, baud_rate, timeout,
+        )?))
+    }
+
+    /// Open the device named *name* in the address book. The port, the
+    /// address and the baud rate, if any, are taken from the book, and the
+    /// handle is named after the device.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::AddressBook;
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    ///
+    /// let mut book = AddressBook::new();
+    /// book.insert("left-drive", "/dev/ttyUSB0", 129);
+    /// let saber = PacketSerial::open_named(&book, "left-drive");
+    /// ```
+    pub fn open_named(book: &AddressBook, name: &str) -> Result<PacketSerial<SabertoothPort>> {
+        let (port, address) = book.lookup(name).ok_or_else(|| {
+            Error::InvalidInput(format!("no device named {:?} in the address book", name))
+        })?;
+        let saber = PacketSerial::new(port)?
+            .with_address(address)
+            .with_name(name);
+        Ok(match book.baud_rate(name) {
+            Some(baud_rate) => saber.with_baud_rate(baud_rate),
+            None => saber,
+        })
+    }
+}
+
+#[cfg(feature = "emulator")]
+impl PacketSerial<DryRun> {
+    /// Return a new instance of `PacketSerial` with default settings on a
+    /// [DryRun](../testing/struct.DryRun.html), emulating the devices
+    /// without hardware. **Requires** the "emulator" feature.
+    pub fn dry_run() -> PacketSerial<DryRun> {
+        PacketSerial::from(DryRun::new())
+    }
 }
 
 impl<T: SabertoothSerial> PacketSerial<T> {
@@ -144,69 +236,1095 @@ impl<T: SabertoothSerial> PacketSerial<T> {
         self
     }
 
+    /// Switch to the other packet type after *threshold* consecutive getters
+    /// without a valid reply, and retry the request with it.
+    ///
+    /// This helps when the packet type of the device is unknown: frames of
+    /// the wrong type are ignored by the device or fail the integrity check.
+    /// The change is logged, and the packet type in use is given by
+    /// [packet_type()](#method.packet_type). If the other packet type fails
+    /// as well, the handle switches back after *threshold* more failures.
+    ///
+    /// Only the getters can detect a mismatch, since the device does not
+    /// acknowledge the setters.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_packet_type_fallback(3);
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_packet_type_fallback(mut self, threshold: u32) -> Self {
+        self.fallback_threshold = Some(threshold.max(1));
+        self
+    }
+
+    /// Take the retries of the packet type fallback, see
+    /// [with_packet_type_fallback()](#method.with_packet_type_fallback),
+    /// from *budget*, possibly shared with the
+    /// [Supervisor](../struct.Supervisor.html)s of the control cycle. The
+    /// other packet type is not tried while the budget is exhausted.
+    /// **Requires** the "control" feature.
+    #[cfg(feature = "control")]
+    pub fn with_retry_budget(mut self, budget: RetryBudget) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Return the packet type currently used.
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    /// Run *recovery* after *threshold* consecutive getters without a valid
+    /// reply, instead of timing out forever. The diagnosis is logged, and
+    /// the last one is given by [diagnosis()](#method.diagnosis). See the
+    /// [recovery](recovery/index.html) module.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// use saberrs::sabertooth2x32::recovery::Recovery;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_recovery(5, Recovery::new());
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_recovery(mut self, threshold: u32, recovery: Recovery) -> Self {
+        self.recovery = Some((threshold.max(1), recovery));
+        self
+    }
+
+    /// Last diagnosis of the recovery set with
+    /// [with_recovery()](#method.with_recovery), if it ran.
+    pub fn diagnosis(&self) -> Option<Diagnosis> {
+        self.diagnosis
+    }
+
+    /// Run a [Sniffer] over every byte received by the handle, including the
+    /// bytes of the incomplete or discarded replies, to tell which protocol the
+    /// device answers. Its report is given by
+    /// [sniff_report()](#method.sniff_report), and logged by the diagnosis.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x32::recovery::Traffic;
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+    /// use saberrs::testing::{FrameTap, MockPort};
+    ///
+    /// let mut port = MockPort::new();
+    /// let mut saber = PacketSerial::from(&port).with_sniffer();
+    /// port.queue_reply(b"M1:B240\r\n");
+    /// assert!(saber.get_voltage(1).is_err());
+    /// assert_eq!(Some(Traffic::PlainText), saber.sniff_report().map(|r| r.traffic()));
+    /// ```
+    ///
+    /// [Sniffer]: recovery/struct.Sniffer.html
+    pub fn with_sniffer(mut self) -> Self {
+        self.sniffer = Some(Sniffer::new());
+        self
+    }
+
+    /// Report of the sniffer set with [with_sniffer()](#method.with_sniffer).
+    pub fn sniff_report(&self) -> Option<SniffReport> {
+        self.sniffer.as_ref().map(Sniffer::report)
+    }
+
+    /// Convert the handle into a "Plain Text" handle on the same port, for a
+    /// device restarted in that mode. The name, the frame transform, the
+    /// command filter, the scaling and the baud rate are kept, and a handle with an interlock is
+    /// converted disarmed. The statistics, the health and the history start
+    /// afresh.
+    pub fn into_plain_text(self) -> PlainText<T> {
+        PlainText::from_parts(
+            self.dev,
+            self.name,
+            self.transform,
+            self.scaling,
+            self.baud_rate,
+            self.armed.map(|_| false),
+            self.reply_timing,
+        )
+        .with_channel_states(self.channels)
+        .with_filter_slot(self.filter)
+    }
+
+    pub(crate) fn port_mut(&mut self) -> &mut T {
+        &mut self.dev
+    }
+
+    pub(crate) fn set_packet_type(&mut self, packet_type: PacketType) {
+        self.packet_type = packet_type;
+    }
+
+    pub(crate) fn baud_rate_mut(&mut self) -> &mut Option<u32> {
+        &mut self.baud_rate
+    }
+
+    /// Send a get request with *packet_type*, and return whether the device
+    /// replied. Errors are only returned for failures of the port itself.
+    pub(crate) fn probe(&mut self, packet_type: PacketType) -> Result<bool> {
+        self.packet_type = packet_type;
+        let (command, source) = get_request(Operation::GetVoltage(1))?;
+        let value = self.request(command.spec().opcode, source);
+        match value {
+            Ok(_) => Ok(true),
+            _ if !reply_received(&value) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Set how the replies from another address or for another source than
+    /// the request are handled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{PacketSerial, Strictness};
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_strictness(Strictness::Skip);
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    /// Set the deviations of the replies from the specification, for
+    /// firmware revisions padding or shortening them. See
+    /// [QuirksProfile](struct.QuirksProfile.html).
+    pub fn with_quirks(mut self, quirks: QuirksProfile) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Return the deviations of the replies from the specification.
+    pub fn quirks(&self) -> &QuirksProfile {
+        &self.quirks
+    }
+
+    /// Return the strictness policy.
+    pub fn strictness(&self) -> Strictness {
+        self.strictness
+    }
+
+    /// Remove and return the mismatches accepted by the `Lenient`
+    /// strictness, oldest first.
+    pub fn drain_mismatches(&mut self) -> impl Iterator<Item = Mismatch> + '_ {
+        self.mismatches.drain(..)
+    }
+
+    /// Set a human-readable name for the device, used in the logs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_name("rear-axle");
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Apply *transform* to every frame before writing it, for adapters
+    /// which need the frames wrapped or escaped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::EscapedAddress;
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_frame_transform(EscapedAddress::new(0x1b));
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_frame_transform<F>(mut self, transform: F) -> Self
+    where
+        F: FrameTransform + Send + 'static,
+    {
+        self.transform = Box::new(transform);
+        self
+    }
+
+    /// Convert the values returned by the getters with *scaling*, for
+    /// calibrated hardware or model variants with another scale. See
+    /// [ScalingProfile](../struct.ScalingProfile.html).
+    pub fn with_scaling<S>(mut self, scaling: S) -> Self
+    where
+        S: Scaling + Send + 'static,
+    {
+        self.scaling = Box::new(scaling);
+        self
+    }
+
+    /// Re-assert *baud_rate* on the port before each transaction, for
+    /// handles sharing a port, for example through a multiplexer, with
+    /// devices at other rates. The port is only reconfigured when its rate
+    /// differs.
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// After a get request left without a reply, wait for the line to be
+    /// quiet for *quiet* before sending the next one, discarding the late
+    /// reply instead of reading it as the answer of the next request.
+    ///
+    /// Without a barrier, a late reply received in place of the current one
+    /// is still detected when the requests differ by their command or their
+    /// source, and `Error::StaleReply` is returned. The barrier also covers
+    /// the repeated requests, at the cost of *quiet* after each failure. It
+    /// does not apply to [start_get()](#method.start_get), which does not
+    /// block.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_reply_barrier(Duration::from_millis(20));
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_reply_barrier(mut self, quiet: Duration) -> Self {
+        self.reply_barrier = Some(quiet);
+        self
+    }
+
+    /// Wait *grace* after writing a get request before reading its reply,
+    /// for links where the request reaches the device late, like
+    /// opto-isolated adapters adding several milliseconds. The wait does not
+    /// count against the timeout of the reply.
+    pub fn with_reply_grace(mut self, grace: Duration) -> Self {
+        self.reply_timing.grace = Some(grace);
+        self
+    }
+
+    /// Wait up to *timeout* for each read of a reply, instead of the timeout
+    /// of the port. Slow links get the time they need for the replies
+    /// without raising the timeout of the port, which is still used by the
+    /// other reads, like the [reply barrier](#method.with_reply_barrier).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    ///
+    /// # fn example() -> saberrs::Result<()> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?
+    ///     .with_reply_grace(Duration::from_millis(5))
+    ///     .with_reply_timeout(Duration::from_millis(50));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_reply_timeout(mut self, timeout: Duration) -> Self {
+        self.reply_timing.timeout = Some(timeout);
+        self
+    }
+
+    /// Tune the reply timeout from the measured round trips with
+    /// *adaptive*, in place of the [reply timeout](#method.with_reply_timeout).
+    /// See [AdaptiveTimeout](../struct.AdaptiveTimeout.html).
+    pub fn with_adaptive_timeout(mut self, adaptive: AdaptiveTimeout) -> Self {
+        self.reply_timing.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Timeout of the next reply, if the handle sets one in place of the
+    /// timeout of the port.
+    pub fn reply_timeout(&self) -> Option<Duration> {
+        self.reply_timing.tuned(&self.stats).timeout
+    }
+
+    /// Call *callback* with the quantity and the latency of the get requests
+    /// whose round trip takes longer than *threshold*, for catching latency
+    /// spikes as they happen. The latencies of all the get requests are
+    /// available in the [stats](#method.stats).
+    pub fn with_latency_alert<F>(mut self, threshold: Duration, callback: F) -> Self
+    where
+        F: FnMut(Quantity, Duration) + Send + 'static,
+    {
+        self.latency_alert = Some(LatencyAlert::new(threshold, callback));
+        self
+    }
+
+    /// Give up writing a frame after *deadline* with `Error::TxTimeout`,
+    /// instead of waiting for as long as the port lets `write_all()` block,
+    /// so that a dead TX path, like a wedged USB adapter with full buffers,
+    /// is detected quickly and can trigger the failsafes.
+    pub fn with_write_deadline(mut self, deadline: Duration) -> Self {
+        self.write_deadline = Some(deadline);
+        self
+    }
+
+    /// Wait for the replies according to *strategy*, instead of blocking in
+    /// the port. See [ReadStrategy](../enum.ReadStrategy.html).
+    pub fn with_read_strategy(mut self, strategy: ReadStrategy) -> Self {
+        self.read_strategy = strategy;
+        self
+    }
+
+    /// Call *hook* while waiting for the replies, instead of blocking in the
+    /// port, for cooperative schedulers. See [Yield](../trait.Yield.html).
+    pub fn with_yield_hook<Y>(mut self, hook: Y) -> Self
+    where
+        Y: Yield + 'static,
+    {
+        self.yield_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Inject faults inside the transactions with *injector*, for testing
+    /// the error handling. See [FaultInjector](../testing/trait.FaultInjector.html).
+    /// **Requires** the "emulator" feature.
+    #[cfg(any(test, feature = "emulator"))]
+    pub fn with_fault_injector<F>(mut self, injector: F) -> Self
+    where
+        F: FaultInjector + 'static,
+    {
+        self.faults = Some(Box::new(injector));
+        self
+    }
+
+    /// Inspect the commands with *filter* before sending them: it may let
+    /// them through, deny them with `Error::CommandDenied`, or replace them.
+    /// See [CommandFilter](../trait.CommandFilter.html).
+    pub fn with_command_filter<F>(mut self, filter: F) -> Self
+    where
+        F: CommandFilter + Send + 'static,
+    {
+        self.filter.set(filter);
+        self
+    }
+
+    /// Remove and return the denials and the modifications of the command
+    /// filter, oldest first.
+    pub fn drain_filter_events(&mut self) -> impl Iterator<Item = FilterEvent> + '_ {
+        self.filter.drain_events()
+    }
+
+    /// Create the handle disarmed: the motion commands return
+    /// `Error::NotArmed` without being sent until [arm()] is called, so that
+    /// no init code path can move the motors by accident during bring-up.
+    /// The handle is disarmed again by the failsafe paths, see [disarm()].
+    ///
+    /// [arm()]: trait.Sabertooth2x32.html#method.arm
+    /// [disarm()]: trait.Sabertooth2x32.html#method.disarm
+    pub fn with_interlock(mut self) -> Self {
+        self.armed = Some(false);
+        self
+    }
+
+    /// Return the name of the device, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Traffic statistics since the creation of the handle or the last call
+    /// to [reset_stats()](#method.reset_stats).
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Reset the traffic statistics.
+    pub fn reset_stats(&mut self) {
+        self.stats.reset()
+    }
+
+    /// Set the speed like [set_speed()], and check that the command was
+    /// written within *deadline*, including the time waiting for a shared
+    /// port. Return the time taken, or `Error::DeadlineMissed` if it took
+    /// longer, in which case the command was sent anyway. The latencies are
+    /// recorded in the [stats](#method.stats).
+    ///
+    /// [set_speed()]: trait.Sabertooth2x32.html#tymethod.set_speed
+    pub fn set_speed_deadline<R: Into<Ratio>>(
+        &mut self,
+        channel: usize,
+        ratio: R,
+        deadline: Duration,
+    ) -> Result<Duration> {
+        let start = Instant::now();
+        self.set(Operation::SetSpeed(channel, ratio.into().get()))?;
+        utils::check_deadline(&mut self.stats, start.elapsed(), deadline)
+    }
+
+    /// Reset the serial timeout of *channel* without changing its setpoint.
+    ///
+    /// When a serial timeout is configured on the device, it stops the
+    /// motors if no command arrives in time. An application with nothing
+    /// else to send keeps the motors running with a periodic keep-alive, see
+    /// [WatchdogFeeder](../struct.WatchdogFeeder.html) for a background one.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x32::{PacketSerial, PacketType};
+    /// use saberrs::testing::{FrameTap, MockPort};
+    ///
+    /// let mut port = MockPort::new();
+    /// let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    /// saber.keep_alive(1).unwrap();
+    /// assert_eq!(port.take_written(), b"\x80\x28\x10\x38\x00\x00\x4d\x31\x7e");
+    /// ```
+    pub fn keep_alive(&mut self, channel: usize) -> Result<()> {
+        let target = match_channel_to!(channel, *b"M1", *b"M2");
+        self.write_set(op::SET_KEEP_ALIVE, 0, target)
+    }
+
+    /// Set the serial timeout of the device: the motors stop when no command
+    /// is received for *timeout*, rounded up to the millisecond. It must be
+    /// non-zero and not exceed
+    /// [MAX_SERIAL_TIMEOUT](constant.MAX_SERIAL_TIMEOUT.html), or
+    /// `Error::InvalidInput` is returned.
+    ///
+    /// The timeout applies to both channels, and is lost when the device
+    /// reboots. Use [keep_alive()](#method.keep_alive) when there is no
+    /// command to send.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::{PacketSerial, PacketType};
+    /// use saberrs::testing::{FrameTap, MockPort};
+    ///
+    /// let mut port = MockPort::new();
+    /// let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    /// saber.set_serial_timeout(Duration::from_millis(100)).unwrap();
+    /// assert_eq!(
+    ///     port.take_written(),
+    ///     b"\x80\x28\x40\x68\x64\x00\x4d\x31\x62\x80\x28\x40\x68\x64\x00\x4d\x32\x63"
+    /// );
+    /// assert!(saber.set_serial_timeout(Duration::from_secs(60)).is_err());
+    /// ```
+    pub fn set_serial_timeout(&mut self, timeout: Duration) -> Result<()> {
+        if timeout.is_zero() || timeout > MAX_SERIAL_TIMEOUT {
+            return Err(Error::InvalidInput(format!(
+                "serial timeout should be non-zero and at most {:?} (was {:?})",
+                MAX_SERIAL_TIMEOUT, timeout
+            )));
+        }
+        let millis = timeout.as_nanos().div_ceil(1_000_000) as i32;
+        self.write_set(op::SET_TIMEOUT, millis, *b"M1")?;
+        self.write_set(op::SET_TIMEOUT, millis, *b"M2")?;
+        let value = SettingValue::Timeout(Some(timeout));
+        self.settings.record(Setting::SerialTimeout, value);
+        Ok(())
+    }
+
+    /// Disable the serial timeout of the device, see
+    /// [set_serial_timeout()](#method.set_serial_timeout).
+    pub fn disable_serial_timeout(&mut self) -> Result<()> {
+        self.write_set(op::SET_TIMEOUT, 0, *b"M1")?;
+        self.write_set(op::SET_TIMEOUT, 0, *b"M2")?;
+        let value = SettingValue::Timeout(None);
+        self.settings.record(Setting::SerialTimeout, value);
+        Ok(())
+    }
+
+    /// Write *value* to *setting*, see the [settings](settings/index.html)
+    /// module. Return `Error::Unsupported` for a setting only editable with
+    /// DEScribe, and `Error::InvalidInput` for a value of the wrong kind.
+    pub fn set_setting(&mut self, setting: Setting, value: SettingValue) -> Result<()> {
+        setting.check()?;
+        match (setting, value) {
+            (Setting::Ramp(channel), SettingValue::Ratio(ratio)) => self.set_ramp(channel, ratio),
+            (Setting::SerialTimeout, SettingValue::Timeout(Some(timeout))) => {
+                self.set_serial_timeout(timeout)
+            }
+            (Setting::SerialTimeout, SettingValue::Timeout(None)) => self.disable_serial_timeout(),
+            _ => Err(Error::InvalidInput(format!(
+                "{:?} cannot be set to {:?}",
+                setting, value
+            ))),
+        }
+    }
+
+    /// Return the value last written to *setting* by the handle, or `None`
+    /// if it was not written, since the device does not report its settings.
+    /// Return `Error::Unsupported` for a setting only editable with DEScribe.
+    pub fn get_setting(&self, setting: Setting) -> Result<Option<SettingValue>> {
+        setting.check()?;
+        Ok(self.settings.get(setting))
+    }
+
+    /// Return the settings written by the handle.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Return the frame written for *operation* with the address and the
+    /// packet type of the handle, before the [frame
+    /// transform](#method.with_frame_transform), without sending it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType};
+    /// use saberrs::testing::MockPort;
+    ///
+    /// let saber = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// let frame = saber.encode(Operation::SetSpeed(1, -1.0)).unwrap();
+    /// assert_eq!(frame, b"\x80\x28\x01\x29\x7f\x0f\x4d\x31\x0c");
+    /// ```
+    pub fn encode(&self, operation: Operation) -> Result<Vec<u8>> {
+        Ok(self.frame(operation)?.as_ref().to_vec())
+    }
+
+    /// Estimate the time taken on the wire by a transaction of *operation*:
+    /// the set command, or the get request and its reply. It is computed
+    /// from the baud rate and the frame sizes, without the bytes added by a
+    /// [FrameTransform](../trait.FrameTransform.html), nor the latencies of
+    /// the port and the device, which are measured in the
+    /// [stats](#method.stats).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x32::{Operation, PacketSerial, PacketType};
+    /// use saberrs::testing::MockPort;
+    ///
+    /// // 7 bytes for the request and 9 for the reply, at 9600 baud
+    /// let saber = PacketSerial::from(MockPort::new()).with_packet_type(PacketType::Checksum);
+    /// let time = saber.estimate_transaction_time(Operation::GetCurrent(1)).unwrap();
+    /// assert_eq!(16_666, time.as_micros());
+    /// ```
+    pub fn estimate_transaction_time(&self, operation: Operation) -> Result<Duration> {
+        let tx = self.frame(operation)?.as_ref().len();
+        let rx = if operation.is_get() {
+            self.reply_size()
+        } else {
+            0
+        };
+        let baud_rate = match self.baud_rate {
+            Some(baud_rate) => baud_rate,
+            None => self.dev.baud_rate()?,
+        };
+        Ok(bandwidth::transfer_time(baud_rate, tx + rx))
+    }
+
+    /// Read the raw value of *source*, from -2047 to 2047, for the sources
+    /// without a dedicated getter, like the signal inputs S1 and S2.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x32::{DataKind, DataSource, PacketSerial};
+    ///
+    /// # fn example() -> saberrs::Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+    /// let s1 = saber.get_raw(DataSource::new(DataKind::Signal, 1)?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_raw(&mut self, source: DataSource) -> Result<i32> {
+        self.get(op::GET_VALUE, source.bytes())
+    }
+
+    /// Link health monitor of the handle.
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Mutable access to the link health monitor, for configuring it and
+    /// draining its events.
+    pub fn health_mut(&mut self) -> &mut Health {
+        &mut self.health
+    }
+
+    /// Keep the last *capacity* transactions in the
+    /// [history](#method.history).
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history = History::new(capacity);
+        self
+    }
+
+    /// Snapshot of the handle for bug reports: the port settings, the
+    /// statistics, the history and its last errors, the packet type and
+    /// quirks, the configuration and the settings written. See
+    /// [Diagnostics](../struct.Diagnostics.html).
+    pub fn export_diagnostics(&self) -> Diagnostics {
+        let port = PortSettings {
+            baud_rate: self.baud_rate.or_else(|| self.dev.baud_rate().ok()),
+            timeout: self.dev.timeout(),
+            reply_timeout: self.reply_timeout(),
+            write_deadline: self.write_deadline,
+            read_strategy: self.read_strategy,
+        };
+        let capabilities = vec![
+            ("address", self.address.to_string()),
+            ("packet_type", format!("{:?}", self.packet_type)),
+            ("quirks", format!("{:?}", self.quirks)),
+            ("firmware", "not reported by the device".to_string()),
+        ];
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+        let mut configuration = vec![
+            ("strictness", format!("{:?}", self.strictness)),
+            (
+                "packet_type_fallback",
+                optional(self.fallback_threshold.map(|t| t.to_string())),
+            ),
+            (
+                "recovery",
+                optional(self.recovery.as_ref().map(|r| r.0.to_string())),
+            ),
+            (
+                "reply_grace",
+                optional(self.reply_timing.grace.map(|g| format!("{:?}", g))),
+            ),
+            (
+                "reply_barrier",
+                optional(self.reply_barrier.map(|q| format!("{:?}", q))),
+            ),
+            ("interlock", optional(self.armed.map(|a| a.to_string()))),
+            ("command_filter", self.filter.is_set().to_string()),
+            ("yield_hook", self.yield_hook.is_some().to_string()),
+            ("frame_logging", format!("{:?}", self.frame_logging())),
+        ];
+        let settings: Vec<_> = self
+            .settings
+            .iter()
+            .map(|(setting, value)| format!("{:?} = {:?}", setting, value))
+            .collect();
+        configuration.push(("settings", settings.join("; ")));
+        let status = vec![
+            ("link_state", format!("{:?}", self.health.state())),
+            ("reply_failures", self.reply_failures.to_string()),
+            (
+                "diagnosis",
+                optional(self.diagnosis.map(|d| format!("{:?}", d))),
+            ),
+            (
+                "sniff_report",
+                optional(self.sniff_report().map(|r| format!("{:?}", r))),
+            ),
+            ("pending_mismatches", self.mismatches.len().to_string()),
+        ];
+        Diagnostics {
+            device: "sabertooth-2x32 packet serial",
+            name: self.name.clone(),
+            port,
+            stats: self.stats.clone(),
+            history: self.history.iter().cloned().collect(),
+            errors: diagnostics::last_errors(self.history.iter()),
+            capabilities,
+            configuration,
+            status,
+        }
+    }
+
+    /// History of the last transactions, empty unless enabled with
+    /// [with_history()](#method.with_history).
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// What the handle last commanded on *channel*, without any traffic,
+    /// see [ChannelState](../struct.ChannelState.html).
+    pub fn channel_state(&self, channel: usize) -> Result<ChannelState> {
+        self.channels.get(channel)
+    }
+
+    /// Set the verbosity of the frame dumps, see
+    /// [FrameLogging](../enum.FrameLogging.html).
+    pub fn with_frame_logging(mut self, logging: FrameLogging) -> Self {
+        self.frame_log.set_logging(logging);
+        self
+    }
+
+    /// Hand the frame dumps over to the consumer thread of *sink* instead of
+    /// logging them in the transactions, see
+    /// [FrameSink](../struct.FrameSink.html).
+    pub fn with_frame_sink(mut self, sink: FrameSink) -> Self {
+        self.frame_log.set_sink(sink);
+        self
+    }
+
+    /// Change the verbosity of the frame dumps at runtime.
+    pub fn set_frame_logging(&mut self, logging: FrameLogging) {
+        self.frame_log.set_logging(logging);
+    }
+
+    /// Verbosity of the frame dumps.
+    pub fn frame_logging(&self) -> FrameLogging {
+        self.frame_log.logging()
+    }
+
+    /// Send the get request *operation*, for example
+    /// `Operation::GetVoltage(1)`, without waiting for the reply. The reply
+    /// is then polled with [try_get()](#method.try_get).
+    ///
+    /// Only one request can be in progress at a time: if the reply of the
+    /// previous one was not received yet, nothing is sent and an
+    /// `io::ErrorKind::WouldBlock` error is returned.
+    ///
+    /// The setters do not wait for the device, so they have no non-blocking
+    /// variant. The packet type fallback does not apply to these requests.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io;
+    /// use saberrs::Error;
+    /// use saberrs::sabertooth2x32::{Operation, PacketSerial};
+    ///
+    /// # fn example() -> saberrs::Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+    /// saber.start_get(Operation::GetVoltage(1))?;
+    /// loop {
+    ///     match saber.try_get() {
+    ///         Ok(voltage) => break println!("{} V", voltage),
+    ///         Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+    ///             // do something else, or wait for the port to be readable
+    ///         }
+    ///         Err(e) => return Err(e),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start_get(&mut self, operation: Operation) -> Result<()> {
+        if self.pending.is_some() {
+            return Err(utils::would_block_error());
+        }
+        let (command, source) = get_request(operation)?;
+        let opcode = command.spec().opcode;
+        let packet = PacketFrame::new_get_frame(self.packet_type, self.address, opcode, source)?;
+        self.dev.clear_input()?;
+        self.write_frame(packet.as_ref())?;
+        self.pending = Some(PendingGet {
+            command,
+            source,
+            buf: [0u8; PACKET_MAX_REPLY_SIZE],
+            len: 0,
+        });
+        Ok(())
+    }
+
+    /// Read the bytes of the reply received so far, without waiting, and
+    /// return the value of the request sent by
+    /// [start_get()](#method.start_get) once it is complete. Until then an
+    /// `io::ErrorKind::WouldBlock` error is returned.
+    pub fn try_get(&mut self) -> Result<f32> {
+        let mut pending = self
+            .pending
+            .take()
+            .ok_or_else(|| Error::InvalidInput("no get request in progress".to_string()))?;
+
+        let size = self.reply_size();
+        loop {
+            let buf = &mut pending.buf[pending.len..size];
+            match utils::read_available(&mut self.dev, buf, None) {
+                Ok(count) => pending.len += count,
+                Err(e) => {
+                    let res = Err(e);
+                    self.health.record_rx(false);
+                    self.history.record_outcome(&res);
+                    self.frame_log.record_outcome(&self.name, &res);
+                    return res;
+                }
+            }
+            let start = frame_start(&pending.buf[..pending.len]);
+            if start == 0 {
+                break;
+            }
+            self.discard(&pending.buf[..start]);
+            pending.buf.copy_within(start..pending.len, 0);
+            pending.len -= start;
+        }
+        if pending.len < size {
+            self.pending = Some(pending);
+            return Err(utils::would_block_error());
+        }
+
+        #[cfg(any(test, feature = "emulator"))]
+        if !utils::inject_reply(&mut self.faults, &mut pending.buf[..size]) {
+            pending.len = 0;
+            self.pending = Some(pending);
+            return Err(utils::would_block_error());
+        }
+        let resp = &pending.buf[..size];
+        self.frame_log.record_rx(&self.name, resp);
+        self.stats.record_rx(size);
+        self.history.record_rx(resp);
+        let spec = pending.command.spec();
+        let value = match self.parse_response(resp, spec.opcode, pending.source) {
+            Ok(Some(value)) => Ok(utils::scale_reply(
+                &*self.scaling,
+                pending.command,
+                value,
+                Protocol::Packetized,
+            )),
+            Ok(None) => {
+                pending.len = 0;
+                self.pending = Some(pending);
+                return Err(utils::would_block_error());
+            }
+            Err(Error::StaleReply) => {
+                self.unanswered = Some((spec.opcode, pending.source));
+                Err(Error::StaleReply)
+            }
+            Err(e) => Err(e),
+        };
+        if value.is_ok() {
+            self.unanswered = None;
+        }
+        self.health.record_rx(value.is_ok());
+        self.history.record_outcome(&value);
+        self.frame_log.record_outcome(&self.name, &value);
+        value
+    }
+
+    /// Abandon the get request in progress, if any. A late reply is discarded
+    /// by the next request, or detected as stale.
+    pub fn cancel_get(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.unanswered = Some((pending.command.spec().opcode, pending.source));
+        }
+    }
+
+    /// Process the bytes received when the port becomes readable, in an
+    /// event loop. Return the value of the request sent by
+    /// [start_get()](#method.start_get) once its reply is complete, and
+    /// `None` until then. Bytes received while no request is in progress are
+    /// discarded.
+    pub fn on_readable(&mut self) -> Result<Option<f32>> {
+        if self.pending.is_none() {
+            self.dev.clear_input()?;
+            return Ok(None);
+        }
+        match self.try_get() {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Register the port in the *registry* of a [mio] event loop, so that
+    /// *token* is notified when the port is readable. The events are then
+    /// processed with [on_readable()](#method.on_readable).
+    ///
+    /// **Requires** the "mio" feature, on Unix.
+    ///
+    /// [mio]: https://docs.rs/mio
+    #[cfg(all(unix, feature = "mio"))]
+    pub fn register(&self, registry: &mio::Registry, token: mio::Token) -> Result<()>
+    where
+        T: AsRawFd,
+    {
+        let fd = self.dev.as_raw_fd();
+        registry.register(&mut SourceFd(&fd), token, mio::Interest::READABLE)?;
+        Ok(())
+    }
+
+    /// Remove the port from the *registry* of a mio event loop.
+    ///
+    /// **Requires** the "mio" feature, on Unix.
+    #[cfg(all(unix, feature = "mio"))]
+    pub fn deregister(&self, registry: &mio::Registry) -> Result<()>
+    where
+        T: AsRawFd,
+    {
+        let fd = self.dev.as_raw_fd();
+        registry.deregister(&mut SourceFd(&fd))?;
+        Ok(())
+    }
+
     fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
-        dbg_frame!(tx, txdata);
-        Ok(self.dev.write_all(txdata)?)
+        self.assert_baud_rate()?;
+        self.frame_log.record_tx(&self.name, txdata);
+        #[cfg(any(test, feature = "emulator"))]
+        utils::inject_write(&mut self.faults, txdata);
+        let wire = self.transform.transform(txdata);
+        let res = match self.write_deadline {
+            Some(deadline) => utils::write_bounded(&mut self.dev, &wire, deadline),
+            None => self.dev.write_all(&wire).map_err(Error::from),
+        };
+        self.health.record_tx(res.is_ok());
+        self.history.record_tx(txdata, &res);
+        self.frame_log.record_outcome(&self.name, &res);
+        res?;
+        self.stats.record_tx(wire.len());
+        Ok(())
+    }
+
+    /// Set the baud rate given with [with_baud_rate()](#method.with_baud_rate)
+    /// on the port, if it differs.
+    fn assert_baud_rate(&mut self) -> Result<()> {
+        match self.baud_rate {
+            Some(baud_rate) if self.dev.baud_rate()? != baud_rate => {
+                self.dev.set_baud_rate(baud_rate)
+            }
+            _ => Ok(()),
+        }
     }
 
-    fn read_frame(&mut self, mut buf: &mut [u8]) -> Result<()> {
-        self.dev.read_exact(&mut buf)?;
-        dbg_frame!(rx, buf);
+    fn read_frame(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.read_exact(buf)?;
+
+        // Bytes before the address byte come from a break or a late reply, so
+        // they are dropped and the missing part of the frame is read.
+        let mut discarded = 0;
+        loop {
+            let start = frame_start(buf);
+            if start == 0 {
+                break;
+            }
+            self.discard(&buf[..start]);
+            discarded += start;
+            if discarded > MAX_RESYNC_BYTES {
+                return Err(Error::Response("no reply start found".to_string()));
+            }
+            buf.copy_within(start.., 0);
+            let len = buf.len();
+            self.read_exact(&mut buf[len - start..])?;
+        }
+
+        #[cfg(any(test, feature = "emulator"))]
+        if !utils::inject_reply(&mut self.faults, buf) {
+            return Err(utils::dropped_reply_error());
+        }
+        self.frame_log.record_rx(&self.name, buf);
+        self.history.record_rx(buf);
+        self.stats.record_rx(buf.len());
         Ok(())
     }
 
-    fn set(&mut self, cmd_value: CommandSet, value: i32, target: [u8; 2]) -> Result<()> {
-        let packet =
-            PacketFrame::new_set_frame(self.packet_type, self.address, cmd_value, value, target)?;
+    /// Fill *buf* from the port, through the sniffer if any.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.sniffer.as_mut() {
+            Some(sniffer) => {
+                let mut tap = SniffedPort::new(&mut self.dev, sniffer);
+                utils::read_exact_with(&mut tap, buf, self.read_strategy, &mut self.yield_hook)
+            }
+            None => {
+                utils::read_exact_with(&mut self.dev, buf, self.read_strategy, &mut self.yield_hook)
+            }
+        }
+    }
+
+    fn discard(&mut self, bytes: &[u8]) {
+        self.frame_log.record_discarded(&self.name, bytes);
+        let line_conditions = bytes
+            .iter()
+            .filter(|&&byte| self.dev.is_line_condition(byte))
+            .count();
+        self.stats.record_discarded(bytes.len(), line_conditions);
+        self.history.record_rx(bytes);
+    }
+
+    fn set(&mut self, operation: Operation) -> Result<()> {
+        let operation = self.filter.check(&self.name, operation)?;
+        utils::check_armed(self.armed, operation)?;
+        let packet = self.frame(operation)?;
+        self.write_frame(packet.as_ref())?;
+        self.channels.record(operation, Instant::now());
+        Ok(())
+    }
+
+    /// Write a set frame outside of the operations, bypassing the command
+    /// filter and the interlock.
+    fn write_set(&mut self, command_value: u8, data_value: i32, target: [u8; 2]) -> Result<()> {
+        let packet = PacketFrame::new_set_frame(
+            self.packet_type,
+            self.address,
+            command_value,
+            data_value,
+            target,
+        )?;
         self.write_frame(packet.as_ref())
     }
 
-    fn set_ratio(&mut self, ratio: f32, target: [u8; 2]) -> Result<()> {
-        let value = utils::ratio_to_value(ratio)?;
-        self.set(CommandSet::Value, value, target)
+    fn frame(&self, operation: Operation) -> Result<PacketFrame> {
+        let (command, channel, arg) = Command::from_operation(operation);
+        let spec = command.spec();
+        let target = spec.target(channel)?;
+        if operation.is_get() {
+            return PacketFrame::new_get_frame(self.packet_type, self.address, spec.opcode, target);
+        }
+        let value = spec.raw_value(arg)?;
+        PacketFrame::new_set_frame(self.packet_type, self.address, spec.opcode, value, target)
     }
 
     fn reply_size(&self) -> usize {
-        match self.packet_type {
-            PacketType::Checksum => checksum::PACKET_REPLY_SIZE,
-            PacketType::CRC => crc::PACKET_REPLY_SIZE,
-        }
+        self.quirks.reply_size(self.packet_type)
     }
 
     fn parse_response(
-        &self,
+        &mut self,
         resp: &[u8],
-        expected_cmdvalue: CommandGet,
+        expected_cmdvalue: u8,
         expected_source: [u8; 2],
-    ) -> Result<i32> {
+    ) -> Result<Option<i32>> {
         let error = |s: &str| Err(Error::Response(s.to_string()));
 
+        // The padding is not part of the frame
+        let resp = &resp[..resp.len() - self.quirks.reply_padding()];
         let resp_cmdnum = resp[1];
         let resp_cmdvalue = resp[2];
         let resp_data_value = &resp[4..6];
         let resp_data_source = &resp[6..8];
 
-        let validity = match self.packet_type {
-            PacketType::Checksum => checksum::packet_is_valid(resp, self.address),
-            PacketType::CRC => crc::packet_is_valid(resp, self.address),
-        };
-
-        match validity {
+        match packet_is_valid(self.packet_type, resp, self.address, &self.quirks) {
             Ok(_) => {}
             Err(ParseError::PacketSize) => return error("invalid packet size"),
-            Err(ParseError::ChecksumError) => return error("invalid checksum or CRC"),
-            Err(ParseError::AddressError) => return error("invalid address"),
+            Err(ParseError::ChecksumError) => {
+                self.stats.record_crc_error();
+                return error("invalid checksum or CRC");
+            }
+            Err(ParseError::AddressError) => {
+                let received = match self.packet_type {
+                    PacketType::Checksum => resp[0],
+                    PacketType::CRC => resp[0].wrapping_sub(op::CRC_ADDRESS_OFFSET),
+                };
+                let mismatch = Mismatch::Address {
+                    expected: self.address,
+                    received,
+                };
+                if !self.tolerate(mismatch)? {
+                    return Ok(None);
+                }
+            }
         }
 
         if resp_cmdnum != CMD_NUM_REPLY {
             return error("invalid command num");
         }
 
-        let expected_cmdvalue = expected_cmdvalue as u8;
+        if let Some((stale_cmdvalue, stale_source)) = self.unanswered {
+            let stale = (stale_cmdvalue, stale_source) != (expected_cmdvalue, expected_source)
+                && (resp_cmdvalue == stale_cmdvalue || resp_cmdvalue == stale_cmdvalue + 1)
+                && resp_data_source == &stale_source[..];
+            if stale {
+                debug!("stale reply: {:?}", resp);
+                return Err(Error::StaleReply);
+            }
+        }
+
         let is_negative = match resp_cmdvalue {
             _ if resp_cmdvalue == (expected_cmdvalue + 1) => true,
             _ if resp_cmdvalue == expected_cmdvalue => false,
-            _ => return error("invalid command value"),
+            _ => return error("unexpected command"),
         };
 
         let mut data_value = i32::from(unpack_data_value(resp_data_value));
@@ -215,26 +1333,207 @@ impl<T: SabertoothSerial> PacketSerial<T> {
         }
 
         if resp_data_source != &expected_source[..] {
-            return error("invalid source");
+            let mismatch = Mismatch::Source {
+                expected: expected_source,
+                received: [resp_data_source[0], resp_data_source[1]],
+            };
+            if !self.tolerate(mismatch)? {
+                return Ok(None);
+            }
         }
 
-        Ok(data_value)
+        Ok(Some(data_value))
     }
 
-    fn get(&mut self, cmd_value: CommandGet, source: [u8; 2]) -> Result<i32> {
+    /// Apply the strictness policy to *mismatch*. Return true if the reply
+    /// is accepted, and false if it is skipped.
+    fn tolerate(&mut self, mismatch: Mismatch) -> Result<bool> {
+        let prefix = match &self.name {
+            Some(name) => format!("{}: ", name),
+            None => String::new(),
+        };
+        match self.strictness {
+            Strictness::Strict => Err(Error::Response(mismatch.to_string())),
+            Strictness::Skip => {
+                debug!("{}skipped reply: {:?}", prefix, mismatch);
+                Ok(false)
+            }
+            Strictness::Lenient => {
+                log_warn!("{}accepted reply: {:?}", prefix, mismatch);
+                if self.mismatches.len() == MAX_MISMATCHES {
+                    self.mismatches.pop_front();
+                }
+                self.mismatches.push_back(mismatch);
+                Ok(true)
+            }
+        }
+    }
+
+    fn get(&mut self, cmd_value: u8, source: [u8; 2]) -> Result<i32> {
+        let value = self.get_with_fallback(cmd_value, source);
+        if self.recovery.is_some() {
+            self.check_recovery(reply_received(&value));
+        }
+        value
+    }
+
+    /// Count the consecutive getters without a valid reply, and run the
+    /// recovery when they reach its threshold.
+    fn check_recovery(&mut self, replied: bool) {
+        if replied {
+            self.recovery_failures = 0;
+            return;
+        }
+        self.recovery_failures += 1;
+        let (threshold, recovery) = match self.recovery.take() {
+            Some((threshold, recovery)) if self.recovery_failures >= threshold => {
+                (threshold, recovery)
+            }
+            other => {
+                self.recovery = other;
+                return;
+            }
+        };
+
+        self.recovery_failures = 0;
+        let prefix = match &self.name {
+            Some(name) => format!("{}: ", name),
+            None => String::new(),
+        };
+        log_warn!("{}no valid reply {} times, diagnosing", prefix, threshold);
+        match recovery.diagnose(self) {
+            Ok(diagnosis) => {
+                log_warn!("{}diagnosis: {:?}", prefix, diagnosis);
+                self.diagnosis = Some(diagnosis);
+            }
+            Err(e) => log_warn!("{}diagnosis failed: {}", prefix, e),
+        }
+        self.recovery = Some((threshold, recovery));
+    }
+
+    fn get_with_fallback(&mut self, cmd_value: u8, source: [u8; 2]) -> Result<i32> {
+        let value = self.request(cmd_value, source);
+        let threshold = match self.fallback_threshold {
+            Some(threshold) if !reply_received(&value) => threshold,
+            _ => {
+                self.reply_failures = 0;
+                return value;
+            }
+        };
+
+        self.reply_failures += 1;
+        if self.reply_failures < threshold {
+            return value;
+        }
+
+        let prefix = match &self.name {
+            Some(name) => format!("{}: ", name),
+            None => String::new(),
+        };
+        #[cfg(feature = "control")]
+        if let Some(budget) = &self.retry_budget {
+            if !budget.acquire() {
+                log_warn!(
+                    "{}retry budget exhausted, not trying another packet type",
+                    prefix
+                );
+                return value;
+            }
+        }
+
+        self.reply_failures = 0;
+        log_warn!(
+            "{}no valid reply with {:?} frames, trying {:?}",
+            prefix,
+            self.packet_type,
+            self.packet_type.other()
+        );
+        self.packet_type = self.packet_type.other();
+
+        #[cfg(feature = "control")]
+        let start = Instant::now();
+        let value = self.request(cmd_value, source);
+        #[cfg(feature = "control")]
+        if let Some(budget) = &self.retry_budget {
+            budget.spend(start.elapsed());
+        }
+        if reply_received(&value) {
+            log_info!("{}device replies to {:?} frames", prefix, self.packet_type);
+        } else {
+            self.reply_failures += 1;
+        }
+        value
+    }
+
+    fn request(&mut self, cmd_value: u8, source: [u8; 2]) -> Result<i32> {
         let packet = PacketFrame::new_get_frame(self.packet_type, self.address, cmd_value, source)?;
-        self.dev.clear_all()?;
+        self.dev.clear_input()?;
+        if let (Some(_), Some(quiet)) = (self.unanswered, self.reply_barrier) {
+            let count = utils::flush_until_quiet(&mut self.dev, quiet)?;
+            if count > 0 {
+                self.stats.record_discarded(count, 0);
+            }
+            self.unanswered = None;
+        }
         self.write_frame(packet.as_ref())?;
+        let timing = self.reply_timing.tuned(&self.stats);
+        let timeout = utils::begin_reply(&mut self.dev, timing)?;
+        let value = self.read_reply(cmd_value, source);
+        utils::end_reply(&mut self.dev, timeout)?;
+        self.reply_timing.record(&value);
+        self.unanswered = match &value {
+            Ok(_) => None,
+            Err(Error::StaleReply) => Some((cmd_value, source)),
+            _ if !reply_received(&value) => Some((cmd_value, source)),
+            _ => self.unanswered,
+        };
+        self.health.record_rx(value.is_ok());
+        self.history.record_outcome(&value);
+        self.frame_log.record_outcome(&self.name, &value);
+        value
+    }
+
+    fn read_reply(&mut self, cmd_value: u8, source: [u8; 2]) -> Result<i32> {
         let mut buf = [0u8; PACKET_MAX_REPLY_SIZE];
         let resp = &mut buf[..self.reply_size()];
-        self.read_frame(resp)?;
-        self.parse_response(resp, cmd_value, source)
+        for _ in 0..=MAX_SKIPPED_REPLIES {
+            self.read_frame(resp)?;
+            if let Some(value) = self.parse_response(resp, cmd_value, source)? {
+                return Ok(value);
+            }
+        }
+        Err(Error::Response("too many replies skipped".to_string()))
     }
 
-    fn get_ratio(&mut self, cmd_value: CommandGet, source: [u8; 2]) -> Result<f32> {
-        let value = self.get(cmd_value, source)?;
-        let ratio = utils::value_to_ratio(value);
-        Ok(ratio)
+    fn query(&mut self, operation: Operation) -> Result<f32> {
+        let (command, source) = get_request(operation)?;
+        let start = Instant::now();
+        let value = self.get(command.spec().opcode, source)?;
+        utils::record_round_trip(
+            &mut self.stats,
+            &mut self.latency_alert,
+            command,
+            start.elapsed(),
+        );
+        Ok(utils::scale_reply(
+            &*self.scaling,
+            command,
+            value,
+            Protocol::Packetized,
+        ))
+    }
+}
+
+/// Whether the device sent a valid reply, even if it refused the request.
+fn reply_received(res: &Result<i32>) -> bool {
+    match res {
+        Ok(_) => true,
+        Err(Error::Response(_)) => false,
+        Err(Error::Io(e)) => !matches!(
+            e.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::UnexpectedEof
+        ),
+        Err(_) => true,
     }
 }
 
@@ -244,6 +1543,39 @@ impl<T: SabertoothSerial> From<T> for PacketSerial<T> {
             dev,
             address: DEFAULT_ADDRESS,
             packet_type: DEFAULT_PACKET_TYPE,
+            stats: Stats::new(),
+            health: Health::new(),
+            history: History::default(),
+            frame_log: FrameLog::new(FrameFormat::Binary),
+            name: None,
+            transform: Box::new(Passthrough),
+            scaling: Box::new(ScalingProfile::new()),
+            fallback_threshold: None,
+            #[cfg(feature = "control")]
+            retry_budget: None,
+            reply_failures: 0,
+            pending: None,
+            strictness: Strictness::default(),
+            mismatches: VecDeque::new(),
+            quirks: QuirksProfile::default(),
+            baud_rate: None,
+            latency_alert: None,
+            write_deadline: None,
+            read_strategy: ReadStrategy::Blocking,
+            #[cfg(any(test, feature = "emulator"))]
+            faults: None,
+            yield_hook: None,
+            armed: None,
+            recovery: None,
+            recovery_failures: 0,
+            diagnosis: None,
+            unanswered: None,
+            reply_barrier: None,
+            reply_timing: ReplyTiming::default(),
+            channels: ChannelStates::default(),
+            filter: FilterSlot::default(),
+            sniffer: None,
+            settings: Settings::default(),
         }
     }
 }
@@ -253,147 +1585,559 @@ where
     T: SabertoothSerial + Clone,
 {
     fn from(dev: &T) -> Self {
-        PacketSerial {
-            dev: dev.clone(),
-            address: DEFAULT_ADDRESS,
-            packet_type: DEFAULT_PACKET_TYPE,
-        }
+        PacketSerial::from(dev.clone())
     }
 }
 
 impl<T: SabertoothSerial> Sabertooth2x32 for PacketSerial<T> {
     fn startup(&mut self, channel: usize) -> Result<()> {
-        let target = [b'M', match_channel_to!(channel, b'1', b'2')];
-        self.set(CommandSet::Shutdown, 0, target)
+        self.set(Operation::Startup(channel))
     }
 
     fn shutdown(&mut self, channel: usize) -> Result<()> {
-        let target = [b'M', match_channel_to!(channel, b'1', b'2')];
-        self.set(CommandSet::Shutdown, 1, target)
+        self.set(Operation::Shutdown(channel))
     }
 
     fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'M', match_channel_to!(channel, b'1', b'2')])
+        self.set(Operation::SetSpeed(channel, ratio))
     }
 
     fn get_speed(&mut self, channel: usize) -> Result<f32> {
-        self.get_ratio(
-            CommandGet::Value,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )
+        self.query(Operation::GetSpeed(channel))
     }
 
     fn set_drive(&mut self, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'M', b'D'])
+        self.set(Operation::SetDrive(ratio))
     }
 
     fn set_turn(&mut self, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'M', b'T'])
+        self.set(Operation::SetTurn(ratio))
     }
 
     fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'P', match_channel_to!(channel, b'1', b'2')])
+        self.set(Operation::SetPower(channel, ratio))
     }
 
     fn get_power(&mut self, channel: usize) -> Result<f32> {
-        self.get_ratio(
-            CommandGet::Value,
-            [b'P', match_channel_to!(channel, b'1', b'2')],
-        )
+        self.query(Operation::GetPower(channel))
     }
 
     fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'R', match_channel_to!(channel, b'1', b'2')])
+        self.set(Operation::SetRamp(channel, ratio))?;
+        let value = SettingValue::Ratio(ratio);
+        self.settings.record(Setting::Ramp(channel), value);
+        Ok(())
     }
 
     fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'Q', match_channel_to!(channel, b'1', b'2')])
+        self.set(Operation::SetAux(channel, ratio))
     }
 
     fn get_voltage(&mut self, channel: usize) -> Result<f32> {
-        let value = self.get(
-            CommandGet::Battery,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )?;
-        Ok(value as f32 / 10.0)
+        self.query(Operation::GetVoltage(channel))
     }
 
     fn get_current(&mut self, channel: usize) -> Result<f32> {
-        let value = self.get(
-            CommandGet::Current,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )?;
-        Ok(value as f32)
+        self.query(Operation::GetCurrent(channel))
     }
 
     fn get_temperature(&mut self, channel: usize) -> Result<f32> {
-        let value = self.get(
-            CommandGet::Temperature,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )?;
-        Ok(value as f32)
+        self.query(Operation::GetTemperature(channel))
     }
-}
 
-#[derive(Clone, PartialEq, Debug)]
-enum PacketFrame {
-    ChecksumSet(checksum::PacketSet),
-    ChecksumGet(checksum::PacketGet),
-    CRCSet(crc::PacketSet),
-    CRCGet(crc::PacketGet),
-}
+    fn arm(&mut self) {
+        if let Some(armed) = self.armed.as_mut() {
+            *armed = true;
+        }
+    }
 
-impl PacketFrame {
-    fn new_set_frame(
-        packet_type: PacketType,
-        address: u8,
-        command_value: CommandSet,
-        data_value: i32,
-        target: [u8; 2],
-    ) -> Result<PacketFrame> {
-        let frame = match packet_type {
-            PacketType::Checksum => PacketFrame::ChecksumSet(checksum::PacketSet::new(
-                address,
-                command_value,
-                data_value,
-                target,
-            )?),
-            PacketType::CRC => PacketFrame::CRCSet(crc::PacketSet::new(
-                address,
-                command_value,
-                data_value,
-                target,
-            )?),
-        };
-        Ok(frame)
-    }
-
-    pub fn new_get_frame(
-        packet_type: PacketType,
-        address: u8,
-        command_value: CommandGet,
-        source: [u8; 2],
-    ) -> Result<PacketFrame> {
-        let frame = match packet_type {
-            PacketType::Checksum => {
-                PacketFrame::ChecksumGet(checksum::PacketGet::new(address, command_value, source)?)
-            }
-            PacketType::CRC => {
-                PacketFrame::CRCGet(crc::PacketGet::new(address, command_value, source)?)
-            }
-        };
-        Ok(frame)
+    fn disarm(&mut self) {
+        if let Some(armed) = self.armed.as_mut() {
+            *armed = false;
+        }
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed != Some(false)
+    }
+
+    fn shutdown_commanded(&self, channel: usize) -> bool {
+        self.channels
+            .get(channel)
+            .is_ok_and(|state| state.is_shut_down())
     }
 }
 
-impl AsRef<[u8]> for PacketFrame {
-    #[inline]
-    fn as_ref(&self) -> &[u8] {
-        match self {
-            PacketFrame::ChecksumSet(ar) => ar.as_ref(),
-            PacketFrame::ChecksumGet(ar) => ar.as_ref(),
-            PacketFrame::CRCSet(ar) => ar.as_ref(),
-            PacketFrame::CRCGet(ar) => ar.as_ref(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::packet::encode_reply;
+    use crate::testing::{FrameTap, MockPort, Script, ScriptStep};
+
+    #[test]
+    fn test_unexpected_command() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+        port.queue_reply(b"\x80\x49\x7f\x48\x05\x00\x4d\x31\x03");
+        match saber.get_voltage(1) {
+            Err(Error::Response(msg)) => assert_eq!("unexpected command", msg),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Not a live device answering
+        port.queue_reply(b"\x80\x49\x7f\x48\x05\x00\x4d\x31\x03");
+        assert!(!saber.probe(PacketType::Checksum).unwrap());
+    }
+
+    #[test]
+    fn test_strictness() {
+        let other_address = b"\x81\x49\x10\x5a\x78\x00\x4d\x31\x76";
+        let other_source = b"\x80\x49\x10\x59\x78\x00\x4d\x32\x77";
+        let valid = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+        port.queue_reply(other_address);
+        match saber.get_voltage(1) {
+            Err(Error::Response(msg)) => assert_eq!("invalid address", msg),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        saber = saber.with_strictness(Strictness::Skip);
+        port.queue_reply(&[&other_address[..], other_source, valid].concat());
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(0, saber.drain_mismatches().count());
+
+        saber = saber.with_strictness(Strictness::Lenient);
+        port.queue_reply(other_source);
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(
+            vec![Mismatch::Source {
+                expected: *b"M1",
+                received: *b"M2"
+            }],
+            saber.drain_mismatches().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_baud_rate() {
+        let mut port = MockPort::new();
+        let mut legacy = PacketSerial::from(&port).with_baud_rate(9600);
+        let mut fast = PacketSerial::from(&port)
+            .with_address(129)
+            .with_baud_rate(115_200);
+
+        legacy.stop_motors().unwrap();
+        assert_eq!(9600, port.baud_rate().unwrap());
+        fast.get_voltage(1).unwrap_err();
+        assert_eq!(115_200, port.baud_rate().unwrap());
+        legacy.stop_motors().unwrap();
+        assert_eq!(9600, port.baud_rate().unwrap());
+        assert_eq!(4 * 10 + 8, port.take_written().len());
+    }
+
+    #[test]
+    fn test_latency_alert() {
+        let get_voltage = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let port = MockPort::with_script(
+            Script::new()
+                .step(ScriptStep::expect(get_voltage).respond(reply))
+                .step(
+                    ScriptStep::expect(get_voltage)
+                        .respond(reply)
+                        .with_delay(Duration::from_millis(30)),
+                ),
+        );
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_latency_alert(Duration::from_millis(10), move |quantity, elapsed| {
+                tx.send((quantity, elapsed)).unwrap()
+            });
+
+        saber.get_voltage(1).unwrap();
+        saber.get_voltage(1).unwrap();
+        saber.get_current(1).unwrap_err();
+
+        let alerts: Vec<_> = rx.try_iter().collect();
+        assert_eq!(1, alerts.len());
+        assert_eq!(Quantity::Voltage, alerts[0].0);
+        assert!(alerts[0].1 >= Duration::from_millis(30));
+        let summary = saber.stats().round_trip_latency().unwrap();
+        assert_eq!(2, summary.count);
+        assert_eq!(summary.max, alerts[0].1);
+    }
+
+    #[test]
+    fn test_crc_errors() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+        port.queue_reply(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x77");
+        assert!(matches!(saber.get_voltage(1), Err(Error::Response(_))));
+        assert_eq!(1, saber.stats().crc_errors());
+        assert_eq!(1, saber.stats().frames_received());
+    }
+
+    #[test]
+    fn test_packet_type_fallback() {
+        let get_crc = b"\xf0\x29\x10\x2e\x4d\x31\x06\x24";
+        let get_checksum = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let port = MockPort::with_script(
+            Script::new()
+                .step(ScriptStep::expect(get_crc))
+                .step(ScriptStep::expect(get_crc))
+                .step(ScriptStep::expect(get_checksum).respond(reply))
+                .step(ScriptStep::expect(get_checksum).respond(reply)),
+        );
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::CRC)
+            .with_packet_type_fallback(2);
+
+        saber.get_voltage(1).unwrap_err();
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(PacketType::Checksum, saber.packet_type());
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        port.check_script().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "control")]
+    fn test_packet_type_fallback_budget() {
+        let get_crc = b"\xf0\x29\x10\x2e\x4d\x31\x06\x24";
+        let get_checksum = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let port = MockPort::with_script(
+            Script::new()
+                .step(ScriptStep::expect(get_crc))
+                .step(ScriptStep::expect(get_crc))
+                .step(ScriptStep::expect(get_crc))
+                .step(ScriptStep::expect(get_checksum).respond(reply)),
+        );
+        let budget = RetryBudget::new(1);
+        assert!(budget.acquire());
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::CRC)
+            .with_packet_type_fallback(2)
+            .with_retry_budget(budget.clone());
+
+        // Exhausted budget: the other packet type is not tried
+        saber.get_voltage(1).unwrap_err();
+        saber.get_voltage(1).unwrap_err();
+        assert_eq!(PacketType::CRC, saber.packet_type());
+        assert_eq!(1, budget.denied());
+
+        budget.reset();
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(PacketType::Checksum, saber.packet_type());
+        assert_eq!(0, budget.remaining());
+        port.check_script().unwrap();
+    }
+
+    #[test]
+    fn test_frame_transform() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_frame_transform(crate::EscapedAddress::new(0x80));
+        saber.set_speed(1, 0.0).unwrap();
+        assert_eq!(
+            port.take_written(),
+            b"\x80\x80\x28\x00\x28\x00\x00\x4d\x31\x7e"
+        );
+        assert_eq!(10, saber.stats().bytes_sent());
+    }
+
+    #[test]
+    fn test_command_filter() {
+        let mut port = MockPort::new();
+        let envelope = crate::Envelope::new()
+            .with_max_speed(0.5)
+            .with_channels(&[1]);
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_command_filter(envelope);
+        saber.set_speed(1, 1.0).unwrap();
+        assert_eq!(port.take_written(), b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04");
+        assert!(matches!(
+            saber.set_speed(2, 0.5),
+            Err(Error::CommandDenied(_))
+        ));
+        assert_eq!(2, saber.drain_filter_events().count());
+
+        let mut saber = saber.into_plain_text();
+        assert!(saber.set_speed(2, 0.5).is_err());
+        saber.set_speed(2, 0.0).unwrap();
+        assert_eq!(port.take_written(), b"M2: 0\r\n");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_history(2);
+        saber.set_speed(1, 0.0).unwrap();
+        saber.set_speed(2, 0.0).unwrap();
+        port.queue_reply(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76");
+        saber.get_voltage(1).unwrap();
+
+        let history: Vec<_> = saber.history().iter().collect();
+        assert_eq!(2, history.len());
+        assert_eq!(b"\x80\x28\x00\x28\x00\x00\x4d\x32\x7f", &history[0].tx[..]);
+        assert_eq!(crate::Outcome::Sent, history[0].outcome);
+        assert_eq!(b"\x80\x29\x10\x39\x4d\x31\x7e", &history[1].tx[..]);
+        assert_eq!(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76", &history[1].rx[..]);
+        assert_eq!(crate::Outcome::Replied, history[1].outcome);
+    }
+
+    #[test]
+    fn test_export_diagnostics() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_name("front")
+            .with_history(8);
+        saber.set_ramp(1, 0.5).unwrap();
+        port.queue_reply(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76");
+        saber.get_voltage(1).unwrap();
+        assert!(saber.get_voltage(2).is_err());
+
+        let bundle = saber.export_diagnostics();
+        assert_eq!(Some("front"), bundle.name.as_deref());
+        assert_eq!(3, bundle.history.len());
+        assert_eq!(1, bundle.errors.len());
+        assert_eq!(3, bundle.stats.frames_sent());
+        assert!(bundle
+            .configuration
+            .contains(&("settings", "Ramp(1) = Ratio(0.5)".to_string())));
+
+        let json = bundle.to_json();
+        assert!(json.contains(r#""name": "front""#));
+        assert!(json.contains(r#""packet_type": "Checksum""#));
+        assert!(json.contains(r#""tx": "80291039"#));
+        assert!(json.contains(r#""rx": "804910597800"#));
+        assert!(json.contains(r#""outcome": {"failed": "#));
+    }
+
+    #[test]
+    fn test_try_get() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+        saber.try_get().unwrap_err();
+        saber.start_get(Operation::GetVoltage(1)).unwrap();
+        assert_eq!(port.take_written(), b"\x80\x29\x10\x39\x4d\x31\x7e");
+
+        port.push_rx(b"\x80\x49\x10\x59");
+        match saber.try_get() {
+            Err(Error::Io(e)) => assert_eq!(io::ErrorKind::WouldBlock, e.kind()),
+            res => panic!("unexpected result {:?}", res),
         }
+        saber.start_get(Operation::GetCurrent(1)).unwrap_err();
+
+        port.push_rx(b"\x78\x00\x4d\x31\x76");
+        assert_eq!(12.0, saber.try_get().unwrap());
+        assert_eq!(1, saber.stats().frames_received());
+    }
+
+    #[test]
+    fn test_get_raw() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port);
+        let source = DataSource::from_bytes(*b"S2").unwrap();
+        let reply = encode_reply(DEFAULT_PACKET_TYPE, DEFAULT_ADDRESS, 0, -512, *b"S2").unwrap();
+        port.queue_reply(&reply);
+        assert_eq!(-512, saber.get_raw(source).unwrap());
+        let written = port.take_written();
+        assert_eq!(b"S2", &written[4..6]);
+    }
+
+    #[test]
+    fn test_resync() {
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+        // Two breaks and a stray byte before the reply
+        port.queue_reply(&[b"\x00\x00\x31", &reply[..]].concat());
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(1, saber.stats().resyncs());
+        assert_eq!(3, saber.stats().bytes_discarded());
+        assert_eq!(2, saber.stats().line_conditions());
+        assert_eq!(12, saber.stats().bytes_received());
+
+        saber.start_get(Operation::GetVoltage(1)).unwrap();
+        port.push_rx(&[b"\x00", &reply[..]].concat());
+        assert_eq!(12.0, saber.try_get().unwrap());
+        assert_eq!(3, saber.stats().line_conditions());
+
+        port.queue_reply(&[0u8; MAX_RESYNC_BYTES + PACKET_MAX_REPLY_SIZE]);
+        match saber.get_voltage(1) {
+            Err(Error::Response(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_quirks() {
+        let mut port = MockPort::new();
+        let quirks = QuirksProfile::new().with_truncated_replies();
+        let mut saber = PacketSerial::from(&port).with_quirks(quirks);
+        port.queue_reply(b"\xf0\x49\x10\x56\x78\x00\x4d\x31");
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+
+        let quirks = QuirksProfile::new()
+            .with_unchecked_header()
+            .with_reply_padding(2);
+        let mut saber = saber
+            .with_packet_type(PacketType::Checksum)
+            .with_quirks(quirks);
+        port.queue_reply(b"\x80\x49\x10\x00\x78\x00\x4d\x31\x76\x0d\x0a");
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        port.queue_reply(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x00\x0d\x0a");
+        assert!(matches!(saber.get_voltage(1), Err(Error::Response(_))));
+    }
+
+    #[test]
+    fn test_stale_reply() {
+        let voltage1 = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let voltage2 = b"\x80\x49\x10\x59\x78\x00\x4d\x32\x77";
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+        // The reply of the first request arrives during the second one
+        saber.get_voltage(1).unwrap_err();
+        port.queue_reply(voltage1);
+        assert!(matches!(saber.get_voltage(2), Err(Error::StaleReply)));
+        port.queue_reply(voltage2);
+        assert!(matches!(saber.get_voltage(1), Err(Error::StaleReply)));
+        port.queue_reply(voltage1);
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        port.queue_reply(voltage1);
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+
+        saber.start_get(Operation::GetVoltage(2)).unwrap();
+        saber.cancel_get();
+        port.queue_reply(voltage2);
+        assert!(matches!(saber.get_voltage(1), Err(Error::StaleReply)));
+
+        let mut saber = saber.with_reply_barrier(Duration::from_millis(1));
+        port.queue_reply(voltage1);
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+    }
+
+    #[test]
+    fn test_write_deadline() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_write_deadline(Duration::from_millis(5));
+
+        // The adapter accepts 4 bytes of the 9 bytes frame, then nothing
+        port.set_tx_capacity(Some(4));
+        assert!(matches!(
+            saber.set_speed(1, 0.0),
+            Err(Error::TxTimeout { written: 4, len: 9 })
+        ));
+        assert_eq!(4, port.take_written().len());
+        assert_eq!(0, saber.stats().frames_sent());
+
+        port.set_tx_capacity(None);
+        saber.set_speed(1, 0.0).unwrap();
+        assert_eq!(9, port.take_written().len());
+    }
+
+    #[test]
+    fn test_read_strategy() {
+        let get_voltage = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let delayed = || {
+            ScriptStep::expect(get_voltage)
+                .respond(reply)
+                .with_delay(Duration::from_millis(20))
+        };
+        let port = MockPort::with_script(
+            Script::new()
+                .step(ScriptStep::expect(get_voltage).respond(reply))
+                .step(delayed())
+                .step(delayed()),
+        );
+        let spin = Duration::from_millis(1);
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_read_strategy(ReadStrategy::BusyPoll { spin });
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        // The reply arrives after the spin budget
+        assert!(saber.get_voltage(1).is_err());
+
+        let mut saber = saber.with_read_strategy(ReadStrategy::Hybrid { spin });
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(100, port.timeout().as_millis());
+    }
+
+    #[test]
+    fn test_yield_hook() {
+        use crate::WaitPoint;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let get_voltage = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let port = MockPort::with_script(
+            Script::new().step(
+                ScriptStep::expect(get_voltage)
+                    .respond(reply)
+                    .with_delay(Duration::from_millis(20)),
+            ),
+        );
+        let yields = Arc::new(AtomicUsize::new(0));
+        let count = yields.clone();
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_yield_hook(move |point| {
+                assert_eq!(WaitPoint::Reply, point);
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert!(yields.load(Ordering::SeqCst) > 0);
+        assert_eq!(100, port.timeout().as_millis());
+
+        // Nothing received within the port timeout
+        assert!(saber.get_voltage(1).is_err());
+    }
+
+    #[test]
+    fn test_reply_timing() {
+        let get_voltage = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let delayed = || {
+            ScriptStep::expect(get_voltage)
+                .respond(reply)
+                .with_delay(Duration::from_millis(30))
+        };
+        let mut port = MockPort::with_script(
+            Script::new()
+                .step(delayed())
+                .step(delayed())
+                .step(delayed()),
+        );
+        port.set_timeout(Duration::from_millis(10)).unwrap();
+        let saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+        // The reply arrives after the port timeout
+        let mut saber = saber.with_reply_timeout(Duration::from_millis(100));
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(10, port.timeout().as_millis());
+
+        let mut saber = PacketSerial::from(&port)
+            .with_packet_type(PacketType::Checksum)
+            .with_reply_grace(Duration::from_millis(40));
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+        assert!(saber.get_voltage(1).is_err());
     }
 }
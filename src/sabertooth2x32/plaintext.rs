@@ -1,58 +1,627 @@
 use std::convert::From;
+use std::io;
+#[cfg(all(unix, feature = "mio"))]
+use std::os::unix::io::AsRawFd;
 use std::str;
+use std::time::{Duration, Instant};
 
 #[allow(unused_imports)]
 use log::debug;
+#[cfg(all(unix, feature = "mio"))]
+use mio::unix::SourceFd;
 
-use super::Sabertooth2x32;
+use super::commands::Command;
+use super::{Operation, Sabertooth2x32};
+use crate::bandwidth;
+use crate::command_table::Protocol;
 use crate::error::{Error, Result};
-use crate::port::SabertoothSerial;
-use crate::utils;
+use crate::filter::{CommandFilter, FilterEvent, FilterSlot};
+use crate::framelog::{FrameFormat, FrameLog, FrameLogging, FrameSink};
+use crate::health::Health;
+use crate::history::History;
+use crate::port::{ReadStrategy, SabertoothSerial, Yield};
+use crate::protocol::scaling::{Scaling, ScalingProfile};
+use crate::protocol::transform::{FrameTransform, Passthrough};
+use crate::state::{ChannelState, ChannelStates};
+use crate::stats::{LatencyAlert, Stats};
+use crate::telemetry::Quantity;
+#[cfg(feature = "emulator")]
+use crate::testing::DryRun;
+#[cfg(any(test, feature = "emulator"))]
+use crate::testing::FaultInjector;
+use crate::timeout::AdaptiveTimeout;
+use crate::units::Ratio;
+use crate::utils::{self, ReplyTiming};
 
 #[cfg(feature = "serialport")]
 use crate::port::sabertoothport::SabertoothPort;
 
-macro_rules! make_cmd_str {
-    ($token:expr, $channel:expr, $value:expr) => {
-        format!("{}{}: {}\r\n", $token, $channel, $value)
-    };
-}
+/// Maximum size of a reply.
+const REPLY_MAX_SIZE: usize = 32;
 
-#[cfg(debug_assertions)]
-macro_rules! dbg_frame {
-    ($head:ident, $frame:expr) => {
-        let frame = std::str::from_utf8($frame)
-            .unwrap_or("<decode error>")
-            .trim_matches(char::from(0))
-            .trim(); // avoid empty lines caused by '\n' in logs
-        debug!("{} = {}", stringify!($head), frame);
-    };
-}
+/// Maximum number of lines skipped before the first reply, like a startup
+/// banner.
+const BANNER_MAX_LINES: usize = 8;
 
-#[cfg(not(debug_assertions))]
-macro_rules! dbg_frame {
-    ($head:ident, $frame:expr) => {};
+/// Get request waiting for its reply, see `PlainText::start_get()`.
+#[derive(Clone, Copy, Debug)]
+struct PendingGet {
+    command: Command,
+    token: char,
+    channel: char,
+    prefix: Option<char>,
+    buf: [u8; REPLY_MAX_SIZE],
+    len: usize,
 }
 
 /// Interface using "Plain Text" protocol.
 pub struct PlainText<T: SabertoothSerial> {
     dev: T,
+    stats: Stats,
+    health: Health,
+    history: History,
+    frame_log: FrameLog,
+    name: Option<String>,
+    pending: Option<PendingGet>,
+    transform: Box<dyn FrameTransform + Send>,
+    scaling: Box<dyn Scaling + Send>,
+    baud_rate: Option<u32>,
+    latency_alert: Option<LatencyAlert>,
+    write_deadline: Option<Duration>,
+    read_strategy: ReadStrategy,
+    #[cfg(any(test, feature = "emulator"))]
+    faults: utils::Faults,
+    yield_hook: utils::YieldHook,
+    armed: Option<bool>,
+    synced: bool,
+    reply_timing: ReplyTiming,
+    channels: ChannelStates,
+    filter: FilterSlot,
 }
 
 #[cfg(feature = "serialport")]
 impl PlainText<SabertoothPort> {
     /// Create a default new "Plain Text" interface.
     pub fn new(port: &str) -> Result<PlainText<SabertoothPort>> {
-        Ok(PlainText {
-            dev: SabertoothPort::new(port)?,
-        })
+        Ok(PlainText::from(SabertoothPort::new(port)?))
+    }
+
+    /// Create a new "Plain Text" interface on a serial port opened at
+    /// *baud_rate* with *timeout*.
+    pub fn open_with(
+        port: &str,
+        baud_rate: u32,
+        timeout: Duration,
+    ) -> Result<PlainText<SabertoothPort>> {
+        Ok(PlainText::from(SabertoothPort::open_with(
+            port, baud_rate, timeout,
+        )?))
+    }
+}
+
+#[cfg(feature = "emulator")]
+impl PlainText<DryRun> {
+    /// Create a new "Plain Text" interface on a
+    /// [DryRun](../testing/struct.DryRun.html), emulating the devices
+    /// without hardware. **Requires** the "emulator" feature.
+    pub fn dry_run() -> PlainText<DryRun> {
+        PlainText::from(DryRun::new())
     }
 }
 
 impl<T: SabertoothSerial> PlainText<T> {
+    /// Create a handle with the settings of another handle, see
+    /// `PacketSerial::into_plain_text()`.
+    pub(crate) fn from_parts(
+        dev: T,
+        name: Option<String>,
+        transform: Box<dyn FrameTransform + Send>,
+        scaling: Box<dyn Scaling + Send>,
+        baud_rate: Option<u32>,
+        armed: Option<bool>,
+        reply_timing: ReplyTiming,
+    ) -> PlainText<T> {
+        PlainText {
+            name,
+            transform,
+            scaling,
+            baud_rate,
+            armed,
+            reply_timing,
+            ..PlainText::from(dev)
+        }
+    }
+
+    /// Carry over the commanded state of another handle on the same device.
+    pub(crate) fn with_channel_states(mut self, channels: ChannelStates) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Carry over the command filter of another handle on the same device.
+    pub(crate) fn with_filter_slot(mut self, filter: FilterSlot) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Set a human-readable name for the device, used in the logs.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Apply *transform* to every frame before writing it, for adapters
+    /// which need the frames wrapped or escaped.
+    pub fn with_frame_transform<F>(mut self, transform: F) -> Self
+    where
+        F: FrameTransform + Send + 'static,
+    {
+        self.transform = Box::new(transform);
+        self
+    }
+
+    /// Convert the values returned by the getters with *scaling*, for
+    /// calibrated hardware or model variants with another scale. See
+    /// [ScalingProfile](../struct.ScalingProfile.html).
+    pub fn with_scaling<S>(mut self, scaling: S) -> Self
+    where
+        S: Scaling + Send + 'static,
+    {
+        self.scaling = Box::new(scaling);
+        self
+    }
+
+    /// Re-assert *baud_rate* on the port before each transaction, for
+    /// handles sharing a port, for example through a multiplexer, with
+    /// devices at other rates. The port is only reconfigured when its rate
+    /// differs.
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// Call *callback* with the quantity and the latency of the get requests
+    /// whose round trip takes longer than *threshold*, for catching latency
+    /// spikes as they happen. The latencies of all the get requests are
+    /// available in the [stats](#method.stats).
+    pub fn with_latency_alert<F>(mut self, threshold: Duration, callback: F) -> Self
+    where
+        F: FnMut(Quantity, Duration) + Send + 'static,
+    {
+        self.latency_alert = Some(LatencyAlert::new(threshold, callback));
+        self
+    }
+
+    /// Wait *grace* after writing a get request before reading its reply,
+    /// for links where the request reaches the device late, like
+    /// opto-isolated adapters. See `PacketSerial::with_reply_grace()`.
+    pub fn with_reply_grace(mut self, grace: Duration) -> Self {
+        self.reply_timing.grace = Some(grace);
+        self
+    }
+
+    /// Wait up to *timeout* for each read of a reply, instead of the timeout
+    /// of the port. See `PacketSerial::with_reply_timeout()`.
+    pub fn with_reply_timeout(mut self, timeout: Duration) -> Self {
+        self.reply_timing.timeout = Some(timeout);
+        self
+    }
+
+    /// Tune the reply timeout from the measured round trips with
+    /// *adaptive*, in place of the [reply timeout](#method.with_reply_timeout).
+    /// See [AdaptiveTimeout](../struct.AdaptiveTimeout.html).
+    pub fn with_adaptive_timeout(mut self, adaptive: AdaptiveTimeout) -> Self {
+        self.reply_timing.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Timeout of the next reply, if the handle sets one in place of the
+    /// timeout of the port.
+    pub fn reply_timeout(&self) -> Option<Duration> {
+        self.reply_timing.tuned(&self.stats).timeout
+    }
+
+    /// Give up writing a frame after *deadline* with `Error::TxTimeout`,
+    /// instead of waiting for as long as the port lets `write_all()` block,
+    /// so that a dead TX path, like a wedged USB adapter with full buffers,
+    /// is detected quickly and can trigger the failsafes.
+    pub fn with_write_deadline(mut self, deadline: Duration) -> Self {
+        self.write_deadline = Some(deadline);
+        self
+    }
+
+    /// Wait for the replies according to *strategy*, instead of blocking in
+    /// the port. See [ReadStrategy](../enum.ReadStrategy.html).
+    pub fn with_read_strategy(mut self, strategy: ReadStrategy) -> Self {
+        self.read_strategy = strategy;
+        self
+    }
+
+    /// Call *hook* while waiting for the replies, instead of blocking in the
+    /// port, for cooperative schedulers. See [Yield](../trait.Yield.html).
+    pub fn with_yield_hook<Y>(mut self, hook: Y) -> Self
+    where
+        Y: Yield + 'static,
+    {
+        self.yield_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Inject faults inside the transactions with *injector*, for testing
+    /// the error handling. See [FaultInjector](../testing/trait.FaultInjector.html).
+    /// **Requires** the "emulator" feature.
+    #[cfg(any(test, feature = "emulator"))]
+    pub fn with_fault_injector<F>(mut self, injector: F) -> Self
+    where
+        F: FaultInjector + 'static,
+    {
+        self.faults = Some(Box::new(injector));
+        self
+    }
+
+    /// Inspect the commands with *filter* before sending them: it may let
+    /// them through, deny them with `Error::CommandDenied`, or replace them.
+    /// See [CommandFilter](../trait.CommandFilter.html).
+    pub fn with_command_filter<F>(mut self, filter: F) -> Self
+    where
+        F: CommandFilter + Send + 'static,
+    {
+        self.filter.set(filter);
+        self
+    }
+
+    /// Remove and return the denials and the modifications of the command
+    /// filter, oldest first.
+    pub fn drain_filter_events(&mut self) -> impl Iterator<Item = FilterEvent> + '_ {
+        self.filter.drain_events()
+    }
+
+    /// Create the handle disarmed: the motion commands return
+    /// `Error::NotArmed` without being sent until [arm()] is called, so that
+    /// no init code path can move the motors by accident during bring-up.
+    /// The handle is disarmed again by the failsafe paths, see [disarm()].
+    ///
+    /// [arm()]: trait.Sabertooth2x32.html#method.arm
+    /// [disarm()]: trait.Sabertooth2x32.html#method.disarm
+    pub fn with_interlock(mut self) -> Self {
+        self.armed = Some(false);
+        self
+    }
+
+    /// Return the frame written for *operation*, before the [frame
+    /// transform](#method.with_frame_transform), without sending it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x32::{Operation, PlainText};
+    /// use saberrs::testing::MockPort;
+    ///
+    /// let saber = PlainText::from(MockPort::new());
+    /// let frame = saber.encode(Operation::SetSpeed(1, -1.0)).unwrap();
+    /// assert_eq!(frame, b"M1: -2047\r\n");
+    /// ```
+    pub fn encode(&self, operation: Operation) -> Result<Vec<u8>> {
+        let (command, channel, arg) = Command::from_operation(operation);
+        Ok(command.spec().text_frame(channel, arg)?.into_bytes())
+    }
+
+    /// Estimate the time taken on the wire by a transaction of *operation*:
+    /// the set command, or the get request and its longest reply. It is
+    /// computed from the baud rate and the frame sizes, without the bytes
+    /// added by a [FrameTransform](../trait.FrameTransform.html), nor the
+    /// latencies of the port and the device, which are measured in the
+    /// [stats](#method.stats).
+    pub fn estimate_transaction_time(&self, operation: Operation) -> Result<Duration> {
+        let tx = self.encode(operation)?.len();
+        let rx = if operation.is_get() {
+            bandwidth::TEXT_REPLY_SIZE
+        } else {
+            0
+        };
+        let baud_rate = match self.baud_rate {
+            Some(baud_rate) => baud_rate,
+            None => self.dev.baud_rate()?,
+        };
+        Ok(bandwidth::transfer_time(baud_rate, tx + rx))
+    }
+
+    /// Return the name of the device, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Traffic statistics since the creation of the handle or the last call
+    /// to [reset_stats()](#method.reset_stats).
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Reset the traffic statistics.
+    pub fn reset_stats(&mut self) {
+        self.stats.reset()
+    }
+
+    /// Set the speed like [set_speed()], and check that the command was
+    /// written within *deadline*, including the time waiting for a shared
+    /// port. Return the time taken, or `Error::DeadlineMissed` if it took
+    /// longer, in which case the command was sent anyway. The latencies are
+    /// recorded in the [stats](#method.stats).
+    ///
+    /// [set_speed()]: trait.Sabertooth2x32.html#tymethod.set_speed
+    pub fn set_speed_deadline<R: Into<Ratio>>(
+        &mut self,
+        channel: usize,
+        ratio: R,
+        deadline: Duration,
+    ) -> Result<Duration> {
+        let start = Instant::now();
+        self.send(Operation::SetSpeed(channel, ratio.into().get()))?;
+        utils::check_deadline(&mut self.stats, start.elapsed(), deadline)
+    }
+
+    /// Drop the bytes received until the device stays silent for *quiet*,
+    /// like the banner sent by the device on power-up, and return how many
+    /// were dropped. Meant for the bring-up, before the first command. An
+    /// `io::ErrorKind::TimedOut` error is returned if the device keeps
+    /// sending for 20 times *quiet*.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    ///
+    /// # fn example() -> saberrs::Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyS0")?;
+    /// saber.flush_until_quiet(Duration::from_millis(100))?;
+    /// let voltage = saber.get_voltage(1)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn flush_until_quiet(&mut self, quiet: Duration) -> Result<usize> {
+        let count = utils::flush_until_quiet(&mut self.dev, quiet)?;
+        if count > 0 {
+            debug!(
+                "{}{}flushed {} byte(s)",
+                self.name.as_deref().unwrap_or(""),
+                if self.name.is_some() { ": " } else { "" },
+                count
+            );
+            self.stats.record_discarded(count, 0);
+        }
+        Ok(count)
+    }
+
+    /// Link health monitor of the handle.
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Mutable access to the link health monitor, for configuring it and
+    /// draining its events.
+    pub fn health_mut(&mut self) -> &mut Health {
+        &mut self.health
+    }
+
+    /// Keep the last *capacity* transactions in the
+    /// [history](#method.history).
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history = History::new(capacity);
+        self
+    }
+
+    /// History of the last transactions, empty unless enabled with
+    /// [with_history()](#method.with_history).
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// What the handle last commanded on *channel*, without any traffic,
+    /// see [ChannelState](../struct.ChannelState.html).
+    pub fn channel_state(&self, channel: usize) -> Result<ChannelState> {
+        self.channels.get(channel)
+    }
+
+    /// Set the verbosity of the frame dumps, see
+    /// [FrameLogging](../enum.FrameLogging.html).
+    pub fn with_frame_logging(mut self, logging: FrameLogging) -> Self {
+        self.frame_log.set_logging(logging);
+        self
+    }
+
+    /// Hand the frame dumps over to the consumer thread of *sink* instead of
+    /// logging them in the transactions, see
+    /// [FrameSink](../struct.FrameSink.html).
+    pub fn with_frame_sink(mut self, sink: FrameSink) -> Self {
+        self.frame_log.set_sink(sink);
+        self
+    }
+
+    /// Change the verbosity of the frame dumps at runtime.
+    pub fn set_frame_logging(&mut self, logging: FrameLogging) {
+        self.frame_log.set_logging(logging);
+    }
+
+    /// Verbosity of the frame dumps.
+    pub fn frame_logging(&self) -> FrameLogging {
+        self.frame_log.logging()
+    }
+
+    /// Send the get request *operation*, for example
+    /// `Operation::GetVoltage(1)`, without waiting for the reply. The reply
+    /// is then polled with [try_get()](#method.try_get).
+    ///
+    /// Only one request can be in progress at a time: if the reply of the
+    /// previous one was not received yet, nothing is sent and an
+    /// `io::ErrorKind::WouldBlock` error is returned. The setters do not wait
+    /// for the device, so they have no non-blocking variant.
+    pub fn start_get(&mut self, operation: Operation) -> Result<()> {
+        if self.pending.is_some() {
+            return Err(utils::would_block_error());
+        }
+        let (command, channel, _) = Command::from_get(operation)?;
+        let spec = command.spec();
+        let target = spec.target(channel)?;
+        let cmdstr = spec.text_frame(channel, None)?;
+        self.dev.clear_input()?;
+        self.write_frame(cmdstr.as_bytes())?;
+        self.pending = Some(PendingGet {
+            command,
+            token: char::from(target[0]),
+            channel: char::from(target[1]),
+            prefix: spec.prefix.map(char::from),
+            buf: [0u8; REPLY_MAX_SIZE],
+            len: 0,
+        });
+        Ok(())
+    }
+
+    /// Read the bytes of the reply received so far, without waiting, and
+    /// return the value of the request sent by
+    /// [start_get()](#method.start_get) once it is complete. Until then an
+    /// `io::ErrorKind::WouldBlock` error is returned.
+    pub fn try_get(&mut self) -> Result<f32> {
+        let mut pending = self
+            .pending
+            .take()
+            .ok_or_else(|| Error::InvalidInput("no get request in progress".to_string()))?;
+
+        loop {
+            let buf = &mut pending.buf[pending.len..];
+            let count = match utils::read_available(&mut self.dev, buf, Some(b'\n')) {
+                Ok(count) => count,
+                Err(e) => {
+                    let res = Err(e);
+                    self.health.record_rx(false);
+                    self.history.record_outcome(&res);
+                    self.frame_log.record_outcome(&self.name, &res);
+                    return res;
+                }
+            };
+            // Line conditions are dropped, and reading continues in their
+            // place
+            let mut kept = pending.len;
+            for i in pending.len..pending.len + count {
+                let byte = pending.buf[i];
+                if !self.dev.is_line_condition(byte) {
+                    pending.buf[kept] = byte;
+                    kept += 1;
+                }
+            }
+            let discarded = pending.len + count - kept;
+            pending.len = kept;
+            if discarded == 0 {
+                break;
+            }
+            self.discard(discarded);
+        }
+        let complete = pending.len == REPLY_MAX_SIZE || pending.buf[..pending.len].ends_with(b"\n");
+        if !complete {
+            self.pending = Some(pending);
+            return Err(utils::would_block_error());
+        }
+
+        #[cfg(any(test, feature = "emulator"))]
+        if !utils::inject_reply(&mut self.faults, &mut pending.buf[..pending.len]) {
+            pending.len = 0;
+            self.pending = Some(pending);
+            return Err(utils::would_block_error());
+        }
+        let resp = &pending.buf[..pending.len];
+        self.frame_log.record_rx(&self.name, resp);
+        self.stats.record_rx(resp.len());
+        self.history.record_rx(resp);
+        let value =
+            check_response(resp, pending.token, pending.channel, pending.prefix).map(|value| {
+                utils::scale_reply(&*self.scaling, pending.command, value, Protocol::PlainText)
+            });
+        self.health.record_rx(value.is_ok());
+        self.history.record_outcome(&value);
+        self.frame_log.record_outcome(&self.name, &value);
+        value
+    }
+
+    /// Abandon the get request in progress, if any. A late reply is discarded
+    /// by the next request.
+    pub fn cancel_get(&mut self) {
+        self.pending = None;
+    }
+
+    /// Process the bytes received when the port becomes readable, in an
+    /// event loop. Return the value of the request sent by
+    /// [start_get()](#method.start_get) once its reply is complete, and
+    /// `None` until then. Bytes received while no request is in progress are
+    /// discarded.
+    pub fn on_readable(&mut self) -> Result<Option<f32>> {
+        if self.pending.is_none() {
+            self.dev.clear_input()?;
+            return Ok(None);
+        }
+        match self.try_get() {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Register the port in the *registry* of a [mio] event loop, so that
+    /// *token* is notified when the port is readable. The events are then
+    /// processed with [on_readable()](#method.on_readable).
+    ///
+    /// **Requires** the "mio" feature, on Unix.
+    ///
+    /// [mio]: https://docs.rs/mio
+    #[cfg(all(unix, feature = "mio"))]
+    pub fn register(&self, registry: &mio::Registry, token: mio::Token) -> Result<()>
+    where
+        T: AsRawFd,
+    {
+        let fd = self.dev.as_raw_fd();
+        registry.register(&mut SourceFd(&fd), token, mio::Interest::READABLE)?;
+        Ok(())
+    }
+
+    /// Remove the port from the *registry* of a mio event loop.
+    ///
+    /// **Requires** the "mio" feature, on Unix.
+    #[cfg(all(unix, feature = "mio"))]
+    pub fn deregister(&self, registry: &mio::Registry) -> Result<()>
+    where
+        T: AsRawFd,
+    {
+        let fd = self.dev.as_raw_fd();
+        registry.deregister(&mut SourceFd(&fd))?;
+        Ok(())
+    }
+
+    /// Set the baud rate given with [with_baud_rate()](#method.with_baud_rate)
+    /// on the port, if it differs.
+    fn assert_baud_rate(&mut self) -> Result<()> {
+        match self.baud_rate {
+            Some(baud_rate) if self.dev.baud_rate()? != baud_rate => {
+                self.dev.set_baud_rate(baud_rate)
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
-        dbg_frame!(tx, txdata);
-        Ok(self.dev.write_all(txdata)?)
+        self.assert_baud_rate()?;
+        self.frame_log.record_tx(&self.name, txdata);
+        #[cfg(any(test, feature = "emulator"))]
+        utils::inject_write(&mut self.faults, txdata);
+        let wire = self.transform.transform(txdata);
+        let res = match self.write_deadline {
+            Some(deadline) => utils::write_bounded(&mut self.dev, &wire, deadline),
+            None => self.dev.write_all(&wire).map_err(Error::from),
+        };
+        self.health.record_tx(res.is_ok());
+        self.history.record_tx(txdata, &res);
+        self.frame_log.record_outcome(&self.name, &res);
+        res?;
+        self.stats.record_tx(wire.len());
+        Ok(())
     }
 
     fn read_response(&mut self, rxdata: &mut [u8]) -> Result<usize> {
@@ -60,65 +629,159 @@ impl<T: SabertoothSerial> PlainText<T> {
 
         let mut bytebuf = [0u8; 1];
         let mut count: usize = 0;
+        let mut discarded: usize = 0;
 
-        for byte in &mut rxdata[..] {
-            if self.dev.read(&mut bytebuf)? == 1 {
-                *byte = bytebuf[0];
-                count += 1;
-                if bytebuf[0] == ENDFLAG {
-                    break;
-                }
-            } else {
+        while count < rxdata.len() && discarded < REPLY_MAX_SIZE {
+            if utils::read_with(
+                &mut self.dev,
+                &mut bytebuf,
+                self.read_strategy,
+                &mut self.yield_hook,
+            )? != 1
+            {
+                break;
+            }
+            if self.dev.is_line_condition(bytebuf[0]) {
+                discarded += 1;
+                continue;
+            }
+            rxdata[count] = bytebuf[0];
+            count += 1;
+            if bytebuf[0] == ENDFLAG {
                 break;
             }
         }
 
+        if discarded > 0 {
+            self.discard(discarded);
+        }
+        #[cfg(any(test, feature = "emulator"))]
+        if count > 0 && !utils::inject_reply(&mut self.faults, &mut rxdata[..count]) {
+            return Err(utils::dropped_reply_error());
+        }
         Ok(count)
     }
 
-    fn send_ratio_to_channel(&mut self, token: char, channel: usize, ratio: f32) -> Result<()> {
-        let channel = match_channel_to!(channel, '1', '2');
-        self.send_ratio(token, channel, ratio)
+    /// Account for *count* line conditions dropped from a reply. Replies are
+    /// ASCII text, so every discarded byte is a line condition.
+    fn discard(&mut self, count: usize) {
+        debug!(
+            "{}{}discarded {} line condition(s)",
+            self.name.as_deref().unwrap_or(""),
+            if self.name.is_some() { ": " } else { "" },
+            count
+        );
+        self.stats.record_discarded(count, count);
     }
 
-    fn send_ratio(&mut self, token: char, channel: char, ratio: f32) -> Result<()> {
-        let value = utils::ratio_to_value(ratio)?;
-        let cmdstr = make_cmd_str!(token, channel, value);
-        let buf = cmdstr.as_bytes();
-        self.write_frame(buf)
+    fn send(&mut self, operation: Operation) -> Result<()> {
+        let operation = self.filter.check(&self.name, operation)?;
+        utils::check_armed(self.armed, operation)?;
+        let frame = self.encode(operation)?;
+        self.write_frame(&frame)?;
+        self.channels.record(operation, Instant::now());
+        Ok(())
     }
 
-    fn request(&mut self, txdata: &[u8], rxdata: &mut [u8]) -> Result<usize> {
-        self.dev.clear_all()?;
-        self.write_frame(txdata)?;
-        let read_len = self.read_response(rxdata)?;
-        dbg_frame!(rx, rxdata);
-        Ok(read_len)
+    fn query(&mut self, operation: Operation) -> Result<f32> {
+        let (command, channel, _) = Command::from_get(operation)?;
+        let spec = command.spec();
+        let target = spec.target(channel)?;
+        let cmdstr = spec.text_frame(channel, None)?;
+        self.dev.clear_input()?;
+        let start = Instant::now();
+        self.write_frame(cmdstr.as_bytes())?;
+        let (token, ch) = (char::from(target[0]), char::from(target[1]));
+        let timing = self.reply_timing.tuned(&self.stats);
+        let timeout = utils::begin_reply(&mut self.dev, timing)?;
+        let value = self.read_value(token, ch, spec.prefix.map(char::from));
+        utils::end_reply(&mut self.dev, timeout)?;
+        self.reply_timing.record(&value);
+        self.health.record_rx(value.is_ok());
+        self.history.record_outcome(&value);
+        self.frame_log.record_outcome(&self.name, &value);
+        let value = value?;
+        utils::record_round_trip(
+            &mut self.stats,
+            &mut self.latency_alert,
+            command,
+            start.elapsed(),
+        );
+        Ok(utils::scale_reply(
+            &*self.scaling,
+            command,
+            value,
+            Protocol::PlainText,
+        ))
     }
 
-    fn get_value(&mut self, token: char, ch: char, prefix: Option<char>, req: &str) -> Result<i32> {
-        let cmdstr = make_cmd_str!(token, ch, req);
-        let mut rxbuf = [0u8; 32];
-        let size = self.request(cmdstr.as_bytes(), &mut rxbuf)?;
-        let resp = &rxbuf[..size];
-        let splitted = split_response(&resp)?;
-        if splitted.0 != token || splitted.1 != ch || splitted.2 != prefix {
-            let expected = format!("{}{}: {}<value>", token, splitted.1, prefix.unwrap_or(' '));
-            let received = String::from_utf8(resp.to_vec()).unwrap_or(format!("{:?}", resp));
-            let descr = format!(
-                "expected the form {:?} but received {:?}",
-                &expected, received
-            );
-            return Err(Error::Response(descr));
+    fn read_value(&mut self, token: char, ch: char, prefix: Option<char>) -> Result<i32> {
+        if !self.synced {
+            return self.read_first_value(token, ch, prefix);
+        }
+        let mut rxbuf = [0u8; REPLY_MAX_SIZE];
+        let size = self.read_response(&mut rxbuf)?;
+        self.frame_log.record_rx(&self.name, &rxbuf);
+        self.stats.record_rx(size);
+        self.history.record_rx(&rxbuf[..size]);
+        check_response(&rxbuf[..size], token, ch, prefix)
+    }
+
+    /// Read the first reply received by the handle, which can be preceded by
+    /// leftovers of a startup banner: the lines not holding the reply are
+    /// skipped, and so are the bytes before the reply on its line.
+    fn read_first_value(&mut self, token: char, ch: char, prefix: Option<char>) -> Result<i32> {
+        let mut rxbuf = [0u8; REPLY_MAX_SIZE];
+        let mut res = Err(Error::Response("no reply".to_string()));
+        for _ in 0..BANNER_MAX_LINES {
+            let size = self.read_response(&mut rxbuf)?;
+            self.frame_log.record_rx(&self.name, &rxbuf);
+            let line = &rxbuf[..size];
+            let start = reply_start(line, token, ch);
+            if start > 0 {
+                self.stats.record_discarded(start, 0);
+            }
+            self.stats.record_rx(size - start);
+            self.history.record_rx(line);
+            res = check_response(&line[start..], token, ch, prefix);
+            if res.is_ok() {
+                self.synced = true;
+                break;
+            }
+            if size == 0 {
+                break;
+            }
         }
-        Ok(splitted.3)
+        res
     }
 }
 
 // should work with SabertoothPort
 impl<T: SabertoothSerial> From<T> for PlainText<T> {
     fn from(dev: T) -> Self {
-        PlainText { dev }
+        PlainText {
+            dev,
+            stats: Stats::new(),
+            health: Health::new(),
+            history: History::default(),
+            frame_log: FrameLog::new(FrameFormat::Text),
+            name: None,
+            pending: None,
+            transform: Box::new(Passthrough),
+            scaling: Box::new(ScalingProfile::new()),
+            baud_rate: None,
+            latency_alert: None,
+            write_deadline: None,
+            read_strategy: ReadStrategy::Blocking,
+            #[cfg(any(test, feature = "emulator"))]
+            faults: None,
+            yield_hook: None,
+            armed: None,
+            synced: false,
+            reply_timing: ReplyTiming::default(),
+            channels: ChannelStates::default(),
+            filter: FilterSlot::default(),
+        }
     }
 }
 
@@ -128,79 +791,137 @@ where
     T: SabertoothSerial + Clone,
 {
     fn from(dev: &T) -> Self {
-        PlainText {
-            dev: (*dev).clone(),
-        }
+        PlainText::from(dev.clone())
     }
 }
 
 impl<T: SabertoothSerial> Sabertooth2x32 for PlainText<T> {
     fn startup(&mut self, channel: usize) -> Result<()> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let cmdstr = make_cmd_str!('M', ch, "startup");
-        self.write_frame(cmdstr.as_bytes())
+        self.send(Operation::Startup(channel))
     }
 
     fn shutdown(&mut self, channel: usize) -> Result<()> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let cmdstr = make_cmd_str!('M', ch, "shutdown");
-        self.dev.write_all(cmdstr.as_bytes())?;
-        Ok(())
+        self.send(Operation::Shutdown(channel))
     }
 
     fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('M', channel, ratio)
+        self.send(Operation::SetSpeed(channel, ratio))
     }
 
     fn get_speed(&mut self, channel: usize) -> Result<f32> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let value = self.get_value('M', ch, None, "get")?;
-        Ok(utils::value_to_ratio(value))
+        self.query(Operation::GetSpeed(channel))
     }
 
     fn set_drive(&mut self, ratio: f32) -> Result<()> {
-        self.send_ratio('M', 'D', ratio)
+        self.send(Operation::SetDrive(ratio))
     }
 
     fn set_turn(&mut self, ratio: f32) -> Result<()> {
-        self.send_ratio('M', 'T', ratio)
+        self.send(Operation::SetTurn(ratio))
     }
 
     fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('P', channel, ratio)
+        self.send(Operation::SetPower(channel, ratio))
     }
 
     fn get_power(&mut self, channel: usize) -> Result<f32> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let value = self.get_value('P', ch, None, "get")?;
-        Ok(utils::value_to_ratio(value))
+        self.query(Operation::GetPower(channel))
     }
 
     fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('R', channel, ratio)
+        self.send(Operation::SetRamp(channel, ratio))
     }
 
     fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('Q', channel, ratio)
+        self.send(Operation::SetAux(channel, ratio))
     }
 
     fn get_voltage(&mut self, channel: usize) -> Result<f32> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let value = self.get_value('M', ch, Some('B'), "getb")?;
-        Ok(value as f32 * 0.1f32)
+        self.query(Operation::GetVoltage(channel))
     }
 
     fn get_current(&mut self, channel: usize) -> Result<f32> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let value = self.get_value('M', ch, Some('C'), "getc")?;
-        Ok(value as f32 * 0.1f32)
+        self.query(Operation::GetCurrent(channel))
     }
 
     fn get_temperature(&mut self, channel: usize) -> Result<f32> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let value = self.get_value('M', ch, Some('T'), "gett")?;
-        Ok(value as f32)
+        self.query(Operation::GetTemperature(channel))
+    }
+
+    fn arm(&mut self) {
+        if let Some(armed) = self.armed.as_mut() {
+            *armed = true;
+        }
+    }
+
+    fn disarm(&mut self) {
+        if let Some(armed) = self.armed.as_mut() {
+            *armed = false;
+        }
     }
+
+    fn is_armed(&self) -> bool {
+        self.armed != Some(false)
+    }
+
+    fn shutdown_commanded(&self, channel: usize) -> bool {
+        self.channels
+            .get(channel)
+            .is_ok_and(|state| state.is_shut_down())
+    }
+}
+
+/// Check that the response *resp* answers the request, and return its value.
+fn check_response(resp: &[u8], token: char, ch: char, prefix: Option<char>) -> Result<i32> {
+    let splitted = split_response(resp)?;
+    if splitted.0 != token || splitted.1 != ch || splitted.2 != prefix {
+        let expected = format!("{}{}: {}<value>", token, splitted.1, prefix.unwrap_or(' '));
+        let received = String::from_utf8(resp.to_vec()).unwrap_or(format!("{:?}", resp));
+        let descr = format!(
+            "expected the form {:?} but received {:?}",
+            &expected, received
+        );
+        return Err(Error::Response(descr));
+    }
+    Ok(splitted.3)
+}
+
+/// Whether a device answering the "Plain Text" protocol is on *dev*, by
+/// reading the battery voltage. Errors are only returned for failures of the
+/// port itself.
+pub(crate) fn probe<T: SabertoothSerial + ?Sized>(dev: &mut T) -> Result<bool> {
+    let (command, channel, _) = Command::from_get(Operation::GetVoltage(1))?;
+    let spec = command.spec();
+    let target = spec.target(channel)?;
+    dev.clear_all()?;
+    dev.write_all(spec.text_frame(channel, None)?.as_bytes())?;
+
+    let mut buf = [0u8; REPLY_MAX_SIZE];
+    let mut count = 0;
+    while count < buf.len() {
+        match dev.read(&mut buf[count..count + 1]) {
+            Ok(0) => break,
+            Ok(_) => count += 1,
+            Err(e) if utils::would_block(&e) => break,
+            Err(e) => return Err(e.into()),
+        }
+        if buf[count - 1] == b'\n' {
+            break;
+        }
+    }
+    let (token, ch) = (char::from(target[0]), char::from(target[1]));
+    let line = &buf[..count];
+    let start = reply_start(line, token, ch);
+    let prefix = spec.prefix.map(char::from);
+    Ok(check_response(&line[start..], token, ch, prefix).is_ok())
+}
+
+/// Position in *line* of the last reply to *token* and *ch*, or 0.
+fn reply_start(line: &[u8], token: char, ch: char) -> usize {
+    let head = [token as u8, ch as u8, b':'];
+    line.windows(head.len())
+        .rposition(|window| window == head)
+        .unwrap_or(0)
 }
 
 /// (token, channel, Options<prefix>, value)
@@ -258,6 +979,90 @@ fn split_response(rxdata: &[u8]) -> Result<SplitResponse> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_try_get() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+
+        saber.start_get(Operation::GetCurrent(2)).unwrap();
+        assert_eq!(port.take_written(), b"M2: getc\r\n");
+        port.push_rx(b"M2: C");
+        saber.try_get().unwrap_err();
+        port.push_rx(b"-52\r\n");
+        assert!((saber.try_get().unwrap() + 5.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_channel_state() {
+        let port = MockPort::new();
+        let mut saber = PlainText::from(&port).with_interlock();
+
+        assert!(saber.set_speed(1, 0.5).is_err());
+        assert_eq!(None, saber.channel_state(1).unwrap().last_command);
+
+        saber.arm();
+        saber.startup(1).unwrap();
+        saber.set_speed(1, 0.5).unwrap();
+        saber.shutdown(2).unwrap();
+        let state = saber.channel_state(1).unwrap();
+        assert_eq!(Some(true), state.started);
+        assert_eq!(Some(Operation::SetSpeed(1, 0.5)), state.setpoint);
+        assert!(saber.channel_state(2).unwrap().is_shut_down());
+    }
+
+    #[test]
+    fn test_banner() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+
+        port.push_rx(b"Sabertooth 2x32\r\n");
+        let quiet = Duration::from_millis(1);
+        assert_eq!(17, saber.flush_until_quiet(quiet).unwrap());
+        assert_eq!(0, saber.flush_until_quiet(quiet).unwrap());
+
+        // Leftovers of the banner before the first reply
+        port.queue_reply(b"Firmware revision 1.0, please wait...\r\n??M1:B240\r\n");
+        assert_eq!(24.0, saber.get_voltage(1).unwrap());
+
+        // Only the first reply is tolerant
+        port.queue_reply(b"??M1:B240\r\n");
+        assert!(matches!(saber.get_voltage(1), Err(Error::Response(_))));
+    }
+
+    #[test]
+    fn test_line_conditions() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+
+        port.queue_reply(b"\0M1: B12\x003\r\n");
+        assert!((saber.get_voltage(1).unwrap() - 12.3).abs() < 1e-4);
+        assert_eq!(2, saber.stats().line_conditions());
+        assert_eq!(1, saber.stats().resyncs());
+
+        saber.start_get(Operation::GetSpeed(1)).unwrap();
+        port.push_rx(b"M1: 0\0\r\n");
+        assert_eq!(0.0, saber.try_get().unwrap());
+        assert_eq!(3, saber.stats().line_conditions());
+        assert_eq!(2, saber.stats().resyncs());
+    }
+
+    #[test]
+    fn test_set_speed_deadline() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+
+        let hour = Duration::from_secs(3600);
+        let elapsed = saber.set_speed_deadline(1, 0.5, hour).unwrap();
+        match saber.set_speed_deadline(2, 0.5, Duration::from_secs(0)) {
+            Err(Error::DeadlineMissed { deadline, .. }) => assert_eq!(0, deadline.as_nanos()),
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(port.take_written(), b"M1: 1023\r\nM2: 1023\r\n");
+        assert_eq!(1, saber.stats().deadline_misses());
+        assert!(saber.stats().max_latency().unwrap() >= elapsed);
+    }
 
     #[test]
     fn test_split_response() {
@@ -282,4 +1087,33 @@ mod tests {
             Some(SplitResponse('S', '2', None, -52))
         );
     }
+
+    #[test]
+    fn test_estimate_transaction_time() {
+        let saber = PlainText::from(MockPort::new()).with_baud_rate(9600);
+        let time = |op| saber.estimate_transaction_time(op).unwrap().as_micros();
+        // "M1: 0\r\n", then "M1: get\r\n" and the longest reply
+        assert_eq!(7_291, time(Operation::SetSpeed(1, 0.0)));
+        assert_eq!(20_833, time(Operation::GetSpeed(1)));
+    }
+
+    #[test]
+    fn test_adaptive_timeout() {
+        let ms = Duration::from_millis;
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port)
+            .with_adaptive_timeout(AdaptiveTimeout::new(ms(20), ms(500)).with_min_samples(2));
+        assert_eq!(Some(ms(500)), saber.reply_timeout());
+        for _ in 0..2 {
+            port.queue_reply(b"M1:B240\r\n");
+            saber.get_voltage(1).unwrap();
+        }
+        assert_eq!(Some(ms(20)), saber.reply_timeout());
+
+        assert!(saber.get_voltage(1).is_err());
+        assert_eq!(Some(ms(500)), saber.reply_timeout());
+        port.queue_reply(b"M1:B240\r\n");
+        saber.get_voltage(1).unwrap();
+        assert_eq!(Some(ms(20)), saber.reply_timeout());
+    }
 }
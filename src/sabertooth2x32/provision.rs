@@ -0,0 +1,347 @@
+//! Provisioning of a factory-default Sabertooth 2x32.
+//!
+//! On the Sabertooth 2x32 the address and the protocol mode are selected with
+//! the DIP switches, and the baud rate of the serial modes with DEScribe, so
+//! none of them can be written over the serial link. Provisioning therefore
+//! applies what the host controls and verifies the rest, in this order:
+//!
+//! 1. set the baud rate of the port, and wait for the line to settle;
+//! 2. probe the desired address with the desired packet type, then with the
+//!    other packet type, then the other addresses, until the device replies;
+//! 3. start up both channels, and verify that they read back a zero speed;
+//! 4. with [with_battery()], check the voltage read while probing against the
+//!    expected pack, as the low-voltage cutoff of the device is not readable.
+//!
+//! The [Report] tells where the device was found, so a wrong DIP switch
+//! setting is reported rather than guessed.
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::{Result, SabertoothPort};
+//! use saberrs::sabertooth2x32::PacketType;
+//! use saberrs::sabertooth2x32::provision::Provisioning;
+//!
+//! # fn example() -> Result<()> {
+//! let port = SabertoothPort::new("/dev/ttyS0")?;
+//! let (mut saber, report) = Provisioning::new()
+//!     .with_address(129)
+//!     .with_packet_type(PacketType::Checksum)
+//!     .with_baud_rate(38400)
+//!     .provision(port)?;
+//!
+//! if !report.passed() {
+//!     eprintln!("{:?}", report);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [Report]: struct.Report.html
+//! [with_battery()]: struct.Provisioning.html#method.with_battery
+
+use std::thread;
+use std::time::Duration;
+
+use super::{PacketSerial, PacketType, Sabertooth2x32, DEFAULT_ADDRESS, DEFAULT_PACKET_TYPE};
+use crate::opcodes::sabertooth2x32 as op;
+use crate::port::SabertoothSerial;
+#[cfg(feature = "control")]
+use crate::BatteryPack;
+use crate::Result;
+
+/// Addresses selectable with the DIP switches.
+const ADDRESSES: std::ops::RangeInclusive<u8> = op::MIN_ADDRESS..=op::MAX_ADDRESS;
+
+/// One step of a provisioning run, in execution order.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Step {
+    /// The port was set to the baud rate.
+    SetBaudRate(u32),
+
+    /// The device was queried at *address* with *packet_type*.
+    Probe {
+        address: u8,
+        packet_type: PacketType,
+        replied: bool,
+    },
+
+    /// The channel was started up.
+    Startup(usize),
+
+    /// The speed of the channel was read back after startup.
+    Verify { channel: usize, stopped: bool },
+
+    /// The voltage read while probing was checked against the expected
+    /// pack.
+    Battery { voltage: Option<f32>, matches: bool },
+}
+
+/// Result of a provisioning run.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Report {
+    address: u8,
+    packet_type: PacketType,
+    found: Option<(u8, PacketType)>,
+    voltage: Option<f32>,
+    steps: Vec<Step>,
+}
+
+impl Report {
+    /// Address and packet type at which the device replied, if any.
+    pub fn found(&self) -> Option<(u8, PacketType)> {
+        self.found
+    }
+
+    /// Battery voltage read when the device replied, if it did, in volts.
+    pub fn voltage(&self) -> Option<f32> {
+        self.voltage
+    }
+
+    /// Steps of the run, in execution order.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// True if the device replied with the desired address and packet type,
+    /// both channels were verified, and the battery matched if checked.
+    pub fn passed(&self) -> bool {
+        let verified = self
+            .steps
+            .iter()
+            .filter(|step| matches!(step, Step::Verify { stopped: true, .. }))
+            .count();
+        let battery = !self
+            .steps
+            .iter()
+            .any(|step| matches!(step, Step::Battery { matches: false, .. }));
+        self.found == Some((self.address, self.packet_type)) && verified == 2 && battery
+    }
+}
+
+/// Provisioning settings. See the [module documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct Provisioning {
+    address: u8,
+    packet_type: PacketType,
+    baud_rate: u32,
+    settle_time: Duration,
+    #[cfg(feature = "control")]
+    battery: Option<BatteryPack>,
+}
+
+impl Provisioning {
+    /// Create settings for the factory defaults: address 128, CRC frames and
+    /// 9600 baud, with 100 ms settle time.
+    pub fn new() -> Provisioning {
+        Provisioning {
+            address: DEFAULT_ADDRESS,
+            packet_type: DEFAULT_PACKET_TYPE,
+            baud_rate: 9600,
+            settle_time: Duration::from_millis(100),
+            #[cfg(feature = "control")]
+            battery: None,
+        }
+    }
+
+    /// Set the desired address, as selected with the DIP switches.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set the desired packet type.
+    pub fn with_packet_type(mut self, packet_type: PacketType) -> Self {
+        self.packet_type = packet_type;
+        self
+    }
+
+    /// Set the baud rate of the port, as configured on the device.
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Set the time to wait after changing the baud rate and after startup.
+    pub fn with_settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = settle_time;
+        self
+    }
+
+    /// Check that the device is powered by *pack*, from the voltage read
+    /// while probing.
+    #[cfg(feature = "control")]
+    pub fn with_battery(mut self, pack: BatteryPack) -> Self {
+        self.battery = Some(pack);
+        self
+    }
+
+    /// Provision the device on *dev*, and return a handle configured for
+    /// where the device was found, or for the desired settings if it was
+    /// not found. Errors are only returned for failures of the port itself.
+    pub fn provision<T: SabertoothSerial>(&self, mut dev: T) -> Result<(PacketSerial<T>, Report)> {
+        let mut steps = Vec::new();
+
+        dev.set_baud_rate(self.baud_rate)?;
+        dev.clear_all()?;
+        steps.push(Step::SetBaudRate(self.baud_rate));
+        thread::sleep(self.settle_time);
+
+        let mut saber = PacketSerial::from(dev);
+        let mut found = None;
+        let mut voltage = None;
+        for (address, packet_type) in self.candidates() {
+            saber = saber.with_address(address).with_packet_type(packet_type);
+            voltage = saber.get_voltage(1).ok();
+            let replied = voltage.is_some();
+            steps.push(Step::Probe {
+                address,
+                packet_type,
+                replied,
+            });
+            if replied {
+                found = Some((address, packet_type));
+                break;
+            }
+        }
+
+        match found {
+            Some(_) => {
+                for channel in 1..=2 {
+                    saber.startup(channel)?;
+                    steps.push(Step::Startup(channel));
+                }
+                thread::sleep(self.settle_time);
+                for channel in 1..=2 {
+                    let stopped = matches!(saber.get_speed(channel), Ok(speed) if speed == 0.0);
+                    steps.push(Step::Verify { channel, stopped });
+                }
+                #[cfg(feature = "control")]
+                if let Some(pack) = self.battery {
+                    let matches = matches!(voltage, Some(v) if pack.matches(v));
+                    steps.push(Step::Battery { voltage, matches });
+                }
+            }
+            None => {
+                saber = saber
+                    .with_address(self.address)
+                    .with_packet_type(self.packet_type);
+            }
+        }
+
+        let report = Report {
+            address: self.address,
+            packet_type: self.packet_type,
+            found,
+            voltage,
+            steps,
+        };
+        Ok((saber, report))
+    }
+
+    /// Addresses and packet types to probe, most likely first.
+    fn candidates(&self) -> Vec<(u8, PacketType)> {
+        let mut addresses = vec![self.address];
+        addresses.extend(ADDRESSES.filter(|&a| a != self.address));
+        addresses
+            .into_iter()
+            .flat_map(|a| vec![(a, self.packet_type), (a, self.packet_type.other())])
+            .collect()
+    }
+}
+
+impl Default for Provisioning {
+    fn default() -> Self {
+        Provisioning::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockPort, Script, ScriptStep};
+
+    #[test]
+    fn test_candidates() {
+        let candidates = Provisioning::new().with_address(130).candidates();
+        assert_eq!(16, candidates.len());
+        assert_eq!((130, PacketType::CRC), candidates[0]);
+        assert_eq!((130, PacketType::Checksum), candidates[1]);
+        assert_eq!((128, PacketType::CRC), candidates[2]);
+    }
+
+    #[test]
+    fn test_provision() {
+        let get_crc = b"\xf0\x29\x10\x2e\x4d\x31\x06\x24";
+        let get_checksum = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let port = MockPort::with_script(
+            Script::new()
+                .step(ScriptStep::expect(get_crc))
+                .step(
+                    ScriptStep::expect(get_checksum)
+                        .respond(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76"),
+                )
+                .step(ScriptStep::expect(b"\x80\x28\x20\x48\x00\x00\x4d\x31\x7e"))
+                .step(ScriptStep::expect(b"\x80\x28\x20\x48\x00\x00\x4d\x32\x7f"))
+                .step(
+                    ScriptStep::expect(b"\x80\x29\x00\x29\x4d\x31\x7e")
+                        .respond(b"\x80\x49\x00\x49\x00\x00\x4d\x31\x7e"),
+                )
+                .step(
+                    ScriptStep::expect(b"\x80\x29\x00\x29\x4d\x32\x7f")
+                        .respond(b"\x80\x49\x00\x49\x00\x00\x4d\x32\x7f"),
+                ),
+        );
+
+        let (saber, report) = Provisioning::new()
+            .with_baud_rate(38400)
+            .with_settle_time(Duration::from_millis(0))
+            .provision(port.clone())
+            .unwrap();
+        port.check_script().unwrap();
+        assert_eq!(38400, port.baud_rate().unwrap());
+        assert_eq!(PacketType::Checksum, saber.packet_type());
+        assert_eq!(Some((128, PacketType::Checksum)), report.found());
+        assert!(!report.passed());
+        assert_eq!(Step::SetBaudRate(38400), report.steps()[0]);
+        assert_eq!(
+            Step::Verify {
+                channel: 2,
+                stopped: true
+            },
+            report.steps()[6]
+        );
+    }
+
+    #[cfg(feature = "control")]
+    #[test]
+    fn test_provision_battery() {
+        use crate::{BatteryPack, Chemistry};
+
+        let reply = |frame: &[u8]| match frame {
+            b"\x80\x29\x10\x39\x4d\x31\x7e" => b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76".to_vec(),
+            _ => Vec::new(),
+        };
+        let provisioning = Provisioning::new()
+            .with_packet_type(PacketType::Checksum)
+            .with_settle_time(Duration::from_millis(0));
+        for (pack, matches) in [
+            (BatteryPack::new(Chemistry::LiPo, 3), true),
+            (BatteryPack::new(Chemistry::NiMH, 20), false),
+        ] {
+            let port = MockPort::with_responder(reply);
+            let (_, report) = provisioning
+                .clone()
+                .with_battery(pack)
+                .provision(port)
+                .unwrap();
+            assert_eq!(
+                Some(&Step::Battery {
+                    voltage: Some(12.0),
+                    matches
+                }),
+                report.steps().last()
+            );
+        }
+    }
+}
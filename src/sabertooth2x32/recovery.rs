@@ -0,0 +1,581 @@
+//! Diagnosis of a device which stopped answering "Packet Serial" frames.
+//!
+//! After a power cycle the Sabertooth 2x32 can come back in another mode or
+//! at another baud rate, for example when the DIP switches or the DEScribe
+//! settings were changed in between. Instead of timing out forever, the
+//! [Recovery] probes the device at each candidate baud rate, first with both
+//! packet types, then with the "Plain Text" protocol, and reports where it
+//! was found as a [Diagnosis].
+//!
+//! The diagnosis can run on demand with [Recovery::diagnose()], or
+//! automatically after a number of consecutive get requests without reply
+//! with [PacketSerial::with_recovery()]. When the device answers "Plain
+//! Text", the handle can be converted with [PacketSerial::into_plain_text()].
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::sabertooth2x32::PacketSerial;
+//! use saberrs::sabertooth2x32::recovery::{Diagnosis, Recovery};
+//!
+//! # fn example() -> saberrs::Result<()> {
+//! let mut saber = PacketSerial::new("/dev/ttyS0")?;
+//!
+//! match Recovery::new().with_switch().diagnose(&mut saber)? {
+//!     Diagnosis::PacketSerial { .. } => println!("device found"),
+//!     Diagnosis::PlainText { baud_rate } => {
+//!         println!("device in Plain Text mode at {} baud", baud_rate);
+//!         let text = saber.into_plain_text();
+//!     }
+//!     Diagnosis::NoReply => println!("device not found"),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Sniffing
+//!
+//! When the device answers in another protocol than the one sent, for
+//! example "Plain Text" lines to "Packet Serial" frames, the replies are
+//! only discarded. A [Sniffer] runs the decoders of both protocols over the
+//! received bytes and reports which one the traffic matches:
+//!
+//! - [PacketSerial::with_sniffer()] feeds it with every byte received by the
+//!   handle, including the discarded ones. Its [SniffReport] is logged by
+//!   the diagnosis.
+//! - [sniff()] listens passively on a port for a while, for example on a
+//!   line driven by another controller.
+//!
+//! [Recovery]: struct.Recovery.html
+//! [Diagnosis]: enum.Diagnosis.html
+//! [Recovery::diagnose()]: struct.Recovery.html#method.diagnose
+//! [PacketSerial::with_recovery()]: ../struct.PacketSerial.html#method.with_recovery
+//! [PacketSerial::into_plain_text()]: ../struct.PacketSerial.html#method.into_plain_text
+//! [Sniffer]: struct.Sniffer.html
+//! [SniffReport]: struct.SniffReport.html
+//! [PacketSerial::with_sniffer()]: ../struct.PacketSerial.html#method.with_sniffer
+//! [sniff()]: fn.sniff.html
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{plaintext, PacketSerial, PacketType};
+use crate::port::SabertoothSerial;
+use crate::protocol::packet::{self, crc};
+use crate::utils;
+use crate::Result;
+
+/// Baud rates supported by the Sabertooth 2x32, most common first.
+pub const DEFAULT_BAUD_RATES: [u32; 5] = [9600, 2400, 19200, 38400, 115200];
+
+/// Longest "Plain Text" line recognized by a [Sniffer](struct.Sniffer.html).
+const MAX_LINE: usize = 64;
+
+/// Pause of [sniff()](fn.sniff.html) when the port has nothing to read.
+const IDLE_POLL: Duration = Duration::from_millis(1);
+
+/// Where the device was found by a [Recovery](struct.Recovery.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Diagnosis {
+    /// The device answers "Packet Serial" frames of *packet_type* at
+    /// *baud_rate*. If it is the rate of the port, the failures were
+    /// transient.
+    PacketSerial {
+        baud_rate: u32,
+        packet_type: PacketType,
+    },
+
+    /// The device answers the "Plain Text" protocol at *baud_rate*: it was
+    /// restarted in another mode.
+    PlainText { baud_rate: u32 },
+
+    /// The device did not answer at any of the baud rates.
+    NoReply,
+}
+
+/// Recovery settings. See the [module documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct Recovery {
+    baud_rates: Vec<u32>,
+    switch: bool,
+}
+
+impl Recovery {
+    /// Create settings probing the [DEFAULT_BAUD_RATES], after the current
+    /// rate of the port, and restoring the port afterwards.
+    ///
+    /// [DEFAULT_BAUD_RATES]: constant.DEFAULT_BAUD_RATES.html
+    pub fn new() -> Recovery {
+        Recovery {
+            baud_rates: DEFAULT_BAUD_RATES.to_vec(),
+            switch: false,
+        }
+    }
+
+    /// Set the baud rates to probe after the current rate of the port.
+    pub fn with_baud_rates(mut self, baud_rates: &[u32]) -> Self {
+        self.baud_rates = baud_rates.to_vec();
+        self
+    }
+
+    /// Leave the handle configured where the device was found: the port at
+    /// its baud rate, and for "Packet Serial" the handle with its packet
+    /// type. By default the handle and the port are restored.
+    pub fn with_switch(mut self) -> Self {
+        self.switch = true;
+        self
+    }
+
+    /// Probe the device of *saber* and return where it was found. Errors are
+    /// only returned for failures of the port itself.
+    pub fn diagnose<T: SabertoothSerial>(&self, saber: &mut PacketSerial<T>) -> Result<Diagnosis> {
+        if let Some(report) = saber.sniff_report() {
+            log_info!(
+                "traffic received so far: {:?} ({:?})",
+                report.traffic(),
+                report
+            );
+        }
+        let baud_rate = saber.port_mut().baud_rate()?;
+        let packet_type = saber.packet_type();
+        // The rate given with with_baud_rate() would be re-asserted by every
+        // probe
+        let asserted = saber.baud_rate_mut().take();
+
+        let mut diagnosis = Ok(Diagnosis::NoReply);
+        for rate in self.candidates(baud_rate) {
+            diagnosis = saber
+                .port_mut()
+                .set_baud_rate(rate)
+                .and_then(|_| self.probe(saber, rate, packet_type));
+            if !matches!(diagnosis, Ok(Diagnosis::NoReply)) {
+                break;
+            }
+        }
+        saber.set_packet_type(packet_type);
+        *saber.baud_rate_mut() = asserted;
+        let diagnosis = match diagnosis {
+            Ok(diagnosis) => diagnosis,
+            Err(e) => {
+                saber.port_mut().set_baud_rate(baud_rate)?;
+                return Err(e);
+            }
+        };
+
+        let found = match diagnosis {
+            Diagnosis::PacketSerial {
+                baud_rate,
+                packet_type,
+            } => Some((baud_rate, Some(packet_type))),
+            Diagnosis::PlainText { baud_rate } => Some((baud_rate, None)),
+            Diagnosis::NoReply => None,
+        };
+        match found {
+            Some((rate, found_type)) if self.switch => {
+                saber.port_mut().set_baud_rate(rate)?;
+                if asserted.is_some() {
+                    *saber.baud_rate_mut() = Some(rate);
+                }
+                if let Some(found_type) = found_type {
+                    saber.set_packet_type(found_type);
+                }
+            }
+            _ => saber.port_mut().set_baud_rate(baud_rate)?,
+        }
+
+        match diagnosis {
+            Diagnosis::NoReply => log_warn!("device not found at any baud rate"),
+            _ => log_info!("device found: {:?}", diagnosis),
+        }
+        Ok(diagnosis)
+    }
+
+    /// Probe the device at the current rate of the port, *baud_rate*,
+    /// starting with *packet_type*.
+    fn probe<T: SabertoothSerial>(
+        &self,
+        saber: &mut PacketSerial<T>,
+        baud_rate: u32,
+        packet_type: PacketType,
+    ) -> Result<Diagnosis> {
+        for packet_type in [packet_type, packet_type.other()].iter().copied() {
+            if saber.probe(packet_type)? {
+                return Ok(Diagnosis::PacketSerial {
+                    baud_rate,
+                    packet_type,
+                });
+            }
+        }
+        if plaintext::probe(saber.port_mut())? {
+            return Ok(Diagnosis::PlainText { baud_rate });
+        }
+        Ok(Diagnosis::NoReply)
+    }
+
+    /// Baud rates to probe, the current one first.
+    fn candidates(&self, baud_rate: u32) -> Vec<u32> {
+        let mut rates = vec![baud_rate];
+        rates.extend(self.baud_rates.iter().filter(|&&r| r != baud_rate));
+        rates
+    }
+}
+
+impl Default for Recovery {
+    fn default() -> Self {
+        Recovery::new()
+    }
+}
+
+/// Protocol matched by the traffic seen by a [Sniffer](struct.Sniffer.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Traffic {
+    /// Nothing was received.
+    Silent,
+
+    /// Only "Packet Serial" frames, mostly of *packet_type*.
+    PacketSerial { packet_type: PacketType },
+
+    /// Only "Plain Text" lines.
+    PlainText,
+
+    /// Both "Packet Serial" frames and "Plain Text" lines: the devices or the
+    /// controllers on the line are not configured alike.
+    Mixed,
+
+    /// Bytes matching neither protocol, like a wrong baud rate gives.
+    Unknown,
+}
+
+/// Counts of the frames and lines decoded by a [Sniffer](struct.Sniffer.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SniffReport {
+    /// "Packet Serial" frames with a valid checksum.
+    pub checksum_frames: u64,
+
+    /// "Packet Serial" frames with a valid CRC.
+    pub crc_frames: u64,
+
+    /// "Plain Text" lines, requests or replies.
+    pub text_lines: u64,
+
+    /// Bytes matching neither protocol.
+    pub unknown_bytes: u64,
+}
+
+impl SniffReport {
+    /// Protocol matched by the traffic. A few unknown bytes besides the
+    /// frames or the lines, like after a break, are ignored.
+    pub fn traffic(&self) -> Traffic {
+        let frames = self.checksum_frames + self.crc_frames;
+        match (frames, self.text_lines) {
+            (0, 0) if self.unknown_bytes == 0 => Traffic::Silent,
+            (0, 0) => Traffic::Unknown,
+            (_, 0) => Traffic::PacketSerial {
+                packet_type: if self.crc_frames > self.checksum_frames {
+                    PacketType::CRC
+                } else {
+                    PacketType::Checksum
+                },
+            },
+            (0, _) => Traffic::PlainText,
+            _ => Traffic::Mixed,
+        }
+    }
+}
+
+/// Decoder running both protocols over received bytes. See the [module
+/// documentation](index.html).
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::recovery::{Sniffer, Traffic};
+///
+/// let mut sniffer = Sniffer::new();
+/// sniffer.feed(b"M1:B240\r\n");
+/// sniffer.feed(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76");
+/// assert_eq!(Traffic::Mixed, sniffer.report().traffic());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Sniffer {
+    pending: Vec<u8>,
+    report: SniffReport,
+}
+
+impl Sniffer {
+    /// Create a sniffer which saw nothing.
+    pub fn new() -> Sniffer {
+        Sniffer::default()
+    }
+
+    /// Decode the received *bytes*. An incomplete frame or line is kept
+    /// until the next bytes.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        self.decode(false);
+    }
+
+    /// Decode the incomplete frame or line kept, as unknown bytes.
+    pub fn flush(&mut self) {
+        self.decode(true);
+    }
+
+    /// Counts of the bytes decoded so far.
+    pub fn report(&self) -> SniffReport {
+        self.report
+    }
+
+    /// Forget the bytes decoded so far.
+    pub fn reset(&mut self) {
+        *self = Sniffer::default();
+    }
+
+    fn decode(&mut self, flush: bool) {
+        let report = &mut self.report;
+        let mut pos = 0;
+        while pos < self.pending.len() {
+            let rest = &self.pending[pos..];
+            if rest[0] & 0x80 != 0 {
+                match packet::match_frame(rest) {
+                    Some((packet_type, size)) => {
+                        match packet_type {
+                            PacketType::Checksum => report.checksum_frames += 1,
+                            PacketType::CRC => report.crc_frames += 1,
+                        }
+                        pos += size;
+                    }
+                    None if rest.len() < crc::PACKET_SET_SIZE && !flush => break,
+                    None => {
+                        report.unknown_bytes += 1;
+                        pos += 1;
+                    }
+                }
+                continue;
+            }
+            // A line ends at its newline, or is cut by an address byte
+            match rest.iter().position(|&b| b == b'\n' || b & 0x80 != 0) {
+                Some(end) if rest[end] == b'\n' => {
+                    if is_text_line(&rest[..end]) {
+                        report.text_lines += 1;
+                    } else {
+                        report.unknown_bytes += end as u64 + 1;
+                    }
+                    pos += end + 1;
+                }
+                Some(end) => {
+                    report.unknown_bytes += end as u64;
+                    pos += end;
+                }
+                None if rest.len() < MAX_LINE && !flush => break,
+                None => {
+                    report.unknown_bytes += rest.len() as u64;
+                    pos = self.pending.len();
+                }
+            }
+        }
+        self.pending.drain(..pos);
+    }
+}
+
+/// Port feeding a [Sniffer] with the bytes read from its inner port.
+pub(crate) struct SniffedPort<'a, T: ?Sized> {
+    dev: &'a mut T,
+    sniffer: &'a mut Sniffer,
+}
+
+impl<'a, T: SabertoothSerial + ?Sized> SniffedPort<'a, T> {
+    pub(crate) fn new(dev: &'a mut T, sniffer: &'a mut Sniffer) -> SniffedPort<'a, T> {
+        SniffedPort { dev, sniffer }
+    }
+}
+
+impl<T: SabertoothSerial + ?Sized> io::Read for SniffedPort<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.dev.read(buf)?;
+        self.sniffer.feed(&buf[..count]);
+        Ok(count)
+    }
+}
+
+impl<T: SabertoothSerial + ?Sized> io::Write for SniffedPort<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.dev.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.dev.flush()
+    }
+}
+
+impl<T: SabertoothSerial + ?Sized> SabertoothSerial for SniffedPort<'_, T> {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.dev.set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.dev.timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        self.dev.set_baud_rate(baud_rate)
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        self.dev.baud_rate()
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        self.dev.clear_all()
+    }
+
+    fn clear_input(&self) -> Result<()> {
+        self.dev.clear_input()
+    }
+
+    fn is_line_condition(&self, byte: u8) -> bool {
+        self.dev.is_line_condition(byte)
+    }
+}
+
+/// Whether *line* looks like a "Plain Text" request or reply, like
+/// `M1: 2047` or `M1:B240`.
+fn is_text_line(line: &[u8]) -> bool {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    line.len() >= 4
+        && line[0].is_ascii_uppercase()
+        && line[2] == b':'
+        && line.iter().all(|b| b.is_ascii_graphic() || *b == b' ')
+}
+
+/// Listen on *port* for *window* without writing anything, and return what
+/// the traffic received matches. The window is exceeded by up to the
+/// timeout of the port. Errors are only returned for failures of the port
+/// itself.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::SabertoothPort;
+/// use saberrs::sabertooth2x32::recovery::sniff;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut port = SabertoothPort::new("/dev/ttyS0")?;
+/// let report = sniff(&mut port, Duration::from_secs(1))?;
+/// println!("{:?}", report.traffic());
+/// # Ok(())
+/// # }
+/// ```
+pub fn sniff<T: SabertoothSerial + ?Sized>(port: &mut T, window: Duration) -> Result<SniffReport> {
+    let mut sniffer = Sniffer::new();
+    let mut buf = [0u8; MAX_LINE];
+    let start = Instant::now();
+    while start.elapsed() < window {
+        match port.read(&mut buf) {
+            Ok(count) if count > 0 => sniffer.feed(&buf[..count]),
+            Ok(_) => thread::sleep(IDLE_POLL),
+            Err(e) if utils::would_block(&e) => thread::sleep(IDLE_POLL),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    sniffer.flush();
+    Ok(sniffer.report())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::Sabertooth2x32;
+    use crate::testing::MockPort;
+
+    /// Device answering the battery voltage in "Plain Text" only.
+    fn text_device() -> MockPort {
+        MockPort::with_responder(|frame| match frame {
+            b"M1: getb\r\n" => b"M1:B240\r\n".to_vec(),
+            _ => Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_sniffer() {
+        let crc_frame = crc::PacketSet::new(128, 0, 1024, *b"M1").unwrap();
+        let mut sniffer = Sniffer::new();
+        for &byte in crc_frame.as_ref() {
+            sniffer.feed(&[byte]);
+        }
+        assert_eq!(
+            Traffic::PacketSerial {
+                packet_type: PacketType::CRC
+            },
+            sniffer.report().traffic()
+        );
+        sniffer.feed(b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04\x80\x29\x10\x39\x4d");
+        sniffer.feed(b"\x31\x7e\x00\xff");
+        sniffer.flush();
+        let report = sniffer.report();
+        assert_eq!(
+            (2, 1, 0, 2),
+            (
+                report.checksum_frames,
+                report.crc_frames,
+                report.text_lines,
+                report.unknown_bytes
+            )
+        );
+
+        sniffer.reset();
+        sniffer.feed(b"M1: 2047\r\nM1:B2");
+        sniffer.feed(b"40\r\n#@!\n");
+        assert_eq!(2, sniffer.report().text_lines);
+        assert_eq!(4, sniffer.report().unknown_bytes);
+        assert_eq!(Traffic::PlainText, sniffer.report().traffic());
+        sniffer.reset();
+        assert_eq!(Traffic::Silent, sniffer.report().traffic());
+    }
+
+    #[test]
+    fn test_sniff() {
+        let mut port = MockPort::new();
+        port.push_rx(b"M1:B240\r\n\x80\x49\x10\x59\x78\x00\x4d\x31\x76");
+        let report = sniff(&mut port, Duration::from_millis(10)).unwrap();
+        assert_eq!(Traffic::Mixed, report.traffic());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let rates = Recovery::new().candidates(38400);
+        assert_eq!(rates, vec![38400, 9600, 2400, 19200, 115200]);
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let port = text_device();
+        let mut saber = PacketSerial::from(&port);
+        let recovery = Recovery::new().with_baud_rates(&[2400]);
+        let diagnosis = recovery.diagnose(&mut saber).unwrap();
+        assert_eq!(diagnosis, Diagnosis::PlainText { baud_rate: 9600 });
+        assert_eq!(PacketType::CRC, saber.packet_type());
+
+        let mut text = saber.into_plain_text();
+        assert_eq!(24.0, text.get_voltage(1).unwrap());
+
+        let mut saber = PacketSerial::from(MockPort::new());
+        let diagnosis = recovery.diagnose(&mut saber).unwrap();
+        assert_eq!(diagnosis, Diagnosis::NoReply);
+    }
+
+    #[test]
+    fn test_with_recovery() {
+        let port = text_device();
+        let mut saber = PacketSerial::from(&port).with_recovery(2, Recovery::new());
+        assert!(saber.get_voltage(1).is_err());
+        assert_eq!(None, saber.diagnosis());
+        assert!(saber.get_voltage(1).is_err());
+        assert_eq!(
+            Some(Diagnosis::PlainText { baud_rate: 9600 }),
+            saber.diagnosis()
+        );
+    }
+}
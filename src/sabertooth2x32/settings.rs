@@ -0,0 +1,159 @@
+//! Typed access to the configuration of a Sabertooth 2x32.
+//!
+//! DEScribe edits the settings stored by the device over USB, but "Packet
+//! Serial" only writes a few of them, volatile, and reads back none:
+//!
+//! | Setting                  | Over serial             | Value                   |
+//! |--------------------------|-------------------------|-------------------------|
+//! | [Setting::Ramp]          | written, lost on reboot | `SettingValue::Ratio`   |
+//! | [Setting::SerialTimeout] | written, lost on reboot | `SettingValue::Timeout` |
+//! | [Setting::Deadband]      | DEScribe only           |                         |
+//! | [Setting::CurrentLimit]  | DEScribe only           |                         |
+//! | [Setting::OperatingMode] | DEScribe only           |                         |
+//!
+//! [PacketSerial::set_setting()] writes the writable settings and records
+//! them, and [PacketSerial::get_setting()] returns the value recorded by the
+//! handle, since the device cannot report it. The settings only editable with
+//! DEScribe return `Error::Unsupported`, so provisioning code can tell them
+//! apart from an invalid value.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use saberrs::sabertooth2x32::PacketSerial;
+//! use saberrs::sabertooth2x32::settings::{Setting, SettingValue};
+//! use saberrs::testing::MockPort;
+//!
+//! let mut saber = PacketSerial::from(MockPort::new());
+//! saber.set_setting(Setting::Ramp(1), SettingValue::Ratio(0.25)).unwrap();
+//! saber
+//!     .set_setting(Setting::SerialTimeout, SettingValue::Timeout(Some(Duration::from_millis(500))))
+//!     .unwrap();
+//!
+//! assert_eq!(Some(SettingValue::Ratio(0.25)), saber.get_setting(Setting::Ramp(1)).unwrap());
+//! assert_eq!(None, saber.get_setting(Setting::Ramp(2)).unwrap());
+//! assert!(saber.get_setting(Setting::Deadband).is_err());
+//! ```
+//!
+//! [Setting::Ramp]: enum.Setting.html#variant.Ramp
+//! [Setting::SerialTimeout]: enum.Setting.html#variant.SerialTimeout
+//! [Setting::Deadband]: enum.Setting.html#variant.Deadband
+//! [Setting::CurrentLimit]: enum.Setting.html#variant.CurrentLimit
+//! [Setting::OperatingMode]: enum.Setting.html#variant.OperatingMode
+//! [PacketSerial::set_setting()]: ../struct.PacketSerial.html#method.set_setting
+//! [PacketSerial::get_setting()]: ../struct.PacketSerial.html#method.get_setting
+
+use std::time::Duration;
+
+use crate::{Error, Result};
+
+/// Configuration value of the device.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Setting {
+    /// Ramping of the channel.
+    Ramp(usize),
+
+    /// Serial timeout, shared by both channels.
+    SerialTimeout,
+
+    /// Deadband of the analog and R/C inputs.
+    Deadband,
+
+    /// Current limit of the channel.
+    CurrentLimit(usize),
+
+    /// Operating mode, like the mixed mode.
+    OperatingMode,
+}
+
+impl Setting {
+    /// Whether the setting can be written over serial.
+    pub fn is_writable(self) -> bool {
+        matches!(self, Setting::Ramp(_) | Setting::SerialTimeout)
+    }
+
+    /// Return `Error::Unsupported` if the setting cannot be written over
+    /// serial, or `Error::InvalidInput` for a wrong channel.
+    pub(crate) fn check(self) -> Result<()> {
+        if !self.is_writable() {
+            return Err(Error::Unsupported(format!(
+                "{:?} can only be edited with DEScribe",
+                self
+            )));
+        }
+        match self {
+            Setting::Ramp(channel) if channel != 1 && channel != 2 => Err(Error::InvalidInput(
+                format!("channel should be 1 or 2 (was {})", channel),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Value of a [Setting](enum.Setting.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SettingValue {
+    /// Ratio from 0.0 to 1.0, for the ramping.
+    Ratio(f32),
+
+    /// Timeout, `None` when disabled, for the serial timeout.
+    Timeout(Option<Duration>),
+}
+
+/// Settings written by a handle, in the order they were first written.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    values: Vec<(Setting, SettingValue)>,
+}
+
+impl Settings {
+    /// Value of *setting*, if it was written.
+    pub fn get(&self, setting: Setting) -> Option<SettingValue> {
+        self.values
+            .iter()
+            .find(|(s, _)| *s == setting)
+            .map(|&(_, value)| value)
+    }
+
+    /// Iterate over the settings written and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (Setting, SettingValue)> + '_ {
+        self.values.iter().copied()
+    }
+
+    pub(crate) fn record(&mut self, setting: Setting, value: SettingValue) {
+        match self.values.iter_mut().find(|(s, _)| *s == setting) {
+            Some(entry) => entry.1 = value,
+            None => self.values.push((setting, value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        assert!(Setting::Ramp(2).check().is_ok());
+        assert!(matches!(
+            Setting::Ramp(3).check(),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            Setting::CurrentLimit(1).check(),
+            Err(Error::Unsupported(_))
+        ));
+
+        let mut settings = Settings::default();
+        settings.record(Setting::Ramp(1), SettingValue::Ratio(0.5));
+        settings.record(Setting::SerialTimeout, SettingValue::Timeout(None));
+        settings.record(Setting::Ramp(1), SettingValue::Ratio(0.1));
+        assert_eq!(
+            Some(SettingValue::Ratio(0.1)),
+            settings.get(Setting::Ramp(1))
+        );
+        assert_eq!(2, settings.iter().count());
+        assert_eq!(None, settings.get(Setting::Ramp(2)));
+    }
+}
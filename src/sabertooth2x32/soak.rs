@@ -0,0 +1,236 @@
+//! Soak test for measuring the reliability of a link before installing it.
+//!
+//! The test commands a benign speed pattern on both channels and polls the
+//! telemetry for a given duration, retrying the failed operations and
+//! tallying the errors by kind. The report gives a one-call reliability
+//! measurement of a cable, adapter and device combination.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use saberrs::Result;
+//! use saberrs::sabertooth2x32::PacketSerial;
+//! use saberrs::sabertooth2x32::soak::{soak_test, SoakProfile};
+//!
+//! # fn example() -> Result<()> {
+//! let mut saber = PacketSerial::new("/dev/ttyS0")?;
+//!
+//! let report = soak_test(&mut saber, Duration::from_secs(3600), &SoakProfile::new());
+//! println!("{:?}", report);
+//! assert!(report.passed());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Operation, Sabertooth2x32};
+use crate::Error;
+
+/// Settings of a [soak_test()](fn.soak_test.html).
+#[derive(Clone, Debug)]
+pub struct SoakProfile {
+    speed: f32,
+    period: Duration,
+    retries: u32,
+}
+
+impl SoakProfile {
+    /// Create settings commanding a zero speed, which is safe with the
+    /// motors attached, every 50 ms with one retry per operation.
+    pub fn new() -> SoakProfile {
+        SoakProfile {
+            speed: 0.0,
+            period: Duration::from_millis(50),
+            retries: 1,
+        }
+    }
+
+    /// Alternate the commanded speed between *speed* and -*speed* at each
+    /// cycle, for exercising the motors as well as the link.
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed.abs().min(1.0);
+        self
+    }
+
+    /// Set the time between the starts of two cycles.
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Set the number of retries of a failed operation.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Operations of the cycle *index*: the speeds of both channels, then
+    /// the telemetry of both channels.
+    fn operations(&self, index: u64) -> Vec<Operation> {
+        let speed = if index % 2 == 1 {
+            -self.speed
+        } else {
+            self.speed
+        };
+        let mut operations = vec![Operation::SetSpeed(1, speed), Operation::SetSpeed(2, speed)];
+        for channel in 1..=2 {
+            operations.push(Operation::GetVoltage(channel));
+            operations.push(Operation::GetCurrent(channel));
+            operations.push(Operation::GetTemperature(channel));
+        }
+        operations
+    }
+}
+
+impl Default for SoakProfile {
+    fn default() -> Self {
+        SoakProfile::new()
+    }
+}
+
+/// Result of a [soak_test()](fn.soak_test.html).
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SoakReport {
+    /// Time spent testing.
+    pub elapsed: Duration,
+
+    /// Number of completed cycles.
+    pub cycles: u64,
+
+    /// Number of operations attempted, not counting the retries.
+    pub operations: u64,
+
+    /// Number of replies rejected as invalid or unexpected, like checksum
+    /// or CRC errors.
+    pub integrity_errors: u64,
+
+    /// Number of operations which timed out.
+    pub timeouts: u64,
+
+    /// Number of the other errors.
+    pub other_errors: u64,
+
+    /// Number of retries.
+    pub retries: u64,
+
+    /// Number of operations still failing after their retries.
+    pub failures: u64,
+}
+
+impl SoakReport {
+    /// Total number of errors, including the ones fixed by a retry.
+    pub fn errors(&self) -> u64 {
+        self.integrity_errors + self.timeouts + self.other_errors
+    }
+
+    /// Ratio of the attempts which failed, from 0.0 to 1.0.
+    pub fn error_rate(&self) -> f64 {
+        match self.operations + self.retries {
+            0 => 0.0,
+            attempts => self.errors() as f64 / attempts as f64,
+        }
+    }
+
+    /// True if every operation eventually succeeded.
+    pub fn passed(&self) -> bool {
+        self.failures == 0
+    }
+
+    fn record_error(&mut self, error: &Error) {
+        match error {
+            Error::Response(_) | Error::StaleReply => self.integrity_errors += 1,
+            Error::TxTimeout { .. } => self.timeouts += 1,
+            Error::Io(e) if e.kind() == io::ErrorKind::TimedOut => self.timeouts += 1,
+            _ => self.other_errors += 1,
+        }
+    }
+}
+
+/// Run cycles of the operations of *profile* on *saber* for *duration*, and
+/// report the errors. At least one cycle is run, and both channels are
+/// stopped at the end.
+pub fn soak_test<S>(saber: &mut S, duration: Duration, profile: &SoakProfile) -> SoakReport
+where
+    S: Sabertooth2x32 + ?Sized,
+{
+    let start = Instant::now();
+    let mut report = SoakReport::default();
+    loop {
+        let cycle_start = Instant::now();
+        for operation in profile.operations(report.cycles) {
+            report.operations += 1;
+            let mut attempt = 0;
+            while let Err(e) = operation.apply(saber) {
+                report.record_error(&e);
+                if attempt == profile.retries {
+                    report.failures += 1;
+                    break;
+                }
+                attempt += 1;
+                report.retries += 1;
+            }
+        }
+        report.cycles += 1;
+
+        if start.elapsed() >= duration {
+            break;
+        }
+        if let Some(wait) = profile.period.checked_sub(cycle_start.elapsed()) {
+            thread::sleep(wait.min(duration.saturating_sub(start.elapsed())));
+        }
+    }
+    let _ = saber.stop_motors();
+    report.elapsed = start.elapsed();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_soak_test() {
+        // The current of channel 2 times out once in two reads, and its
+        // temperature is never valid
+        let mut reads = 0;
+        let mut port = MockPort::with_responder(move |frame| match frame {
+            b"M1: getb\r\n" => b"M1:B240\r\n".to_vec(),
+            b"M2: getb\r\n" => b"M2:B240\r\n".to_vec(),
+            b"M1: getc\r\n" => b"M1:C0\r\n".to_vec(),
+            b"M2: getc\r\n" => {
+                reads += 1;
+                match reads % 2 {
+                    1 => Vec::new(),
+                    _ => b"M2:C0\r\n".to_vec(),
+                }
+            }
+            b"M1: gett\r\n" => b"M1:T30\r\n".to_vec(),
+            b"M2: gett\r\n" => b"M2:X30\r\n".to_vec(),
+            _ => Vec::new(),
+        });
+        let mut saber = PlainText::from(&port);
+        let profile = SoakProfile::new().with_speed(0.5);
+        let report = soak_test(&mut saber, Duration::from_secs(0), &profile);
+        assert_eq!(1, report.cycles);
+        assert_eq!(8, report.operations);
+        assert_eq!(1, report.timeouts);
+        assert_eq!(2, report.integrity_errors);
+        assert_eq!(2, report.retries);
+        assert_eq!(1, report.failures);
+        assert_eq!(3.0 / 10.0, report.error_rate());
+        assert!(!report.passed());
+        assert!(port.take_written().starts_with(b"M1: 1023\r\nM2: 1023\r\n"));
+
+        let profile = profile.with_retries(0);
+        let report = soak_test(&mut saber, Duration::from_secs(0), &profile);
+        assert_eq!(1, report.timeouts);
+        assert_eq!(0, report.retries);
+        assert_eq!(2, report.failures);
+    }
+}
@@ -0,0 +1,186 @@
+//! Power sweep for characterizing a motor on the bench.
+//!
+//! The sweep drives one channel from full backward to full forward power by
+//! steps, and measures the battery voltage, the motor current and the
+//! temperature at each step. The resulting table can be written as CSV.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use saberrs::Result;
+//! use saberrs::sabertooth2x32::PacketSerial;
+//! use saberrs::sabertooth2x32::sweep::PowerSweep;
+//!
+//! # fn example() -> Result<()> {
+//! let mut saber = PacketSerial::new("/dev/ttyS0")?;
+//!
+//! let table = PowerSweep::new()
+//!     .with_step(0.05)
+//!     .with_settle_time(Duration::from_secs(1))
+//!     .run(&mut saber, 1)?;
+//! table.write_csv(std::io::stdout())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+use super::Sabertooth2x32;
+use crate::Result;
+
+/// Measurements at one step of the sweep.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SweepRow {
+    /// Commanded power ratio.
+    pub power: f32,
+
+    /// Battery voltage, in volts.
+    pub voltage: f32,
+
+    /// Motor current, in amperes.
+    pub current: f32,
+
+    /// Temperature, in degrees Celsius.
+    pub temperature: f32,
+}
+
+/// Result of a power sweep.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SweepTable {
+    channel: usize,
+    rows: Vec<SweepRow>,
+}
+
+impl SweepTable {
+    /// Motor channel of the sweep.
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Measurements, by increasing power.
+    pub fn rows(&self) -> &[SweepRow] {
+        &self.rows
+    }
+
+    /// Write the table as CSV, with a header line.
+    pub fn write_csv<W: Write>(&self, mut w: W) -> Result<()> {
+        writeln!(w, "power,voltage,current,temperature")?;
+        for row in &self.rows {
+            writeln!(
+                w,
+                "{:.3},{:.1},{:.1},{:.0}",
+                row.power, row.voltage, row.current, row.temperature
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Power sweep settings. See the [module documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct PowerSweep {
+    step: f32,
+    settle_time: Duration,
+}
+
+impl PowerSweep {
+    /// Create sweep settings with 10% steps and 500 ms settle time.
+    pub fn new() -> PowerSweep {
+        PowerSweep {
+            step: 0.1,
+            settle_time: Duration::from_millis(500),
+        }
+    }
+
+    /// Set the power increment between two steps, as a ratio between 0.0
+    /// (excluded) and 1.0.
+    pub fn with_step(mut self, step: f32) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Set the time to wait after applying power before measuring.
+    pub fn with_settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = settle_time;
+        self
+    }
+
+    /// Power ratios of the steps, from -1.0 to 1.0.
+    pub fn powers(&self) -> Vec<f32> {
+        let count = (2.0 / self.step.max(0.001)).round() as usize;
+        (0..=count)
+            .map(|i| (-1.0 + 2.0 * i as f32 / count as f32).clamp(-1.0, 1.0))
+            .collect()
+    }
+
+    /// Run the sweep on *channel* of *saber*. The motor is stopped at the
+    /// end of the run, including when a communication error interrupts it.
+    pub fn run<S>(&self, saber: &mut S, channel: usize) -> Result<SweepTable>
+    where
+        S: Sabertooth2x32 + ?Sized,
+    {
+        let res = self.run_steps(saber, channel);
+        let stop = saber.set_power(channel, 0.0);
+        let table = res?;
+        stop?;
+        Ok(table)
+    }
+
+    fn run_steps<S>(&self, saber: &mut S, channel: usize) -> Result<SweepTable>
+    where
+        S: Sabertooth2x32 + ?Sized,
+    {
+        let mut rows = Vec::new();
+        for power in self.powers() {
+            saber.set_power(channel, power)?;
+            thread::sleep(self.settle_time);
+            rows.push(SweepRow {
+                power,
+                voltage: saber.get_voltage(channel)?,
+                current: saber.get_current(channel)?,
+                temperature: saber.get_temperature(channel)?,
+            });
+        }
+        Ok(SweepTable { channel, rows })
+    }
+}
+
+impl Default for PowerSweep {
+    fn default() -> Self {
+        PowerSweep::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_powers() {
+        let sweep = PowerSweep::new().with_step(0.5);
+        assert_eq!(vec![-1.0, -0.5, 0.0, 0.5, 1.0], sweep.powers());
+        assert_eq!(21, PowerSweep::new().powers().len());
+    }
+
+    #[test]
+    fn test_csv() {
+        let table = SweepTable {
+            channel: 1,
+            rows: vec![SweepRow {
+                power: -1.0,
+                voltage: 23.84,
+                current: -12.0,
+                temperature: 31.0,
+            }],
+        };
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            "power,voltage,current,temperature\n-1.000,23.8,-12.0,31\n",
+            String::from_utf8(csv).unwrap()
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! The Sabertooth 2x5 has no packetized protocol, only the "Simplified
+//! Serial" mode where every command is a single byte:
+//!
+//! | Byte       | Command                                               |
+//! |------------|-------------------------------------------------------|
+//! | 0          | stop both motors                                      |
+//! | 1 to 127   | motor 1: 1 full reverse, 64 stop, 127 full forward    |
+//! | 129 to 255 | motor 2: 129 full reverse, 192 stop, 255 full forward |
+//!
+//! The speeds map symmetrically to 63 steps on either side of the stop
+//! byte, so byte 128, which the device also reads as full reverse of motor
+//! 2, is never sent.
+//!
+//! The device never replies, so there are no getters. The same mode exists on
+//! the Sabertooth 2x12, 2x25 and 2x60 when their DIP switches select it, and
+//! [SimplifiedSerial] works with them too. The mixed mode of the packetized
+//! protocols is emulated in software by `set_drive()` and `set_turn()`, and
+//! `supports()` tells which operations of the Sabertooth 2x32 are available.
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::sabertooth2x5::SimplifiedSerial;
+//!
+//! # fn example() -> saberrs::Result<()> {
+//! let mut saber = SimplifiedSerial::new("/dev/ttyS0")?;
+//! saber.set_speed(1, 0.5)?;
+//! saber.set_speed(2, -0.5)?;
+//! saber.stop_motors()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [SimplifiedSerial]: struct.SimplifiedSerial.html
+
+use std::convert::From;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::bandwidth;
+use crate::error::Result;
+use crate::port::SabertoothSerial;
+use crate::sabertooth2x32::{Operation, Support};
+use crate::stats::Stats;
+use crate::units::Ratio;
+use crate::utils;
+
+#[cfg(feature = "serialport")]
+use crate::port::sabertoothport::SabertoothPort;
+
+pub use crate::opcodes::sabertooth2x5::STOP_ALL;
+use crate::opcodes::sabertooth2x5::{MOTOR1_STOP, MOTOR2_STOP, RANGE};
+
+/// Encode the speed *ratio* of motor *channel* (1 or 2) as a command byte.
+/// *ratio* is between -1.0 for full reverse and 1.0 for full forward.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x5::encode_speed;
+///
+/// assert_eq!(64, encode_speed(1, 0.0).unwrap());
+/// assert_eq!(255, encode_speed(2, 1.0).unwrap());
+/// ```
+pub fn encode_speed<R: Into<Ratio>>(channel: usize, ratio: R) -> Result<u8> {
+    let stop = match_channel_to!(channel, MOTOR1_STOP, MOTOR2_STOP);
+    let offset = utils::ratio_to_range(ratio.into().get(), RANGE)?;
+    Ok((i32::from(stop) + offset) as u8)
+}
+
+/// Interface using the "Simplified Serial" protocol of the Sabertooth 2x5.
+/// See the [module documentation](index.html).
+pub struct SimplifiedSerial<T: SabertoothSerial> {
+    dev: T,
+    stats: Stats,
+    name: Option<String>,
+    baud_rate: Option<u32>,
+    write_deadline: Option<Duration>,
+    drive: Option<f32>,
+    turn: Option<f32>,
+}
+
+#[cfg(feature = "serialport")]
+impl SimplifiedSerial<SabertoothPort> {
+    /// Open a serial port and return a new `SimplifiedSerial` handle. The
+    /// port is opened at 9600 baud, the default setting of the DIP switches.
+    pub fn new(port: &str) -> Result<SimplifiedSerial<SabertoothPort>> {
+        Ok(SimplifiedSerial::from(SabertoothPort::new(port)?))
+    }
+
+    /// Open a serial port at *baud_rate*, matching the DIP switches, and
+    /// return a new `SimplifiedSerial` handle. The device never replies, so
+    /// *timeout* only bounds the writes.
+    pub fn open_with(
+        port: &str,
+        baud_rate: u32,
+        timeout: Duration,
+    ) -> Result<SimplifiedSerial<SabertoothPort>> {
+        Ok(SimplifiedSerial::from(SabertoothPort::open_with(
+            port, baud_rate, timeout,
+        )?))
+    }
+}
+
+impl<T: SabertoothSerial> SimplifiedSerial<T> {
+    /// Set a human-readable name for the device, used in the logs.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Re-assert *baud_rate* on the port before each transaction, for
+    /// handles sharing a port, for example through a multiplexer, with
+    /// devices at other rates. The port is only reconfigured when its rate
+    /// differs.
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// Give up writing a command after *deadline* with `Error::TxTimeout`,
+    /// instead of waiting for as long as the port lets `write_all()` block.
+    pub fn with_write_deadline(mut self, deadline: Duration) -> Self {
+        self.write_deadline = Some(deadline);
+        self
+    }
+
+    /// Return the name of the device, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Traffic statistics since the creation of the handle or the last call
+    /// to [reset_stats()](#method.reset_stats).
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Reset the traffic statistics.
+    pub fn reset_stats(&mut self) {
+        self.stats.reset()
+    }
+
+    /// Set the speed of motor *channel* (1 or 2). *ratio* is between -1.0
+    /// for full reverse and 1.0 for full forward.
+    pub fn set_speed<R: Into<Ratio>>(&mut self, channel: usize, ratio: R) -> Result<()> {
+        let byte = encode_speed(channel, ratio)?;
+        self.write_frame(&[byte])
+    }
+
+    /// Set the speeds of both motors, in a single write.
+    pub fn set_speeds<R1, R2>(&mut self, ratio1: R1, ratio2: R2) -> Result<()>
+    where
+        R1: Into<Ratio>,
+        R2: Into<Ratio>,
+    {
+        let bytes = [encode_speed(1, ratio1)?, encode_speed(2, ratio2)?];
+        self.write_frame(&bytes)
+    }
+
+    /// Set the drive of the mixed mode, emulated in software since the
+    /// device has no mixing: *ratio* is between -1.0 for full backward and
+    /// 1.0 for full forward. As on the Sabertooth 2x32, both set_drive() and
+    /// [set_turn()](#method.set_turn) must have been called at least once
+    /// for having an effect.
+    pub fn set_drive<R: Into<Ratio>>(&mut self, ratio: R) -> Result<()> {
+        self.drive = Some(Ratio::new(ratio.into().get())?.get());
+        self.write_mixing()
+    }
+
+    /// Set the turn of the mixed mode, emulated in software: *ratio* is
+    /// between -1.0 for full left and 1.0 for full right. See
+    /// [set_drive()](#method.set_drive).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x5::SimplifiedSerial;
+    /// use saberrs::testing::{FrameTap, MockPort};
+    ///
+    /// let mut port = MockPort::new();
+    /// let mut saber = SimplifiedSerial::from(&port);
+    /// saber.set_drive(0.5).unwrap();
+    /// assert!(port.take_written().is_empty());
+    /// saber.set_turn(0.5).unwrap();
+    /// assert_eq!(port.take_written(), [127, 192]);
+    /// ```
+    pub fn set_turn<R: Into<Ratio>>(&mut self, ratio: R) -> Result<()> {
+        self.turn = Some(Ratio::new(ratio.into().get())?.get());
+        self.write_mixing()
+    }
+
+    /// How the handle performs *operation*: the speeds natively, the drive
+    /// and the turn in software, and nothing else since the device has no
+    /// getters and no other settings.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::sabertooth2x32::{Operation, Support};
+    /// use saberrs::sabertooth2x5::SimplifiedSerial;
+    /// use saberrs::testing::MockPort;
+    ///
+    /// let saber = SimplifiedSerial::from(MockPort::new());
+    /// assert_eq!(Support::Emulated, saber.supports(Operation::SetDrive(0.5)));
+    /// assert_eq!(Support::Unsupported, saber.supports(Operation::GetVoltage(1)));
+    /// ```
+    pub fn supports(&self, operation: Operation) -> Support {
+        match operation {
+            Operation::SetSpeed(..) => Support::Native,
+            Operation::SetDrive(_) | Operation::SetTurn(_) => Support::Emulated,
+            _ => Support::Unsupported,
+        }
+    }
+
+    /// Estimate the time taken on the wire by a command of one byte, from
+    /// the baud rate. [set_speeds()](#method.set_speeds) takes twice as
+    /// long.
+    pub fn estimate_transaction_time(&self) -> Result<Duration> {
+        let baud_rate = match self.baud_rate {
+            Some(baud_rate) => baud_rate,
+            None => self.dev.baud_rate()?,
+        };
+        Ok(bandwidth::transfer_time(baud_rate, 1))
+    }
+
+    /// Stop both motors.
+    pub fn stop_motors(&mut self) -> Result<()> {
+        self.write_frame(&[STOP_ALL])
+    }
+
+    /// Return the serial port.
+    pub fn into_inner(self) -> T {
+        self.dev
+    }
+
+    /// Write the speeds mixed from the drive and the turn, once both are
+    /// set. The speeds are scaled down together when one exceeds 1.0.
+    fn write_mixing(&mut self) -> Result<()> {
+        let (drive, turn) = match (self.drive, self.turn) {
+            (Some(drive), Some(turn)) => (drive, turn),
+            _ => return Ok(()),
+        };
+        let (left, right) = (drive + turn, drive - turn);
+        let scale = left.abs().max(right.abs()).max(1.0);
+        self.set_speeds(left / scale, right / scale)
+    }
+
+    /// Set the baud rate given with [with_baud_rate()](#method.with_baud_rate)
+    /// on the port, if it differs.
+    fn assert_baud_rate(&mut self) -> Result<()> {
+        match self.baud_rate {
+            Some(baud_rate) if self.dev.baud_rate()? != baud_rate => {
+                self.dev.set_baud_rate(baud_rate)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
+        self.assert_baud_rate()?;
+        debug!(
+            "{}{}tx = {:?}",
+            self.name.as_deref().unwrap_or(""),
+            if self.name.is_some() { ": " } else { "" },
+            txdata
+        );
+        match self.write_deadline {
+            Some(deadline) => utils::write_bounded(&mut self.dev, txdata, deadline)?,
+            None => self.dev.write_all(txdata)?,
+        }
+        for _ in txdata {
+            self.stats.record_tx(1);
+        }
+        Ok(())
+    }
+}
+
+impl<T: SabertoothSerial> From<T> for SimplifiedSerial<T> {
+    fn from(dev: T) -> Self {
+        SimplifiedSerial {
+            dev,
+            stats: Stats::new(),
+            name: None,
+            baud_rate: None,
+            write_deadline: None,
+            drive: None,
+            turn: None,
+        }
+    }
+}
+
+impl<T> From<&T> for SimplifiedSerial<T>
+where
+    T: SabertoothSerial + Clone,
+{
+    fn from(dev: &T) -> Self {
+        SimplifiedSerial::from(dev.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FrameTap, MockPort};
+    use crate::units::Percent;
+
+    #[test]
+    fn test_encode_speed() {
+        assert_eq!(1, encode_speed(1, -1.0).unwrap());
+        assert_eq!(64, encode_speed(1, 0.0).unwrap());
+        assert_eq!(127, encode_speed(1, 1.0).unwrap());
+        assert_eq!(129, encode_speed(2, -1.0).unwrap());
+        assert_eq!(192, encode_speed(2, 0.0).unwrap());
+        assert_eq!(255, encode_speed(2, 1.0).unwrap());
+        assert_eq!(95, encode_speed(1, 0.5).unwrap());
+        assert_eq!(161, encode_speed(2, -0.5).unwrap());
+        assert!(encode_speed(3, 0.0).is_err());
+        assert!(encode_speed(1, 1.1).is_err());
+        assert_eq!(64, encode_speed(1, Percent::new(0.0).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut port = MockPort::new();
+        let mut saber = SimplifiedSerial::from(&port);
+        saber.set_speed(2, 1.0).unwrap();
+        saber.set_speeds(0.0, 0.0).unwrap();
+        saber.stop_motors().unwrap();
+        assert_eq!(port.take_written(), [255, 64, 192, 0]);
+        assert_eq!(4, saber.stats().frames_sent());
+    }
+
+    #[test]
+    fn test_mixing() {
+        let mut port = MockPort::new();
+        let mut saber = SimplifiedSerial::from(&port);
+        saber.set_turn(-0.5).unwrap();
+        assert!(port.take_written().is_empty());
+        saber.set_drive(1.0).unwrap();
+        assert_eq!(port.take_written(), [85, 255]);
+        saber.set_drive(0.0).unwrap();
+        assert_eq!(port.take_written(), [33, 223]);
+        assert!(saber.set_turn(1.5).is_err());
+    }
+
+    #[test]
+    fn test_baud_rate() {
+        let port = MockPort::new();
+        let mut legacy = SimplifiedSerial::from(&port).with_baud_rate(9600);
+        let mut fast = SimplifiedSerial::from(&port).with_baud_rate(115_200);
+        let mut any = SimplifiedSerial::from(&port);
+
+        legacy.stop_motors().unwrap();
+        assert_eq!(9600, port.baud_rate().unwrap());
+        fast.stop_motors().unwrap();
+        assert_eq!(115_200, port.baud_rate().unwrap());
+        any.stop_motors().unwrap();
+        assert_eq!(115_200, port.baud_rate().unwrap());
+        legacy.stop_motors().unwrap();
+        assert_eq!(9600, port.baud_rate().unwrap());
+    }
+}
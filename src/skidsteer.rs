@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::stop::{self, StopReport};
+use crate::Result;
+
+/// Wheel of a [SkidSteer4](struct.SkidSteer4.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Wheel {
+    /// Channel 1 of the left controller.
+    FrontLeft,
+    /// Channel 2 of the left controller.
+    RearLeft,
+    /// Channel 1 of the right controller.
+    FrontRight,
+    /// Channel 2 of the right controller.
+    RearRight,
+}
+
+impl Wheel {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Four-wheel skid-steer drive using two controllers, one per side.
+///
+/// The mixing of the linear and angular commands is done on the host, so the
+/// controllers are driven with `set_speed()` on each channel. Each wheel has
+/// a trim factor, for compensating motors of different strength or a motor
+/// wired backwards. When a wheel output would exceed full speed, all the
+/// outputs are scaled down together so that the path curvature is kept.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{SkidSteer4, Wheel};
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let left = PacketSerial::new("/dev/ttyS0")?.with_address(128);
+/// let right = PacketSerial::new("/dev/ttyS0")?.with_address(129);
+/// let mut drive = SkidSteer4::new(left, right).with_trim(Wheel::RearRight, -0.95);
+///
+/// // Forward at half speed, turning left
+/// drive.drive(0.5, 0.2)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SkidSteer4<L: Sabertooth2x32, R: Sabertooth2x32> {
+    left: L,
+    right: R,
+    trim: [f32; 4],
+}
+
+impl<L: Sabertooth2x32, R: Sabertooth2x32> SkidSteer4<L, R> {
+    /// Create a new drive from the *left* and *right* controllers, with the
+    /// front motors on channel 1 and the rear motors on channel 2.
+    pub fn new(left: L, right: R) -> SkidSteer4<L, R> {
+        SkidSteer4 {
+            left,
+            right,
+            trim: [1.0; 4],
+        }
+    }
+
+    /// Set the trim factor of *wheel*, multiplying its output. A negative
+    /// factor reverses the wheel.
+    pub fn with_trim(mut self, wheel: Wheel, trim: f32) -> Self {
+        self.trim[wheel.index()] = trim;
+        self
+    }
+
+    /// Left controller.
+    pub fn left(&mut self) -> &mut L {
+        &mut self.left
+    }
+
+    /// Right controller.
+    pub fn right(&mut self) -> &mut R {
+        &mut self.right
+    }
+
+    /// Return the controllers.
+    pub fn into_inner(self) -> (L, R) {
+        (self.left, self.right)
+    }
+
+    /// Compute the outputs of the wheels, in the order of [Wheel], for
+    /// *linear* (-1.0 for full backward, 1.0 for full forward) and *angular*
+    /// (-1.0 for full right turn, 1.0 for full left turn) commands.
+    ///
+    /// [Wheel]: enum.Wheel.html
+    pub fn outputs(&self, linear: f32, angular: f32) -> [f32; 4] {
+        let left = linear - angular;
+        let right = linear + angular;
+        let mut outputs = [
+            left * self.trim[Wheel::FrontLeft.index()],
+            left * self.trim[Wheel::RearLeft.index()],
+            right * self.trim[Wheel::FrontRight.index()],
+            right * self.trim[Wheel::RearRight.index()],
+        ];
+
+        let max = outputs.iter().fold(0.0f32, |m, o| m.max(o.abs()));
+        if max > 1.0 {
+            for o in outputs.iter_mut() {
+                *o /= max;
+            }
+        }
+        outputs
+    }
+
+    /// Drive with *linear* and *angular* commands. See
+    /// [outputs()](#method.outputs) for the values range.
+    pub fn drive(&mut self, linear: f32, angular: f32) -> Result<()> {
+        let outputs = self.outputs(linear, angular);
+        self.left.set_speed(1, outputs[Wheel::FrontLeft.index()])?;
+        self.left.set_speed(2, outputs[Wheel::RearLeft.index()])?;
+        self.right
+            .set_speed(1, outputs[Wheel::FrontRight.index()])?;
+        self.right.set_speed(2, outputs[Wheel::RearRight.index()])?;
+        Ok(())
+    }
+
+    /// Stop the four motors.
+    pub fn stop(&mut self) -> Result<()> {
+        self.left.stop_motors()?;
+        self.right.stop_motors()
+    }
+
+    /// Stop the four motors, and confirm that they stopped within
+    /// *deadline*, see [stop_all_confirmed()](fn.stop_all_confirmed.html).
+    /// The report lists the left controller, then the right one.
+    pub fn stop_all_confirmed(&mut self, deadline: Duration) -> StopReport {
+        stop::stop_all_confirmed(&mut [&mut self.left, &mut self.right], deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    fn skidsteer() -> (
+        SkidSteer4<PlainText<MockPort>, PlainText<MockPort>>,
+        MockPort,
+        MockPort,
+    ) {
+        let left = MockPort::new();
+        let right = MockPort::new();
+        let drive = SkidSteer4::new(PlainText::from(&left), PlainText::from(&right));
+        (drive, left, right)
+    }
+
+    #[test]
+    fn test_outputs() {
+        let (drive, _, _) = skidsteer();
+        assert_eq!([0.5, 0.5, 0.5, 0.5], drive.outputs(0.5, 0.0));
+        assert_eq!([0.25, 0.25, 0.75, 0.75], drive.outputs(0.5, 0.25));
+
+        // Saturation keeps the ratio between both sides
+        assert_eq!([0.0, 0.0, 1.0, 1.0], drive.outputs(1.0, 1.0));
+        let drive = drive
+            .with_trim(Wheel::FrontLeft, 4.0)
+            .with_trim(Wheel::RearLeft, -1.0);
+        assert_eq!([1.0, -0.25, 0.25, 0.25], drive.outputs(0.5, 0.0));
+    }
+
+    #[test]
+    fn test_drive() {
+        let (drive, mut left, mut right) = skidsteer();
+        let mut drive = drive.with_trim(Wheel::RearRight, -1.0);
+        drive.drive(1.0, 0.0).unwrap();
+        assert_eq!(left.take_written(), b"M1: 2047\r\nM2: 2047\r\n");
+        assert_eq!(right.take_written(), b"M1: 2047\r\nM2: -2047\r\n");
+    }
+}
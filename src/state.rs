@@ -0,0 +1,97 @@
+use std::time::Instant;
+
+use crate::sabertooth2x32::Operation;
+use crate::Result;
+
+/// What a handle last commanded on a channel, as returned by
+/// `channel_state()` on the handles.
+///
+/// The state only reflects the commands written by the handle, without any
+/// traffic nor check of the device: it tells what the driver was last told,
+/// not what it does. A command failing to be written is not recorded.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ChannelState {
+    /// `Some(true)` if the last of startup and shutdown sent on the channel
+    /// was a startup, `Some(false)` if it was a shutdown, `None` if neither
+    /// was sent.
+    pub started: Option<bool>,
+
+    /// Last motion command driving the channel: `SetSpeed` or `SetPower` on
+    /// the channel, or `SetDrive` or `SetTurn`, which drive both channels in
+    /// mixed mode.
+    pub setpoint: Option<Operation>,
+
+    /// Time at which the last command on the channel was written, including
+    /// the settings like the ramping.
+    pub last_command: Option<Instant>,
+}
+
+impl ChannelState {
+    /// Whether the channel was shut down and not started up again since.
+    pub fn is_shut_down(&self) -> bool {
+        self.started == Some(false)
+    }
+}
+
+/// Commanded state of the two channels of a handle.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ChannelStates([ChannelState; 2]);
+
+impl ChannelStates {
+    /// Record the command *operation*, written at *now*. The getters are
+    /// ignored.
+    pub(crate) fn record(&mut self, operation: Operation, now: Instant) {
+        let (channels, setpoint, started) = match operation {
+            Operation::Startup(ch) => (ch..=ch, None, Some(true)),
+            Operation::Shutdown(ch) => (ch..=ch, None, Some(false)),
+            Operation::SetSpeed(ch, _) | Operation::SetPower(ch, _) => {
+                (ch..=ch, Some(operation), None)
+            }
+            Operation::SetDrive(_) | Operation::SetTurn(_) => (1..=2, Some(operation), None),
+            Operation::SetRamp(ch, _) | Operation::SetAux(ch, _) => (ch..=ch, None, None),
+            _ => return,
+        };
+        for channel in channels {
+            if let Some(state) = self.0.get_mut(channel.wrapping_sub(1)) {
+                state.started = started.or(state.started);
+                state.setpoint = setpoint.or(state.setpoint);
+                state.last_command = Some(now);
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, channel: usize) -> Result<ChannelState> {
+        Ok(match_channel_to!(channel, self.0[0], self.0[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut states = ChannelStates::default();
+        let now = Instant::now();
+        assert_eq!(ChannelState::default(), states.get(1).unwrap());
+        assert!(states.get(3).is_err());
+
+        states.record(Operation::Startup(1), now);
+        states.record(Operation::SetSpeed(1, 0.5), now);
+        states.record(Operation::GetSpeed(2), now);
+        let state = states.get(1).unwrap();
+        assert_eq!(Some(true), state.started);
+        assert_eq!(Some(Operation::SetSpeed(1, 0.5)), state.setpoint);
+        assert_eq!(Some(now), state.last_command);
+        assert_eq!(ChannelState::default(), states.get(2).unwrap());
+
+        states.record(Operation::SetDrive(0.2), now);
+        states.record(Operation::Shutdown(1), now);
+        assert!(states.get(1).unwrap().is_shut_down());
+        assert_eq!(
+            Some(Operation::SetDrive(0.2)),
+            states.get(2).unwrap().setpoint
+        );
+        assert_eq!(None, states.get(2).unwrap().started);
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::telemetry::Quantity;
+
+/// Number of bits on the wire for one byte: start bit, 8 data bits, stop bit.
+pub(crate) const BITS_PER_BYTE: u64 = 10;
+
+/// Number of recent round trips kept for computing the percentiles.
+const ROUND_TRIP_WINDOW: usize = 256;
+
+/// Weight of the last round trip in the smoothed round trip, as in the
+/// smoothed RTT of TCP.
+const SMOOTHING: f64 = 0.125;
+
+/// Number of quantities, ie. of get commands.
+const QUANTITIES: usize = 5;
+
+/// Traffic statistics collected by a handle.
+///
+/// The counters start when the handle is created, or when they are reset.
+/// They can be used for checking that a command and polling schedule fits in
+/// the bandwidth of the serial link.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::Result;
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// saber.set_speed(1, 0.5)?;
+/// let _ = saber.get_voltage(1)?;
+///
+/// let stats = saber.stats();
+/// println!("{:.1} frames/s", stats.frames_per_sec());
+/// println!("{:.1}% of TX bandwidth", stats.tx_utilization(9600) * 100.0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Stats {
+    since: Instant,
+    last_tx: Option<Instant>,
+    frames_sent: u64,
+    frames_received: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    bytes_discarded: u64,
+    line_conditions: u64,
+    resyncs: u64,
+    crc_errors: u64,
+    latency: Option<Latency>,
+    deadline_misses: u64,
+    round_trips: RoundTrips,
+    round_trips_of: [RoundTrips; QUANTITIES],
+}
+
+impl Stats {
+    /// Create new statistics with all counters at zero.
+    pub fn new() -> Stats {
+        Stats {
+            since: Instant::now(),
+            last_tx: None,
+            frames_sent: 0,
+            frames_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            bytes_discarded: 0,
+            line_conditions: 0,
+            resyncs: 0,
+            crc_errors: 0,
+            latency: None,
+            deadline_misses: 0,
+            round_trips: RoundTrips::default(),
+            round_trips_of: Default::default(),
+        }
+    }
+
+    /// Set all the counters back to zero and restart the measurement period.
+    /// The time of the last frame sent is kept.
+    pub fn reset(&mut self) {
+        let last_tx = self.last_tx;
+        *self = Stats::new();
+        self.last_tx = last_tx;
+    }
+
+    pub(crate) fn record_tx(&mut self, len: usize) {
+        self.last_tx = Some(Instant::now());
+        self.frames_sent += 1;
+        self.bytes_sent += len as u64;
+    }
+
+    pub(crate) fn record_rx(&mut self, len: usize) {
+        self.frames_received += 1;
+        self.bytes_received += len as u64;
+    }
+
+    pub(crate) fn record_discarded(&mut self, len: usize, line_conditions: usize) {
+        self.resyncs += 1;
+        self.bytes_received += len as u64;
+        self.bytes_discarded += len as u64;
+        self.line_conditions += line_conditions as u64;
+    }
+
+    pub(crate) fn record_crc_error(&mut self) {
+        self.crc_errors += 1;
+    }
+
+    pub(crate) fn record_latency(&mut self, elapsed: Duration, missed: bool) {
+        let latency = self.latency.get_or_insert(Latency {
+            count: 0,
+            total: Duration::from_secs(0),
+            min: elapsed,
+            max: elapsed,
+        });
+        latency.count += 1;
+        latency.total += elapsed;
+        latency.min = latency.min.min(elapsed);
+        latency.max = latency.max.max(elapsed);
+        if missed {
+            self.deadline_misses += 1;
+        }
+    }
+
+    pub(crate) fn record_round_trip(&mut self, quantity: Quantity, elapsed: Duration) {
+        self.round_trips.record(elapsed);
+        self.round_trips_of[quantity as usize].record(elapsed);
+    }
+
+    /// Duration of the measurement period.
+    pub fn elapsed(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// Time at which the last frame was written to the port, if any.
+    pub fn last_tx(&self) -> Option<Instant> {
+        self.last_tx
+    }
+
+    /// Time since the last frame was written to the port, or `None` if none
+    /// was.
+    pub fn idle_time(&self) -> Option<Duration> {
+        self.last_tx.map(|time| time.elapsed())
+    }
+
+    /// Return true if no frame was written to the port for at least
+    /// *period*. A periodic keep-alive is redundant while the application
+    /// commands the device more often than that, and can be skipped:
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+    ///
+    /// # fn example(speed: f32) -> saberrs::Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+    /// let period = Duration::from_millis(100);
+    ///
+    /// // In the keep-alive loop
+    /// if saber.stats().is_idle(period) {
+    ///     saber.set_speed(1, speed)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_idle(&self, period: Duration) -> bool {
+        match self.idle_time() {
+            Some(idle) => idle >= period,
+            None => true,
+        }
+    }
+
+    /// Number of frames written to the port.
+    pub fn frames_sent(&self) -> u64 {
+        self.frames_sent
+    }
+
+    /// Number of reply frames read from the port.
+    pub fn frames_received(&self) -> u64 {
+        self.frames_received
+    }
+
+    /// Number of bytes written to the port.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Number of bytes read from the port.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Number of bytes read from the port and discarded while looking for
+    /// the start of a reply. They are included in
+    /// [bytes_received()](#method.bytes_received).
+    pub fn bytes_discarded(&self) -> u64 {
+        self.bytes_discarded
+    }
+
+    /// Number of line conditions (breaks or framing errors) detected, see
+    /// [SabertoothSerial::is_line_condition()].
+    ///
+    /// [SabertoothSerial::is_line_condition()]: trait.SabertoothSerial.html#method.is_line_condition
+    pub fn line_conditions(&self) -> u64 {
+        self.line_conditions
+    }
+
+    /// Number of times the decoder discarded bytes to resynchronize on the
+    /// start of a reply.
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs
+    }
+
+    /// Number of reply frames rejected because of an invalid checksum or
+    /// CRC. They are included in [frames_received()](#method.frames_received).
+    pub fn crc_errors(&self) -> u64 {
+        self.crc_errors
+    }
+
+    /// Shortest latency of the deadline-checked commands, like
+    /// `set_speed_deadline()`, or `None` if there was none.
+    pub fn min_latency(&self) -> Option<Duration> {
+        self.latency.as_ref().map(|l| l.min)
+    }
+
+    /// Longest latency of the deadline-checked commands.
+    pub fn max_latency(&self) -> Option<Duration> {
+        self.latency.as_ref().map(|l| l.max)
+    }
+
+    /// Average latency of the deadline-checked commands.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        self.latency
+            .as_ref()
+            .map(|l| Duration::from_secs_f64(l.total.as_secs_f64() / l.count as f64))
+    }
+
+    /// Spread between the longest and the shortest latency of the
+    /// deadline-checked commands.
+    pub fn jitter(&self) -> Option<Duration> {
+        self.latency.as_ref().map(|l| l.max - l.min)
+    }
+
+    /// Number of deadline-checked commands which missed their deadline.
+    pub fn deadline_misses(&self) -> u64 {
+        self.deadline_misses
+    }
+
+    /// Round-trip latency of the get requests, from the write of the request
+    /// to the decoding of the reply, or `None` if there was none. Only the
+    /// requests which got a valid reply are counted.
+    pub fn round_trip_latency(&self) -> Option<LatencySummary> {
+        self.round_trips.summary()
+    }
+
+    /// Exponential moving average of the round-trip latency of the get
+    /// requests, giving 1/8 of the weight to the last one, or `None` if there
+    /// was none. It follows a change of the latency faster than the
+    /// percentiles of [round_trip_latency()](#method.round_trip_latency).
+    pub fn smoothed_round_trip(&self) -> Option<Duration> {
+        self.round_trips.smoothed
+    }
+
+    /// Round-trip latency of the get requests of *quantity*.
+    pub fn round_trip_latency_of(&self, quantity: Quantity) -> Option<LatencySummary> {
+        self.round_trips_of[quantity as usize].summary()
+    }
+
+    /// Average number of frames per second, in both directions.
+    pub fn frames_per_sec(&self) -> f64 {
+        rate(self.frames_sent + self.frames_received, self.elapsed())
+    }
+
+    /// Estimated ratio of the TX line bandwidth used during the measurement
+    /// period, between 0.0 (idle) and 1.0 (saturated), at *baud_rate*.
+    pub fn tx_utilization(&self, baud_rate: u32) -> f64 {
+        utilization(self.bytes_sent, baud_rate, self.elapsed())
+    }
+
+    /// Estimated ratio of the RX line bandwidth used during the measurement
+    /// period, between 0.0 (idle) and 1.0 (saturated), at *baud_rate*.
+    pub fn rx_utilization(&self, baud_rate: u32) -> f64 {
+        utilization(self.bytes_received, baud_rate, self.elapsed())
+    }
+}
+
+/// Latency of the deadline-checked commands.
+#[derive(Clone, Debug)]
+struct Latency {
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+/// Distribution of round-trip latencies, see
+/// [Stats::round_trip_latency()](struct.Stats.html#method.round_trip_latency).
+///
+/// The percentiles are computed over the last 256 round trips, the maximum
+/// over the whole measurement period.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LatencySummary {
+    /// Number of round trips.
+    pub count: u64,
+    /// Median latency.
+    pub p50: Duration,
+    /// 95th percentile.
+    pub p95: Duration,
+    /// Longest latency.
+    pub max: Duration,
+}
+
+#[derive(Clone, Debug, Default)]
+struct RoundTrips {
+    count: u64,
+    max: Duration,
+    smoothed: Option<Duration>,
+    recent: VecDeque<Duration>,
+}
+
+impl RoundTrips {
+    fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.max = self.max.max(elapsed);
+        self.smoothed = Some(match self.smoothed {
+            Some(smoothed) => Duration::from_secs_f64(
+                smoothed.as_secs_f64() * (1.0 - SMOOTHING) + elapsed.as_secs_f64() * SMOOTHING,
+            ),
+            None => elapsed,
+        });
+        if self.recent.len() == ROUND_TRIP_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    fn summary(&self) -> Option<LatencySummary> {
+        if self.count == 0 {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.recent.iter().cloned().collect();
+        sorted.sort();
+        Some(LatencySummary {
+            count: self.count,
+            p50: percentile(&sorted, 50),
+            p95: percentile(&sorted, 95),
+            max: self.max,
+        })
+    }
+}
+
+/// Nearest-rank *p*th percentile of the non-empty *sorted* samples.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (sorted.len() * p).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+/// Callback of the handles for slow round trips, set with their
+/// `with_latency_alert()`.
+pub(crate) struct LatencyAlert {
+    threshold: Duration,
+    callback: Box<dyn FnMut(Quantity, Duration) + Send>,
+}
+
+impl LatencyAlert {
+    pub fn new<F>(threshold: Duration, callback: F) -> LatencyAlert
+    where
+        F: FnMut(Quantity, Duration) + Send + 'static,
+    {
+        LatencyAlert {
+            threshold,
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Call the callback if *elapsed* exceeds the threshold.
+    pub fn check(&mut self, quantity: Quantity, elapsed: Duration) {
+        if elapsed > self.threshold {
+            (self.callback)(quantity, elapsed)
+        }
+    }
+}
+
+impl fmt::Debug for LatencyAlert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LatencyAlert")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+fn rate(count: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        count as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Ratio of the line used by *bytes* during *elapsed*. A short period gives
+/// a rate above the baud rate, for example for bytes buffered before it
+/// started, so the ratio is clamped to 1.0.
+fn utilization(bytes: u64, baud_rate: u32, elapsed: Duration) -> f64 {
+    if baud_rate == 0 {
+        return 0.0;
+    }
+    (rate(bytes * BITS_PER_BYTE, elapsed) / f64::from(baud_rate)).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utilization() {
+        // 96 bytes in one second at 9600 baud is 10% of the line
+        let ratio = utilization(96, 9600, Duration::from_secs(1));
+        assert!((ratio - 0.1).abs() < 1e-9);
+        assert_eq!(0.0, utilization(96, 9600, Duration::from_secs(0)));
+        assert_eq!(0.0, utilization(96, 0, Duration::from_secs(1)));
+        assert_eq!(1.0, utilization(96, 9600, Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn test_latency() {
+        let ms = Duration::from_millis;
+        let mut stats = Stats::new();
+        assert_eq!(None, stats.jitter());
+        stats.record_latency(ms(2), false);
+        stats.record_latency(ms(6), true);
+        stats.record_latency(ms(1), false);
+        assert_eq!(Some(ms(1)), stats.min_latency());
+        assert_eq!(Some(ms(6)), stats.max_latency());
+        assert_eq!(Some(ms(3)), stats.mean_latency());
+        assert_eq!(Some(ms(5)), stats.jitter());
+        assert_eq!(1, stats.deadline_misses());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ms = Duration::from_millis;
+        let mut stats = Stats::new();
+        assert_eq!(None, stats.round_trip_latency());
+        for i in 1..=100 {
+            stats.record_round_trip(Quantity::Voltage, ms(i));
+        }
+        stats.record_round_trip(Quantity::Current, ms(500));
+
+        let summary = stats.round_trip_latency_of(Quantity::Voltage).unwrap();
+        assert_eq!(100, summary.count);
+        assert_eq!(ms(50), summary.p50);
+        assert_eq!(ms(95), summary.p95);
+        assert_eq!(ms(100), summary.max);
+        assert_eq!(ms(500), stats.round_trip_latency().unwrap().max);
+        assert_eq!(None, stats.round_trip_latency_of(Quantity::Speed));
+
+        // The percentiles follow the recent round trips
+        for _ in 0..ROUND_TRIP_WINDOW {
+            stats.record_round_trip(Quantity::Voltage, ms(2));
+        }
+        let summary = stats.round_trip_latency_of(Quantity::Voltage).unwrap();
+        assert_eq!(
+            (ms(2), ms(2), ms(100)),
+            (summary.p50, summary.p95, summary.max)
+        );
+    }
+
+    #[test]
+    fn test_idle() {
+        let mut stats = Stats::new();
+        assert_eq!(None, stats.idle_time());
+        assert!(stats.is_idle(Duration::from_secs(3600)));
+        stats.record_tx(9);
+        assert!(!stats.is_idle(Duration::from_secs(3600)));
+        assert!(stats.is_idle(Duration::from_secs(0)));
+        stats.reset();
+        assert_eq!(0, stats.frames_sent());
+        assert!(stats.last_tx().is_some());
+    }
+
+    #[test]
+    fn test_rate() {
+        assert!((rate(50, Duration::from_millis(500)) - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_smoothed_round_trip() {
+        let ms = Duration::from_millis;
+        let mut stats = Stats::new();
+        assert_eq!(None, stats.smoothed_round_trip());
+        stats.record_round_trip(Quantity::Voltage, ms(8));
+        assert_eq!(Some(ms(8)), stats.smoothed_round_trip());
+        stats.record_round_trip(Quantity::Voltage, ms(16));
+        assert_eq!(Some(ms(9)), stats.smoothed_round_trip());
+        for _ in 0..100 {
+            stats.record_round_trip(Quantity::Voltage, ms(40));
+        }
+        let smoothed = stats.smoothed_round_trip().unwrap();
+        assert!(ms(39) < smoothed && smoothed <= ms(40));
+    }
+}
@@ -0,0 +1,236 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::Errors;
+
+/// Output power ratio under which a channel is considered stopped.
+const POWER_THRESHOLD: f32 = 0.01;
+
+/// Interval between two rounds of confirmation reads.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Outcome of a confirmed stop for one channel, see [stop_all_confirmed()].
+///
+/// [stop_all_confirmed()]: fn.stop_all_confirmed.html
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ChannelStop {
+    /// True if the stop command was written.
+    pub sent: bool,
+
+    /// Last output power read, if any read succeeded.
+    pub power: Option<f32>,
+
+    /// Time from the stop command to the confirmation, or `None` if the
+    /// stop was not confirmed before the deadline.
+    pub confirmed_after: Option<Duration>,
+}
+
+impl ChannelStop {
+    /// True if the channel was read back stopped before the deadline.
+    pub fn confirmed(&self) -> bool {
+        self.confirmed_after.is_some()
+    }
+}
+
+/// Report of [stop_all_confirmed()], with the outcome of both channels of
+/// each device, in the order of the devices.
+///
+/// [stop_all_confirmed()]: fn.stop_all_confirmed.html
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct StopReport {
+    devices: Vec<[ChannelStop; 2]>,
+}
+
+impl StopReport {
+    /// Outcomes of channels 1 and 2 of each device.
+    pub fn devices(&self) -> &[[ChannelStop; 2]] {
+        &self.devices
+    }
+
+    /// True if every channel of every device was confirmed stopped.
+    pub fn confirmed(&self) -> bool {
+        self.devices.iter().flatten().all(ChannelStop::confirmed)
+    }
+}
+
+/// Stop both channels of every device in *devices*, trying all of them even
+/// when some fail, and return the errors of the failed channels, by index in
+/// *devices*.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::stop_all;
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut front = PacketSerial::new("/dev/ttyS0")?;
+/// let mut rear = PacketSerial::new("/dev/ttyS1")?;
+///
+/// let mut devices = [&mut front, &mut rear];
+/// if let Err(errors) = stop_all(&mut devices) {
+///     for device in errors.devices() {
+///         let _ = devices[device].stop_motors();
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn stop_all<S>(devices: &mut [&mut S]) -> Result<(), Errors>
+where
+    S: Sabertooth2x32 + ?Sized,
+{
+    let mut errors = Errors::new();
+    for (i, saber) in devices.iter_mut().enumerate() {
+        for channel in 1..=2 {
+            if let Err(e) = saber.set_speed(channel, 0.0) {
+                log_warn!("stop of device {} channel {} failed: {}", i, channel, e);
+                errors.push(i, Some(channel), e);
+            }
+        }
+    }
+    errors.into_result()
+}
+
+/// Stop both channels of every device in *devices*, then read back their
+/// output power until all of them are confirmed stopped or *deadline* is
+/// elapsed, and report the outcome of each channel.
+///
+/// The stop commands are sent to all the devices first, before any read, and
+/// a failure on one device does not prevent stopping the others. A channel is
+/// confirmed when its output power is read near zero: unlike a successful
+/// write, this is evidence that the stop took effect.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::stop_all_confirmed;
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut front = PacketSerial::new("/dev/ttyS0")?.with_address(128);
+/// let mut rear = PacketSerial::new("/dev/ttyS1")?.with_address(129);
+///
+/// let report = stop_all_confirmed(&mut [&mut front, &mut rear], Duration::from_millis(500));
+/// if !report.confirmed() {
+///     eprintln!("stop not confirmed: {:?}", report.devices());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn stop_all_confirmed(
+    devices: &mut [&mut dyn Sabertooth2x32],
+    deadline: Duration,
+) -> StopReport {
+    let start = Instant::now();
+    let mut report = StopReport {
+        devices: vec![[ChannelStop::default(); 2]; devices.len()],
+    };
+
+    for (saber, stops) in devices.iter_mut().zip(report.devices.iter_mut()) {
+        for (channel, stop) in (1..=2).zip(stops.iter_mut()) {
+            stop.sent = saber.set_speed(channel, 0.0).is_ok();
+        }
+    }
+
+    loop {
+        let mut pending = false;
+        for (saber, stops) in devices.iter_mut().zip(report.devices.iter_mut()) {
+            for (channel, stop) in (1..=2).zip(stops.iter_mut()) {
+                if stop.confirmed() {
+                    continue;
+                }
+                if let Ok(power) = saber.get_output_power(channel) {
+                    stop.power = Some(power);
+                    if power.abs() <= POWER_THRESHOLD {
+                        stop.confirmed_after = Some(start.elapsed());
+                        continue;
+                    }
+                }
+                pending = true;
+            }
+        }
+        if !pending {
+            break;
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= deadline {
+            log_warn!("stop not confirmed within {:?}", deadline);
+            break;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - elapsed));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_stop_all_confirmed() {
+        // Channel 2 of the second device needs two reads to stop
+        let mut port1 = MockPort::with_responder(|frame| match frame {
+            b"P1: get\r\n" => b"P1: 0\r\n".to_vec(),
+            b"P2: get\r\n" => b"P2: 0\r\n".to_vec(),
+            _ => Vec::new(),
+        });
+        let mut reads = 0;
+        let port2 = MockPort::with_responder(move |frame| match frame {
+            b"P1: get\r\n" => b"P1: 0\r\n".to_vec(),
+            b"P2: get\r\n" => {
+                reads += 1;
+                match reads {
+                    1 => b"P2: 1023\r\n".to_vec(),
+                    _ => b"P2: 0\r\n".to_vec(),
+                }
+            }
+            _ => Vec::new(),
+        });
+        let mut saber1 = PlainText::from(&port1);
+        let mut saber2 = PlainText::from(&port2);
+
+        let deadline = Duration::from_secs(1);
+        let report = stop_all_confirmed(&mut [&mut saber1, &mut saber2], deadline);
+        assert!(report.confirmed());
+        assert_eq!(2, report.devices().len());
+        assert_eq!(Some(0.0), report.devices()[1][1].power);
+        assert_eq!(
+            port1.take_written(),
+            b"M1: 0\r\nM2: 0\r\nP1: get\r\nP2: get\r\n"
+        );
+
+        // Never stops
+        let port = MockPort::with_responder(|_| b"P1: 2047\r\n".to_vec());
+        let mut saber = PlainText::from(&port);
+        let report = stop_all_confirmed(&mut [&mut saber], Duration::from_millis(20));
+        assert!(!report.confirmed());
+        let stop = report.devices()[0][0];
+        assert!(stop.sent);
+        assert_eq!(Some(1.0), stop.power);
+        assert!(!report.devices()[0][1].confirmed());
+    }
+
+    #[test]
+    fn test_stop_all() {
+        let mut port1 = MockPort::new();
+        let mut saber1 = PlainText::from(&port1);
+        // The second device accepts nothing
+        let port2 = MockPort::new();
+        port2.set_tx_capacity(Some(0));
+        let deadline = Duration::from_millis(1);
+        let mut saber2 = PlainText::from(&port2).with_write_deadline(deadline);
+        let mut saber3 = PlainText::from(MockPort::new());
+        let errors = stop_all(&mut [&mut saber1, &mut saber2, &mut saber3]).unwrap_err();
+        assert_eq!(vec![1], errors.devices());
+        assert!(errors.get(1, Some(2)).is_some());
+        assert!(errors
+            .to_string()
+            .starts_with("2 operation(s) failed: device 1 channel 1"));
+        assert_eq!(port1.take_written(), b"M1: 0\r\nM2: 0\r\n");
+    }
+}
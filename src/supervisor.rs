@@ -0,0 +1,513 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::sabertooth2x32::{Operation, Sabertooth2x32, Support};
+use crate::{Error, FailsafeAction, Result, RetryBudget, WaitPoint, Yield};
+
+/// Default interval between two reconnection attempts while the link is lost.
+pub const DEFAULT_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum number of pending events, older events are dropped.
+const MAX_EVENTS: usize = 32;
+
+/// State of the link managed by a [Supervisor](struct.Supervisor.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SupervisorState {
+    /// The last operation succeeded.
+    Connected,
+
+    /// Operations are failing, but fewer times in a row than the threshold
+    /// for declaring the link lost.
+    Degraded,
+
+    /// Operations failed too many times in a row. The failsafe action was
+    /// applied and the handle is periodically reconnected, if possible.
+    Lost,
+}
+
+/// Event emitted when the state of a supervisor changes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SupervisorEvent {
+    pub from: SupervisorState,
+    pub to: SupervisorState,
+}
+
+type Reconnect<S> = Box<dyn FnMut() -> Result<S> + Send>;
+
+/// Link management wrapping a handle, combining the retries, the failsafe
+/// and the reconnection policies.
+///
+/// The decoders of the handles already resynchronize on the next frame after
+/// garbage, so a failed operation is simply retried, up to the number of
+/// retries. The operations still failing after their retries are counted,
+/// and the link is declared lost after a number of consecutive failures: the
+/// failsafe action is then applied on a best-effort basis, and the handle is
+/// replaced by a new one from the reconnect function at most once per
+/// reconnect interval, on the next operations. The first operation
+/// succeeding brings the state back to `Connected`.
+///
+/// With [with_retry_budget()](#method.with_retry_budget), the retries are
+/// also taken from a budget shared by several supervisors, which bounds the
+/// time a control cycle can spend retrying.
+///
+/// Errors not caused by the link, like invalid arguments or disarmed
+/// handles, are returned without retry and do not change the state.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{FailsafeAction, Result, Supervisor, SupervisorState};
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyS0")?;
+/// let mut saber = Supervisor::new(saber)
+///     .with_retries(2)
+///     .with_lost_after(3)
+///     .with_failsafe(FailsafeAction::HardBrake)
+///     .with_reconnect(|| PacketSerial::new("/dev/ttyS0"));
+///
+/// saber.set_speed(1, 0.5)?;
+/// for event in saber.drain_events() {
+///     if event.to == SupervisorState::Lost {
+///         println!("link lost, motors stopped");
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Supervisor<S> {
+    saber: S,
+    retries: u32,
+    retry_budget: Option<RetryBudget>,
+    yield_hook: Option<Box<dyn Yield>>,
+    lost_after: u32,
+    failsafe: Option<FailsafeAction>,
+    reconnect: Option<Reconnect<S>>,
+    reconnect_interval: Duration,
+    last_reconnect: Option<Instant>,
+    failures: u32,
+    state: SupervisorState,
+    events: VecDeque<SupervisorEvent>,
+}
+
+impl<S: Sabertooth2x32> Supervisor<S> {
+    /// Wrap *saber* in the `Connected` state, with one retry per operation,
+    /// the link declared lost after 3 consecutive failures, the
+    /// `ZeroSpeed` failsafe action and no reconnection.
+    pub fn new(saber: S) -> Supervisor<S> {
+        Supervisor {
+            saber,
+            retries: 1,
+            retry_budget: None,
+            yield_hook: None,
+            lost_after: 3,
+            failsafe: Some(FailsafeAction::ZeroSpeed),
+            reconnect: None,
+            reconnect_interval: DEFAULT_RECONNECT_INTERVAL,
+            last_reconnect: None,
+            failures: 0,
+            state: SupervisorState::Connected,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Set the number of retries of a failed operation.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Take the retries from *budget*, possibly shared with other
+    /// supervisors. A failed operation is not retried while it is exhausted.
+    pub fn with_retry_budget(mut self, budget: RetryBudget) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Call *hook* before each retry, for cooperative schedulers. The
+    /// handle takes its own hook for the waits inside the transactions. See
+    /// [Yield](trait.Yield.html).
+    pub fn with_yield_hook<Y>(mut self, hook: Y) -> Self
+    where
+        Y: Yield + 'static,
+    {
+        self.yield_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Declare the link lost after *failures* consecutive failed operations,
+    /// at least one.
+    pub fn with_lost_after(mut self, failures: u32) -> Self {
+        self.lost_after = failures.max(1);
+        self
+    }
+
+    /// Set the action applied when the link is lost.
+    pub fn with_failsafe(mut self, action: FailsafeAction) -> Self {
+        self.failsafe = Some(action);
+        self
+    }
+
+    /// Apply no action when the link is lost.
+    pub fn without_failsafe(mut self) -> Self {
+        self.failsafe = None;
+        self
+    }
+
+    /// Replace the handle by the result of *reconnect* while the link is
+    /// lost, for example reopening the port of an USB adapter which was
+    /// unplugged.
+    pub fn with_reconnect<F>(mut self, reconnect: F) -> Self
+    where
+        F: FnMut() -> Result<S> + Send + 'static,
+    {
+        self.reconnect = Some(Box::new(reconnect));
+        self
+    }
+
+    /// Set the minimal time between two reconnection attempts.
+    pub fn with_reconnect_interval(mut self, interval: Duration) -> Self {
+        self.reconnect_interval = interval;
+        self
+    }
+
+    /// Return the current state of the link.
+    pub fn state(&self) -> SupervisorState {
+        self.state
+    }
+
+    /// Number of consecutive failed operations.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Remove and return the pending state change events, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = SupervisorEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Return a reference to the wrapped handle.
+    pub fn get_ref(&self) -> &S {
+        &self.saber
+    }
+
+    /// Return a mutable reference to the wrapped handle. Operations made
+    /// through it are not supervised.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.saber
+    }
+
+    /// Unwrap the handle.
+    pub fn into_inner(self) -> S {
+        self.saber
+    }
+
+    /// Run *op* on the handle with the supervision policies.
+    fn run<R, F>(&mut self, mut op: F) -> Result<R>
+    where
+        F: FnMut(&mut S) -> Result<R>,
+    {
+        if self.state == SupervisorState::Lost {
+            self.try_reconnect();
+        }
+
+        let mut attempt = 0;
+        loop {
+            let start = Instant::now();
+            let res = op(&mut self.saber);
+            if let (true, Some(budget)) = (attempt > 0, &self.retry_budget) {
+                budget.spend(start.elapsed());
+            }
+            match res {
+                Ok(value) => {
+                    self.failures = 0;
+                    self.set_state(SupervisorState::Connected);
+                    return Ok(value);
+                }
+                Err(e) if !is_link_error(&e) => return Err(e),
+                Err(e) if attempt < self.retries && self.acquire_retry() => {
+                    log_warn!("operation failed, retrying: {}", e);
+                    attempt += 1;
+                    if let Some(hook) = self.yield_hook.as_mut() {
+                        hook.yield_now(WaitPoint::Retry);
+                    }
+                }
+                Err(e) => {
+                    self.failures = self.failures.saturating_add(1);
+                    if self.failures >= self.lost_after {
+                        self.lose();
+                    } else {
+                        self.set_state(SupervisorState::Degraded);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Take a retry from the budget, if any.
+    fn acquire_retry(&self) -> bool {
+        match &self.retry_budget {
+            Some(budget) if !budget.acquire() => {
+                log_warn!("retry budget exhausted, not retrying");
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn lose(&mut self) {
+        if self.state == SupervisorState::Lost {
+            return;
+        }
+        self.set_state(SupervisorState::Lost);
+        if let Some(action) = self.failsafe {
+            if let Err(e) = self.saber.emergency_stop(action) {
+                log_warn!("failsafe action {:?} failed: {}", action, e);
+            }
+        }
+    }
+
+    fn try_reconnect(&mut self) {
+        let reconnect = match &mut self.reconnect {
+            Some(reconnect) => reconnect,
+            None => return,
+        };
+        let now = Instant::now();
+        if let Some(last) = self.last_reconnect {
+            if now.duration_since(last) < self.reconnect_interval {
+                return;
+            }
+        }
+        self.last_reconnect = Some(now);
+        match reconnect() {
+            Ok(saber) => {
+                log_info!("reconnected");
+                self.saber = saber;
+            }
+            Err(e) => log_warn!("reconnection failed: {}", e),
+        }
+    }
+
+    fn set_state(&mut self, state: SupervisorState) {
+        if state == self.state {
+            return;
+        }
+        log_warn!(
+            "supervisor state changed from {:?} to {:?}",
+            self.state,
+            state
+        );
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(SupervisorEvent {
+            from: self.state,
+            to: state,
+        });
+        self.state = state;
+    }
+}
+
+/// True if *error* may be caused by the link, rather than by the request or
+/// the state of the handle.
+fn is_link_error(error: &Error) -> bool {
+    !matches!(
+        error,
+        Error::InvalidInput(_) | Error::Unsupported(_) | Error::NotArmed
+    )
+}
+
+impl<S: Sabertooth2x32> Sabertooth2x32 for Supervisor<S> {
+    fn startup(&mut self, channel: usize) -> Result<()> {
+        self.run(|s| s.startup(channel))
+    }
+
+    fn shutdown(&mut self, channel: usize) -> Result<()> {
+        self.run(|s| s.shutdown(channel))
+    }
+
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.run(|s| s.set_speed(channel, ratio))
+    }
+
+    fn get_speed(&mut self, channel: usize) -> Result<f32> {
+        self.run(|s| s.get_speed(channel))
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        self.run(|s| s.set_drive(ratio))
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        self.run(|s| s.set_turn(ratio))
+    }
+
+    fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.run(|s| s.set_power(channel, ratio))
+    }
+
+    fn get_power(&mut self, channel: usize) -> Result<f32> {
+        self.run(|s| s.get_power(channel))
+    }
+
+    fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.run(|s| s.set_ramp(channel, ratio))
+    }
+
+    fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.run(|s| s.set_aux(channel, ratio))
+    }
+
+    fn get_voltage(&mut self, channel: usize) -> Result<f32> {
+        self.run(|s| s.get_voltage(channel))
+    }
+
+    fn get_current(&mut self, channel: usize) -> Result<f32> {
+        self.run(|s| s.get_current(channel))
+    }
+
+    fn get_temperature(&mut self, channel: usize) -> Result<f32> {
+        self.run(|s| s.get_temperature(channel))
+    }
+
+    fn arm(&mut self) {
+        self.saber.arm()
+    }
+
+    fn disarm(&mut self) {
+        self.saber.disarm()
+    }
+
+    fn is_armed(&self) -> bool {
+        self.saber.is_armed()
+    }
+
+    fn shutdown_commanded(&self, channel: usize) -> bool {
+        self.saber.shutdown_commanded(channel)
+    }
+
+    fn supports(&self, operation: Operation) -> Support {
+        self.saber.supports(operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FaultPlan, FrameTap, MockPort};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn responder(alive: Arc<AtomicBool>) -> MockPort {
+        MockPort::with_responder(move |frame| match frame {
+            b"M1: getb\r\n" if alive.load(Ordering::SeqCst) => b"M1:B120\r\n".to_vec(),
+            _ => Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_supervisor() {
+        let alive = Arc::new(AtomicBool::new(true));
+        let mut port = responder(alive.clone());
+        let new_port = responder(Arc::new(AtomicBool::new(true)));
+        let mut reconnect_port = Some(new_port.clone());
+        let mut saber = Supervisor::new(PlainText::from(&port))
+            .with_lost_after(2)
+            .with_reconnect(move || match reconnect_port.take() {
+                Some(port) => Ok(PlainText::from(port)),
+                None => Err(Error::Unsupported("no port".to_string())),
+            })
+            .with_reconnect_interval(Duration::from_secs(0));
+
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(SupervisorState::Connected, saber.state());
+
+        alive.store(false, Ordering::SeqCst);
+        assert!(saber.get_voltage(1).is_err());
+        assert_eq!(SupervisorState::Degraded, saber.state());
+        port.take_written();
+
+        // The failsafe action is applied once
+        assert!(saber.get_voltage(1).is_err());
+        assert_eq!(SupervisorState::Lost, saber.state());
+        assert_eq!(
+            port.take_written(),
+            b"M1: getb\r\nM1: getb\r\nM1: 0\r\nM2: 0\r\n"
+        );
+
+        // Invalid requests do not count as failures
+        assert!(saber.set_speed(3, 0.0).is_err());
+
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(SupervisorState::Connected, saber.state());
+
+        let events: Vec<_> = saber.drain_events().map(|e| e.to).collect();
+        assert_eq!(
+            vec![
+                SupervisorState::Degraded,
+                SupervisorState::Lost,
+                SupervisorState::Connected
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn test_retry() {
+        // The first reply is lost and the second one is corrupted, so the
+        // third attempt succeeds
+        let port = responder(Arc::new(AtomicBool::new(true)));
+        let faults = FaultPlan::new().drop_reply(0).corrupt_reply(1, 4);
+        let saber = PlainText::from(&port).with_fault_injector(faults);
+        let mut saber = Supervisor::new(saber).with_retries(2);
+
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+        assert_eq!(SupervisorState::Connected, saber.state());
+        assert_eq!(0, saber.drain_events().count());
+
+        let faults = FaultPlan::new().drop_reply(0).corrupt_reply(1, 4);
+        let mut saber = Supervisor::new(saber.into_inner().with_fault_injector(faults));
+        assert!(saber.get_voltage(1).is_err());
+        assert_eq!(SupervisorState::Degraded, saber.state());
+    }
+
+    #[test]
+    fn test_retry_budget() {
+        let alive = Arc::new(AtomicBool::new(false));
+        let budget = RetryBudget::new(3);
+        let mut front = Supervisor::new(PlainText::from(responder(alive.clone())))
+            .with_retries(2)
+            .with_retry_budget(budget.clone());
+        let mut port = responder(alive.clone());
+        let mut rear = Supervisor::new(PlainText::from(&port))
+            .with_retries(2)
+            .with_retry_budget(budget.clone());
+
+        // The front takes 2 retries, leaving one to the rear
+        assert!(front.get_voltage(1).is_err());
+        assert!(rear.get_voltage(1).is_err());
+        assert_eq!(port.take_written(), b"M1: getb\r\nM1: getb\r\n");
+        assert_eq!(1, budget.denied());
+
+        budget.reset();
+        alive.store(true, Ordering::SeqCst);
+        assert_eq!(12.0, rear.get_voltage(1).unwrap());
+        assert_eq!(3, budget.remaining());
+    }
+
+    #[test]
+    fn test_yield_hook() {
+        let alive = Arc::new(AtomicBool::new(false));
+        let points = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = points.clone();
+        let mut saber = Supervisor::new(PlainText::from(responder(alive)))
+            .with_retries(2)
+            .with_yield_hook(move |point| log.lock().unwrap().push(point));
+
+        assert!(saber.get_voltage(1).is_err());
+        assert_eq!(vec![WaitPoint::Retry; 2], *points.lock().unwrap());
+    }
+}
@@ -0,0 +1,10 @@
+//! Synchronization primitives of the threaded subsystems, from `std`, or
+//! from [loom] when built with `--cfg loom` for model-checking their
+//! interleavings.
+//!
+//! [loom]: https://crates.io/crates/loom
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{Mutex, MutexGuard};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Mutex, MutexGuard};
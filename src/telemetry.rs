@@ -0,0 +1,58 @@
+//! Bounded recording of the telemetry, with pluggable persistence.
+//!
+//! A [TelemetryLog] keeps the most recent [Record]s in a [Store]:
+//!
+//! - [MemoryStore] keeps them in memory.
+//! - [FileStore] keeps them in a fixed-size file used as a ring buffer, so
+//!   that the last minutes of data survive a crash or a power loss.
+//!
+//! The records can be exported to CSV or JSON for analysis. The recording
+//! requires the "telemetry" feature, enabled by default; [Quantity] is
+//! always available.
+//!
+//! With the "async" feature as well, [telemetry_stream()] samples a handle
+//! periodically and yields the measurements as a `Stream` of
+//! `TelemetrySnapshot`s, for async applications.
+//!
+//! # Example
+//!
+#![cfg_attr(all(feature = "serialport", feature = "telemetry"), doc = " ```rust")]
+#![cfg_attr(
+    not(all(feature = "serialport", feature = "telemetry")),
+    doc = " ```ignore"
+)]
+//! use saberrs::sabertooth2x32::PacketSerial;
+//! use saberrs::telemetry::{FileStore, TelemetryLog};
+//!
+//! # fn example() -> std::result::Result<(), Box<dyn std::error::Error>> {
+//! let mut saber = PacketSerial::new("/dev/ttyS0")?;
+//! let store = FileStore::open("/var/log/rover/telemetry.bin", 100_000)?;
+//! let mut log = TelemetryLog::new(store);
+//!
+//! // In the control loop
+//! log.poll(&mut saber, 1)?;
+//!
+//! // After retrieval
+//! log.write_csv(std::io::stdout())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [TelemetryLog]: struct.TelemetryLog.html
+//! [Record]: struct.Record.html
+//! [Store]: trait.Store.html
+//! [MemoryStore]: struct.MemoryStore.html
+//! [FileStore]: struct.FileStore.html
+//! [Quantity]: enum.Quantity.html
+//! [telemetry_stream()]: fn.telemetry_stream.html
+
+#[cfg(feature = "telemetry")]
+mod recorder;
+#[cfg(all(feature = "async", feature = "telemetry"))]
+mod stream;
+
+pub use crate::protocol::quantity::Quantity;
+#[cfg(feature = "telemetry")]
+pub use recorder::{FileStore, MemoryStore, Record, Store, TelemetryLog};
+#[cfg(all(feature = "async", feature = "telemetry"))]
+pub use stream::{telemetry_stream, TelemetrySnapshot, TelemetryStream, DEFAULT_SHUTDOWN_TIMEOUT};
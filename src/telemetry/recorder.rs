@@ -0,0 +1,368 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use super::Quantity;
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::{Error, Result};
+
+/// One telemetry measurement.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Record {
+    /// Time of the measurement.
+    pub time: SystemTime,
+    /// Motor channel, 1 or 2.
+    pub channel: u8,
+    /// Measured quantity.
+    pub quantity: Quantity,
+    /// Measured value.
+    pub value: f32,
+}
+
+/// Size of a record in a [FileStore](struct.FileStore.html).
+const RECORD_SIZE: usize = 18;
+
+impl Record {
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let time = self
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut buf = [0u8; RECORD_SIZE];
+        buf[0..8].copy_from_slice(&time.as_secs().to_le_bytes());
+        buf[8..12].copy_from_slice(&time.subsec_nanos().to_le_bytes());
+        buf[12] = self.channel;
+        buf[13] = self.quantity.to_byte();
+        buf[14..18].copy_from_slice(&self.value.to_le_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8; RECORD_SIZE]) -> Option<Record> {
+        let secs = u64::from_le_bytes(buf[0..8].try_into().unwrap());
+        let nanos = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+        Some(Record {
+            time: SystemTime::UNIX_EPOCH + Duration::new(secs, nanos),
+            channel: buf[12],
+            quantity: Quantity::from_byte(buf[13])?,
+            value: f32::from_le_bytes(buf[14..18].try_into().unwrap()),
+        })
+    }
+}
+
+/// Storage of the records of a [TelemetryLog](struct.TelemetryLog.html).
+///
+/// Implementations keep at most a fixed number of records, dropping the
+/// oldest ones.
+pub trait Store {
+    /// Add a record.
+    fn append(&mut self, record: &Record) -> io::Result<()>;
+
+    /// Return the stored records, oldest first.
+    fn records(&mut self) -> io::Result<Vec<Record>>;
+}
+
+/// Store keeping the records in memory.
+#[derive(Clone, Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    records: VecDeque<Record>,
+}
+
+impl MemoryStore {
+    /// Create a new store keeping at most *capacity* records.
+    pub fn new(capacity: usize) -> MemoryStore {
+        MemoryStore {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+        }
+    }
+}
+
+impl Store for MemoryStore {
+    fn append(&mut self, record: &Record) -> io::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(*record);
+        Ok(())
+    }
+
+    fn records(&mut self) -> io::Result<Vec<Record>> {
+        Ok(self.records.iter().cloned().collect())
+    }
+}
+
+const FILE_MAGIC: &[u8; 4] = b"SBTL";
+const FILE_VERSION: u8 = 1;
+const HEADER_SIZE: usize = 24;
+
+/// Store keeping the records in a file used as a ring buffer.
+///
+/// The file has a fixed size: a header followed by *capacity* slots. Each
+/// record is written in place, so the file stays consistent after a crash
+/// and at most the last record is lost.
+#[derive(Debug)]
+pub struct FileStore {
+    file: File,
+    capacity: u64,
+    count: u64,
+}
+
+impl FileStore {
+    /// Open the store at *path*, keeping at most *capacity* records.
+    ///
+    /// The records already in the file are kept if it was created with the
+    /// same capacity, otherwise the file is reset.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: u64) -> io::Result<FileStore> {
+        if capacity == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "capacity must not be zero",
+            ));
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut header = [0u8; HEADER_SIZE];
+        let count = match file.read_exact(&mut header) {
+            Ok(()) if &header[0..4] == FILE_MAGIC && header[4] == FILE_VERSION => {
+                let file_capacity = u64::from_le_bytes(header[8..16].try_into().unwrap());
+                let count = u64::from_le_bytes(header[16..24].try_into().unwrap());
+                if file_capacity == capacity {
+                    Some(count)
+                } else {
+                    None
+                }
+            }
+            Ok(()) => None,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut store = FileStore {
+            file,
+            capacity,
+            count: count.unwrap_or(0),
+        };
+        if count.is_none() {
+            store
+                .file
+                .set_len(HEADER_SIZE as u64 + capacity * RECORD_SIZE as u64)?;
+            store.write_header()?;
+        }
+        Ok(store)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..4].copy_from_slice(FILE_MAGIC);
+        header[4] = FILE_VERSION;
+        header[8..16].copy_from_slice(&self.capacity.to_le_bytes());
+        header[16..24].copy_from_slice(&self.count.to_le_bytes());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)
+    }
+
+    fn slot_offset(&self, index: u64) -> u64 {
+        HEADER_SIZE as u64 + (index % self.capacity) * RECORD_SIZE as u64
+    }
+}
+
+impl Store for FileStore {
+    fn append(&mut self, record: &Record) -> io::Result<()> {
+        let offset = self.slot_offset(self.count);
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&record.encode())?;
+        self.count += 1;
+        self.write_header()
+    }
+
+    fn records(&mut self) -> io::Result<Vec<Record>> {
+        let len = self.count.min(self.capacity);
+        let first = self.count - len;
+        let mut records = Vec::with_capacity(len as usize);
+        let mut buf = [0u8; RECORD_SIZE];
+        for index in first..self.count {
+            self.file.seek(SeekFrom::Start(self.slot_offset(index)))?;
+            self.file.read_exact(&mut buf)?;
+            if let Some(record) = Record::decode(&buf) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Recorder of telemetry measurements into a [Store](trait.Store.html).
+#[derive(Debug)]
+pub struct TelemetryLog<S: Store> {
+    store: S,
+}
+
+impl<S: Store> TelemetryLog<S> {
+    /// Create a new log recording into *store*.
+    pub fn new(store: S) -> TelemetryLog<S> {
+        TelemetryLog { store }
+    }
+
+    /// Access to the store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Record a measurement taken now.
+    pub fn record(&mut self, channel: usize, quantity: Quantity, value: f32) -> Result<()> {
+        let record = Record {
+            time: SystemTime::now(),
+            channel: channel as u8,
+            quantity,
+            value,
+        };
+        self.store.append(&record).map_err(Error::from)
+    }
+
+    /// Read all the quantities of *channel* from *saber* and record them.
+    pub fn poll<T>(&mut self, saber: &mut T, channel: usize) -> Result<()>
+    where
+        T: Sabertooth2x32 + ?Sized,
+    {
+        let speed = saber.get_speed(channel)?;
+        self.record(channel, Quantity::Speed, speed)?;
+        let power = saber.get_power(channel)?;
+        self.record(channel, Quantity::Power, power)?;
+        let voltage = saber.get_voltage(channel)?;
+        self.record(channel, Quantity::Voltage, voltage)?;
+        let current = saber.get_current(channel)?;
+        self.record(channel, Quantity::Current, current)?;
+        let temperature = saber.get_temperature(channel)?;
+        self.record(channel, Quantity::Temperature, temperature)
+    }
+
+    /// Return the recorded measurements, oldest first.
+    pub fn records(&mut self) -> Result<Vec<Record>> {
+        self.store.records().map_err(Error::from)
+    }
+
+    /// Write the records as CSV, with a header line. The time is in seconds
+    /// since the Unix epoch.
+    pub fn write_csv<W: Write>(&mut self, mut w: W) -> Result<()> {
+        writeln!(w, "time,channel,quantity,value")?;
+        for r in self.records()? {
+            writeln!(
+                w,
+                "{:.6},{},{},{}",
+                unix_secs(r.time),
+                r.channel,
+                r.quantity.name(),
+                r.value
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the records as a JSON array of objects. The time is in seconds
+    /// since the Unix epoch.
+    pub fn write_json<W: Write>(&mut self, mut w: W) -> Result<()> {
+        write!(w, "[")?;
+        for (i, r) in self.records()?.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            // JSON has no representation for NaN or infinities
+            let value = if r.value.is_finite() {
+                r.value.to_string()
+            } else {
+                "null".to_string()
+            };
+            write!(
+                w,
+                "{}\n  {{\"time\": {:.6}, \"channel\": {}, \"quantity\": \"{}\", \"value\": {}}}",
+                sep,
+                unix_secs(r.time),
+                r.channel,
+                r.quantity.name(),
+                value
+            )?;
+        }
+        writeln!(w, "\n]")?;
+        Ok(())
+    }
+}
+
+fn unix_secs(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn record(value: f32) -> Record {
+        Record {
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_500),
+            channel: 1,
+            quantity: Quantity::Voltage,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_file_store() {
+        let path = std::env::temp_dir().join(format!("saberrs-telemetry-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = FileStore::open(&path, 2).unwrap();
+        for v in 0..3 {
+            store.append(&record(v as f32)).unwrap();
+        }
+        drop(store);
+
+        // Reopen as after a crash
+        let mut store = FileStore::open(&path, 2).unwrap();
+        let values: Vec<_> = store.records().unwrap().iter().map(|r| r.value).collect();
+        assert_eq!(vec![1.0, 2.0], values);
+
+        // Another capacity resets the file
+        let mut store = FileStore::open(&path, 3).unwrap();
+        assert!(store.records().unwrap().is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export() {
+        let mut store = MemoryStore::new(2);
+        store.append(&record(12.5)).unwrap();
+        store.append(&record(f32::NAN)).unwrap();
+        let mut log = TelemetryLog::new(store);
+
+        let mut csv = Vec::new();
+        log.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            "time,channel,quantity,value\n\
+             1.500000,1,voltage,12.5\n\
+             1.500000,1,voltage,NaN\n",
+            String::from_utf8(csv).unwrap()
+        );
+
+        let mut json = Vec::new();
+        log.write_json(&mut json).unwrap();
+        assert_eq!(
+            "[\n  {\"time\": 1.500000, \"channel\": 1, \"quantity\": \"voltage\", \"value\": 12.5},\
+             \n  {\"time\": 1.500000, \"channel\": 1, \"quantity\": \"voltage\", \"value\": null}\n]\n",
+            String::from_utf8(json).unwrap()
+        );
+    }
+}
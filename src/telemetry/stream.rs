@@ -0,0 +1,465 @@
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_core::Stream;
+
+use super::{Quantity, Record};
+use crate::error::{Error, Result};
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::sync::{Mutex, MutexGuard};
+
+/// Maximum number of snapshots waiting to be consumed, older ones are
+/// dropped.
+const MAX_PENDING: usize = 16;
+
+/// Default time given to the sampling thread for stopping, see
+/// [TelemetryStream](struct.TelemetryStream.html).
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Interval between two checks of a stopping thread.
+const JOIN_POLL: Duration = Duration::from_millis(1);
+
+/// Telemetry sampled at one instant by a [TelemetryStream].
+///
+/// [TelemetryStream]: struct.TelemetryStream.html
+#[derive(Clone, PartialEq, Debug)]
+pub struct TelemetrySnapshot {
+    /// Time at which the sampling started.
+    pub time: SystemTime,
+
+    /// Measurements which were read successfully, in the order of the
+    /// metrics.
+    pub records: Vec<Record>,
+
+    /// Metrics which could not be read, as (channel, quantity).
+    pub failed: Vec<(usize, Quantity)>,
+}
+
+impl TelemetrySnapshot {
+    /// Value of *quantity* on *channel*, if it was read.
+    pub fn get(&self, channel: usize, quantity: Quantity) -> Option<f32> {
+        self.records
+            .iter()
+            .find(|r| usize::from(r.channel) == channel && r.quantity == quantity)
+            .map(|r| r.value)
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    snapshots: VecDeque<TelemetrySnapshot>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Snapshots passed from the sampling thread to the stream, with the waker
+/// of the consumer. It is built on [crate::sync], so that the hand-over can
+/// be model-checked with loom.
+#[derive(Default)]
+struct Mailbox {
+    shared: Mutex<Shared>,
+}
+
+impl Mailbox {
+    /// Add *snapshot*, dropping the oldest one if the consumer is behind,
+    /// and wake the consumer.
+    fn push(&self, snapshot: TelemetrySnapshot) {
+        let waker = {
+            let mut shared = self.lock();
+            if shared.snapshots.len() == MAX_PENDING {
+                shared.snapshots.pop_front();
+            }
+            shared.snapshots.push_back(snapshot);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Mark the end of the snapshots, and wake the consumer.
+    fn close(&self) {
+        let waker = {
+            let mut shared = self.lock();
+            shared.closed = true;
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Return the oldest snapshot, `None` once closed and empty, or register
+    /// *waker* for the next change.
+    fn poll(&self, waker: &Waker) -> Poll<Option<TelemetrySnapshot>> {
+        let mut shared = self.lock();
+        if let Some(snapshot) = shared.snapshots.pop_front() {
+            return Poll::Ready(Some(snapshot));
+        }
+        if shared.closed {
+            return Poll::Ready(None);
+        }
+        shared.waker = Some(waker.clone());
+        Poll::Pending
+    }
+
+    /// Lock the shared state, ignoring the poisoning: the state stays
+    /// consistent.
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Close the mailbox when the sampling thread exits, even by a panic.
+struct CloseOnExit<'a>(&'a Mailbox);
+
+impl Drop for CloseOnExit<'_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Stream of [TelemetrySnapshot]s, returned by [telemetry_stream()].
+///
+/// The handle is sampled by a background thread, so the stream works with
+/// any async runtime.
+///
+/// # Shutdown
+///
+/// The thread finishes the sampling in progress before stopping, which can
+/// take up to the port timeout for each metric. The ways to stop it are:
+///
+/// - [shutdown()](#method.shutdown) waits for the thread for at most the
+///   shutdown timeout, and returns the handle, or an error if the thread
+///   panicked or did not stop in time. In the latter case the thread is
+///   detached and stops on its own.
+/// - Dropping the stream does the same, and logs the errors.
+/// - [stop()](#method.stop) waits for the thread without timeout and
+///   propagates its panics.
+///
+/// The shutdown timeout is [DEFAULT_SHUTDOWN_TIMEOUT] unless set with
+/// [with_shutdown_timeout()](#method.with_shutdown_timeout). The shared state
+/// is never poisoned: a panic of the thread ends the stream.
+///
+/// **Requires** the "async" feature.
+///
+/// [TelemetrySnapshot]: struct.TelemetrySnapshot.html
+/// [telemetry_stream()]: fn.telemetry_stream.html
+/// [DEFAULT_SHUTDOWN_TIMEOUT]: constant.DEFAULT_SHUTDOWN_TIMEOUT.html
+pub struct TelemetryStream<S> {
+    mailbox: Arc<Mailbox>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<S>>,
+    shutdown_timeout: Duration,
+}
+
+impl<S> TelemetryStream<S> {
+    /// Set the time given to the sampling thread for stopping.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Stop the sampling, and return the handle.
+    pub fn stop(mut self) -> S {
+        let thread = self.request_stop().expect("sampling thread already joined");
+        match thread.join() {
+            Ok(saber) => saber,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// Stop the sampling and return the handle, waiting for the thread for
+    /// at most the shutdown timeout.
+    ///
+    /// Return `Error::Io` with `io::ErrorKind::TimedOut` if the thread did
+    /// not stop in time, or with `io::ErrorKind::Other` if it panicked.
+    pub fn shutdown(mut self) -> Result<S> {
+        let thread = self.request_stop().expect("sampling thread already joined");
+        join_timeout(thread, self.shutdown_timeout)
+    }
+
+    fn request_stop(&mut self) -> Option<JoinHandle<S>> {
+        self.stop.store(true, Ordering::Relaxed);
+        let thread = self.thread.take()?;
+        thread.thread().unpark();
+        Some(thread)
+    }
+}
+
+impl<S> Stream for TelemetryStream<S> {
+    type Item = TelemetrySnapshot;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.thread.is_none() {
+            return Poll::Ready(None);
+        }
+        self.mailbox.poll(cx.waker())
+    }
+}
+
+impl<S> Drop for TelemetryStream<S> {
+    fn drop(&mut self) {
+        if let Some(thread) = self.request_stop() {
+            if let Err(e) = join_timeout(thread, self.shutdown_timeout) {
+                log_warn!("telemetry stream: {}", e);
+            }
+        }
+    }
+}
+
+/// Join *thread*, or detach it if it does not finish within *timeout*.
+fn join_timeout<S>(thread: JoinHandle<S>, timeout: Duration) -> Result<S> {
+    let deadline = Instant::now() + timeout;
+    while !thread.is_finished() {
+        if Instant::now() >= deadline {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("sampling thread still running after {:?}", timeout),
+            )));
+        }
+        thread::sleep(JOIN_POLL);
+    }
+    thread
+        .join()
+        .map_err(|_| Error::Io(io::Error::other("sampling thread panicked")))
+}
+
+/// Sample *metrics*, given as (channel, quantity), from *saber* every
+/// *period*, and return them as a stream.
+///
+/// A snapshot is produced for every period, even when some metrics could
+/// not be read. If the consumer falls behind, the oldest snapshots are
+/// dropped.
+///
+/// **Requires** the "async" feature.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use futures_util::StreamExt;
+/// use saberrs::sabertooth2x32::PacketSerial;
+/// use saberrs::telemetry::{telemetry_stream, Quantity};
+///
+/// # async fn example() -> saberrs::Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyS0")?;
+/// let metrics = [(1, Quantity::Current), (2, Quantity::Current)];
+/// let mut stream = telemetry_stream(saber, &metrics, Duration::from_millis(100));
+///
+/// while let Some(snap) = stream.next().await {
+///     println!("{:?}", snap.get(1, Quantity::Current));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn telemetry_stream<S>(
+    saber: S,
+    metrics: &[(usize, Quantity)],
+    period: Duration,
+) -> TelemetryStream<S>
+where
+    S: Sabertooth2x32 + Send + 'static,
+{
+    let mailbox = Arc::new(Mailbox::default());
+    let stop = Arc::new(AtomicBool::new(false));
+    let metrics = metrics.to_vec();
+
+    let thread = {
+        let (mailbox, stop) = (mailbox.clone(), stop.clone());
+        thread::spawn(move || sample_loop(saber, &metrics, period, &mailbox, &stop))
+    };
+
+    TelemetryStream {
+        mailbox,
+        stop,
+        thread: Some(thread),
+        shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+    }
+}
+
+fn sample_loop<S: Sabertooth2x32>(
+    mut saber: S,
+    metrics: &[(usize, Quantity)],
+    period: Duration,
+    mailbox: &Mailbox,
+    stop: &AtomicBool,
+) -> S {
+    let _close = CloseOnExit(mailbox);
+    let mut next = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        mailbox.push(sample(&mut saber, metrics));
+
+        next += period;
+        loop {
+            let now = Instant::now();
+            if now >= next || stop.load(Ordering::Relaxed) {
+                break;
+            }
+            thread::park_timeout(next - now);
+        }
+    }
+    saber
+}
+
+fn sample<S: Sabertooth2x32>(saber: &mut S, metrics: &[(usize, Quantity)]) -> TelemetrySnapshot {
+    let mut snapshot = TelemetrySnapshot {
+        time: SystemTime::now(),
+        records: Vec::with_capacity(metrics.len()),
+        failed: Vec::new(),
+    };
+    for &(channel, quantity) in metrics {
+        let value = match quantity {
+            Quantity::Speed => saber.get_speed(channel),
+            Quantity::Power => saber.get_power(channel),
+            Quantity::Voltage => saber.get_voltage(channel),
+            Quantity::Current => saber.get_current(channel),
+            Quantity::Temperature => saber.get_temperature(channel),
+        };
+        match value {
+            Ok(value) => snapshot.records.push(Record {
+                time: SystemTime::now(),
+                channel: channel as u8,
+                quantity,
+                value,
+            }),
+            Err(_) => snapshot.failed.push((channel, quantity)),
+        }
+    }
+    snapshot
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+    use std::sync::{mpsc, Mutex};
+    use std::task::Wake;
+
+    struct ChannelWaker(Mutex<mpsc::Sender<()>>);
+
+    impl Wake for ChannelWaker {
+        fn wake(self: Arc<Self>) {
+            let _ = self.0.lock().unwrap().send(());
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut port = MockPort::new();
+        port.queue_reply(b"M1:B240\r\n");
+        let saber = PlainText::from(&port);
+
+        let metrics = [(1, Quantity::Voltage), (2, Quantity::Current)];
+        let mut stream = telemetry_stream(saber, &metrics, Duration::from_secs(60));
+
+        let (tx, rx) = mpsc::channel();
+        let waker = Waker::from(Arc::new(ChannelWaker(Mutex::new(tx))));
+        let mut cx = Context::from_waker(&waker);
+        let snapshot = loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(snapshot)) => break snapshot,
+                Poll::Ready(None) => panic!("stream ended"),
+                Poll::Pending => rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            }
+        };
+        assert_eq!(Some(24.0), snapshot.get(1, Quantity::Voltage));
+        assert_eq!(vec![(2, Quantity::Current)], snapshot.failed);
+
+        let mut saber = stream.stop();
+        saber.stop_motors().unwrap();
+        assert!(port.take_written().ends_with(b"M1: 0\r\nM2: 0\r\n"));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let port = MockPort::new();
+        let metrics = [(1, Quantity::Voltage)];
+        let period = Duration::from_secs(60);
+
+        let stream = telemetry_stream(PlainText::from(&port), &metrics, period);
+        let start = Instant::now();
+        stream.shutdown().unwrap();
+        assert!(start.elapsed() < DEFAULT_SHUTDOWN_TIMEOUT);
+
+        let stream = telemetry_stream(PlainText::from(&port), &metrics, period)
+            .with_shutdown_timeout(Duration::from_secs(5));
+        let start = Instant::now();
+        drop(stream);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
+
+/// Model-checked with `RUSTFLAGS="--cfg loom" cargo test --features async --lib loom`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::atomic::AtomicUsize;
+    use std::task::Wake;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn snapshot() -> TelemetrySnapshot {
+        TelemetrySnapshot {
+            time: SystemTime::UNIX_EPOCH,
+            records: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// The sampling thread pushes *pushed* snapshots and exits, while the
+    /// stream polls: every pending poll must be followed by a wake-up, and
+    /// every snapshot must be received before the end of the stream.
+    fn check_hand_over(pushed: usize) {
+        loom::model(move || {
+            let mailbox = loom::sync::Arc::new(Mailbox::default());
+            let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+            let waker = Waker::from(counter.clone());
+
+            let sampler = {
+                let mailbox = mailbox.clone();
+                loom::thread::spawn(move || {
+                    let _close = CloseOnExit(&mailbox);
+                    for _ in 0..pushed {
+                        mailbox.push(snapshot());
+                    }
+                })
+            };
+            let first = mailbox.poll(&waker);
+            sampler.join().unwrap();
+
+            if first.is_pending() {
+                assert!(counter.0.load(Ordering::SeqCst) > 0);
+            }
+            let mut received = usize::from(matches!(first, Poll::Ready(Some(_))));
+            while let Poll::Ready(Some(_)) = mailbox.poll(&waker) {
+                received += 1;
+            }
+            assert_eq!(pushed, received);
+            assert!(matches!(mailbox.poll(&waker), Poll::Ready(None)));
+        });
+    }
+
+    #[test]
+    fn loom_push() {
+        check_hand_over(1);
+    }
+
+    #[test]
+    fn loom_exit_without_snapshot() {
+        check_hand_over(0);
+    }
+}
@@ -0,0 +1,717 @@
+//! Tools for testing applications and alternative implementations without
+//! hardware.
+//!
+//! - [MockPort] is an in-memory [SabertoothSerial] recording the written bytes
+//!   and serving predefined replies. It can also follow a [Script] of
+//!   exchanges, with delayed or corrupted replies.
+//! - [DryRun] is a [SabertoothSerial] emulating devices, for running an
+//!   application without hardware. It requires the "emulator" feature,
+//!   disabled by default. A [TelemetryModel] adds seedable noise and
+//!   drifts to the emulated telemetry, for reproducible long-running tests.
+//! - [FaultInjector] injects faults inside the transactions of a handle,
+//!   like a dropped reply, a corrupted CRC or a delayed write, for testing
+//!   the error handling deterministically. [FaultPlan] injects them at given
+//!   frame numbers. It requires the "emulator" feature as well, so that the
+//!   production handles carry no injection point.
+//! - [check_conformance] replays a transcript of operations on any
+//!   [Sabertooth2x32] implementation and verifies the exact frames it emits and
+//!   the values it returns. The golden transcripts used by the crate's own
+//!   tests are available with [golden_checksum], [golden_crc] and
+//!   [golden_plain_text].
+//!
+//! # Example
+//!
+//! ```rust
+//! use saberrs::sabertooth2x32::{PacketSerial, PacketType};
+//! use saberrs::testing::{check_conformance, golden_checksum, MockPort};
+//!
+//! let mut port = MockPort::new();
+//! let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+//! check_conformance(&mut saber, &mut port, &golden_checksum()).unwrap();
+//! ```
+//!
+//! [MockPort]: struct.MockPort.html
+//! [Script]: struct.Script.html
+//! [DryRun]: struct.DryRun.html
+//! [TelemetryModel]: struct.TelemetryModel.html
+//! [FaultInjector]: trait.FaultInjector.html
+//! [FaultPlan]: struct.FaultPlan.html
+//! [SabertoothSerial]: ../trait.SabertoothSerial.html
+//! [Sabertooth2x32]: ../sabertooth2x32/trait.Sabertooth2x32.html
+//! [check_conformance]: fn.check_conformance.html
+//! [golden_checksum]: fn.golden_checksum.html
+//! [golden_crc]: fn.golden_crc.html
+//! [golden_plain_text]: fn.golden_plain_text.html
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub use crate::sabertooth2x32::Operation;
+use crate::sabertooth2x32::Sabertooth2x32;
+use crate::{Result, SabertoothSerial};
+#[cfg(feature = "emulator")]
+pub use dryrun::{DryRun, Exchange};
+#[cfg(any(test, feature = "emulator"))]
+pub use faults::{FaultInjector, FaultPlan, ReplyFault};
+#[cfg(feature = "emulator")]
+pub use model::TelemetryModel;
+
+#[cfg(feature = "emulator")]
+mod dryrun;
+#[cfg(any(test, feature = "emulator"))]
+mod faults;
+#[cfg(feature = "emulator")]
+mod model;
+
+/// Tolerance used when comparing the values returned by the getters.
+const VALUE_TOLERANCE: f32 = 0.001;
+
+/// Access to the frames exchanged by a handle under test.
+///
+/// It is implemented by [MockPort](struct.MockPort.html), and can be
+/// implemented by the test harness of alternative backends for using
+/// [check_conformance](fn.check_conformance.html).
+pub trait FrameTap {
+    /// Remove and return the bytes written by the handle since the last call.
+    fn take_written(&mut self) -> Vec<u8>;
+
+    /// Make *reply* available for reading after the next write of the handle.
+    fn queue_reply(&mut self, reply: &[u8]);
+}
+
+/// Alteration applied to a scripted reply.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Corruption {
+    /// Invert the bits of the byte at the given index, which breaks the
+    /// checksum or CRC of a packet.
+    Flip(usize),
+
+    /// Keep only the given number of bytes.
+    Truncate(usize),
+
+    /// Send the given bytes before the reply.
+    Garbage(Vec<u8>),
+}
+
+impl Corruption {
+    fn apply(&self, reply: &mut Vec<u8>) {
+        match self {
+            Corruption::Flip(i) => {
+                if let Some(b) = reply.get_mut(*i) {
+                    *b = !*b;
+                }
+            }
+            Corruption::Truncate(len) => reply.truncate(*len),
+            Corruption::Garbage(garbage) => {
+                reply.splice(0..0, garbage.iter().cloned());
+            }
+        }
+    }
+}
+
+/// One exchange of a [Script](struct.Script.html): the bytes the device
+/// expects, and what it responds once they are received.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScriptStep {
+    expect: Vec<u8>,
+    respond: Vec<u8>,
+    delay: Option<Duration>,
+    corruption: Option<Corruption>,
+}
+
+impl ScriptStep {
+    /// Create a step expecting *expect* and sending no response.
+    pub fn expect(expect: &[u8]) -> ScriptStep {
+        ScriptStep {
+            expect: expect.to_vec(),
+            respond: Vec::new(),
+            delay: None,
+            corruption: None,
+        }
+    }
+
+    /// Set the response sent once the expected bytes are received.
+    pub fn respond(mut self, respond: &[u8]) -> Self {
+        self.respond = respond.to_vec();
+        self
+    }
+
+    /// Make the response available only after *delay*.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Alter the response.
+    pub fn with_corruption(mut self, corruption: Corruption) -> Self {
+        self.corruption = Some(corruption);
+        self
+    }
+}
+
+/// Sequence of exchanges followed by a [MockPort](struct.MockPort.html).
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{PacketSerial, PacketType, Sabertooth2x32};
+/// use saberrs::testing::{Corruption, MockPort, Script, ScriptStep};
+///
+/// let get_voltage = b"\x80\x29\x10\x39\x4d\x31\x7e";
+/// let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+///
+/// let port = MockPort::with_script(
+///     Script::new()
+///         .step(ScriptStep::expect(get_voltage).respond(reply).with_corruption(Corruption::Flip(8)))
+///         .step(ScriptStep::expect(get_voltage).respond(reply)),
+/// );
+/// let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+///
+/// assert!(saber.get_voltage(1).is_err());
+/// assert_eq!(12.0, saber.get_voltage(1).unwrap());
+/// port.check_script().unwrap();
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Script {
+    steps: VecDeque<ScriptStep>,
+}
+
+impl Script {
+    /// Create an empty script.
+    pub fn new() -> Script {
+        Script::default()
+    }
+
+    /// Append a step.
+    pub fn step(mut self, step: ScriptStep) -> Self {
+        self.steps.push_back(step);
+        self
+    }
+}
+
+#[derive(Debug)]
+struct MockInner {
+    written: Vec<u8>,
+    rx: VecDeque<u8>,
+    replies: VecDeque<Vec<u8>>,
+    delayed: VecDeque<(Instant, Vec<u8>)>,
+    script: Script,
+    script_rx: Vec<u8>,
+    script_errors: Vec<String>,
+    timeout: Duration,
+    baud_rate: u32,
+    responder: Option<Responder>,
+    tx_capacity: Option<usize>,
+}
+
+type ResponderFn = dyn FnMut(&[u8]) -> Vec<u8> + Send;
+
+/// Closure answering the written frames of a [MockPort](struct.MockPort.html).
+struct Responder(Box<ResponderFn>);
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Responder")
+    }
+}
+
+impl MockInner {
+    fn release_delayed(&mut self, now: Instant) {
+        while let Some((at, _)) = self.delayed.front() {
+            if *at > now {
+                break;
+            }
+            let (_, data) = self.delayed.pop_front().unwrap();
+            self.rx.extend(data);
+        }
+    }
+
+    fn run_script(&mut self, buf: &[u8]) {
+        self.script_rx.extend_from_slice(buf);
+        while let Some(step) = self.script.steps.front() {
+            if self.script_rx.len() < step.expect.len() {
+                break;
+            }
+            let step = self.script.steps.pop_front().unwrap();
+            let received: Vec<u8> = self.script_rx.drain(..step.expect.len()).collect();
+            if received != step.expect {
+                self.script_errors.push(format!(
+                    "expected {:02x?} but received {:02x?}",
+                    step.expect, received
+                ));
+            }
+
+            let mut response = step.respond;
+            if let Some(corruption) = step.corruption {
+                corruption.apply(&mut response);
+            }
+            match step.delay {
+                Some(delay) => self.delayed.push_back((Instant::now() + delay, response)),
+                None => self.rx.extend(response),
+            }
+        }
+    }
+}
+
+/// In-memory serial port.
+///
+/// Clones share the same buffers, so a clone can be given to a handle while
+/// the test keeps another one for inspecting the traffic. Reading with no
+/// bytes available fails immediately with `io::ErrorKind::TimedOut`.
+#[derive(Clone, Debug)]
+pub struct MockPort {
+    inner: Arc<Mutex<MockInner>>,
+}
+
+impl MockPort {
+    /// Create a new mock port with no pending data.
+    pub fn new() -> MockPort {
+        MockPort {
+            inner: Arc::new(Mutex::new(MockInner {
+                written: Vec::new(),
+                rx: VecDeque::new(),
+                replies: VecDeque::new(),
+                delayed: VecDeque::new(),
+                script: Script::new(),
+                script_rx: Vec::new(),
+                script_errors: Vec::new(),
+                timeout: Duration::from_millis(100),
+                baud_rate: 9600,
+                responder: None,
+                tx_capacity: None,
+            })),
+        }
+    }
+
+    /// Create a new mock port following *script*.
+    pub fn with_script(script: Script) -> MockPort {
+        let port = MockPort::new();
+        port.lock().script = script;
+        port
+    }
+
+    /// Create a new mock port answering each write with the bytes returned by
+    /// *responder*, for simulating a device in examples and long-running
+    /// tests. The responder is called with the written bytes.
+    pub fn with_responder<F>(responder: F) -> MockPort
+    where
+        F: FnMut(&[u8]) -> Vec<u8> + Send + 'static,
+    {
+        let port = MockPort::new();
+        port.lock().responder = Some(Responder(Box::new(responder)));
+        port
+    }
+
+    /// Check that every step of the script was received as expected.
+    pub fn check_script(&self) -> std::result::Result<(), String> {
+        let inner = self.lock();
+        if let Some(e) = inner.script_errors.first() {
+            Err(e.clone())
+        } else if !inner.script.steps.is_empty() {
+            Err(format!("{} steps were not run", inner.script.steps.len()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockInner> {
+        // A panicking test must not poison the other clones.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Accept at most *capacity* more bytes, then fail the writes with
+    /// `io::ErrorKind::WouldBlock`, like a wedged adapter with full buffers.
+    /// `None` removes the limit.
+    pub fn set_tx_capacity(&self, capacity: Option<usize>) {
+        self.lock().tx_capacity = capacity;
+    }
+
+    /// Make *data* immediately available for reading.
+    pub fn push_rx(&self, data: &[u8]) {
+        self.lock().rx.extend(data);
+    }
+
+    /// Return a copy of the bytes written since the last call to
+    /// `take_written()`, without removing them.
+    pub fn written(&self) -> Vec<u8> {
+        self.lock().written.clone()
+    }
+}
+
+impl Default for MockPort {
+    fn default() -> Self {
+        MockPort::new()
+    }
+}
+
+impl FrameTap for MockPort {
+    fn take_written(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.lock().written)
+    }
+
+    fn queue_reply(&mut self, reply: &[u8]) {
+        self.lock().replies.push_back(reply.to_vec());
+    }
+}
+
+impl io::Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let wait = {
+            let mut inner = self.lock();
+            inner.release_delayed(Instant::now());
+            match inner.delayed.front() {
+                Some((at, _)) if inner.rx.is_empty() => Some(
+                    at.saturating_duration_since(Instant::now())
+                        .min(inner.timeout),
+                ),
+                _ => None,
+            }
+        };
+
+        // Delayed data: block like a real port, up to the timeout.
+        if let Some(wait) = wait {
+            thread::sleep(wait);
+        }
+
+        let mut inner = self.lock();
+        inner.release_delayed(Instant::now());
+        if inner.rx.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no data"));
+        }
+        let len = buf.len().min(inner.rx.len());
+        for (dst, src) in buf.iter_mut().zip(inner.rx.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.lock();
+        let buf = match inner.tx_capacity {
+            Some(0) if !buf.is_empty() => {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "TX buffer full"))
+            }
+            Some(capacity) => {
+                let len = capacity.min(buf.len());
+                inner.tx_capacity = Some(capacity - len);
+                &buf[..len]
+            }
+            None => buf,
+        };
+        inner.written.extend_from_slice(buf);
+        if let Some(reply) = inner.replies.pop_front() {
+            inner.rx.extend(reply);
+        }
+        inner.run_script(buf);
+        if let Some(Responder(responder)) = inner.responder.as_mut() {
+            let reply = responder(buf);
+            inner.rx.extend(reply);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SabertoothSerial for MockPort {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.lock().timeout = timeout;
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.lock().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        self.lock().baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(self.lock().baud_rate)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        self.lock().rx.clear();
+        Ok(())
+    }
+}
+
+/// One step of a transcript: an operation, the frame it must emit, and for
+/// getters the reply of the device and the expected returned value.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Step {
+    pub operation: Operation,
+    pub expected_tx: Vec<u8>,
+    pub reply: Vec<u8>,
+    pub expected_value: Option<f32>,
+}
+
+impl Step {
+    /// Step for an operation which only writes.
+    pub fn set(operation: Operation, expected_tx: &[u8]) -> Step {
+        Step {
+            operation,
+            expected_tx: expected_tx.to_vec(),
+            reply: Vec::new(),
+            expected_value: None,
+        }
+    }
+
+    /// Step for a getter.
+    pub fn get(operation: Operation, expected_tx: &[u8], reply: &[u8], value: f32) -> Step {
+        Step {
+            operation,
+            expected_tx: expected_tx.to_vec(),
+            reply: reply.to_vec(),
+            expected_value: Some(value),
+        }
+    }
+}
+
+/// Failure of a conformance check.
+#[derive(Debug)]
+pub struct ConformanceError {
+    /// Index of the failed step in the transcript.
+    pub step: usize,
+
+    /// Operation of the failed step.
+    pub operation: Operation,
+
+    /// Description of the failure.
+    pub reason: String,
+}
+
+impl fmt::Display for ConformanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "step {} ({:?}): {}",
+            self.step, self.operation, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ConformanceError {}
+
+/// Replay *transcript* on *saber* and verify that every step emits exactly the
+/// expected frame and returns the expected value. *tap* gives access to the
+/// traffic of *saber*.
+pub fn check_conformance<S, P>(
+    saber: &mut S,
+    tap: &mut P,
+    transcript: &[Step],
+) -> std::result::Result<(), ConformanceError>
+where
+    S: Sabertooth2x32 + ?Sized,
+    P: FrameTap + ?Sized,
+{
+    for (i, step) in transcript.iter().enumerate() {
+        let fail = |reason: String| ConformanceError {
+            step: i,
+            operation: step.operation,
+            reason,
+        };
+
+        tap.take_written();
+        if !step.reply.is_empty() {
+            tap.queue_reply(&step.reply);
+        }
+
+        let value = step
+            .operation
+            .apply(saber)
+            .map_err(|e| fail(format!("operation failed: {}", e)))?;
+
+        let written = tap.take_written();
+        if written != step.expected_tx {
+            return Err(fail(format!(
+                "expected frame {:02x?} but {:02x?} was written",
+                step.expected_tx, written
+            )));
+        }
+
+        match (step.expected_value, value) {
+            (Some(expected), Some(v)) if (expected - v).abs() > VALUE_TOLERANCE => {
+                return Err(fail(format!("expected value {} but got {}", expected, v)));
+            }
+            (Some(expected), None) => {
+                return Err(fail(format!("expected value {} but got none", expected)));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Golden transcript of `PacketSerial` with checksum frames, at the default
+/// address 128.
+#[rustfmt::skip]
+pub fn golden_checksum() -> Vec<Step> {
+    use Operation::*;
+    vec![
+        Step::set(Startup(1), b"\x80\x28\x20\x48\x00\x00\x4d\x31\x7e"),
+        Step::set(Shutdown(2), b"\x80\x28\x20\x48\x01\x00\x4d\x32\x00"),
+        Step::set(SetSpeed(1, -1.0), b"\x80\x28\x01\x29\x7f\x0f\x4d\x31\x0c"),
+        Step::set(SetSpeed(2, 0.5), b"\x80\x28\x00\x28\x7f\x07\x4d\x32\x05"),
+        Step::set(SetDrive(-0.5), b"\x80\x28\x01\x29\x7f\x07\x4d\x44\x17"),
+        Step::set(SetTurn(0.25), b"\x80\x28\x00\x28\x7f\x03\x4d\x54\x23"),
+        Step::set(SetPower(1, -1.0), b"\x80\x28\x01\x29\x7f\x0f\x50\x31\x0f"),
+        Step::set(SetRamp(1, 0.25), b"\x80\x28\x00\x28\x7f\x03\x52\x31\x05"),
+        Step::set(SetAux(2, 0.5), b"\x80\x28\x00\x28\x7f\x07\x51\x32\x09"),
+        Step::get(GetSpeed(2), b"\x80\x29\x00\x29\x4d\x32\x7f", b"\x80\x49\x01\x4a\x2e\x08\x4d\x32\x35", -0.522_716),
+        Step::get(GetPower(1), b"\x80\x29\x00\x29\x50\x31\x01", b"\x80\x49\x01\x4a\x68\x07\x50\x31\x70", -0.488_52),
+        Step::get(GetVoltage(1), b"\x80\x29\x10\x39\x4d\x31\x7e", b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76", 12.0),
+        Step::get(GetCurrent(1), b"\x80\x29\x20\x49\x4d\x31\x7e", b"\x80\x49\x21\x6a\x02\x00\x4d\x31\x00", -2.0),
+        Step::get(GetTemperature(2), b"\x80\x29\x40\x69\x4d\x32\x7f", b"\x80\x49\x40\x09\x1d\x00\x4d\x32\x1c", 29.0),
+    ]
+}
+
+/// Golden transcript of `PacketSerial` with CRC frames, at the default
+/// address 128.
+#[rustfmt::skip]
+pub fn golden_crc() -> Vec<Step> {
+    use Operation::*;
+    vec![
+        Step::set(Startup(1), b"\xf0\x28\x20\x67\x00\x00\x4d\x31\x66\x5c"),
+        Step::set(Shutdown(2), b"\xf0\x28\x20\x67\x01\x00\x4d\x32\x49\x32"),
+        Step::set(SetSpeed(1, -1.0), b"\xf0\x28\x01\x20\x7f\x0f\x4d\x31\x51\x3b"),
+        Step::set(SetSpeed(2, 0.5), b"\xf0\x28\x00\x0c\x7f\x07\x4d\x32\x65\x6c"),
+        Step::set(SetDrive(-0.5), b"\xf0\x28\x01\x20\x7f\x07\x4d\x44\x1b\x76"),
+        Step::set(SetTurn(0.25), b"\xf0\x28\x00\x0c\x7f\x03\x4d\x54\x26\x5d"),
+        Step::set(SetPower(1, -1.0), b"\xf0\x28\x01\x20\x7f\x0f\x50\x31\x6e\x1a"),
+        Step::set(SetRamp(1, 0.25), b"\xf0\x28\x00\x0c\x7f\x03\x52\x31\x0a\x6e"),
+        Step::set(SetAux(2, 0.5), b"\xf0\x28\x00\x0c\x7f\x07\x51\x32\x0a\x00"),
+        Step::get(GetSpeed(2), b"\xf0\x29\x00\x6d\x4d\x32\x74\x34", b"\xf0\x49\x01\x39\x2e\x08\x4d\x32\x6f\x50", -0.522_716),
+        Step::get(GetPower(1), b"\xf0\x29\x00\x6d\x50\x31\x39\x05", b"\xf0\x49\x01\x39\x68\x07\x50\x31\x06\x35", -0.488_52),
+        Step::get(GetVoltage(1), b"\xf0\x29\x10\x2e\x4d\x31\x06\x24", b"\xf0\x49\x10\x56\x78\x00\x4d\x31\x54\x0a", 12.0),
+        Step::get(GetCurrent(1), b"\xf0\x29\x20\x06\x4d\x31\x06\x24", b"\xf0\x49\x21\x52\x02\x00\x4d\x31\x3d\x2a", -2.0),
+        Step::get(GetTemperature(2), b"\xf0\x29\x40\x56\x4d\x32\x74\x34", b"\xf0\x49\x40\x2e\x1d\x00\x4d\x32\x2e\x14", 29.0),
+    ]
+}
+
+/// Golden transcript of `PlainText`.
+#[rustfmt::skip]
+pub fn golden_plain_text() -> Vec<Step> {
+    use Operation::*;
+    vec![
+        Step::set(Startup(1), b"M1: startup\r\n"),
+        Step::set(Shutdown(2), b"M2: shutdown\r\n"),
+        Step::set(SetSpeed(1, -1.0), b"M1: -2047\r\n"),
+        Step::set(SetSpeed(2, 0.5), b"M2: 1023\r\n"),
+        Step::set(SetDrive(-0.5), b"MD: -1023\r\n"),
+        Step::set(SetTurn(0.25), b"MT: 511\r\n"),
+        Step::set(SetPower(1, -1.0), b"P1: -2047\r\n"),
+        Step::set(SetRamp(1, 0.25), b"R1: 511\r\n"),
+        Step::set(SetAux(2, 0.5), b"Q2: 1023\r\n"),
+        Step::get(GetSpeed(1), b"M1: get\r\n", b"M1: 1256\r\n", 0.613_58),
+        Step::get(GetPower(1), b"P1: get\r\n", b"P1: -1000\r\n", -0.488_52),
+        Step::get(GetVoltage(2), b"M2: getb\r\n", b"M2:B240\r\n", 24.0),
+        Step::get(GetCurrent(2), b"M2: getc\r\n", b"M2:C-20\r\n", -2.0),
+        Step::get(GetTemperature(1), b"M1: gett\r\n", b"M1: T30\r\n", 30.0),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::{PacketSerial, PacketType, PlainText, Sabertooth2x32};
+
+    #[test]
+    fn test_conformance_packet() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+        check_conformance(&mut saber, &mut port, &golden_checksum()).unwrap();
+
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::CRC);
+        check_conformance(&mut saber, &mut port, &golden_crc()).unwrap();
+    }
+
+    #[test]
+    fn test_conformance_text() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        check_conformance(&mut saber, &mut port, &golden_plain_text()).unwrap();
+    }
+
+    #[test]
+    fn test_script_delay() {
+        let get_voltage = b"\x80\x29\x10\x39\x4d\x31\x7e";
+        let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+        let mut port = MockPort::with_script(
+            Script::new()
+                .step(
+                    ScriptStep::expect(get_voltage)
+                        .respond(reply)
+                        .with_delay(Duration::from_millis(5)),
+                )
+                .step(
+                    ScriptStep::expect(get_voltage)
+                        .respond(reply)
+                        .with_corruption(Corruption::Garbage(b"\x00\x01".to_vec()))
+                        .with_delay(Duration::from_millis(50)),
+                ),
+        );
+        port.set_timeout(Duration::from_millis(20)).unwrap();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+        // Delayed within the timeout
+        assert_eq!(12.0, saber.get_voltage(1).unwrap());
+
+        // Delayed beyond the timeout
+        saber.get_voltage(1).unwrap_err();
+        port.check_script().unwrap();
+    }
+
+    #[test]
+    fn test_script_mismatch() {
+        let port = MockPort::with_script(Script::new().step(ScriptStep::expect(b"M1: 0\r\n")));
+        let mut saber = PlainText::from(&port);
+        saber.set_speed(2, 0.0).unwrap();
+        port.check_script().unwrap_err();
+    }
+
+    #[test]
+    fn test_responder() {
+        let port = MockPort::with_responder(|frame| match frame {
+            b"M1: getb\r\n" => b"M1:B240\r\n".to_vec(),
+            _ => Vec::new(),
+        });
+        let mut saber = PlainText::from(&port);
+        saber.set_speed(1, 0.0).unwrap();
+        assert_eq!(24.0, saber.get_voltage(1).unwrap());
+        assert_eq!(24.0, saber.get_voltage(1).unwrap());
+    }
+
+    #[test]
+    fn test_conformance_mismatch() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_address(129);
+        let err = check_conformance(&mut saber, &mut port, &golden_crc()).unwrap_err();
+        assert_eq!(0, err.step);
+    }
+
+    #[test]
+    fn test_conformance_missing_value() {
+        let mut port = MockPort::new();
+        let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+        let mut transcript = golden_checksum();
+        transcript[0].expected_value = Some(1.0);
+        let err = check_conformance(&mut saber, &mut port, &transcript).unwrap_err();
+        assert_eq!(0, err.step);
+        assert_eq!("expected value 1 but got none", err.reason);
+    }
+}
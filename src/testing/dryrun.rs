@@ -0,0 +1,540 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::command_table::Protocol;
+use crate::sabertooth2x32::commands::Command;
+use crate::sabertooth2x32::{encode_reply, Operation, PacketType};
+use crate::telemetry::Quantity;
+use crate::{DeviceProfile, Result, SabertoothSerial};
+
+use super::TelemetryModel;
+
+/// Raw value of a full scale ratio.
+const FULL_SCALE: f32 = 2047.0;
+
+/// Current drawn by an emulated motor at full power, in amperes.
+const FULL_POWER_CURRENT: f32 = 20.0;
+
+/// Battery voltage of the emulated devices, in volts.
+const BATTERY_VOLTAGE: f32 = 24.0;
+
+/// Temperature of the emulated devices, in degrees Celsius.
+const TEMPERATURE: f32 = 30.0;
+
+const CMD_NUM_SET: u8 = 40;
+const CMD_NUM_GET: u8 = 41;
+
+/// Key of the device answering "Plain Text", which has no address.
+const TEXT_DEVICE: u8 = 0;
+
+/// Bytes written in one call by the handle, and the reply synthesized for
+/// them by a [DryRun](struct.DryRun.html).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Exchange {
+    /// Bytes written by the handle.
+    pub tx: Vec<u8>,
+
+    /// Reply of the emulated device, empty for the set commands.
+    pub rx: Vec<u8>,
+}
+
+/// State of an emulated Sabertooth 2x32.
+#[derive(Clone, Copy, Debug, Default)]
+struct Device {
+    speed: [f32; 2],
+    power: [f32; 2],
+    shutdown: [bool; 2],
+    drive: f32,
+    turn: f32,
+    heat: f32,
+    discharged: f32,
+}
+
+impl Device {
+    fn set(&mut self, kind: u8, target: u8, ratio: f32) {
+        let ratio = ratio.clamp(-1.0, 1.0);
+        match (kind, target) {
+            (b'M', b'D') => self.drive = ratio,
+            (b'M', b'T') => self.turn = ratio,
+            (b'M', _) => {
+                if let Some(channel) = channel(target) {
+                    self.speed[channel] = ratio;
+                    self.power[channel] = ratio;
+                }
+                return;
+            }
+            (b'P', _) => {
+                if let Some(channel) = channel(target) {
+                    self.power[channel] = ratio;
+                }
+                return;
+            }
+            _ => return,
+        }
+        self.speed = [
+            (self.drive + self.turn).clamp(-1.0, 1.0),
+            (self.drive - self.turn).clamp(-1.0, 1.0),
+        ];
+        self.power = self.speed;
+    }
+
+    /// Advance the drifts of *model* by one step, under the mean power of
+    /// the channels.
+    fn step(&mut self, model: Option<&TelemetryModel>) {
+        if let Some(model) = model {
+            let load = (0..2).map(|ch| self.output(ch).abs()).sum::<f32>() / 2.0;
+            model.step(load, &mut self.heat, &mut self.discharged);
+        }
+    }
+
+    /// Power output on *channel*, null when it is shut down.
+    fn output(&self, channel: usize) -> f32 {
+        if self.shutdown[channel] {
+            0.0
+        } else {
+            self.power[channel]
+        }
+    }
+
+    fn set_shutdown(&mut self, target: u8, shutdown: bool) {
+        if let Some(channel) = channel(target) {
+            self.shutdown[channel] = shutdown;
+        }
+    }
+
+    /// Raw value of *quantity* on *channel*, as sent in the replies of
+    /// *protocol*, with *noise* added to the measures.
+    fn read(
+        &self,
+        quantity: Quantity,
+        channel: usize,
+        profile: &DeviceProfile,
+        protocol: Protocol,
+        noise: f32,
+    ) -> i32 {
+        let power = self.output(channel);
+        let (operation, value) = match quantity {
+            Quantity::Speed => (Operation::GetSpeed(channel + 1), self.speed[channel]),
+            Quantity::Power => (Operation::GetPower(channel + 1), power),
+            Quantity::Voltage => {
+                let (min, max) = profile.voltage_range();
+                let voltage = (BATTERY_VOLTAGE - self.discharged).max(min).min(max);
+                (Operation::GetVoltage(channel + 1), voltage + noise)
+            }
+            Quantity::Current => {
+                let current = (power.abs() * FULL_POWER_CURRENT).min(profile.peak_current());
+                (
+                    Operation::GetCurrent(channel + 1),
+                    current.copysign(power) + noise,
+                )
+            }
+            Quantity::Temperature => {
+                let temperature = TEMPERATURE + self.heat + noise;
+                (Operation::GetTemperature(channel + 1), temperature)
+            }
+        };
+        let (command, _, _) = Command::from_operation(operation);
+        command.spec().unscale(value, protocol)
+    }
+}
+
+/// Index of the channel targeted by *target*, '1' or '2'.
+fn channel(target: u8) -> Option<usize> {
+    match target {
+        b'1' => Some(0),
+        b'2' => Some(1),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct DryRunInner {
+    devices: HashMap<u8, Device>,
+    profile: DeviceProfile,
+    model: Option<TelemetryModel>,
+    pending: Vec<u8>,
+    rx: VecDeque<u8>,
+    exchanges: Vec<Exchange>,
+    timeout: Duration,
+    baud_rate: u32,
+}
+
+impl DryRunInner {
+    /// Process the complete frames and lines written so far, and return the
+    /// replies.
+    fn process(&mut self) -> Vec<u8> {
+        let mut reply = Vec::new();
+        while let Some(&start) = self.pending.first() {
+            let processed = if start & 0x80 != 0 {
+                self.process_packet(&mut reply)
+            } else {
+                self.process_line(&mut reply)
+            };
+            if !processed {
+                break;
+            }
+        }
+        reply
+    }
+
+    /// Process the "Packet Serial" frame at the start of the pending bytes.
+    /// Return false if it is not complete yet.
+    fn process_packet(&mut self, reply: &mut Vec<u8>) -> bool {
+        let (packet_type, address) = PacketType::from_address_byte(self.pending[0]);
+        let (set_size, get_size, _) = packet_type.frame_sizes();
+        let size = match self.pending.get(1) {
+            None => return false,
+            Some(&CMD_NUM_SET) => set_size,
+            Some(&CMD_NUM_GET) => get_size,
+            Some(_) => {
+                self.pending.remove(0);
+                return true;
+            }
+        };
+        if self.pending.len() < size {
+            return false;
+        }
+        let frame: Vec<u8> = self.pending.drain(..size).collect();
+
+        let opcode = frame[2] & !1;
+        let device = self.devices.entry(address).or_default();
+        device.step(self.model.as_ref());
+        if frame[1] == CMD_NUM_SET {
+            let raw = u16::from(frame[4]) | u16::from(frame[5]) << 7;
+            let mut ratio = f32::from(raw) / FULL_SCALE;
+            if frame[2] & 1 != 0 {
+                ratio = -ratio;
+            }
+            match opcode {
+                0x00 => device.set(frame[6], frame[7], ratio),
+                0x20 => device.set_shutdown(frame[7], raw != 0),
+                _ => {}
+            }
+            return true;
+        }
+
+        let source = [frame[4], frame[5]];
+        let quantity = match (opcode, source[0]) {
+            (0x00, b'M') => Quantity::Speed,
+            (0x00, b'P') => Quantity::Power,
+            (0x10, _) => Quantity::Voltage,
+            (0x20, _) => Quantity::Current,
+            (0x40, _) => Quantity::Temperature,
+            _ => return true,
+        };
+        if let Some(channel) = channel(source[1]) {
+            let noise = self.model.as_mut().map_or(0.0, |m| m.noise(quantity));
+            let value = device.read(
+                quantity,
+                channel,
+                &self.profile,
+                Protocol::Packetized,
+                noise,
+            );
+            if let Ok(frame) = encode_reply(packet_type, address, opcode, value, source) {
+                reply.extend(frame);
+            }
+        }
+        true
+    }
+
+    /// Process the "Plain Text" line at the start of the pending bytes.
+    /// Return false if it is not complete yet.
+    fn process_line(&mut self, reply: &mut Vec<u8>) -> bool {
+        let end = match self.pending.iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None => return false,
+        };
+        let line: Vec<u8> = self.pending.drain(..=end).collect();
+        let line = String::from_utf8_lossy(&line);
+        if let Some(text) = self.answer_line(line.trim()) {
+            reply.extend(text.bytes());
+        }
+        true
+    }
+
+    fn answer_line(&mut self, line: &str) -> Option<String> {
+        let (key, arg) = line.split_once(':')?;
+        let (kind, target) = match key.as_bytes() {
+            &[kind, target] => (kind, target),
+            _ => return None,
+        };
+        let device = self.devices.entry(TEXT_DEVICE).or_default();
+        device.step(self.model.as_ref());
+        let quantity = match arg.trim() {
+            "startup" => {
+                device.set_shutdown(target, false);
+                return None;
+            }
+            "shutdown" => {
+                device.set_shutdown(target, true);
+                return None;
+            }
+            "get" if kind == b'P' => Quantity::Power,
+            "get" => Quantity::Speed,
+            "getb" => Quantity::Voltage,
+            "getc" => Quantity::Current,
+            "gett" => Quantity::Temperature,
+            value => {
+                let raw: i32 = value.parse().ok()?;
+                device.set(kind, target, raw as f32 / FULL_SCALE);
+                return None;
+            }
+        };
+        let channel = channel(target)?;
+        let noise = self.model.as_mut().map_or(0.0, |m| m.noise(quantity));
+        let value = device.read(quantity, channel, &self.profile, Protocol::PlainText, noise);
+        let text = match quantity {
+            Quantity::Speed | Quantity::Power => format!("{}: {}\r\n", key, value),
+            Quantity::Voltage => format!("{}:B{}\r\n", key, value),
+            Quantity::Current => format!("{}:C{}\r\n", key, value),
+            Quantity::Temperature => format!("{}: T{}\r\n", key, value),
+        };
+        Some(text)
+    }
+}
+
+/// Port emulating Sabertooth 2x32 devices, for running the full software
+/// stack without hardware, in rehearsals and demos.
+///
+/// The port accepts every write, and answers the get requests of both
+/// protocols with plausible values: the speeds and powers last commanded, a
+/// current proportional to the power within the peak current of the
+/// profile, a 24 V battery and a 30 °C temperature, to which a
+/// [TelemetryModel] can add noise and drifts. "Packet Serial" frames
+/// of both packet types are answered at any address. Every write is logged
+/// at the info level and recorded as an [Exchange] with its reply.
+///
+/// It is given to a handle like any port, or opened with
+/// `PacketSerial::dry_run()` and `PlainText::dry_run()`. A dry run is never
+/// selected by a port name, so that a configuration error cannot silently
+/// disconnect an application from its hardware.
+///
+/// Clones share the same devices and records. Reading with no reply pending
+/// fails immediately with `io::ErrorKind::TimedOut`.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+/// use saberrs::testing::DryRun;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let port = DryRun::new();
+/// let mut saber = PacketSerial::from(&port);
+///
+/// saber.set_speed(1, 0.5)?;
+/// assert_eq!(10.0, saber.get_current(1)?);
+/// assert_eq!(2, port.exchanges().len());
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+///
+/// [Exchange]: struct.Exchange.html
+/// [TelemetryModel]: struct.TelemetryModel.html
+#[derive(Clone, Debug)]
+pub struct DryRun {
+    inner: Arc<Mutex<DryRunInner>>,
+}
+
+impl DryRun {
+    /// Create a port emulating devices with the ratings of the Sabertooth
+    /// 2x32.
+    pub fn new() -> DryRun {
+        DryRun {
+            inner: Arc::new(Mutex::new(DryRunInner {
+                devices: HashMap::new(),
+                profile: DeviceProfile::sabertooth_2x32(),
+                model: None,
+                pending: Vec::new(),
+                rx: VecDeque::new(),
+                exchanges: Vec::new(),
+                timeout: Duration::from_millis(100),
+                baud_rate: 9600,
+            })),
+        }
+    }
+
+    /// Emulate devices with the ratings of *profile*.
+    pub fn with_profile(self, profile: DeviceProfile) -> Self {
+        self.lock().profile = profile;
+        self
+    }
+
+    /// Add the noise and drifts of *model* to the telemetry, see
+    /// [TelemetryModel](struct.TelemetryModel.html).
+    pub fn with_model(self, model: TelemetryModel) -> Self {
+        self.lock().model = Some(model);
+        self
+    }
+
+    /// Return a copy of the exchanges recorded since the last call to
+    /// [take_exchanges()](#method.take_exchanges).
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.lock().exchanges.clone()
+    }
+
+    /// Remove and return the exchanges recorded so far.
+    pub fn take_exchanges(&self) -> Vec<Exchange> {
+        std::mem::take(&mut self.lock().exchanges)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DryRunInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for DryRun {
+    fn default() -> Self {
+        DryRun::new()
+    }
+}
+
+impl io::Read for DryRun {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut inner = self.lock();
+        if inner.rx.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no reply"));
+        }
+        let len = buf.len().min(inner.rx.len());
+        for (dst, src) in buf.iter_mut().zip(inner.rx.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for DryRun {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.lock();
+        inner.pending.extend_from_slice(buf);
+        let reply = inner.process();
+        log_info!("dry run: tx = {:02x?}, rx = {:02x?}", buf, reply);
+        inner.rx.extend(&reply);
+        inner.exchanges.push(Exchange {
+            tx: buf.to_vec(),
+            rx: reply,
+        });
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SabertoothSerial for DryRun {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.lock().timeout = timeout;
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.lock().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        self.lock().baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(self.lock().baud_rate)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        self.lock().rx.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::{PacketSerial, PlainText, Sabertooth2x32};
+
+    #[test]
+    fn test_packet_serial() {
+        let port = DryRun::new();
+        for packet_type in [PacketType::Checksum, PacketType::CRC].iter().copied() {
+            let mut saber = PacketSerial::from(&port)
+                .with_address(130)
+                .with_packet_type(packet_type);
+            saber.set_drive(0.5).unwrap();
+            saber.set_turn(0.25).unwrap();
+            assert!((saber.get_speed(1).unwrap() - 0.75).abs() < 0.001);
+            saber.set_power(2, -1.0).unwrap();
+            assert_eq!(-20.0, saber.get_current(2).unwrap());
+            saber.shutdown(2).unwrap();
+            assert_eq!(0.0, saber.get_output_power(2).unwrap());
+            assert_eq!(24.0, saber.get_voltage(1).unwrap());
+            assert_eq!(30.0, saber.get_temperature(1).unwrap());
+            saber.startup(2).unwrap();
+        }
+        // Devices at other addresses are independent
+        let mut other = PacketSerial::from(&port);
+        assert_eq!(0.0, other.get_speed(1).unwrap());
+
+        let exchanges = port.take_exchanges();
+        assert_eq!(
+            b"\x82\x28\x00\x2a\x7f\x07\x4d\x44\x17",
+            &exchanges[0].tx[..]
+        );
+        assert!(exchanges[0].rx.is_empty());
+        assert!(!exchanges[2].rx.is_empty());
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let port = DryRun::new().with_profile(DeviceProfile::new("x").with_current(5.0, 8.0));
+        let mut saber = PlainText::from(&port);
+        saber.set_speed(1, -1.0).unwrap();
+        assert_eq!(-1.0, saber.get_commanded_speed(1).unwrap());
+        assert_eq!(-8.0, saber.get_current(1).unwrap());
+        assert_eq!(24.0, saber.get_voltage(1).unwrap());
+        assert_eq!(30.0, saber.get_temperature(2).unwrap());
+        assert_eq!(
+            port.exchanges()[1],
+            Exchange {
+                tx: b"M1: get\r\n".to_vec(),
+                rx: b"M1: -2047\r\n".to_vec()
+            }
+        );
+    }
+
+    #[test]
+    fn test_model() {
+        let run = |seed| {
+            let model = TelemetryModel::new(seed)
+                .with_voltage_noise(0.2)
+                .with_discharge(0.01);
+            let mut saber = PacketSerial::from(DryRun::new().with_model(model));
+            saber.set_power(1, 1.0).unwrap();
+            saber.set_power(2, 1.0).unwrap();
+            (0..200)
+                .map(|_| saber.get_voltage(1).unwrap())
+                .collect::<Vec<f32>>()
+        };
+        let voltages = run(1);
+        assert_eq!(voltages, run(1));
+        assert_ne!(voltages, run(2));
+        assert!((voltages[0] - 24.0).abs() < 0.3);
+        assert!((voltages[199] - 22.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let mut saber = PacketSerial::dry_run();
+        saber.set_speed(2, 0.5).unwrap();
+        assert!((saber.get_power(2).unwrap() - 0.5).abs() < 0.001);
+
+        let mut saber = PlainText::dry_run();
+        assert_eq!(24.0, saber.get_voltage(1).unwrap());
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+/// Fate of a reply given by a [FaultInjector](trait.FaultInjector.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplyFault {
+    /// The reply is processed normally, possibly altered.
+    Deliver,
+
+    /// The reply is lost, as if it was never received.
+    Drop,
+}
+
+/// Fault-injection points inside the transactions of a handle, installed
+/// with `with_fault_injector()` on `PacketSerial` and `PlainText`.
+///
+/// Unlike a faulty port, the injector acts on whole frames at the layer
+/// where the retries, the resynchronization and the validation happen, so
+/// their handling of a given fault can be tested deterministically. The
+/// default methods inject nothing. [FaultPlan] injects faults at given
+/// frame numbers.
+///
+/// [FaultPlan]: struct.FaultPlan.html
+pub trait FaultInjector: Send {
+    /// Called before writing *frame*. Return a delay to wait before the
+    /// write.
+    fn delay_write(&mut self, frame: &[u8]) -> Option<Duration> {
+        let _ = frame;
+        None
+    }
+
+    /// Called with each complete *reply*, before it is validated. Its bytes
+    /// can be altered, for example for breaking its checksum or CRC.
+    fn on_reply(&mut self, reply: &mut [u8]) -> ReplyFault {
+        let _ = reply;
+        ReplyFault::Deliver
+    }
+}
+
+/// [FaultInjector](trait.FaultInjector.html) injecting faults at given frame
+/// numbers, counted from 0 separately for the writes and the replies.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{PacketSerial, PacketType, Sabertooth2x32};
+/// use saberrs::testing::{FaultPlan, FrameTap, MockPort};
+///
+/// let reply = b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76";
+/// let mut port = MockPort::new();
+/// let mut saber = PacketSerial::from(&port)
+///     .with_packet_type(PacketType::Checksum)
+///     .with_fault_injector(FaultPlan::new().drop_reply(0).corrupt_reply(1, 8));
+///
+/// for _ in 0..3 {
+///     port.queue_reply(reply);
+/// }
+/// assert!(saber.get_voltage(1).is_err()); // dropped
+/// assert!(saber.get_voltage(1).is_err()); // invalid checksum
+/// assert_eq!(12.0, saber.get_voltage(1).unwrap());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FaultPlan {
+    writes: u64,
+    replies: u64,
+    delays: BTreeMap<u64, Duration>,
+    drops: BTreeSet<u64>,
+    corruptions: BTreeMap<u64, usize>,
+}
+
+impl FaultPlan {
+    /// Create a plan injecting nothing.
+    pub fn new() -> FaultPlan {
+        FaultPlan::default()
+    }
+
+    /// Delay the write *n* by *delay*.
+    pub fn delay_write(mut self, n: u64, delay: Duration) -> Self {
+        self.delays.insert(n, delay);
+        self
+    }
+
+    /// Lose the reply *n*.
+    pub fn drop_reply(mut self, n: u64) -> Self {
+        self.drops.insert(n);
+        self
+    }
+
+    /// Invert the bits of the byte at *index* of the reply *n*, which breaks
+    /// the checksum or CRC of a packet.
+    pub fn corrupt_reply(mut self, n: u64, index: usize) -> Self {
+        self.corruptions.insert(n, index);
+        self
+    }
+}
+
+impl FaultInjector for FaultPlan {
+    fn delay_write(&mut self, _frame: &[u8]) -> Option<Duration> {
+        self.writes += 1;
+        self.delays.get(&(self.writes - 1)).cloned()
+    }
+
+    fn on_reply(&mut self, reply: &mut [u8]) -> ReplyFault {
+        let n = self.replies;
+        self.replies += 1;
+        if let Some(&index) = self.corruptions.get(&n) {
+            if let Some(b) = reply.get_mut(index) {
+                *b = !*b;
+            }
+        }
+        match self.drops.contains(&n) {
+            true => ReplyFault::Drop,
+            false => ReplyFault::Deliver,
+        }
+    }
+}
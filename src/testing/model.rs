@@ -0,0 +1,163 @@
+use crate::telemetry::Quantity;
+
+/// Seedable model of the telemetry noise and drifts of a
+/// [DryRun](struct.DryRun.html), installed with `DryRun::with_model()`.
+///
+/// The noise is a uniform pseudo-random offset within the given amplitude,
+/// added to the voltage, current and temperature replies. The drifts are
+/// the temperature rising under load and cooling back to its ambient value,
+/// and the battery discharging under load. They advance by one step for
+/// every command received by a device, so a scenario depends on the traffic
+/// only, not on the timing, and the same seed and traffic always give the
+/// same replies. Nothing is added by default.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+/// use saberrs::testing::{DryRun, TelemetryModel};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let model = TelemetryModel::new(42)
+///     .with_temperature_noise(0.5)
+///     .with_heating(0.05, 0.001);
+/// let mut saber = PlainText::from(DryRun::new().with_model(model));
+///
+/// saber.set_speed(1, 1.0)?;
+/// for _ in 0..1000 {
+///     saber.get_temperature(1)?;
+/// }
+/// assert!(saber.get_temperature(1)? > 40.0);
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TelemetryModel {
+    state: u64,
+    voltage_noise: f32,
+    current_noise: f32,
+    temperature_noise: f32,
+    heating: f32,
+    cooling: f32,
+    discharge: f32,
+}
+
+impl TelemetryModel {
+    /// Create a model drawing its noise from *seed*, without noise nor
+    /// drift.
+    pub fn new(seed: u64) -> TelemetryModel {
+        TelemetryModel {
+            state: seed,
+            voltage_noise: 0.0,
+            current_noise: 0.0,
+            temperature_noise: 0.0,
+            heating: 0.0,
+            cooling: 0.0,
+            discharge: 0.0,
+        }
+    }
+
+    /// Add noise within ±*amplitude* volts to the voltage.
+    pub fn with_voltage_noise(mut self, amplitude: f32) -> Self {
+        self.voltage_noise = amplitude.abs();
+        self
+    }
+
+    /// Add noise within ±*amplitude* amperes to the current.
+    pub fn with_current_noise(mut self, amplitude: f32) -> Self {
+        self.current_noise = amplitude.abs();
+        self
+    }
+
+    /// Add noise within ±*amplitude* degrees Celsius to the temperature.
+    pub fn with_temperature_noise(mut self, amplitude: f32) -> Self {
+        self.temperature_noise = amplitude.abs();
+        self
+    }
+
+    /// Raise the temperature by *heating* degrees Celsius per step at full
+    /// load, and bring it back to the ambient temperature by the fraction
+    /// *cooling* of the difference per step. Under a constant load the
+    /// temperature settles at `load * heating / cooling` above the ambient.
+    pub fn with_heating(mut self, heating: f32, cooling: f32) -> Self {
+        self.heating = heating.max(0.0);
+        self.cooling = cooling.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Lower the battery voltage by *discharge* volts per step at full load,
+    /// down to the minimum voltage of the profile.
+    pub fn with_discharge(mut self, discharge: f32) -> Self {
+        self.discharge = discharge.max(0.0);
+        self
+    }
+
+    /// Advance the drifts of a device by one step under *load*, from 0 to 1:
+    /// *heat* is its temperature above the ambient one, and *discharged* the
+    /// voltage lost by its battery.
+    pub(crate) fn step(&self, load: f32, heat: &mut f32, discharged: &mut f32) {
+        *heat += load * self.heating - *heat * self.cooling;
+        *discharged += load * self.discharge;
+    }
+
+    /// Draw the noise of *quantity*.
+    pub(crate) fn noise(&mut self, quantity: Quantity) -> f32 {
+        let amplitude = match quantity {
+            Quantity::Voltage => self.voltage_noise,
+            Quantity::Current => self.current_noise,
+            Quantity::Temperature => self.temperature_noise,
+            Quantity::Speed | Quantity::Power => return 0.0,
+        };
+        if amplitude == 0.0 {
+            return 0.0;
+        }
+        amplitude * (2.0 * self.next_unit() - 1.0)
+    }
+
+    /// Next pseudo-random number in [0, 1), from the SplitMix64 generator.
+    fn next_unit(&mut self) -> f32 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise() {
+        let mut model = TelemetryModel::new(7).with_current_noise(2.0);
+        let mut same = model.clone();
+        let mut other = TelemetryModel::new(8).with_current_noise(2.0);
+        let draws: Vec<f32> = (0..100).map(|_| model.noise(Quantity::Current)).collect();
+        assert!(draws.iter().all(|n| n.abs() <= 2.0));
+        assert!(draws.iter().any(|&n| n != draws[0]));
+        assert!(draws.iter().all(|&n| n == same.noise(Quantity::Current)));
+        assert_ne!(draws[0], other.noise(Quantity::Current));
+        assert_eq!(0.0, model.noise(Quantity::Voltage));
+    }
+
+    #[test]
+    fn test_drift() {
+        let model = TelemetryModel::new(0)
+            .with_heating(0.1, 0.01)
+            .with_discharge(0.01);
+        let (mut heat, mut discharged) = (0.0, 0.0);
+        for _ in 0..2000 {
+            model.step(0.5, &mut heat, &mut discharged);
+        }
+        assert!((heat - 5.0).abs() < 0.01);
+        assert!((discharged - 10.0).abs() < 0.01);
+        for _ in 0..2000 {
+            model.step(0.0, &mut heat, &mut discharged);
+        }
+        assert!(heat < 0.01);
+        assert!((discharged - 10.0).abs() < 0.01);
+    }
+}
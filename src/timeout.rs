@@ -0,0 +1,122 @@
+use std::io;
+use std::time::Duration;
+
+use crate::{Error, Result, Stats};
+
+/// Default ratio between the reply timeout and the round-trip latency.
+pub const DEFAULT_TIMEOUT_FACTOR: f32 = 3.0;
+
+/// Default number of round trips measured before tuning the timeout.
+pub const DEFAULT_MIN_SAMPLES: u64 = 8;
+
+/// Reply timeout tuned from the measured round trips, installed with
+/// `with_adaptive_timeout()` on the handles.
+///
+/// The latency of the USB serial adapters varies a lot between models and
+/// drivers, so a fixed timeout is either too long for the fast ones or too
+/// short for the slow ones. The adaptive timeout is the largest of the 95th
+/// percentile and the [smoothed round trip] of the handle, multiplied by the
+/// factor, and bounded by *min* and *max*.
+///
+/// Until the minimum number of round trips is measured, and for the request
+/// following a timeout, the timeout is *max*: a latency rising past the
+/// tuned timeout cannot be measured otherwise, since only the replies are.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::AdaptiveTimeout;
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let ms = Duration::from_millis;
+/// let saber = PacketSerial::new("/dev/ttyUSB0")?
+///     .with_adaptive_timeout(AdaptiveTimeout::new(ms(5), ms(200)).with_factor(2.0));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [smoothed round trip]: struct.Stats.html#method.smoothed_round_trip
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveTimeout {
+    min: Duration,
+    max: Duration,
+    factor: f32,
+    min_samples: u64,
+    relaxed: bool,
+}
+
+impl AdaptiveTimeout {
+    /// Create an adaptive timeout bounded by *min* and *max*, with the
+    /// default factor and minimum number of samples.
+    pub fn new(min: Duration, max: Duration) -> AdaptiveTimeout {
+        AdaptiveTimeout {
+            min: min.min(max),
+            max,
+            factor: DEFAULT_TIMEOUT_FACTOR,
+            min_samples: DEFAULT_MIN_SAMPLES,
+            relaxed: false,
+        }
+    }
+
+    /// Set the ratio between the timeout and the round-trip latency.
+    pub fn with_factor(mut self, factor: f32) -> Self {
+        self.factor = factor.max(1.0);
+        self
+    }
+
+    /// Set the number of round trips measured before tuning the timeout.
+    pub fn with_min_samples(mut self, min_samples: u64) -> Self {
+        self.min_samples = min_samples.max(1);
+        self
+    }
+
+    /// Timeout of the next reply, for the round trips of *stats*.
+    pub fn timeout(&self, stats: &Stats) -> Duration {
+        let summary = match stats.round_trip_latency() {
+            Some(summary) if !self.relaxed && summary.count >= self.min_samples => summary,
+            _ => return self.max,
+        };
+        let base = summary
+            .p95
+            .max(stats.smoothed_round_trip().unwrap_or_default());
+        base.mul_f64(f64::from(self.factor))
+            .clamp(self.min, self.max)
+    }
+
+    /// Record the outcome of a request: the timeout after a timeout is the
+    /// maximum one.
+    pub(crate) fn record<T>(&mut self, res: &Result<T>) {
+        self.relaxed = matches!(res, Err(Error::Io(e)) if e.kind() == io::ErrorKind::TimedOut);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::Quantity;
+
+    #[test]
+    fn test_adaptive_timeout() {
+        let ms = Duration::from_millis;
+        let mut stats = Stats::new();
+        let mut adaptive = AdaptiveTimeout::new(ms(5), ms(100)).with_min_samples(4);
+        assert_eq!(ms(100), adaptive.timeout(&stats));
+
+        for _ in 0..4 {
+            stats.record_round_trip(Quantity::Voltage, ms(10));
+        }
+        assert_eq!(ms(30), adaptive.timeout(&stats));
+        assert_eq!(ms(20), adaptive.with_factor(2.0).timeout(&stats));
+        assert_eq!(ms(100), adaptive.with_factor(20.0).timeout(&stats));
+        let fast = AdaptiveTimeout::new(ms(50), ms(100)).with_min_samples(4);
+        assert_eq!(ms(50), fast.timeout(&stats));
+
+        let timed_out: Result<()> = Err(io::Error::from(io::ErrorKind::TimedOut).into());
+        adaptive.record(&timed_out);
+        assert_eq!(ms(100), adaptive.timeout(&stats));
+        adaptive.record(&Ok(()));
+        assert_eq!(ms(30), adaptive.timeout(&stats));
+    }
+}
@@ -0,0 +1,200 @@
+use alloc::format;
+use core::fmt;
+
+use crate::error::{Error, Result};
+use crate::sabertooth2x32::Sabertooth2x32;
+
+/// Ratio between -1.0 and 1.0, as taken by the setters.
+///
+/// [Ratio::new()](#method.new) validates the range. Converting from a plain
+/// `f32` does not, for compatibility with the setters taking an `f32`: the
+/// value is then checked when it is sent.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{Percent, Ratio};
+///
+/// let half = Ratio::new(0.5).unwrap();
+/// assert_eq!(half, Ratio::from(Percent::new(50.0).unwrap()));
+/// assert!(Ratio::new(50.0).is_err());
+/// ```
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+pub struct Ratio(f32);
+
+impl Ratio {
+    /// Create a ratio, or return `Error::InvalidInput` if *ratio* is not
+    /// between -1.0 and 1.0.
+    pub fn new(ratio: f32) -> Result<Ratio> {
+        check_range(ratio, 1.0).map(Ratio)
+    }
+
+    /// Value of the ratio.
+    pub fn get(self) -> f32 {
+        self.0
+    }
+}
+
+/// Percentage between -100.0 and 100.0, converted to a [Ratio] by the
+/// setters.
+///
+/// [Ratio]: struct.Ratio.html
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+pub struct Percent(f32);
+
+impl Percent {
+    /// Create a percentage, or return `Error::InvalidInput` if *percent* is
+    /// not between -100.0 and 100.0.
+    pub fn new(percent: f32) -> Result<Percent> {
+        check_range(percent, 100.0).map(Percent)
+    }
+
+    /// Value of the percentage.
+    pub fn get(self) -> f32 {
+        self.0
+    }
+}
+
+fn check_range(value: f32, max: f32) -> Result<f32> {
+    if (-max..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(Error::InvalidInput(format!(
+            "value ({}) out of range -{}~{}",
+            value, max, max
+        )))
+    }
+}
+
+impl From<f32> for Ratio {
+    fn from(ratio: f32) -> Self {
+        Ratio(ratio)
+    }
+}
+
+impl From<Percent> for Ratio {
+    fn from(percent: Percent) -> Self {
+        Ratio(percent.0 / 100.0)
+    }
+}
+
+impl From<Ratio> for Percent {
+    fn from(ratio: Ratio) -> Self {
+        Percent(ratio.0 * 100.0)
+    }
+}
+
+impl From<Ratio> for f32 {
+    fn from(ratio: Ratio) -> Self {
+        ratio.0
+    }
+}
+
+impl fmt::Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)?;
+        f.write_str(" %")
+    }
+}
+
+/// Setters of [Sabertooth2x32] taking a [Ratio] or a [Percent], implemented
+/// for all its implementors.
+///
+/// # Example
+///
+#[cfg_attr(feature = "serialport", doc = " ```rust")]
+#[cfg_attr(not(feature = "serialport"), doc = " ```ignore")]
+/// use saberrs::{Percent, RatioSetters};
+/// use saberrs::sabertooth2x32::PacketSerial;
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyS0")?;
+/// saber.set_speed_to(1, Percent::new(50.0)?)?;
+/// saber.set_power_to(2, 0.25)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [Sabertooth2x32]: sabertooth2x32/trait.Sabertooth2x32.html
+/// [Ratio]: struct.Ratio.html
+/// [Percent]: struct.Percent.html
+pub trait RatioSetters {
+    /// Same as `set_speed()`.
+    fn set_speed_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()>;
+
+    /// Same as `set_drive()`.
+    fn set_drive_to<R: Into<Ratio>>(&mut self, value: R) -> Result<()>;
+
+    /// Same as `set_turn()`.
+    fn set_turn_to<R: Into<Ratio>>(&mut self, value: R) -> Result<()>;
+
+    /// Same as `set_power()`.
+    fn set_power_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()>;
+
+    /// Same as `set_ramp()`.
+    fn set_ramp_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()>;
+
+    /// Same as `set_aux()`.
+    fn set_aux_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()>;
+}
+
+impl<S: Sabertooth2x32 + ?Sized> RatioSetters for S {
+    fn set_speed_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()> {
+        self.set_speed(channel, value.into().get())
+    }
+
+    fn set_drive_to<R: Into<Ratio>>(&mut self, value: R) -> Result<()> {
+        self.set_drive(value.into().get())
+    }
+
+    fn set_turn_to<R: Into<Ratio>>(&mut self, value: R) -> Result<()> {
+        self.set_turn(value.into().get())
+    }
+
+    fn set_power_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()> {
+        self.set_power(channel, value.into().get())
+    }
+
+    fn set_ramp_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()> {
+        self.set_ramp(channel, value.into().get())
+    }
+
+    fn set_aux_to<R: Into<Ratio>>(&mut self, channel: usize, value: R) -> Result<()> {
+        self.set_aux(channel, value.into().get())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::PlainText;
+    use crate::testing::{FrameTap, MockPort};
+
+    #[test]
+    fn test_conversions() {
+        assert!(Ratio::new(1.0).is_ok());
+        assert!(Ratio::new(-1.01).is_err());
+        assert!(Ratio::new(f32::NAN).is_err());
+        assert!(Percent::new(-100.0).is_ok());
+        assert!(Percent::new(150.0).is_err());
+        assert_eq!(-0.25, Ratio::from(Percent::new(-25.0).unwrap()).get());
+        assert_eq!(Percent(50.0), Percent::from(Ratio(0.5)));
+        assert_eq!("50 %", Percent(50.0).to_string());
+    }
+
+    #[test]
+    fn test_setters() {
+        let mut port = MockPort::new();
+        let mut saber = PlainText::from(&port);
+        saber.set_speed_to(1, Percent::new(100.0).unwrap()).unwrap();
+        saber.set_speed_to(2, -1.0).unwrap();
+        assert!(saber.set_power_to(1, 50.0).is_err());
+        assert_eq!(port.take_written(), b"M1: 2047\r\nM2: -2047\r\n");
+    }
+}
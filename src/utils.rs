@@ -1,40 +1,490 @@
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+use alloc::format;
+
+#[cfg(feature = "std")]
+use crate::command_table::Protocol;
+#[cfg(any(feature = "std", feature = "embedded-io"))]
 use crate::error::{Error, Result};
+#[cfg(feature = "std")]
+use crate::port::{ReadStrategy, SabertoothSerial, WaitPoint, Yield};
+#[cfg(feature = "std")]
+use crate::protocol::scaling::Scaling;
+#[cfg(feature = "std")]
+use crate::sabertooth2x32::commands::Command;
+#[cfg(feature = "std")]
+use crate::sabertooth2x32::Operation;
+#[cfg(feature = "std")]
+use crate::stats::{LatencyAlert, Stats};
+#[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+use crate::testing::{FaultInjector, ReplyFault};
+#[cfg(feature = "std")]
+use crate::timeout::AdaptiveTimeout;
+
+pub const RANGE_MAX: i32 = crate::opcodes::sabertooth2x32::RANGE_MAX;
+
+/// Number of quiet periods after which `flush_until_quiet()` gives up.
+#[cfg(feature = "std")]
+pub const QUIET_MAX_WAIT: u32 = 20;
 
-pub const RANGE_MAX: i32 = 2047;
-pub const RANGE_MIN: i32 = -2047;
+/// Pause of `write_bounded()` when the port accepts no byte.
+#[cfg(feature = "std")]
+const WRITE_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Log a warning with `log`, mirrored with `defmt` when the feature is
+/// enabled.
+#[allow(unused_macros)]
+macro_rules! log_warn {
+    ($($arg:tt)+) => {{
+        log::warn!($($arg)+);
+        #[cfg(feature = "defmt")]
+        {
+            let msg = alloc::format!($($arg)+);
+            defmt::warn!("{=str}", msg.as_str());
+        }
+    }};
+}
 
+/// Log an information with `log`, mirrored with `defmt` when the feature is
+/// enabled.
+#[allow(unused_macros)]
+macro_rules! log_info {
+    ($($arg:tt)+) => {{
+        log::info!($($arg)+);
+        #[cfg(feature = "defmt")]
+        {
+            let msg = alloc::format!($($arg)+);
+            defmt::info!("{=str}", msg.as_str());
+        }
+    }};
+}
+
+#[cfg(any(feature = "std", feature = "embedded-io"))]
 macro_rules! match_channel_to {
     ($channel:expr, $ch1:expr, $ch2:expr) => {
         match $channel {
             1 => $ch1,
             2 => $ch2,
             _ => {
-                let msg = format!("channel should be 1 or 2 (was {})", $channel);
+                let msg = alloc::format!("channel should be 1 or 2 (was {})", $channel);
                 return Err(crate::error::Error::InvalidInput(msg));
             }
         }
     };
 }
 
-pub fn ratio_to_value(ratio: f32) -> Result<i32> {
-    if ratio > 1.0 || ratio < -1.0 {
+/// Convert *ratio* to a raw value from -*max* to *max*.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+pub fn ratio_to_range(ratio: f32, max: i32) -> Result<i32> {
+    if !(-1.0..=1.0).contains(&ratio) {
         return Err(Error::InvalidInput(format!(
             "value ({}) out of range -1.0~1.0",
             ratio
         )));
     }
 
-    let value = (ratio * RANGE_MAX as f32) as i32;
+    let value = (ratio * max as f32) as i32;
+    Ok(value.clamp(-max, max))
+}
 
-    if value > RANGE_MAX {
-        Ok(RANGE_MAX)
-    } else if value < RANGE_MIN {
-        Ok(RANGE_MIN)
-    } else {
-        Ok(value)
+/// Write all of *data* to the `embedded-io` transport *dev*, and flush it.
+/// Unlike its `write_all()`, which panics, a transport accepting no byte
+/// fails with `ErrorKind::WriteZero`.
+#[cfg(feature = "embedded-io")]
+pub fn write_embedded<T>(dev: &mut T, mut data: &[u8]) -> Result<()>
+where
+    T: embedded_io::Write + ?Sized,
+{
+    while !data.is_empty() {
+        match dev.write(data) {
+            Ok(0) => return Err(Error::Transport(embedded_io::ErrorKind::WriteZero)),
+            Ok(n) => data = &data[n..],
+            Err(e) => return Err(transport_error(e)),
+        }
     }
+    dev.flush().map_err(transport_error)
+}
+
+/// Error of an `embedded-io` transport.
+#[cfg(feature = "embedded-io")]
+pub fn transport_error<E: embedded_io::Error>(e: E) -> Error {
+    Error::Transport(e.kind())
+}
+
+/// Round *value* half away from zero, like `f32::round()` which is not
+/// available without `std`.
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+pub fn round(value: f32) -> f32 {
+    match value < 0.0 {
+        true => -((-value + 0.5) as i64 as f32),
+        false => (value + 0.5) as i64 as f32,
+    }
+}
+
+#[cfg(feature = "std")]
+/// Read the bytes already received, without waiting, until *buf* is full or
+/// the *end* byte is read. Return the number of bytes read.
+pub fn read_available<T>(dev: &mut T, buf: &mut [u8], end: Option<u8>) -> Result<usize>
+where
+    T: SabertoothSerial + ?Sized,
+{
+    let timeout = dev.timeout();
+    dev.set_timeout(Duration::from_secs(0))?;
+
+    let mut count = 0;
+    let res = loop {
+        if count == buf.len() || (count > 0 && end == Some(buf[count - 1])) {
+            break Ok(count);
+        }
+        // Byte by byte when looking for the end, so nothing is read after it
+        let chunk_end = match end {
+            Some(_) => count + 1,
+            None => buf.len(),
+        };
+        match dev.read(&mut buf[count..chunk_end]) {
+            Ok(0) => break Ok(count),
+            Ok(n) => count += n,
+            Err(e) if would_block(&e) => break Ok(count),
+            Err(e) => break Err(Error::from(e)),
+        }
+    };
+
+    dev.set_timeout(timeout)?;
+    res
+}
+
+#[cfg(feature = "std")]
+/// Yield hook of a handle.
+pub type YieldHook = Option<Box<dyn Yield>>;
+
+#[cfg(feature = "std")]
+/// Read some bytes from *dev* into *buf*, waiting for them according to
+/// *strategy*. With a yield hook, the blocking reads are replaced by polls
+/// for up to the port timeout, and the hook is called between the polls.
+pub fn read_with<T>(
+    dev: &mut T,
+    buf: &mut [u8],
+    strategy: ReadStrategy,
+    hook: &mut YieldHook,
+) -> Result<usize>
+where
+    T: SabertoothSerial + ?Sized,
+{
+    let strategy = match (strategy, hook.is_some()) {
+        (ReadStrategy::Blocking, true) => ReadStrategy::BusyPoll {
+            spin: dev.timeout(),
+        },
+        _ => strategy,
+    };
+    let spin = match strategy {
+        ReadStrategy::Blocking => return Ok(dev.read(buf)?),
+        ReadStrategy::BusyPoll { spin } | ReadStrategy::Hybrid { spin } => spin,
+    };
+    let timeout = dev.timeout();
+    dev.set_timeout(Duration::from_secs(0))?;
+
+    let start = Instant::now();
+    let res = loop {
+        match dev.read(buf) {
+            Ok(0) => (),
+            Err(e) if would_block(&e) => (),
+            res => break Some(res),
+        }
+        if start.elapsed() >= spin {
+            break None;
+        }
+        match hook {
+            Some(hook) => hook.yield_now(WaitPoint::Reply),
+            None => std::hint::spin_loop(),
+        }
+    };
+
+    dev.set_timeout(timeout)?;
+    match (res, strategy) {
+        (Some(res), _) => Ok(res?),
+        (None, ReadStrategy::Hybrid { .. }) => Ok(dev.read(buf)?),
+        (None, _) => Err(Error::Io(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "no data within the spin budget",
+        ))),
+    }
+}
+
+#[cfg(feature = "std")]
+/// Fill *buf* from *dev*, waiting for each chunk according to *strategy*.
+pub fn read_exact_with<T>(
+    dev: &mut T,
+    buf: &mut [u8],
+    strategy: ReadStrategy,
+    hook: &mut YieldHook,
+) -> Result<()>
+where
+    T: SabertoothSerial + ?Sized,
+{
+    if strategy == ReadStrategy::Blocking && hook.is_none() {
+        return Ok(dev.read_exact(buf)?);
+    }
+    let mut count = 0;
+    while count < buf.len() {
+        match read_with(dev, &mut buf[count..], strategy, hook)? {
+            0 => {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                )))
+            }
+            n => count += n,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+/// Read and drop the bytes received until nothing is received for *quiet*.
+/// Return the number of bytes dropped, or an `io::ErrorKind::TimedOut`
+/// error if the line is still busy after `QUIET_MAX_WAIT` times *quiet*.
+pub fn flush_until_quiet<T>(dev: &mut T, quiet: Duration) -> Result<usize>
+where
+    T: SabertoothSerial + ?Sized,
+{
+    let timeout = dev.timeout();
+    dev.set_timeout(quiet)?;
+
+    let deadline = Instant::now() + quiet * QUIET_MAX_WAIT;
+    let mut buf = [0u8; 64];
+    let mut count = 0;
+    let res = loop {
+        match dev.read(&mut buf) {
+            Ok(0) => break Ok(count),
+            Ok(n) => count += n,
+            Err(e) if would_block(&e) => break Ok(count),
+            Err(e) => break Err(Error::from(e)),
+        }
+        if Instant::now() >= deadline {
+            break Err(Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the line did not become quiet",
+            )));
+        }
+    };
+
+    dev.set_timeout(timeout)?;
+    res
 }
 
-pub fn value_to_ratio(value: i32) -> f32 {
-    value as f32 / RANGE_MAX as f32
+#[cfg(feature = "std")]
+/// Timing of the wait for a reply, for links delaying the requests like
+/// opto-isolated adapters.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ReplyTiming {
+    /// Pause after writing a request, before reading its reply.
+    pub grace: Option<Duration>,
+
+    /// Timeout of the port while reading a reply, in place of its own.
+    pub timeout: Option<Duration>,
+
+    /// Timeout tuned from the round trips, in place of *timeout*.
+    pub adaptive: Option<AdaptiveTimeout>,
+}
+
+#[cfg(feature = "std")]
+impl ReplyTiming {
+    /// Timing of the next reply, with the adaptive timeout tuned from the
+    /// round trips of *stats*.
+    pub fn tuned(&self, stats: &Stats) -> ReplyTiming {
+        match self.adaptive {
+            Some(adaptive) => ReplyTiming {
+                timeout: Some(adaptive.timeout(stats)),
+                ..*self
+            },
+            None => *self,
+        }
+    }
+
+    /// Record the outcome of a request for the adaptive timeout.
+    pub fn record<T>(&mut self, res: &Result<T>) {
+        if let Some(adaptive) = self.adaptive.as_mut() {
+            adaptive.record(res);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// Prepare *dev* for reading the reply to the request just written: wait
+/// the grace period of *timing*, and set its reply timeout. Return the
+/// timeout to give back to `end_reply()`.
+pub fn begin_reply<T>(dev: &mut T, timing: ReplyTiming) -> Result<Option<Duration>>
+where
+    T: SabertoothSerial + ?Sized,
+{
+    if let Some(grace) = timing.grace {
+        thread::sleep(grace);
+    }
+    match timing.timeout {
+        Some(timeout) => {
+            let previous = dev.timeout();
+            dev.set_timeout(timeout)?;
+            Ok(Some(previous))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "std")]
+/// Restore the timeout of *dev* changed by `begin_reply()`.
+pub fn end_reply<T>(dev: &mut T, previous: Option<Duration>) -> Result<()>
+where
+    T: SabertoothSerial + ?Sized,
+{
+    match previous {
+        Some(timeout) => dev.set_timeout(timeout),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "std")]
+/// Write all of *data* to *dev* within *deadline*, retrying the partial
+/// writes and the writes which would block, unlike `write_all()` which can
+/// block for as long as the port lets it.
+pub fn write_bounded<T>(dev: &mut T, data: &[u8], deadline: Duration) -> Result<()>
+where
+    T: io::Write + ?Sized,
+{
+    let start = Instant::now();
+    let mut written = 0;
+    while written < data.len() {
+        let progress = match dev.write(&data[written..]) {
+            Ok(n) => n,
+            Err(e) if would_block(&e) || e.kind() == io::ErrorKind::Interrupted => 0,
+            Err(e) => return Err(Error::from(e)),
+        };
+        written += progress;
+        if written == data.len() || progress > 0 {
+            continue;
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= deadline {
+            return Err(Error::TxTimeout {
+                written,
+                len: data.len(),
+            });
+        }
+        thread::sleep(WRITE_RETRY_INTERVAL.min(deadline - elapsed));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+/// Whether *e* means that no data is available yet.
+pub fn would_block(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(feature = "std")]
+/// Error returned by the non-blocking methods when the reply is incomplete.
+pub fn would_block_error() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::WouldBlock,
+        "reply not received yet",
+    ))
+}
+
+#[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+/// Fault injector of a handle.
+pub type Faults = Option<Box<dyn FaultInjector>>;
+
+#[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+/// Wait before writing *frame* if the fault injector of a handle asks so.
+pub fn inject_write(faults: &mut Faults, frame: &[u8]) {
+    if let Some(delay) = faults.as_mut().and_then(|f| f.delay_write(frame)) {
+        thread::sleep(delay);
+    }
+}
+
+#[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+/// Pass the received *reply* to the fault injector of a handle. Return false
+/// if the reply is dropped.
+pub fn inject_reply(faults: &mut Faults, reply: &mut [u8]) -> bool {
+    match faults {
+        Some(f) => f.on_reply(reply) == ReplyFault::Deliver,
+        None => true,
+    }
+}
+
+#[cfg(any(all(test, feature = "std"), feature = "emulator"))]
+/// Error returned for a reply dropped by the fault injector.
+pub fn dropped_reply_error() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "reply dropped by the fault injector",
+    ))
+}
+
+#[cfg(feature = "std")]
+/// Convert the raw *value* of the reply to the get request *command*, with
+/// the scaling of the protocol and then *scaling*.
+pub fn scale_reply(scaling: &dyn Scaling, command: Command, value: i32, protocol: Protocol) -> f32 {
+    let value = command.spec().scale(value, protocol);
+    match command.quantity() {
+        Some(quantity) => scaling.scale(quantity, value),
+        None => value,
+    }
+}
+
+#[cfg(feature = "std")]
+/// Return `Error::NotArmed` if *operation* is a motion command and the
+/// interlock *armed* is present but not armed.
+pub fn check_armed(armed: Option<bool>, operation: Operation) -> Result<()> {
+    if armed == Some(false) && operation.is_motion() {
+        log_warn!("{:?} rejected: not armed", operation);
+        return Err(Error::NotArmed);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+/// Record the round-trip latency of the get request *command* in *stats*,
+/// and check it against *alert*.
+pub fn record_round_trip(
+    stats: &mut Stats,
+    alert: &mut Option<LatencyAlert>,
+    command: Command,
+    elapsed: Duration,
+) {
+    if let Some(quantity) = command.quantity() {
+        stats.record_round_trip(quantity, elapsed);
+        if let Some(alert) = alert {
+            alert.check(quantity, elapsed);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// Record the latency of a deadline-checked command in *stats*, and return
+/// it, or `Error::DeadlineMissed` if it is longer than *deadline*.
+pub fn check_deadline(
+    stats: &mut Stats,
+    elapsed: Duration,
+    deadline: Duration,
+) -> Result<Duration> {
+    let missed = elapsed > deadline;
+    stats.record_latency(elapsed, missed);
+    if missed {
+        log_warn!(
+            "deadline missed: took {:?}, deadline {:?}",
+            elapsed,
+            deadline
+        );
+        return Err(Error::DeadlineMissed { elapsed, deadline });
+    }
+    Ok(elapsed)
 }
@@ -0,0 +1,236 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::sabertooth2x32::PacketSerial;
+use crate::SabertoothSerial;
+
+/// Default interval between two keep-alives of a
+/// [WatchdogFeeder](struct.WatchdogFeeder.html).
+pub const DEFAULT_FEED_PERIOD: Duration = Duration::from_millis(100);
+
+/// Lock *mutex*, ignoring the poisoning: a panic of the application while
+/// using the handle does not invalidate it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// State shared by the guard and the feeding thread.
+struct Shared<T: SabertoothSerial> {
+    saber: Mutex<Option<PacketSerial<T>>>,
+    stop: AtomicBool,
+    last_kick: Mutex<Instant>,
+}
+
+/// Helper sending the keep-alives of a "Packet Serial" handle from a
+/// background thread, for as long as the application holds the returned
+/// [WatchdogGuard].
+///
+/// With a serial timeout configured on the device, the motors stop when no
+/// command arrives in time. The feeder keeps them running while the
+/// application has nothing else to send, and the feeding ends with the
+/// guard: a control loop which exits, even by a panic, lets the device
+/// stop the motors. With [with_kick_timeout()](#method.with_kick_timeout),
+/// the feeding also pauses while the application does not kick the guard,
+/// so that a stalled control loop stops the motors too.
+///
+/// The serial timeout itself must be enabled on the device, for example
+/// with DEScribe; the feeder does not configure it. The feeding period must
+/// be well below it.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use saberrs::WatchdogFeeder;
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> saberrs::Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyS0")?;
+/// let guard = WatchdogFeeder::new(saber)
+///     .with_period(Duration::from_millis(50))
+///     .with_kick_timeout(Duration::from_millis(200))
+///     .start();
+///
+/// // In the control loop
+/// guard.with_handle(|saber| saber.set_speed(1, 0.5))?;
+///
+/// let saber = guard.stop();
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [WatchdogGuard]: struct.WatchdogGuard.html
+pub struct WatchdogFeeder<T: SabertoothSerial> {
+    saber: PacketSerial<T>,
+    period: Duration,
+    channels: Vec<usize>,
+    kick_timeout: Option<Duration>,
+}
+
+impl<T: SabertoothSerial + Send + 'static> WatchdogFeeder<T> {
+    /// Create a feeder for *saber*, sending a keep-alive to both channels
+    /// every [DEFAULT_FEED_PERIOD](constant.DEFAULT_FEED_PERIOD.html).
+    pub fn new(saber: PacketSerial<T>) -> WatchdogFeeder<T> {
+        WatchdogFeeder {
+            saber,
+            period: DEFAULT_FEED_PERIOD,
+            channels: vec![1, 2],
+            kick_timeout: None,
+        }
+    }
+
+    /// Set the interval between two keep-alives.
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Set the channels receiving the keep-alives.
+    pub fn with_channels(mut self, channels: &[usize]) -> Self {
+        self.channels = channels.to_vec();
+        self
+    }
+
+    /// Pause the feeding while the guard was not kicked for *timeout*.
+    pub fn with_kick_timeout(mut self, timeout: Duration) -> Self {
+        self.kick_timeout = Some(timeout);
+        self
+    }
+
+    /// Start feeding, until the returned guard is stopped or dropped.
+    pub fn start(self) -> WatchdogGuard<T> {
+        let shared = Arc::new(Shared {
+            saber: Mutex::new(Some(self.saber)),
+            stop: AtomicBool::new(false),
+            last_kick: Mutex::new(Instant::now()),
+        });
+        let thread = {
+            let shared = shared.clone();
+            let (period, channels, kick_timeout) = (self.period, self.channels, self.kick_timeout);
+            thread::spawn(move || feed_loop(&shared, period, &channels, kick_timeout))
+        };
+        WatchdogGuard {
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Guard returned by [WatchdogFeeder::start()], keeping the keep-alives
+/// flowing while it is held. Dropping it stops the feeding.
+///
+/// [WatchdogFeeder::start()]: struct.WatchdogFeeder.html#method.start
+pub struct WatchdogGuard<T: SabertoothSerial> {
+    shared: Arc<Shared<T>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: SabertoothSerial> WatchdogGuard<T> {
+    /// Signal that the control loop is alive, see
+    /// [with_kick_timeout()](struct.WatchdogFeeder.html#method.with_kick_timeout).
+    pub fn kick(&self) {
+        *lock(&self.shared.last_kick) = Instant::now();
+    }
+
+    /// Run *f* on the handle, between two keep-alives, and kick the guard.
+    pub fn with_handle<R, F: FnOnce(&mut PacketSerial<T>) -> R>(&self, f: F) -> R {
+        self.kick();
+        let mut saber = lock(&self.shared.saber);
+        f(saber.as_mut().expect("handle taken by stop()"))
+    }
+
+    /// Stop the feeding, and return the handle.
+    pub fn stop(mut self) -> PacketSerial<T> {
+        self.join();
+        let saber = lock(&self.shared.saber).take();
+        saber.expect("handle taken by stop()")
+    }
+
+    fn join(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            if thread.join().is_err() {
+                log_warn!("watchdog feeder: feeding thread panicked");
+            }
+        }
+    }
+}
+
+impl<T: SabertoothSerial> Drop for WatchdogGuard<T> {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+fn feed_loop<T: SabertoothSerial>(
+    shared: &Shared<T>,
+    period: Duration,
+    channels: &[usize],
+    kick_timeout: Option<Duration>,
+) {
+    let mut next = Instant::now();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let stalled = match kick_timeout {
+            Some(timeout) => lock(&shared.last_kick).elapsed() > timeout,
+            None => false,
+        };
+        if let (false, Some(saber)) = (stalled, lock(&shared.saber).as_mut()) {
+            for &channel in channels {
+                if let Err(e) = saber.keep_alive(channel) {
+                    log_warn!("watchdog feeder: keep-alive on channel {}: {}", channel, e);
+                }
+            }
+        }
+
+        next += period;
+        loop {
+            let now = Instant::now();
+            if now >= next || shared.stop.load(Ordering::Relaxed) {
+                break;
+            }
+            thread::park_timeout(next - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sabertooth2x32::{PacketType, Sabertooth2x32};
+    use crate::testing::{FrameTap, MockPort};
+
+    const KEEP_ALIVE_1: &[u8] = b"\x80\x28\x10\x38\x00\x00\x4d\x31\x7e";
+
+    #[test]
+    fn test_watchdog_feeder() {
+        let mut port = MockPort::new();
+        let saber = PacketSerial::from(port.clone()).with_packet_type(PacketType::Checksum);
+        let guard = WatchdogFeeder::new(saber)
+            .with_period(Duration::from_millis(5))
+            .with_channels(&[1])
+            .with_kick_timeout(Duration::from_millis(50))
+            .start();
+        thread::sleep(Duration::from_millis(20));
+        guard.with_handle(|saber| saber.set_speed(1, 0.5)).unwrap();
+        let mut saber = guard.stop();
+
+        let written = port.take_written();
+        assert!(written.len() >= 2 * KEEP_ALIVE_1.len());
+        assert_eq!(KEEP_ALIVE_1, &written[..KEEP_ALIVE_1.len()]);
+        let speed = b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04";
+        assert!(written.windows(speed.len()).any(|w| w == speed));
+
+        saber.set_speed(1, 0.0).unwrap();
+        port.take_written();
+        let guard = WatchdogFeeder::new(saber)
+            .with_period(Duration::from_millis(5))
+            .with_kick_timeout(Duration::from_millis(0))
+            .start();
+        thread::sleep(Duration::from_millis(20));
+        drop(guard);
+        assert!(port.take_written().len() <= 2 * KEEP_ALIVE_1.len());
+    }
+}
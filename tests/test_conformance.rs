@@ -0,0 +1,34 @@
+#![cfg(feature = "std")]
+
+use std::fs;
+use std::path::Path;
+
+use saberrs::sabertooth2x32::conformance_json;
+
+/// The conformance document kept in the repository must match the one
+/// generated from the command table. Run the test with `SABERRS_BLESS=1`
+/// for updating it after changing the table.
+#[test]
+fn conformance_document_up_to_date() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/sabertooth2x32.json");
+    let json = conformance_json();
+    if std::env::var_os("SABERRS_BLESS").is_some() {
+        fs::write(&path, &json).expect("cannot write the conformance document");
+    }
+    let stored = fs::read_to_string(&path).expect("cannot read the conformance document");
+    assert!(
+        stored == json,
+        "docs/sabertooth2x32.json is out of date, run the tests with SABERRS_BLESS=1"
+    );
+}
+
+#[test]
+fn conformance_document_content() {
+    let json = conformance_json();
+    assert!(json.contains(r#""format": 1,"#));
+    assert!(json.contains(r#""packet_serial": {"set": 40, "get": 41, "reply": 73},"#));
+    assert!(json.contains(
+        r#"{"name": "GetCurrent", "access": "get", "targets": ["M1", "M2"], "opcode": 32, "value": {"kind": "scaled", "packet_serial_divisor": 1, "plain_text_divisor": 10}, "keyword": "getc", "prefix": "C"}"#
+    ));
+    assert!(json.contains(r#""targets": ["MD"]"#));
+}
@@ -1,3 +1,5 @@
+#![cfg(feature = "serialport")]
+
 use std::io::{Read, Write};
 use std::time::{Duration, Instant};
 
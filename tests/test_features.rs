@@ -0,0 +1,163 @@
+//! Feature matrix of the crate: the optional subsystems are independent of
+//! each other and of the dependencies, and only need `std`. The minimal
+//! build, `no_std` with the protocols and the traits only, depends on `log`
+//! alone, and the one with `std`, adding the port interface, as well.
+//!
+//! The file compiles with any combination of features, which is the
+//! compile-time part of the check:
+//!
+//! ```text
+//! cargo test --no-default-features --test test_features
+//! cargo test --no-default-features --features std --test test_features
+//! cargo test --no-default-features --features control --test test_features
+//! ```
+
+use saberrs::frame::{self, PacketType};
+#[cfg(feature = "std")]
+use saberrs::sabertooth2x32::{PacketSerial, PlainText, Sabertooth2x32};
+#[cfg(feature = "std")]
+use saberrs::testing::{FrameTap, MockPort};
+
+const MANIFEST: &str = include_str!("../Cargo.toml");
+
+/// Optional subsystems, each behind its own feature.
+const SUBSYSTEMS: [&str; 3] = ["telemetry", "control", "emulator"];
+
+/// Subsystems enabled by default. The emulator is not, so that a production
+/// build cannot run without driving the hardware.
+const DEFAULT_SUBSYSTEMS: [&str; 2] = ["telemetry", "control"];
+
+/// Features enabled by each subsystem.
+const SUBSYSTEM_FEATURES: [&str; 1] = ["std"];
+
+/// Dependencies of the minimal build.
+const MINIMAL_DEPENDENCIES: [&str; 1] = ["log"];
+
+/// Lines of the manifest section *name*, without the comments.
+fn section(name: &str) -> Vec<&'static str> {
+    let header = format!("[{}]", name);
+    MANIFEST
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != header)
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Features enabled by the feature *name*.
+fn feature(name: &str) -> Vec<&'static str> {
+    let line = section("features")
+        .into_iter()
+        .find(|line| line.split('=').next().map(str::trim) == Some(name))
+        .unwrap_or_else(|| panic!("no feature {:?}", name));
+    let list = line.split_once('=').unwrap().1.trim();
+    list.trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|item| item.trim().trim_matches('"'))
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+#[test]
+fn test_minimal_dependencies() {
+    for line in section("dependencies") {
+        let name = line.split('=').next().unwrap().trim();
+        assert!(
+            MINIMAL_DEPENDENCIES.contains(&name),
+            "{} is not optional",
+            name
+        );
+    }
+    for table in MANIFEST.lines().filter(|l| l.starts_with("[dependencies.")) {
+        let name = table
+            .trim_start_matches("[dependencies.")
+            .trim_end_matches(']');
+        assert!(
+            section(&format!("dependencies.{}", name)).contains(&"optional = true"),
+            "{} is not optional",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_subsystems_independent() {
+    let default = feature("default");
+    for subsystem in SUBSYSTEMS.iter() {
+        assert_eq!(
+            DEFAULT_SUBSYSTEMS.contains(subsystem),
+            default.contains(subsystem),
+            "{} default",
+            subsystem
+        );
+        assert_eq!(
+            SUBSYSTEM_FEATURES.to_vec(),
+            feature(subsystem),
+            "{} enables other features",
+            subsystem
+        );
+    }
+}
+
+#[test]
+fn test_no_std_api() {
+    let mut buf = [0u8; frame::MAX_FRAME_SIZE];
+    let len = frame::encode_set(PacketType::Checksum, 128, 0, 1023, *b"M1", &mut buf).unwrap();
+    assert_eq!(b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04", &buf[..len]);
+}
+
+#[cfg(feature = "embedded-io")]
+#[test]
+fn test_embedded_io() {
+    use saberrs::sabertooth2x12::{EmbeddedPacketizedSerial, Sabertooth2x12};
+
+    let mut buf = [0u8; 4];
+    let mut saber = EmbeddedPacketizedSerial::new(&mut buf[..]);
+    saber.set_speed(1, 0.5).unwrap();
+    assert_eq!([128, 0, 63, 63], buf);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_minimal_api() {
+    let mut port = MockPort::new();
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    saber.set_speed(1, 0.5).unwrap();
+    assert_eq!(port.take_written(), b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04");
+
+    port.queue_reply(b"M1:B240\r\n");
+    let mut saber: PlainText<MockPort> = saber.into_plain_text();
+    assert_eq!(24.0, saber.get_voltage(1).unwrap());
+}
+
+#[cfg(feature = "telemetry")]
+#[test]
+fn test_telemetry() {
+    use saberrs::telemetry::{MemoryStore, TelemetryLog};
+
+    let mut log = TelemetryLog::new(MemoryStore::new(10));
+    assert!(log.records().unwrap().is_empty());
+}
+
+#[cfg(feature = "control")]
+#[test]
+fn test_control() {
+    use saberrs::{Mailbox, SoftFuse};
+
+    let saber = Mailbox::new(PlainText::from(MockPort::new()));
+    assert_eq!(0, saber.pending());
+    assert!(!SoftFuse::new(10.0, 100.0).is_tripped(1));
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_emulator() {
+    use saberrs::testing::DryRun;
+
+    let mut saber = PlainText::from(DryRun::new());
+    assert_eq!(24.0, saber.get_voltage(1).unwrap());
+    assert_eq!(24.0, PacketSerial::dry_run().get_voltage(1).unwrap());
+}
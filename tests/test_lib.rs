@@ -1,3 +1,5 @@
+#![cfg(feature = "serialport")]
+
 use std::io::Write;
 
 use saberrs::sabertooth2x32::{PacketSerial, PlainText, Sabertooth2x32};
@@ -0,0 +1,90 @@
+//! Compile-time checks of the `Send` and `Sync` guarantees documented in the
+//! crate root.
+#![cfg(feature = "std")]
+
+use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+use saberrs::sabertooth2x12::PacketizedSerial;
+use saberrs::sabertooth2x32::{PacketSerial, PlainText};
+use saberrs::sabertooth2x5::SimplifiedSerial;
+use saberrs::testing::MockPort;
+use saberrs::{
+    AdaptiveTimeout, BusDevice, DeviceProfile, Envelope, Error, Health, History, SabertoothBus,
+    ScalingProfile, Stats,
+};
+
+// Ports
+assert_impl_all!(MockPort: Send, Sync, Clone);
+
+#[cfg(feature = "serialport")]
+mod serialport {
+    use super::*;
+    use saberrs::{SabertoothPort, SabertoothPortShared, SabertoothPortSync};
+
+    assert_impl_all!(SabertoothPort: Send);
+    assert_not_impl_any!(SabertoothPort: Sync);
+    assert_not_impl_any!(SabertoothPortShared: Send, Sync);
+    assert_impl_all!(SabertoothPortSync: Send, Sync, Clone);
+
+    assert_impl_all!(PacketSerial<SabertoothPort>: Send);
+    assert_impl_all!(PacketSerial<SabertoothPortSync>: Send);
+    assert_not_impl_any!(PacketSerial<SabertoothPortShared>: Send);
+    assert_impl_all!(PlainText<SabertoothPortSync>: Send);
+    assert_impl_all!(SimplifiedSerial<SabertoothPortSync>: Send, Sync);
+    assert_impl_all!(PacketizedSerial<SabertoothPortSync>: Send, Sync);
+}
+
+// Handles are Send when their port is, never Sync
+assert_impl_all!(PacketSerial<MockPort>: Send);
+assert_not_impl_any!(PacketSerial<MockPort>: Sync);
+assert_impl_all!(PlainText<MockPort>: Send);
+assert_not_impl_any!(PlainText<MockPort>: Sync);
+assert_impl_all!(SimplifiedSerial<MockPort>: Send, Sync);
+assert_impl_all!(PacketizedSerial<MockPort>: Send, Sync);
+assert_impl_all!(BusDevice<MockPort>: Send);
+assert_impl_all!(SabertoothBus<MockPort>: Send, Sync);
+
+// Subsystems
+assert_impl_all!(Stats: Send, Sync);
+assert_impl_all!(Health: Send, Sync);
+assert_impl_all!(History: Send, Sync);
+assert_impl_all!(ScalingProfile: Send, Sync);
+assert_impl_all!(DeviceProfile: Send, Sync);
+assert_impl_all!(Envelope: Send, Sync, Clone);
+assert_impl_all!(AdaptiveTimeout: Send, Sync, Copy);
+
+#[cfg(feature = "control")]
+mod control {
+    use super::*;
+    use saberrs::{
+        AsymmetryMonitor, AutoRestart, CommandQueue, Mailbox, MotorChannel, MotorChannels,
+        RebootMonitor, RetryBudget, SkidSteer4, SoftFuse, WatchdogGuard,
+    };
+
+    assert_impl_all!(SkidSteer4<PacketSerial<MockPort>, PacketSerial<MockPort>>: Send);
+    assert_impl_all!(Mailbox<PacketSerial<MockPort>>: Send);
+    assert_impl_all!(CommandQueue: Send, Sync);
+    assert_impl_all!(SoftFuse: Send, Sync);
+    assert_impl_all!(AutoRestart: Send, Sync);
+    assert_impl_all!(RebootMonitor: Send, Sync);
+    assert_impl_all!(AsymmetryMonitor: Send, Sync);
+    assert_impl_all!(WatchdogGuard<MockPort>: Send, Sync);
+    assert_impl_all!(RetryBudget: Send, Sync, Clone);
+    assert_impl_all!(MotorChannels<PacketSerial<MockPort>>: Send, Sync, Clone);
+    assert_impl_all!(MotorChannel<PacketSerial<MockPort>>: Send, Sync);
+}
+
+#[cfg(feature = "telemetry")]
+mod telemetry {
+    use saberrs::telemetry::{FileStore, MemoryStore, TelemetryLog};
+    use static_assertions::assert_impl_all;
+
+    assert_impl_all!(TelemetryLog<MemoryStore>: Send, Sync);
+    assert_impl_all!(TelemetryLog<FileStore>: Send, Sync);
+}
+
+#[cfg(all(feature = "async", feature = "telemetry"))]
+assert_impl_all!(saberrs::telemetry::TelemetryStream<PacketSerial<MockPort>>: Send);
+
+// Errors can be moved to other threads and boxed
+assert_impl_all!(Error: Send, Sync);
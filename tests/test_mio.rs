@@ -0,0 +1,34 @@
+#![cfg(all(unix, feature = "mio", feature = "serialport"))]
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use mio::{Events, Poll, Token};
+
+use saberrs::sabertooth2x32::Operation;
+
+#[macro_use]
+mod utils;
+
+#[test]
+fn on_readable() {
+    let (mut saber, mut tty) = utils::saberchecksum_harness();
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(4);
+    saber.register(poll.registry(), Token(0)).unwrap();
+
+    saber.start_get(Operation::GetVoltage(1)).unwrap();
+    let mut buf = [0u8; 7];
+    tty.read_exact(&mut buf).unwrap();
+    assert_eq!(b"\x80\x29\x10\x39\x4d\x31\x7e", &buf);
+    assert_eq!(None, saber.on_readable().unwrap());
+
+    tty.write_all(b"\x80\x49\x10\x59\x78\x00\x4d\x31\x76")
+        .unwrap();
+    poll.poll(&mut events, Some(Duration::from_secs(1)))
+        .unwrap();
+    assert_eq!(Some(Token(0)), events.iter().next().map(|e| e.token()));
+    assert_eq!(Some(12.0), saber.on_readable().unwrap());
+
+    saber.deregister(poll.registry()).unwrap();
+}
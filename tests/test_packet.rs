@@ -1,8 +1,11 @@
+#![cfg(feature = "serialport")]
+
 use std::io::Read;
 
 use serialport::SerialPort;
 
 use saberrs::sabertooth2x32::Sabertooth2x32;
+use saberrs::FailsafeAction;
 
 #[macro_use]
 mod utils;
@@ -58,6 +61,31 @@ mod checksum {
             .expect_err("Channel 3 should fail");
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn emergency_stop() {
+        let (mut saberchecksum, mut tty) = utils::saberchecksum_harness();
+
+        saberchecksum.emergency_stop(FailsafeAction::HardBrake).expect("Emergency stop failure");
+        let mut buf = [0u8; 36];
+        tty.read_exact(&mut buf).expect("Read fail");
+        let expected = b"\x80\x28\x20\x48\x01\x00\x4d\x31\x7f\
+            \x80\x28\x20\x48\x01\x00\x4d\x32\x00\
+            \x80\x28\x00\x28\x00\x00\x4d\x31\x7e\
+            \x80\x28\x00\x28\x00\x00\x4d\x32\x7f";
+        assert_eq!(expected, &buf, "Wrong data");
+
+        saberchecksum.emergency_stop(FailsafeAction::ZeroSpeed).expect("Emergency stop failure");
+        let mut buf = [0u8; 18];
+        tty.read_exact(&mut buf).expect("Read fail");
+        let expected = b"\x80\x28\x00\x28\x00\x00\x4d\x31\x7e\
+            \x80\x28\x00\x28\x00\x00\x4d\x32\x7f";
+        assert_eq!(expected, &buf, "Wrong data");
+
+        saberchecksum.emergency_stop(FailsafeAction::Coast).expect_err("Coast should fail");
+        assert_eq!(0, tty.bytes_to_read().unwrap());
+    }
+
     #[test]
     #[rustfmt::skip]
     fn set_speed() {
@@ -193,6 +221,27 @@ mod checksum {
         test_get_method!(saberchecksum, get_temperature, vectors, responder);
         responder.stop();
     }
+
+    #[test]
+    fn stats() {
+        let (mut saberchecksum, responder) = utils::saberchecksum_responder_harness();
+
+        responder.set_expected(b"\x80\x28\x00\x28\x00\x00\x4d\x31\x7e\x80\x29\x10\x39\x4D\x31\x7E");
+        responder.set_response(b"\x80\x49\x10\x59\x78\x00\x4D\x31\x76");
+        saberchecksum.set_speed(1, 0.0).expect("Set value failure");
+        saberchecksum.get_voltage(1).expect("Get value failure");
+        responder.stop();
+
+        let stats = saberchecksum.stats();
+        assert_eq!(2, stats.frames_sent());
+        assert_eq!(16, stats.bytes_sent());
+        assert_eq!(1, stats.frames_received());
+        assert_eq!(9, stats.bytes_received());
+        assert!(stats.tx_utilization(9600) > 0.0);
+
+        saberchecksum.reset_stats();
+        assert_eq!(0, saberchecksum.stats().frames_sent());
+    }
 }
 
 mod crc {
@@ -242,6 +291,31 @@ mod crc {
         sabercrc.shutdown(3).expect_err("Channel 3 should fail");
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn emergency_stop() {
+        let (mut sabercrc, mut tty) = utils::sabercrc_harness();
+
+        sabercrc.emergency_stop(FailsafeAction::HardBrake).expect("Emergency stop failure");
+        let mut buf = [0u8; 40];
+        tty.read_exact(&mut buf).expect("Read fail");
+        let expected = b"\xf0\x28\x20\x67\x01\x00\x4d\x31\x3b\x22\
+            \xf0\x28\x20\x67\x01\x00\x4d\x32\x49\x32\
+            \xf0\x28\x00\x0c\x00\x00\x4d\x31\x66\x5c\
+            \xf0\x28\x00\x0c\x00\x00\x4d\x32\x14\x4c";
+        assert_eq!(expected, &buf, "Wrong data");
+
+        sabercrc.emergency_stop(FailsafeAction::ZeroSpeed).expect("Emergency stop failure");
+        let mut buf = [0u8; 20];
+        tty.read_exact(&mut buf).expect("Read fail");
+        let expected = b"\xf0\x28\x00\x0c\x00\x00\x4d\x31\x66\x5c\
+            \xf0\x28\x00\x0c\x00\x00\x4d\x32\x14\x4c";
+        assert_eq!(expected, &buf, "Wrong data");
+
+        sabercrc.emergency_stop(FailsafeAction::Coast).expect_err("Coast should fail");
+        assert_eq!(0, tty.bytes_to_read().unwrap());
+    }
+
     #[test]
     #[rustfmt::skip]
     fn set_speed() {
@@ -1,8 +1,11 @@
+#![cfg(feature = "serialport")]
+
 use std::io::Read;
 
 use serialport::SerialPort;
 
 use saberrs::sabertooth2x32::Sabertooth2x32;
+use saberrs::FailsafeAction;
 
 #[macro_use]
 mod utils;
@@ -33,6 +36,32 @@ fn shutdown() {
     assert_eq!(expected, &buf[0..expected.len()]);
 }
 
+#[test]
+fn emergency_stop() {
+    let (mut sabertext, mut tty) = utils::sabertext_harness();
+
+    sabertext
+        .emergency_stop(FailsafeAction::HardBrake)
+        .expect("Emergency stop failure");
+    let expected = b"M1: shutdown\r\nM2: shutdown\r\nM1: 0\r\nM2: 0\r\n";
+    let mut buf = [0u8; 42];
+    tty.read_exact(&mut buf).expect("Read fail");
+    assert_eq!(&expected[..], &buf[..]);
+
+    sabertext
+        .emergency_stop(FailsafeAction::ZeroSpeed)
+        .expect("Emergency stop failure");
+    let expected = b"M1: 0\r\nM2: 0\r\n";
+    let mut buf = [0u8; 14];
+    tty.read_exact(&mut buf).expect("Read fail");
+    assert_eq!(&expected[..], &buf[..]);
+
+    sabertext
+        .emergency_stop(FailsafeAction::Coast)
+        .expect_err("Coast should fail");
+    assert_eq!(0, tty.bytes_to_read().unwrap());
+}
+
 #[test]
 fn set_speed() {
     let vectors = [
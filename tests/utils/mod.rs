@@ -26,6 +26,7 @@ pub fn tty_pair() -> (TTYPort, TTYPort) {
 pub fn saberdevice_harness() -> (SabertoothPort, TTYPort) {
     let (master, slave) = tty_pair();
     let slave_name = &slave.name().expect("TTY has no name");
+    drop(slave); // release the lock held on the slave side
     let saber = SabertoothPort::new(slave_name).expect("Cannot open the sabertooth device");
     (saber, master)
 }
@@ -33,6 +34,7 @@ pub fn saberdevice_harness() -> (SabertoothPort, TTYPort) {
 pub fn saberdevice_harness_shared() -> (SabertoothPortShared, TTYPort) {
     let (master, slave) = tty_pair();
     let slave_name = &slave.name().expect("TTY has no name");
+    drop(slave); // release the lock held on the slave side
     let saber = SabertoothPortShared::new(slave_name).expect("Cannot open the sabertooth device");
     (saber, master)
 }
@@ -78,7 +80,7 @@ pub fn sabercrc_responder_harness() -> (PacketSerial<SabertoothPort>, ResponderC
     let (saber, tty) = sabercrc_harness();
     (
         saber.with_packet_type(PacketType::CRC),
-        Responder::new(Box::new(tty), ResponderType::CRC).start(),
+        Responder::new(Box::new(tty), ResponderType::Crc).start(),
     )
 }
 
@@ -131,7 +133,7 @@ macro_rules! test_get_method {
                     panic!("{}", e);
                 }
             } else {
-                panic!($responder.join_panic().unwrap_err());
+                panic!("{}", $responder.join_panic().unwrap_err());
             }
         }
     };
@@ -10,7 +10,7 @@ use serialport::SerialPort;
 pub enum ResponderType {
     Text,     // response sent when b'\n' is received
     Checksum, // response is sent after the last expected byte is received
-    CRC,      // same as Checksum
+    Crc,      // same as Checksum
 }
 
 /// Structure used for mocking a Sabertooth (real) device.
@@ -82,10 +82,10 @@ impl Responder {
     fn assert_next_byte(&mut self, received: u8) {
         let expected_byte = self.expected.pop_front().expect("Received too many bytes");
         if received != expected_byte {
-            panic!(format!(
+            panic!(
                 "Expected {:#02x} ({:?}) but received {:#02x} ({:?})",
                 expected_byte, expected_byte as char, received, received as char
-            ))
+            )
         }
         if self.must_respond(received) {
             self.tty
@@ -98,7 +98,7 @@ impl Responder {
         match self.type_ {
             ResponderType::Text => received == b'\n',
             ResponderType::Checksum => self.expected.is_empty(),
-            ResponderType::CRC => self.expected.is_empty(),
+            ResponderType::Crc => self.expected.is_empty(),
         }
     }
 }